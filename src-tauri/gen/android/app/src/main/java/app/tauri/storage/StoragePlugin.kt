@@ -1,23 +1,31 @@
 package app.tauri.storage
 
+import android.Manifest
 import android.app.Activity
 import android.content.ClipData
 import android.content.ClipboardManager
 import android.content.Context
 import android.content.Intent
+import android.content.pm.PackageManager
+import android.net.ConnectivityManager
+import android.net.NetworkCapabilities
 import android.net.Uri
 import android.os.Build
+import android.os.Environment
+import android.os.StatFs
 import android.provider.DocumentsContract
 import android.provider.OpenableColumns
 import androidx.activity.ComponentActivity
 import androidx.activity.result.ActivityResultLauncher
 import androidx.activity.result.contract.ActivityResultContracts
+import androidx.core.content.ContextCompat
 import app.tauri.annotation.Command
 import app.tauri.annotation.InvokeArg
 import app.tauri.annotation.TauriPlugin
 import app.tauri.plugin.Invoke
 import app.tauri.plugin.JSObject
 import app.tauri.plugin.Plugin
+import java.io.File
 import java.io.OutputStream
 import java.io.InputStream
 import android.util.Base64
@@ -48,12 +56,24 @@ internal class DeleteDocumentArgs {
     lateinit var document_uri: String
 }
 
+@InvokeArg
+internal class RenameDocumentArgs {
+    lateinit var document_uri: String
+    lateinit var display_name: String
+}
+
 @InvokeArg
 internal class FindOrCreateSubdirectoryArgs {
     lateinit var tree_uri: String
     lateinit var relative_path: String
 }
 
+@InvokeArg
+internal class PrecreateDirsArgs {
+    lateinit var tree_uri: String
+    var relative_dirs: Array<String> = arrayOf()
+}
+
 @InvokeArg
 internal class ListFolderContentsArgs {
     lateinit var tree_uri: String
@@ -76,6 +96,29 @@ internal class SetClipboardArgs {
     lateinit var content: String
 }
 
+@InvokeArg
+internal class FindDocumentArgs {
+    lateinit var tree_uri: String
+    lateinit var file_name: String
+}
+
+@InvokeArg
+internal class SetDocumentMtimeArgs {
+    lateinit var document_uri: String
+    var mtime: Long = 0
+}
+
+@InvokeArg
+internal class FreeSpaceArgs {
+    lateinit var path: String
+}
+
+@InvokeArg
+internal class TransferServiceArgs {
+    var text: String = ""
+    var progress: Int = -1
+}
+
 @TauriPlugin
 class StoragePlugin(private val activity: Activity) : Plugin(activity) {
     private val outputStreams = mutableMapOf<Long, OutputStream>()
@@ -84,6 +127,9 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
     private var pendingInvoke: Invoke? = null
     private lateinit var pickFolderLauncher: ActivityResultLauncher<Intent>
     private lateinit var pickFilesLauncher: ActivityResultLauncher<Intent>
+    private lateinit var notificationPermissionLauncher: ActivityResultLauncher<String>
+    // 每次启动应用只请求一次通知权限，用户拒绝后传输照常进行，只是看不到进度通知
+    private var notificationPermissionRequested = false
 
     init {
         // 使用 activityResultRegistry.register 而不是 registerForActivityResult
@@ -158,6 +204,26 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
                 invoke.reject("User cancelled")
             }
         }
+
+        // Android 13+ 需要运行时授予通知权限，前台服务的进度通知才会显示
+        notificationPermissionLauncher = (activity as ComponentActivity).activityResultRegistry.register(
+            "postNotifications",
+            ActivityResultContracts.RequestPermission()
+        ) { }
+    }
+
+    private fun requestNotificationPermission() {
+        if (Build.VERSION.SDK_INT < Build.VERSION_CODES.TIRAMISU || notificationPermissionRequested) {
+            return
+        }
+        val granted = ContextCompat.checkSelfPermission(activity, Manifest.permission.POST_NOTIFICATIONS) ==
+            PackageManager.PERMISSION_GRANTED
+        if (!granted) {
+            notificationPermissionRequested = true
+            activity.runOnUiThread {
+                notificationPermissionLauncher.launch(Manifest.permission.POST_NOTIFICATIONS)
+            }
+        }
     }
 
     @Command
@@ -288,6 +354,101 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
         }
     }
 
+    @Command
+    fun renameDocument(invoke: Invoke) {
+        val args = invoke.parseArgs(RenameDocumentArgs::class.java)
+        val uri = Uri.parse(args.document_uri)
+
+        try {
+            val renamed = DocumentsContract.renameDocument(activity.contentResolver, uri, args.display_name) ?: run {
+                invoke.reject("Failed to rename document")
+                return
+            }
+            val ret = JSObject()
+            ret.put("uri", renamed.toString())
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Rename error: ${e.message}")
+        }
+    }
+
+    @Command
+    fun findDocument(invoke: Invoke) {
+        val args = invoke.parseArgs(FindDocumentArgs::class.java)
+        val treeUri = Uri.parse(args.tree_uri)
+
+        try {
+            val found = findChildDocument(treeUri, DocumentsContract.getTreeDocumentId(treeUri), args.file_name)
+            val ret = JSObject()
+            ret.put("uri", found?.toString())
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Find error: ${e.message}")
+        }
+    }
+
+    @Command
+    fun setDocumentMtime(invoke: Invoke) {
+        val args = invoke.parseArgs(SetDocumentMtimeArgs::class.java)
+        val uri = Uri.parse(args.document_uri)
+
+        // SAF 没有设置修改时间的接口：仅外部存储提供方的文档可按其文件路径尝试设置
+        var applied = false
+        try {
+            if (uri.authority == "com.android.externalstorage.documents") {
+                val docId = DocumentsContract.getDocumentId(uri)
+                val volume = docId.substringBefore(':')
+                val root = if (volume == "primary") Environment.getExternalStorageDirectory().path else "/storage/$volume"
+                applied = File(root, docId.substringAfter(':', "")).setLastModified(args.mtime)
+            }
+        } catch (e: Exception) {
+            applied = false
+        }
+
+        val ret = JSObject()
+        ret.put("applied", applied)
+        invoke.resolve(ret)
+    }
+
+    @Command
+    fun getFreeSpace(invoke: Invoke) {
+        val args = invoke.parseArgs(FreeSpaceArgs::class.java)
+
+        try {
+            val path = if (args.path.startsWith("content://")) {
+                // SAF 目录无法直接 statfs：按 tree 文档 ID 中的存储卷（primary 或 SD 卡 ID）找到挂载路径
+                val volume = DocumentsContract.getTreeDocumentId(Uri.parse(args.path)).substringBefore(':')
+                if (volume == "primary") Environment.getExternalStorageDirectory().path else "/storage/$volume"
+            } else {
+                args.path
+            }
+            val stat = StatFs(path)
+            // 卷大小为 0 说明该路径无法统计（例如存储卷未挂载），报错而不是返回 0
+            if (stat.totalBytes == 0L) {
+                invoke.reject("Free space not available for $path")
+                return
+            }
+            val ret = JSObject()
+            ret.put("bytes", stat.availableBytes)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Free space error: ${e.message}")
+        }
+    }
+
+    @Command
+    fun getMimeType(invoke: Invoke) {
+        val args = invoke.parseArgs(UriArgs::class.java)
+
+        try {
+            val ret = JSObject()
+            ret.put("mime", activity.contentResolver.getType(Uri.parse(args.uri)) ?: "application/octet-stream")
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("MIME type error: ${e.message}")
+        }
+    }
+
     @Command
     fun getFileInfo(invoke: Invoke) {
         val args = invoke.parseArgs(UriArgs::class.java)
@@ -299,12 +460,17 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
                     val nameIndex = cursor.getColumnIndex(OpenableColumns.DISPLAY_NAME)
                     val sizeIndex = cursor.getColumnIndex(OpenableColumns.SIZE)
 
+                    // 只有 DocumentsProvider 提供修改时间，其他提供方返回 0
+                    val lastModifiedIndex = cursor.getColumnIndex(DocumentsContract.Document.COLUMN_LAST_MODIFIED)
+
                     val name = if (nameIndex >= 0) cursor.getString(nameIndex) else "unknown"
                     val size = if (sizeIndex >= 0) cursor.getLong(sizeIndex) else 0L
+                    val lastModified = if (lastModifiedIndex >= 0 && !cursor.isNull(lastModifiedIndex)) cursor.getLong(lastModifiedIndex) else 0L
 
                     val ret = JSObject()
                     ret.put("name", name)
                     ret.put("size", size)
+                    ret.put("last_modified", lastModified)
                     invoke.resolve(ret)
                 } else {
                     invoke.reject("Failed to read file info")
@@ -315,6 +481,24 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
         }
     }
 
+    @Command
+    fun openReadFd(invoke: Invoke) {
+        val args = invoke.parseArgs(UriArgs::class.java)
+
+        try {
+            // 交出文件描述符的所有权，由 Rust 端直接读取并负责关闭，文件数据不再经过插件桥
+            val fd = activity.contentResolver.openFileDescriptor(Uri.parse(args.uri), "r")?.detachFd() ?: run {
+                invoke.reject("Failed to open file descriptor")
+                return
+            }
+            val ret = JSObject()
+            ret.put("fd", fd)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Error opening file descriptor: ${e.message}")
+        }
+    }
+
     @Command
     fun readUriChunk(invoke: Invoke) {
         val args = invoke.parseArgs(ReadUriChunkArgs::class.java)
@@ -410,6 +594,70 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
         }
     }
 
+    @Command
+    fun getConnectionType(invoke: Invoke) {
+        try {
+            val connectivityManager = activity.getSystemService(Context.CONNECTIVITY_SERVICE) as ConnectivityManager
+            val capabilities = connectivityManager.getNetworkCapabilities(connectivityManager.activeNetwork)
+            val type = when {
+                capabilities == null -> "none"
+                capabilities.hasTransport(NetworkCapabilities.TRANSPORT_WIFI) -> "wifi"
+                capabilities.hasTransport(NetworkCapabilities.TRANSPORT_ETHERNET) -> "ethernet"
+                capabilities.hasTransport(NetworkCapabilities.TRANSPORT_CELLULAR) -> "cellular"
+                else -> "unknown"
+            }
+            val ret = JSObject()
+            ret.put("connection_type", type)
+            ret.put("metered", connectivityManager.isActiveNetworkMetered)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Failed to get connection type: ${e.message}")
+        }
+    }
+
+    @Command
+    fun startTransferService(invoke: Invoke) {
+        try {
+            val args = invoke.parseArgs(TransferServiceArgs::class.java)
+            requestNotificationPermission()
+            TransferService.start(activity, args.text, args.progress)
+
+            val ret = JSObject()
+            ret.put("ok", true)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Failed to start transfer service: ${e.message}")
+        }
+    }
+
+    // 只更新正在运行的服务的通知，服务已停止时不会重新启动它
+    @Command
+    fun updateTransferNotification(invoke: Invoke) {
+        try {
+            val args = invoke.parseArgs(TransferServiceArgs::class.java)
+            val updated = TransferService.update(args.text, args.progress)
+
+            val ret = JSObject()
+            ret.put("ok", updated)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Failed to update transfer notification: ${e.message}")
+        }
+    }
+
+    @Command
+    fun stopTransferService(invoke: Invoke) {
+        try {
+            TransferService.stop(activity)
+
+            val ret = JSObject()
+            ret.put("ok", true)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Failed to stop transfer service: ${e.message}")
+        }
+    }
+
     @Command
     fun findOrCreateSubdirectory(invoke: Invoke) {
         val args = invoke.parseArgs(FindOrCreateSubdirectoryArgs::class.java)
@@ -469,6 +717,63 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
         }
     }
 
+    @Command
+    fun precreateDirs(invoke: Invoke) {
+        val args = invoke.parseArgs(PrecreateDirsArgs::class.java)
+        val treeUri = Uri.parse(args.tree_uri)
+        val baseTreeUri = args.tree_uri.substringBefore("/document/")
+
+        try {
+            // Cache document IDs by path prefix so shared parents are looked up only once
+            val docIds = HashMap<String, String>()
+            docIds[""] = DocumentsContract.getTreeDocumentId(treeUri)
+            val dirs = JSObject()
+
+            // Shorter paths first so parents are resolved before their children
+            for (relativeDir in args.relative_dirs.distinct().sortedBy { it.length }) {
+                val pathComponents = relativeDir.split("/").filter { it.isNotEmpty() }
+                var prefix = ""
+                var currentDocId = docIds[""]!!
+
+                for (component in pathComponents) {
+                    prefix = if (prefix.isEmpty()) component else "$prefix/$component"
+                    val cached = docIds[prefix]
+                    if (cached != null) {
+                        currentDocId = cached
+                        continue
+                    }
+
+                    val childUri = findChildDocument(treeUri, currentDocId, component)
+                    currentDocId = if (childUri != null) {
+                        DocumentsContract.getDocumentId(childUri)
+                    } else {
+                        val currentDocUri = DocumentsContract.buildDocumentUriUsingTree(treeUri, currentDocId)
+                        val newDirUri = DocumentsContract.createDocument(
+                            activity.contentResolver,
+                            currentDocUri,
+                            DocumentsContract.Document.MIME_TYPE_DIR,
+                            component
+                        )
+                        if (newDirUri == null) {
+                            invoke.reject("Failed to create directory: $prefix")
+                            return
+                        }
+                        DocumentsContract.getDocumentId(newDirUri)
+                    }
+                    docIds[prefix] = currentDocId
+                }
+
+                dirs.put(relativeDir, "$baseTreeUri/tree/${Uri.encode(currentDocId)}")
+            }
+
+            val ret = JSObject()
+            ret.put("dirs", dirs)
+            invoke.resolve(ret)
+        } catch (e: Exception) {
+            invoke.reject("Error creating directories: ${e.message}")
+        }
+    }
+
     private fun findChildDocument(treeUri: Uri, parentDocId: String, childName: String): Uri? {
         val childrenUri = DocumentsContract.buildChildDocumentsUriUsingTree(treeUri, parentDocId)
 
@@ -545,7 +850,8 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
                 DocumentsContract.Document.COLUMN_DOCUMENT_ID,
                 DocumentsContract.Document.COLUMN_DISPLAY_NAME,
                 DocumentsContract.Document.COLUMN_MIME_TYPE,
-                DocumentsContract.Document.COLUMN_SIZE
+                DocumentsContract.Document.COLUMN_SIZE,
+                DocumentsContract.Document.COLUMN_LAST_MODIFIED
             ),
             null, null, null
         )?.use { cursor ->
@@ -554,12 +860,26 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
                 val displayName = cursor.getString(1)
                 val mimeType = cursor.getString(2)
                 val size = cursor.getLong(3)
+                val lastModified = if (cursor.isNull(4)) 0L else cursor.getLong(4)
 
                 val childPath = if (relativePath.isEmpty()) displayName else "$relativePath/$displayName"
 
                 if (mimeType == DocumentsContract.Document.MIME_TYPE_DIR) {
                     // Recurse into subdirectory
+                    val before = files.size
                     listFolderRecursive(treeUri, docId, childPath, files)
+                    // Record directories without any files so they can be recreated on the receiver
+                    if (files.size == before) {
+                        val dirObj = JSObject()
+                        dirObj.put("uri", DocumentsContract.buildDocumentUriUsingTree(treeUri, docId).toString())
+                        dirObj.put("name", displayName)
+                        dirObj.put("relative_path", childPath)
+                        dirObj.put("size", 0)
+                        dirObj.put("last_modified", lastModified)
+                        dirObj.put("mime", "inode/directory")
+                        dirObj.put("is_dir", true)
+                        files.add(dirObj)
+                    }
                 } else {
                     // Add file to list
                     val docUri = DocumentsContract.buildDocumentUriUsingTree(treeUri, docId)
@@ -568,6 +888,8 @@ class StoragePlugin(private val activity: Activity) : Plugin(activity) {
                     fileObj.put("name", displayName)
                     fileObj.put("relative_path", childPath)
                     fileObj.put("size", size)
+                    fileObj.put("last_modified", lastModified)
+                    fileObj.put("mime", mimeType ?: "application/octet-stream")
                     files.add(fileObj)
                 }
             }
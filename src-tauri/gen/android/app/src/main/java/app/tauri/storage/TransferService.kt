@@ -0,0 +1,103 @@
+package app.tauri.storage
+
+import android.app.NotificationChannel
+import android.app.NotificationManager
+import android.app.PendingIntent
+import android.app.Service
+import android.content.Context
+import android.content.Intent
+import android.content.pm.ServiceInfo
+import android.os.Build
+import android.os.IBinder
+import androidx.core.app.NotificationCompat
+
+// 传输期间的前台服务：应用切到后台时保持进程存活，并在常驻通知中显示进度
+class TransferService : Service() {
+    companion object {
+        private const val CHANNEL_ID = "transfer"
+        private const val NOTIFICATION_ID = 7878
+        private const val EXTRA_TEXT = "text"
+        private const val EXTRA_PROGRESS = "progress"
+
+        // 启动服务；服务已在运行时只更新通知内容。progress 为 0-100，负数表示进度未知
+        fun start(context: Context, text: String, progress: Int) {
+            val intent = Intent(context, TransferService::class.java)
+                .putExtra(EXTRA_TEXT, text)
+                .putExtra(EXTRA_PROGRESS, progress)
+            if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.O) {
+                context.startForegroundService(intent)
+            } else {
+                context.startService(intent)
+            }
+        }
+
+        fun stop(context: Context) {
+            context.stopService(Intent(context, TransferService::class.java))
+        }
+
+        // 正在运行的服务实例，服务停止后为 null
+        @Volatile
+        private var running: TransferService? = null
+
+        // 更新正在运行的服务的通知；服务未运行时返回 false，不会启动服务
+        fun update(text: String, progress: Int): Boolean {
+            val service = running ?: return false
+            val manager = service.getSystemService(Context.NOTIFICATION_SERVICE) as NotificationManager
+            manager.notify(NOTIFICATION_ID, service.buildNotification(text, progress))
+            return true
+        }
+    }
+
+    override fun onBind(intent: Intent?): IBinder? = null
+
+    override fun onCreate() {
+        super.onCreate()
+        running = this
+    }
+
+    override fun onDestroy() {
+        running = null
+        super.onDestroy()
+    }
+
+    override fun onStartCommand(intent: Intent?, flags: Int, startId: Int): Int {
+        val text = intent?.getStringExtra(EXTRA_TEXT) ?: ""
+        val progress = intent?.getIntExtra(EXTRA_PROGRESS, -1) ?: -1
+        val notification = buildNotification(text, progress)
+
+        // 每次启动请求都必须调用 startForeground，同时也起到更新通知的作用
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
+            startForeground(NOTIFICATION_ID, notification, ServiceInfo.FOREGROUND_SERVICE_TYPE_DATA_SYNC)
+        } else {
+            startForeground(NOTIFICATION_ID, notification)
+        }
+        // 进程被系统回收后不自动重启，传输已随进程中断
+        return START_NOT_STICKY
+    }
+
+    private fun buildNotification(text: String, progress: Int): android.app.Notification {
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.O) {
+            val manager = getSystemService(Context.NOTIFICATION_SERVICE) as NotificationManager
+            if (manager.getNotificationChannel(CHANNEL_ID) == null) {
+                val channel = NotificationChannel(CHANNEL_ID, "File transfers", NotificationManager.IMPORTANCE_LOW)
+                manager.createNotificationChannel(channel)
+            }
+        }
+
+        // 点击通知回到应用
+        val launchIntent = packageManager.getLaunchIntentForPackage(packageName)
+        val contentIntent = launchIntent?.let {
+            PendingIntent.getActivity(this, 0, it, PendingIntent.FLAG_IMMUTABLE or PendingIntent.FLAG_UPDATE_CURRENT)
+        }
+
+        return NotificationCompat.Builder(this, CHANNEL_ID)
+            .setSmallIcon(android.R.drawable.stat_sys_download)
+            .setContentTitle("Transferring files")
+            .setContentText(text)
+            .setOngoing(true)
+            .setOnlyAlertOnce(true)
+            .setContentIntent(contentIntent)
+            .setProgress(100, progress.coerceIn(0, 100), progress < 0)
+            .build()
+    }
+}
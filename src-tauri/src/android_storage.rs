@@ -34,6 +34,14 @@ struct UriPayload {
 struct FileInfoResponse {
     name: String,
     size: u64,
+    /// 毫秒时间戳，文档提供方不支持时为 0
+    #[serde(default)]
+    last_modified: i64,
+}
+
+#[derive(Deserialize)]
+struct MimeTypeResponse {
+    mime: String,
 }
 
 #[derive(Serialize)]
@@ -49,6 +57,11 @@ struct ReadUriChunkResponse {
     bytes_read: i32,
 }
 
+#[derive(Deserialize)]
+struct ReadFdResponse {
+    fd: i32,
+}
+
 #[derive(Serialize)]
 struct OpenWriterPayload {
     tree_uri: String,
@@ -92,6 +105,49 @@ struct DeleteDocumentResponse {
     ok: bool,
 }
 
+#[derive(Serialize)]
+struct RenameDocumentPayload {
+    document_uri: String,
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct RenameDocumentResponse {
+    uri: String,
+}
+
+#[derive(Serialize)]
+struct FindDocumentPayload {
+    tree_uri: String,
+    file_name: String,
+}
+
+#[derive(Deserialize)]
+struct FindDocumentResponse {
+    uri: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SetDocumentMtimePayload {
+    document_uri: String,
+    mtime: i64,
+}
+
+#[derive(Deserialize)]
+struct SetDocumentMtimeResponse {
+    applied: bool,
+}
+
+#[derive(Serialize)]
+struct FreeSpacePayload {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct FreeSpaceResponse {
+    bytes: u64,
+}
+
 #[derive(Serialize)]
 struct FindOrCreateSubdirectoryPayload {
     tree_uri: String,
@@ -103,6 +159,17 @@ struct FindOrCreateSubdirectoryResponse {
     uri: String,
 }
 
+#[derive(Serialize)]
+struct PrecreateDirsPayload {
+    tree_uri: String,
+    relative_dirs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PrecreateDirsResponse {
+    dirs: std::collections::HashMap<String, String>,
+}
+
 #[derive(Serialize)]
 struct ListFolderContentsPayload {
     tree_uri: String,
@@ -114,6 +181,19 @@ pub struct FolderFileInfo {
     pub name: String,
     pub relative_path: String,
     pub size: u64,
+    /// 毫秒时间戳，文档提供方不支持时为 0
+    #[serde(default)]
+    pub last_modified: i64,
+    /// 不含任何文件的子目录，uri 为目录本身
+    #[serde(default)]
+    pub is_dir: bool,
+    /// 文档提供方报告的 MIME 类型，未知时为 application/octet-stream
+    #[serde(default = "default_mime")]
+    pub mime: String,
+}
+
+fn default_mime() -> String {
+    "application/octet-stream".to_string()
 }
 
 #[derive(Deserialize)]
@@ -131,6 +211,12 @@ struct GetDeviceNameResponse {
     name: String,
 }
 
+#[derive(Deserialize)]
+pub struct ConnectionTypeResponse {
+    pub connection_type: String,
+    pub metered: bool,
+}
+
 #[derive(Serialize)]
 struct SetClipboardPayload {
     content: String,
@@ -141,6 +227,17 @@ struct SetClipboardResponse {
     ok: bool,
 }
 
+#[derive(Serialize)]
+struct TransferServicePayload {
+    text: String,
+    progress: i32,
+}
+
+#[derive(Deserialize)]
+struct TransferServiceResponse {
+    ok: bool,
+}
+
 pub fn init() -> TauriPlugin<Wry> {
     let mut builder = Builder::<Wry>::new("android-storage");
 
@@ -203,6 +300,38 @@ impl AndroidStorage {
         Err("getFileInfo is only supported on Android".to_string())
     }
 
+    /// content:// URI 的修改时间（毫秒时间戳），提供方不支持时为 0
+    pub fn get_last_modified(&self, _uri: String) -> Result<i64, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = UriPayload { uri: _uri };
+            let res = self
+                .0
+                .run_mobile_plugin::<FileInfoResponse>("getFileInfo", payload);
+            return res
+                .map(|r| r.last_modified)
+                .map_err(|e| format!("getFileInfo failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("getFileInfo is only supported on Android".to_string())
+    }
+
+    /// content:// URI 的 MIME 类型，提供方未报告时为 application/octet-stream
+    pub fn get_mime_type(&self, _uri: String) -> Result<String, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = UriPayload { uri: _uri };
+            let res = self
+                .0
+                .run_mobile_plugin::<MimeTypeResponse>("getMimeType", payload);
+            return res
+                .map(|r| r.mime)
+                .map_err(|e| format!("getMimeType failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("getMimeType is only supported on Android".to_string())
+    }
+
     pub fn read_uri_chunk(&self, _uri: String, _offset: u64, _size: i32) -> Result<(String, i32), String> {
         #[cfg(target_os = "android")]
         {
@@ -218,6 +347,49 @@ impl AndroidStorage {
         Err("readUriChunk is only supported on Android".to_string())
     }
 
+    /// 打开 content:// URI 的只读文件描述符，所有权交给调用方
+    pub fn open_read_fd(&self, _uri: String) -> Result<i32, String> {
+        #[cfg(target_os = "android")]
+        {
+            let res = self
+                .0
+                .run_mobile_plugin::<ReadFdResponse>("openReadFd", UriPayload { uri: _uri });
+            return res
+                .map(|r| r.fd)
+                .map_err(|e| format!("openReadFd failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("openReadFd is only supported on Android".to_string())
+    }
+
+    /// 打开按偏移读取 URI 的读取器。能拿到可定位的文件描述符时直接读取字节；
+    /// 否则（如管道形式的文档提供方）退回 readUriChunk
+    pub fn open_uri_reader(&self, uri: String) -> UriReader<'_> {
+        #[cfg(target_os = "android")]
+        let file = self.open_read_fd(uri.clone())
+            .map_err(|e| eprintln!("Reading {} through the plugin bridge: {}", uri, e))
+            .ok()
+            .map(|fd| {
+                use std::os::fd::FromRawFd;
+                // SAFETY: openReadFd 通过 detachFd 交出了该描述符的所有权，不会在 Kotlin 端再被关闭
+                unsafe { std::fs::File::from_raw_fd(fd) }
+            })
+            .filter(|file| file.metadata().is_ok_and(|m| m.is_file()));
+        UriReader {
+            storage: self,
+            uri,
+            #[cfg(target_os = "android")]
+            file,
+        }
+    }
+
+    /// 读取一块数据并解码到调用方复用的 `buf`（先清空），返回读取的字节数。
+    /// 插件桥只能传 JSON，数据以 base64 过桥，仅在拿不到可定位的文件描述符时使用
+    pub fn read_uri_chunk_binary(&self, uri: String, offset: u64, size: i32, buf: &mut Vec<u8>) -> Result<usize, String> {
+        let (data, bytes_read) = self.read_uri_chunk(uri, offset, size)?;
+        decode_chunk(&data, bytes_read, buf)
+    }
+
     pub fn open_writer(&self, _tree_uri: String, _file_name: String) -> Result<(i64, String), String> {
         #[cfg(target_os = "android")]
         {
@@ -284,6 +456,69 @@ impl AndroidStorage {
         Err("deleteDocument is only supported on Android".to_string())
     }
 
+    pub fn rename_document(&self, _document_uri: String, _display_name: String) -> Result<String, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = RenameDocumentPayload { document_uri: _document_uri, display_name: _display_name };
+            let res = self
+                .0
+                .run_mobile_plugin::<RenameDocumentResponse>("renameDocument", payload);
+            return res
+                .map(|r| r.uri)
+                .map_err(|e| format!("renameDocument failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("renameDocument is only supported on Android".to_string())
+    }
+
+    /// 在目录中按文件名查找文档，不存在时返回 None
+    pub fn find_document(&self, _tree_uri: String, _file_name: String) -> Result<Option<String>, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = FindDocumentPayload { tree_uri: _tree_uri, file_name: _file_name };
+            let res = self
+                .0
+                .run_mobile_plugin::<FindDocumentResponse>("findDocument", payload);
+            return res
+                .map(|r| r.uri)
+                .map_err(|e| format!("findDocument failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("findDocument is only supported on Android".to_string())
+    }
+
+    /// 设置文档的修改时间（毫秒时间戳），返回是否生效（多数文档提供方不支持）
+    pub fn set_document_mtime(&self, _document_uri: String, _mtime: i64) -> Result<bool, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = SetDocumentMtimePayload { document_uri: _document_uri, mtime: _mtime };
+            let res = self
+                .0
+                .run_mobile_plugin::<SetDocumentMtimeResponse>("setDocumentMtime", payload);
+            return res
+                .map(|r| r.applied)
+                .map_err(|e| format!("setDocumentMtime failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("setDocumentMtime is only supported on Android".to_string())
+    }
+
+    /// 目录（SAF tree Uri 或文件路径）所在存储卷的可用字节数
+    pub fn get_free_space(&self, _path: String) -> Result<u64, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = FreeSpacePayload { path: _path };
+            let res = self
+                .0
+                .run_mobile_plugin::<FreeSpaceResponse>("getFreeSpace", payload);
+            return res
+                .map(|r| r.bytes)
+                .map_err(|e| format!("getFreeSpace failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("getFreeSpace is only supported on Android".to_string())
+    }
+
     pub fn find_or_create_subdirectory(&self, _tree_uri: String, _relative_path: String) -> Result<String, String> {
         #[cfg(target_os = "android")]
         {
@@ -299,6 +534,21 @@ impl AndroidStorage {
         Err("findOrCreateSubdirectory is only supported on Android".to_string())
     }
 
+    pub fn precreate_dirs(&self, _tree_uri: String, _relative_dirs: Vec<String>) -> Result<std::collections::HashMap<String, String>, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = PrecreateDirsPayload { tree_uri: _tree_uri, relative_dirs: _relative_dirs };
+            let res = self
+                .0
+                .run_mobile_plugin::<PrecreateDirsResponse>("precreateDirs", payload);
+            return res
+                .map(|r| r.dirs)
+                .map_err(|e| format!("precreateDirs failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("precreateDirs is only supported on Android".to_string())
+    }
+
     pub fn list_folder_contents(&self, _tree_uri: String) -> Result<Vec<FolderFileInfo>, String> {
         #[cfg(target_os = "android")]
         {
@@ -358,4 +608,196 @@ impl AndroidStorage {
         #[allow(unreachable_code)]
         Err("getDeviceName is only supported on Android".to_string())
     }
+
+    /// 启动传输前台服务；已在运行时只更新通知（progress 为 0-100，负数表示未知）
+    pub fn start_transfer_service(&self, _text: String, _progress: i32) -> Result<(), String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = TransferServicePayload { text: _text, progress: _progress };
+            let res = self
+                .0
+                .run_mobile_plugin::<TransferServiceResponse>("startTransferService", payload);
+            return res
+                .map(|r| {
+                    let _ = r.ok;
+                })
+                .map_err(|e| format!("startTransferService failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("startTransferService is only supported on Android".to_string())
+    }
+
+    /// 更新正在运行的传输前台服务的通知；服务未运行时返回 false，不会启动服务
+    pub fn update_transfer_notification(&self, _text: String, _progress: i32) -> Result<bool, String> {
+        #[cfg(target_os = "android")]
+        {
+            let payload = TransferServicePayload { text: _text, progress: _progress };
+            let res = self
+                .0
+                .run_mobile_plugin::<TransferServiceResponse>("updateTransferNotification", payload);
+            return res
+                .map(|r| r.ok)
+                .map_err(|e| format!("updateTransferNotification failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("updateTransferNotification is only supported on Android".to_string())
+    }
+
+    pub fn stop_transfer_service(&self) -> Result<(), String> {
+        #[cfg(target_os = "android")]
+        {
+            let res = self
+                .0
+                .run_mobile_plugin::<TransferServiceResponse>("stopTransferService", EmptyPayload {});
+            return res
+                .map(|r| {
+                    let _ = r.ok;
+                })
+                .map_err(|e| format!("stopTransferService failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("stopTransferService is only supported on Android".to_string())
+    }
+
+    pub fn get_connection_type(&self) -> Result<ConnectionTypeResponse, String> {
+        #[cfg(target_os = "android")]
+        {
+            let res = self
+                .0
+                .run_mobile_plugin::<ConnectionTypeResponse>("getConnectionType", EmptyPayload {});
+            return res.map_err(|e| format!("getConnectionType failed: {e}"));
+        }
+        #[allow(unreachable_code)]
+        Err("getConnectionType is only supported on Android".to_string())
+    }
+}
+
+/// 按偏移读取一个 content:// URI，见 `AndroidStorage::open_uri_reader`
+pub struct UriReader<'a> {
+    storage: &'a AndroidStorage,
+    uri: String,
+    #[cfg(target_os = "android")]
+    file: Option<std::fs::File>,
+}
+
+impl UriReader<'_> {
+    /// 从 offset 读取至多 size 字节到复用的 `buf`（先清空），返回读取的字节数，0 表示已到文件末尾
+    pub fn read_chunk(&self, offset: u64, size: i32, buf: &mut Vec<u8>) -> Result<usize, String> {
+        #[cfg(target_os = "android")]
+        if let Some(file) = &self.file {
+            return read_file_chunk(file, offset, size.max(0) as usize, buf)
+                .map_err(|e| format!("Failed to read {}: {}", self.uri, e));
+        }
+        self.storage.read_uri_chunk_binary(self.uri.clone(), offset, size, buf)
+    }
+}
+
+/// 从文件的 offset 处读取至多 size 字节到 `buf`（先清空），读满或到文件末尾为止
+#[cfg(any(target_os = "android", all(test, unix)))]
+fn read_file_chunk(file: &std::fs::File, offset: u64, size: usize, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+
+    buf.clear();
+    buf.resize(size, 0);
+    let mut filled = 0;
+    while filled < size {
+        match file.read_at(&mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                buf.clear();
+                return Err(e);
+            }
+        }
+    }
+    buf.truncate(filled);
+    Ok(filled)
+}
+
+/// 将 readUriChunk 返回的 base64 数据解码到复用的 `buf`（先清空），返回字节数
+fn decode_chunk(data: &str, bytes_read: i32, buf: &mut Vec<u8>) -> Result<usize, String> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    buf.clear();
+    if bytes_read <= 0 {
+        return Ok(0);
+    }
+    general_purpose::STANDARD.decode_vec(data, buf)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+    Ok(buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, Engine as _};
+    use std::time::Instant;
+
+    #[test]
+    fn decode_chunk_reuses_the_buffer() {
+        let mut buf = Vec::new();
+        assert_eq!(decode_chunk(&general_purpose::STANDARD.encode(b"hello"), 5, &mut buf).unwrap(), 5);
+        assert_eq!(buf, b"hello");
+        assert_eq!(decode_chunk(&general_purpose::STANDARD.encode(b"ab"), 2, &mut buf).unwrap(), 2);
+        assert_eq!(buf, b"ab");
+        assert_eq!(decode_chunk("", 0, &mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+        assert!(decode_chunk("not base64!", 8, &mut buf).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_file_chunk_reads_at_offsets() {
+        let path = std::env::temp_dir().join(format!("lan-transfer-read-chunk-{}", std::process::id()));
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+
+        let mut buf = Vec::new();
+        assert_eq!(read_file_chunk(&file, 0, 4096, &mut buf).unwrap(), 4096);
+        assert_eq!(buf, data[..4096]);
+        // 最后一块不足 size，读到末尾为止
+        assert_eq!(read_file_chunk(&file, 8192, 4096, &mut buf).unwrap(), 10_000 - 8192);
+        assert_eq!(buf, data[8192..]);
+        assert_eq!(read_file_chunk(&file, 10_000, 4096, &mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// 基准：发送 1 GiB 文件时按 1 MiB 块解码，比较每块分配新 Vec 与解码到复用缓冲区。运行：
+    /// cargo test --release decode_chunk_throughput -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn decode_chunk_throughput() {
+        const CHUNK: usize = 1024 * 1024;
+        const CHUNKS: usize = 1024;
+        let data: Vec<u8> = (0..CHUNK).map(|i| (i * 31 % 251) as u8).collect();
+        let encoded = general_purpose::STANDARD.encode(&data);
+
+        let start = Instant::now();
+        let mut total = 0;
+        for _ in 0..CHUNKS {
+            let chunk = general_purpose::STANDARD.decode(&encoded).unwrap();
+            total += std::hint::black_box(chunk).len();
+        }
+        let fresh = start.elapsed();
+        assert_eq!(total, CHUNK * CHUNKS);
+
+        let start = Instant::now();
+        let mut buf = Vec::with_capacity(CHUNK);
+        let mut total = 0;
+        for _ in 0..CHUNKS {
+            total += decode_chunk(&encoded, CHUNK as i32, &mut buf).unwrap();
+            std::hint::black_box(&buf);
+        }
+        let reused = start.elapsed();
+        assert_eq!(total, CHUNK * CHUNKS);
+
+        let rate = |elapsed: std::time::Duration| (CHUNK * CHUNKS) as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+        println!("fresh Vec per chunk: {:?} ({:.0} MiB/s)", fresh, rate(fresh));
+        println!("reused buffer:       {:?} ({:.0} MiB/s)", reused, rate(reused));
+    }
 }
@@ -0,0 +1,39 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+/// 应用配置目录下的文件路径（目录不存在时自动创建）
+pub fn config_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir()
+        .map_err(|e| format!("Failed to get config dir: {}", e))?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create config dir: {}", e))?;
+    Ok(dir.join(file_name))
+}
+
+/// 读取 JSON 配置文件，文件不存在或解析失败时返回默认值
+pub fn load_json<T: DeserializeOwned + Default>(app: &AppHandle, file_name: &str) -> T {
+    let Ok(path) = config_path(app, file_name) else {
+        return T::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Failed to parse {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// 写入 JSON 配置文件（先写临时文件再重命名，避免写一半导致配置损坏）
+pub fn save_json<T: Serialize>(app: &AppHandle, file_name: &str, value: &T) -> Result<(), String> {
+    let path = config_path(app, file_name)?;
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", file_name, e))?;
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)
+        .map_err(|e| format!("Failed to write {}: {}", file_name, e))?;
+    std::fs::rename(&tmp_path, &path)
+        .map_err(|e| format!("Failed to save {}: {}", file_name, e))
+}
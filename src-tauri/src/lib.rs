@@ -1,5 +1,6 @@
 mod network;
 mod android_storage;
+mod config;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -9,32 +10,122 @@ pub fn run() {
 
     let chat_connections: network::chat::ChatConnections = Arc::new(Mutex::new(HashMap::new()));
     let clipboard_connections: network::clipboard::ClipboardConnections = Arc::new(Mutex::new(HashMap::new()));
+    let control_channels: network::control::ControlChannels = Arc::new(Mutex::new(HashMap::new()));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(android_storage::init())
         .manage(chat_connections)
         .manage(clipboard_connections)
+        .manage(control_channels)
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                network::window_events::window_destroyed(window.label());
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             network::transfer::start_websocket_server,
+            network::transfer::force_reset_servers,
+            network::transfer::get_status,
+            network::transfer::run_loopback_test,
+            network::transfer::stop_websocket_server,
+            network::transfer::stop_websocket_server_graceful,
+            network::transfer_tls::enable_transfer_tls,
             network::transfer::select_folder,
             network::transfer::list_folder_files,
+            network::transfer::validate_folder,
+            network::transfer::preview_folder_transfer,
+            network::transfer::preview_folder_transfer_android,
             network::transfer::pick_multiple_files,
             network::transfer::pick_folder_for_send,
             network::transfer::send_files_android,
             network::transfer::send_folder_android,
             network::transfer::send_folder_desktop,
+            network::transfer::send_files_desktop,
+            network::multi_send::send_files_to_many,
+            network::folder_sync::sync_folder_desktop,
+            network::transfer_history::retry_transfer,
+            network::transfer_history::get_transfer_history,
+            network::transfer_history::clear_transfer_history,
+            network::transfer_queue::enqueue_send,
+            network::transfer_queue::cancel_queued_send,
+            network::transfer_queue::list_queue,
+            network::transfer_queue::pause_queue,
+            network::transfer_queue::resume_queue,
+            network::transfer_queue::reorder_queue,
+            network::transfer_queue::set_queue_concurrency,
+            network::pull_tokens::create_pull_token,
+            network::pull_tokens::revoke_pull_token,
+            network::pull_tokens::pull_transfer,
             network::transfer::cancel_file_sending,
             network::transfer::cancel_file_receiving,
+            network::jobs::list_all_jobs,
+            network::jobs::cancel_job,
+            network::transfer::respond_to_manifest,
+            network::transfer::respond_to_transfer,
+            network::transfer::precreate_dirs,
             network::transfer::get_local_ip,
+            network::transfer::list_local_ips,
+            network::socket_buffers::set_socket_buffers,
+            network::socket_buffers::get_socket_buffers,
             network::transfer::get_download_dir,
+            network::save_profiles::add_save_profile,
+            network::save_profiles::remove_save_profile,
+            network::save_profiles::list_save_profiles,
+            network::save_profiles::set_active_save_profile,
+            network::receive_quota::set_receive_quota,
+            network::receive_quota::get_receive_quota,
+            network::transfer::set_max_receive_file_size,
+            network::transfer::set_max_concurrent_receives,
+            network::disk_space::get_free_space,
+            network::receive_zip::set_package_as_zip,
+            network::receive_zip::get_package_as_zip,
+            network::peer_trust::set_peer_trust,
+            network::peer_trust::list_peer_trust,
+            network::connection_pin::set_connection_pin,
+            network::connection_pin::get_connection_auth_frame,
+            network::partials::cleanup_partials,
+            network::window_events::set_window_gone_policy,
+            network::window_events::get_window_gone_policy,
+            network::window_events::window_reattached,
+            network::size_units::set_size_units,
+            network::size_units::get_size_units,
+            network::throughput::start_throughput_events,
+            network::throughput::stop_throughput_events,
+            network::rate_limit::set_transfer_rate_limit,
+            network::rate_limit::get_transfer_rate_limit,
+            network::metered::get_connection_type,
+            network::metered::set_metered_policy,
+            network::metered::get_metered_policy,
+            network::ports::rebind_server,
+            network::ports::get_service_ports,
+            network::batch_progress::export_received_manifest,
+            network::transfer::peer_supports,
+            network::transfer::measure_clock_skew,
             network::transfer::start_discovery,
+            network::transfer::stop_discovery,
+            network::transfer::test_multicast,
+            network::transfer::add_manual_device,
+            network::discovery_packet::set_compact_discovery,
+            network::discovery_packet::get_compact_discovery,
+            network::device_name::set_device_name,
+            network::device_name::get_device_name,
             network::chat::start_chat_server,
             network::chat::connect_to_chat,
             network::chat::send_chat_message,
+            network::chat::send_chat_once,
+            network::chat::send_chat_attachment,
+            network::chat::save_chat_attachment,
             network::chat::disconnect_chat,
             network::chat::stop_chat_server,
             network::chat::disconnect_all_chats,
+            network::chat::set_chat_message_limit,
+            network::chat_history::get_chat_history,
+            network::chat_history::clear_chat_history,
+            network::chat_crypto::set_chat_key,
+            network::chat_crypto::chat_encryption_enabled,
+            network::keepalive::set_keepalive_interval,
+            network::keepalive::get_keepalive_interval,
             network::clipboard::start_clipboard_server,
             network::clipboard::stop_clipboard_server,
             network::clipboard::connect_to_clipboard,
@@ -42,9 +133,23 @@ pub fn run() {
             network::clipboard::disconnect_all_clipboards,
             network::clipboard::start_clipboard_polling,
             network::clipboard::stop_clipboard_polling,
+            network::clipboard::clipboard_sync_status,
+            network::clipboard::set_clipboard_mode,
+            network::clipboard::set_clipboard_poll_interval,
             network::clipboard::send_clipboard_content,
+            network::clipboard::cancel_clipboard_broadcast,
+            network::clipboard::set_clipboard_max_size,
+            network::clipboard::set_clipboard_exclude_patterns,
+            network::clipboard::send_clipboard_as_file,
             network::clipboard::get_system_clipboard,
             network::clipboard::set_system_clipboard,
+            network::clipboard::get_clipboard_history,
+            network::clipboard::apply_clipboard_history,
+            network::control::start_control_server,
+            network::control::stop_control_server,
+            network::control::open_control_channel,
+            network::control::send_control,
+            network::control::close_control_channel,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
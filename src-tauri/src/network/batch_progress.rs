@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::network::error::TransferError;
+
+const PROGRESS_FILE: &str = "batch_progress.json";
+// 超过该时间未更新的批次记录会被清理
+const BATCH_RETENTION_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+// 记录文件的读-改-写需串行
+static PROGRESS_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Default)]
+struct BatchRecord {
+    #[serde(default)]
+    updated_at: i64,
+    /// 已完整接收的文件（相对路径，没有相对路径时为文件名）
+    #[serde(default)]
+    completed: BTreeSet<String>,
+    /// 接收时使用的保存目录（导出清单的默认位置）
+    #[serde(default)]
+    save_dir: Option<String>,
+    /// 已接收文件的详细信息，键与 completed 相同
+    #[serde(default)]
+    files: BTreeMap<String, ReceivedFile>,
+}
+
+/// 接收清单中的一个文件
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReceivedFile {
+    /// 相对路径，没有相对路径时为文件名
+    pub path: String,
+    /// 实际保存位置（文件路径或 Android 文档 Uri）
+    #[serde(default)]
+    pub location: Option<String>,
+    pub size: u64,
+    /// 启用校验时的 sha256
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub received_at: i64,
+    /// 发送方 IP
+    pub sender: String,
+}
+
+/// 导出清单的格式
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestFormat {
+    Json,
+    Csv,
+}
+
+/// 导出的 JSON 清单
+#[derive(Serialize)]
+struct ReceivedManifest<'a> {
+    batch_id: &'a str,
+    exported_at: i64,
+    files: Vec<&'a ReceivedFile>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BatchProgress {
+    #[serde(default)]
+    batches: HashMap<String, BatchRecord>,
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+/// 接收端：记录批次中一个已完整接收的文件（跨重启保留，用于续传时跳过和导出清单）
+pub(crate) fn record_completed(app: &AppHandle, batch_id: &str, save_dir: &str, file: ReceivedFile) {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    let mut progress: BatchProgress = crate::config::load_json(app, PROGRESS_FILE);
+
+    let now = now_millis();
+    progress.batches.retain(|_, b| now - b.updated_at < BATCH_RETENTION_MS);
+
+    let record = progress.batches.entry(batch_id.to_string()).or_default();
+    record.updated_at = now;
+    record.completed.insert(file.path.clone());
+    record.save_dir = Some(save_dir.to_string());
+    record.files.insert(file.path.clone(), file);
+
+    if let Err(e) = crate::config::save_json(app, PROGRESS_FILE, &progress) {
+        eprintln!("Failed to save batch progress: {}", e);
+    }
+}
+
+/// 接收端：查询批次中已完整接收的文件
+pub(crate) fn completed_files(app: &AppHandle, batch_id: &str) -> Vec<String> {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    let progress: BatchProgress = crate::config::load_json(app, PROGRESS_FILE);
+    progress.batches.get(batch_id)
+        .map(|b| b.completed.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// CSV 字段转义：包含逗号、引号或换行时加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn render_csv(files: &[&ReceivedFile]) -> String {
+    let mut out = String::from("path,location,size,sha256,received_at,sender\n");
+    for file in files {
+        let row = [
+            csv_field(&file.path),
+            csv_field(file.location.as_deref().unwrap_or("")),
+            file.size.to_string(),
+            file.sha256.clone().unwrap_or_default(),
+            file.received_at.to_string(),
+            csv_field(&file.sender),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[tauri::command]
+/// 导出批次的接收清单（JSON 或 CSV）：每个已接收文件的路径、大小、哈希（启用校验时）、接收时间与发送方。
+/// 未指定 output_path 时写到接收时的保存目录下的 `<batch_id>.manifest.json/csv`，返回写入的路径
+pub fn export_received_manifest(
+    batch_id: String,
+    format: ManifestFormat,
+    output_path: Option<String>,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let progress: BatchProgress = {
+        let _guard = PROGRESS_LOCK.lock().unwrap();
+        crate::config::load_json(&app, PROGRESS_FILE)
+    };
+    let record = progress.batches.get(&batch_id)
+        .ok_or_else(|| TransferError::NotFound(format!("No received files recorded for batch {}", batch_id)))?;
+    if record.files.is_empty() {
+        return Err(TransferError::NotFound(format!("No received files recorded for batch {}", batch_id)));
+    }
+
+    let mut files: Vec<&ReceivedFile> = record.files.values().collect();
+    files.sort_by_key(|f| f.received_at);
+
+    let (content, extension) = match format {
+        ManifestFormat::Json => {
+            let manifest = ReceivedManifest { batch_id: &batch_id, exported_at: now_millis(), files };
+            let json = serde_json::to_string_pretty(&manifest)
+                .map_err(|e| TransferError::Other(format!("Failed to serialize manifest: {}", e)))?;
+            (json, "json")
+        }
+        ManifestFormat::Csv => (render_csv(&files), "csv"),
+    };
+
+    let path = match output_path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => {
+            // Android SAF 保存目录是 content:// Uri，无法直接写入，需要指定输出路径
+            let save_dir = record.save_dir.as_deref()
+                .filter(|dir| !dir.starts_with("content://"))
+                .ok_or_else(|| TransferError::Unsupported("Cannot write next to received files, choose an output path".to_string()))?;
+            std::path::Path::new(save_dir).join(format!("{}.manifest.{}", batch_id, extension))
+        }
+    };
+
+    std::fs::write(&path, content)
+        .map_err(|e| TransferError::Io(format!("Failed to write manifest {}: {}", path.display(), e)))?;
+    Ok(path.to_string_lossy().to_string())
+}
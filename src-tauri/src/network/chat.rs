@@ -1,20 +1,157 @@
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, Manager, Window};
-use tokio::net::TcpListener;
+use tauri::{AppHandle, Manager, Window};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{accept_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::{accept_hdr_async, MaybeTlsStream, WebSocketStream};
 use futures_util::{SinkExt, StreamExt};
-use socket2::{Socket, Domain, Type, Protocol};
+use crate::network::chat_crypto::{self, SessionSalt};
+use crate::network::connection_pin;
+use crate::network::keepalive;
+use crate::network::ports::{self, Service};
+use crate::network::error::TransferError;
+use crate::network::transfer::ConnectFailureReason;
+use crate::network::window_events;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ChatMessage {
     pub content: String,
     pub from_ip: String,
     pub timestamp: i64,
+    /// Set by one-shot senders that want the receiver to confirm delivery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack_id: Option<String>,
+    /// Content is sealed with the connection's chat key (see chat_crypto)
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<ChatAttachment>,
+}
+
+/// Small file sent inline with a chat message
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatAttachment {
+    pub name: String,
+    pub mime: String,
+    /// File content; sealed like `content` when the message is encrypted
+    pub data_base64: String,
+}
+
+/// Delivery confirmation sent back for messages carrying an `ack_id`
+#[derive(Serialize, Deserialize, Debug)]
+struct ChatAck {
+    ack: String,
+}
+
+// Query marker for one-shot connections that should not be tracked as a chat session
+const ONCE_QUERY: &str = "once=1";
+const ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+// Auto-reconnect: attempts after an unexpected drop, and the first delay (doubled each attempt)
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const DEFAULT_MESSAGE_LIMIT: usize = 64 * 1024;
+// Larger files have to go through the transfer protocol
+const MAX_ATTACHMENT_SIZE: u64 = 5 * 1024 * 1024;
+// Room for the JSON envelope around content and attachment in a received frame
+const FRAME_OVERHEAD: usize = 64 * 1024;
+// Android attachments are read through the storage plugin in chunks of this size
+#[cfg(target_os = "android")]
+const ATTACHMENT_READ_CHUNK: i32 = 1024 * 1024;
+
+// Maximum chat message content in bytes, checked before sending and after receiving
+static MESSAGE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_MESSAGE_LIMIT);
+
+/// Reject content longer than the configured message limit.
+fn check_message_len(content: &str) -> Result<(), TransferError> {
+    let limit = MESSAGE_LIMIT.load(Ordering::Relaxed);
+    if content.len() > limit {
+        return Err(TransferError::InvalidInput(format!("Message too long: {} bytes (limit {} bytes)", content.len(), limit)));
+    }
+    Ok(())
+}
+
+/// Largest frame a compressed connection may inflate to: content and attachment after
+/// base64 encoding or sealing, plus the envelope.
+fn max_frame_size() -> usize {
+    (MESSAGE_LIMIT.load(Ordering::Relaxed) + MAX_ATTACHMENT_SIZE as usize).saturating_mul(2) + FRAME_OVERHEAD
+}
+
+/// Reject attachments over MAX_ATTACHMENT_SIZE (decoded bytes).
+fn check_attachment_size(size: u64) -> Result<(), TransferError> {
+    if size > MAX_ATTACHMENT_SIZE {
+        return Err(TransferError::InvalidInput(format!(
+            "File too large to attach ({} bytes, limit {} bytes), send it as a file transfer instead",
+            size, MAX_ATTACHMENT_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Seal outgoing content and attachment data with the connection's key when a chat key is set;
+/// returns the message to put on the wire.
+fn seal_message(message: &ChatMessage, salt: Option<&SessionSalt>) -> Result<ChatMessage, TransferError> {
+    let mut sealed = message.clone();
+    if let Some(content) = chat_crypto::seal(salt, &message.content) {
+        sealed.content = content.map_err(TransferError::Other)?;
+        sealed.encrypted = true;
+        if let Some(attachment) = sealed.attachment.as_mut() {
+            if let Some(data) = chat_crypto::seal(salt, &attachment.data_base64) {
+                attachment.data_base64 = data.map_err(TransferError::Other)?;
+            }
+        }
+    }
+    Ok(sealed)
+}
+
+/// Decrypt a received message if it was sealed and forward it to the frontend
+/// (`chat-attachment-received` when it carries an attachment, `chat-message-received` otherwise).
+/// Messages that cannot be decrypted are dropped and reported via `chat-decrypt-failed`,
+/// oversized ones via `chat-message-too-large`. Delivered messages are added to the chat history.
+fn deliver_message(window: &Window, peer_ip: &str, salt: Option<&SessionSalt>, mut chat_msg: ChatMessage) {
+    if chat_msg.encrypted {
+        let opened = chat_crypto::open(salt, &chat_msg.content).and_then(|content| {
+            if let Some(attachment) = chat_msg.attachment.as_mut() {
+                attachment.data_base64 = chat_crypto::open(salt, &attachment.data_base64)?;
+            }
+            Ok(content)
+        });
+        match opened {
+            Ok(content) => chat_msg.content = content,
+            Err(e) => {
+                eprintln!("Dropping chat message from {}: {}", chat_msg.from_ip, e);
+                window_events::emit(window, "chat-decrypt-failed", &chat_msg.from_ip);
+                return;
+            }
+        }
+    }
+    let size_check = check_message_len(&chat_msg.content).and_then(|()| match &chat_msg.attachment {
+        Some(attachment) => check_attachment_size(attachment.data_base64.len() as u64 / 4 * 3),
+        None => Ok(()),
+    });
+    if let Err(e) = size_check {
+        eprintln!("Dropping chat message from {}: {}", chat_msg.from_ip, e);
+        window_events::emit(window, "chat-message-too-large", &chat_msg.from_ip);
+        return;
+    }
+    crate::network::chat_history::record(window.app_handle(), peer_ip, "received", &chat_msg);
+    if chat_msg.attachment.is_some() {
+        window_events::emit(window, "chat-attachment-received", chat_msg);
+    } else {
+        window_events::emit(window, "chat-message-received", chat_msg);
+    }
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
 }
 
 pub(crate) enum WsWriter {
@@ -31,18 +168,54 @@ impl WsWriter {
     }
 }
 
+impl keepalive::PingWriter for WsWriter {
+    async fn ping(&mut self) -> bool {
+        self.send(Message::Ping(Vec::new())).await.is_ok()
+    }
+}
+
 pub struct ChatConnection {
     #[allow(dead_code)]
     pub ip: String,
     pub writer: Arc<Mutex<WsWriter>>,
+    /// Deflate negotiated in the handshake
+    pub compressed: bool,
+    /// Key salts exchanged in the handshake; None if the peer sent none
+    pub salt: Option<SessionSalt>,
+    /// Set before a user-initiated close so the reader does not try to reconnect
+    pub intentional: Arc<AtomicBool>,
+}
+
+/// Payload of `chat-reconnecting`
+#[derive(Serialize, Clone, Debug)]
+struct ChatReconnecting {
+    peer_ip: String,
+    /// 1-based attempt that starts after `delay_ms`
+    attempt: u32,
+    max_attempts: u32,
+    delay_ms: u64,
 }
 
 pub type ChatConnections = Arc<Mutex<HashMap<String, ChatConnection>>>;
 
 static CHAT_SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+// Accept loop task, kept so a forced reset can tear down the listener
+static CHAT_SERVER_TASK: std::sync::Mutex<Option<tokio::task::AbortHandle>> = std::sync::Mutex::new(None);
+/// Whether the chat listener is running
+pub(crate) fn server_running() -> bool {
+    CHAT_SERVER_RUNNING.load(Ordering::Relaxed)
+}
+
+// Peers with a reconnect in progress; disconnect_chat removes the peer to stop retrying
+static RECONNECTING: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
 
 #[tauri::command]
-pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), String> {
+pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), TransferError> {
+    serve(window, app, None).await
+}
+
+/// Start the chat server on `listener` if the caller already bound one, otherwise bind the configured port
+async fn serve(window: Window, app: AppHandle, listener: Option<tokio::net::TcpListener>) -> Result<(), TransferError> {
     if CHAT_SERVER_RUNNING.load(Ordering::Relaxed) {
         return Ok(());
     }
@@ -51,55 +224,20 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
 
     let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
 
-    tokio::spawn(async move {
-        let addr = "0.0.0.0:7879";
-
-        // Create socket with SO_REUSEADDR to allow port reuse
-        let socket = match Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Failed to create socket: {}", e);
-                CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
-                let _ = window.emit("chat-server-error", format!("Failed to create socket: {}", e));
-                return;
-            }
-        };
-
-        if let Err(e) = socket.set_reuse_address(true) {
-            eprintln!("Failed to set SO_REUSEADDR: {}", e);
-        }
-
-        #[cfg(not(windows))]
-        if let Err(e) = socket.set_reuse_port(true) {
-            eprintln!("Failed to set SO_REUSEPORT: {}", e);
-        }
-
-        if let Err(e) = socket.set_nonblocking(true) {
-            eprintln!("Failed to set nonblocking: {}", e);
-        }
+    let task = tokio::spawn(async move {
+        let addr = format!("0.0.0.0:{}", ports::local(Service::Chat));
 
         let sock_addr: std::net::SocketAddr = addr.parse().unwrap();
-        if let Err(e) = socket.bind(&sock_addr.into()) {
-            eprintln!("Failed to bind chat server: {}", e);
-            CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
-            let _ = window.emit("chat-server-error", format!("Failed to bind chat server: {}", e));
-            return;
-        }
-
-        if let Err(e) = socket.listen(128) {
-            eprintln!("Failed to listen: {}", e);
-            CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
-            let _ = window.emit("chat-server-error", format!("Failed to listen: {}", e));
-            return;
-        }
-
-        let std_listener: std::net::TcpListener = socket.into();
-        let listener = match TcpListener::from_std(std_listener) {
+        let bound = match listener {
+            Some(listener) => Ok(listener),
+            None => crate::network::listener::bind_with_retry(sock_addr, "chat", &CHAT_SERVER_RUNNING, &window).await,
+        };
+        let listener = match bound {
             Ok(l) => l,
             Err(e) => {
-                eprintln!("Failed to convert listener: {}", e);
+                eprintln!("Failed to start chat server: {}", e);
                 CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
-                let _ = window.emit("chat-server-error", format!("Failed to convert listener: {}", e));
+                window_events::emit(&window, "chat-server-error", format!("Failed to start chat server: {}", e));
                 return;
             }
         };
@@ -123,7 +261,20 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
             let window_clone = window.clone();
 
             tokio::spawn(async move {
-                let ws_stream = match accept_async(stream).await {
+                let mut is_once = false;
+                let mut compressed = false;
+                let mut salt = None;
+                #[allow(clippy::result_large_err)]
+                let check_once = |req: &Request, mut resp: Response| {
+                    is_once = req
+                        .uri()
+                        .query()
+                        .is_some_and(|q| q.split('&').any(|p| p == ONCE_QUERY));
+                    compressed = crate::network::compression::negotiate(req, &mut resp);
+                    salt = chat_crypto::accept_salt(req, &mut resp);
+                    Ok(resp)
+                };
+                let ws_stream = match accept_hdr_async(stream, check_once).await {
                     Ok(ws) => ws,
                     Err(e) => {
                         eprintln!("WebSocket handshake failed: {}", e);
@@ -131,8 +282,40 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
                     }
                 };
 
-                let (writer, mut reader) = ws_stream.split();
+                let (mut writer, mut reader) = ws_stream.split();
+                if !connection_pin::authorize(&mut reader, &mut writer, &peer_ip).await {
+                    return;
+                }
+
+                // One-shot sender: deliver its messages without registering a chat session
+                if is_once {
+                    while let Some(Ok(msg)) = reader.next().await {
+                        match msg {
+                            // PIN sent to a server without one configured
+                            Message::Text(text) if connection_pin::is_auth_message(&text) => {}
+                            Message::Text(text) => match serde_json::from_str::<ChatMessage>(&text) {
+                                Ok(chat_msg) => {
+                                    if let Some(id) = chat_msg.ack_id.clone() {
+                                        if let Ok(ack) = serde_json::to_string(&ChatAck { ack: id }) {
+                                            let _ = writer.send(Message::Text(ack)).await;
+                                        }
+                                    }
+                                    deliver_message(&window_clone, &peer_ip, salt.as_ref(), chat_msg);
+                                }
+                                Err(e) => {
+                                    eprintln!("Failed to parse chat message: {}", e);
+                                }
+                            },
+                            Message::Close(_) => break,
+                            _ => {}
+                        }
+                    }
+                    return;
+                }
+
                 let writer = Arc::new(Mutex::new(WsWriter::Plain(writer)));
+                let last_seen = Arc::new(AtomicI64::new(now_millis()));
+                let mut dead = keepalive::spawn_with_liveness(Arc::downgrade(&writer), last_seen.clone());
 
                 // Store connection
                 {
@@ -140,18 +323,35 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
                     conns.insert(peer_ip.clone(), ChatConnection {
                         ip: peer_ip.clone(),
                         writer: writer.clone(),
+                        compressed,
+                        salt,
+                        intentional: Arc::new(AtomicBool::new(false)),
                     });
                 }
 
-                let _ = window_clone.emit("chat-connected", &peer_ip);
+                window_events::emit(&window_clone, "chat-connected", &peer_ip);
 
                 // Listen for messages
-                while let Some(msg_result) = reader.next().await {
+                loop {
+                    let msg_result = tokio::select! {
+                        msg = reader.next() => match msg {
+                            Some(msg) => msg,
+                            None => break,
+                        },
+                        Ok(()) = &mut dead => {
+                            println!("Chat peer {} stopped answering pings, dropping connection", peer_ip);
+                            break;
+                        }
+                    };
+                    last_seen.store(now_millis(), Ordering::Relaxed);
+                    let msg_result = msg_result.map(|m| crate::network::compression::inflate_message(m, compressed, max_frame_size()));
                     match msg_result {
+                        // PIN sent to a server without one configured
+                        Ok(Message::Text(text)) if connection_pin::is_auth_message(&text) => {}
                         Ok(Message::Text(text)) => {
                             match serde_json::from_str::<ChatMessage>(&text) {
                                 Ok(chat_msg) => {
-                                    let _ = window_clone.emit("chat-message-received", chat_msg);
+                                    deliver_message(&window_clone, &peer_ip, salt.as_ref(), chat_msg);
                                 }
                                 Err(e) => {
                                     eprintln!("Failed to parse chat message: {}", e);
@@ -163,7 +363,7 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
                             let _ = writer.lock().await.send(Message::Pong(data)).await;
                         }
                         Ok(Message::Pong(_)) => {
-                            // Ignore pong messages
+                            // Already recorded in last_seen
                         }
                         Ok(Message::Close(_)) => {
                             println!("Chat connection closed by {}", peer_ip);
@@ -192,16 +392,21 @@ pub async fn start_chat_server(window: Window, app: AppHandle) -> Result<(), Str
                     conns.remove(&peer_ip);
                 }
 
-                let _ = window_clone.emit("chat-disconnected", &peer_ip);
+                window_events::emit(&window_clone, "chat-disconnected", &peer_ip);
             });
         }
     });
+    *CHAT_SERVER_TASK.lock().unwrap() = Some(task.abort_handle());
 
     Ok(())
 }
 
+/// Connect to a peer's chat server. With `auto_reconnect`, a connection that drops without
+/// `disconnect_chat` is retried with backoff, emitting `chat-reconnecting` before each attempt.
+/// With `compress`, permessage-deflate is offered for this connection (off by default).
 #[tauri::command]
-pub async fn connect_to_chat(target_ip: String, window: Window, app: AppHandle) -> Result<(), String> {
+pub async fn connect_to_chat(target_ip: String, auto_reconnect: Option<bool>, compress: Option<bool>, window: Window, app: AppHandle) -> Result<(), TransferError> {
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
     let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
 
     // Check if already connected
@@ -212,15 +417,31 @@ pub async fn connect_to_chat(target_ip: String, window: Window, app: AppHandle)
         }
     }
 
-    let url = format!("ws://{}:7879", target_ip);
+    let url = format!("ws://{}:{}", target_ip, ports::peer(&target_ip, Service::Chat));
+
+    let mut request = url.into_client_request()
+        .map_err(|e| TransferError::InvalidInput(format!("Invalid chat URL: {}", e)))?;
+    crate::network::compression::offer(&mut request, compress.unwrap_or(false));
+    let offered_salt = chat_crypto::offer_salt(&mut request);
 
-    let ws_stream = tokio_tungstenite::connect_async(&url)
+    let (mut ws_stream, response) = tokio_tungstenite::connect_async(request)
         .await
-        .map_err(|e| format!("Failed to connect to chat: {}", e))?
-        .0;
+        .map_err(|e| {
+            let reason = ConnectFailureReason::from_ws_error(&e);
+            TransferError::ConnectionFailed(format!("Failed to connect to chat: {}", e), Some(reason))
+        })?;
+    if let Some(auth) = connection_pin::auth_message() {
+        ws_stream.send(auth).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send connection PIN: {}", e), None))?;
+    }
+    let compressed = crate::network::compression::accepted(&response);
+    let salt = chat_crypto::connected_salt(offered_salt, &response);
 
     let (writer, mut reader) = ws_stream.split();
     let writer = Arc::new(Mutex::new(WsWriter::Tls(writer)));
+    let last_seen = Arc::new(AtomicI64::new(now_millis()));
+    let mut dead = keepalive::spawn_with_liveness(Arc::downgrade(&writer), last_seen.clone());
+    let intentional = Arc::new(AtomicBool::new(false));
 
     // Store connection
     {
@@ -228,24 +449,42 @@ pub async fn connect_to_chat(target_ip: String, window: Window, app: AppHandle)
         conns.insert(target_ip.clone(), ChatConnection {
             ip: target_ip.clone(),
             writer: writer.clone(),
+            compressed,
+            salt,
+            intentional: intentional.clone(),
         });
     }
+    // A manual connect supersedes any pending reconnect to this peer
+    RECONNECTING.lock().unwrap().retain(|ip| ip != &target_ip);
 
-    let _ = window.emit("chat-connected", &target_ip);
+    window_events::emit(&window, "chat-connected", &target_ip);
 
     let connections_clone = connections.clone();
     let target_ip_clone = target_ip.clone();
     let window_clone = window.clone();
+    let auto_reconnect = auto_reconnect.unwrap_or(false);
 
     // Spawn task to listen for messages
     let writer_clone = writer.clone();
     tokio::spawn(async move {
-        while let Some(msg_result) = reader.next().await {
+        loop {
+            let msg_result = tokio::select! {
+                msg = reader.next() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                Ok(()) = &mut dead => {
+                    println!("Chat peer {} stopped answering pings, dropping connection", target_ip_clone);
+                    break;
+                }
+            };
+            last_seen.store(now_millis(), Ordering::Relaxed);
+            let msg_result = msg_result.map(|m| crate::network::compression::inflate_message(m, compressed, max_frame_size()));
             match msg_result {
                 Ok(Message::Text(text)) => {
                     match serde_json::from_str::<ChatMessage>(&text) {
                         Ok(chat_msg) => {
-                            let _ = window_clone.emit("chat-message-received", chat_msg);
+                            deliver_message(&window_clone, &target_ip_clone, salt.as_ref(), chat_msg);
                         }
                         Err(e) => {
                             eprintln!("Failed to parse chat message: {}", e);
@@ -257,9 +496,12 @@ pub async fn connect_to_chat(target_ip: String, window: Window, app: AppHandle)
                     let _ = writer_clone.lock().await.send(Message::Pong(data)).await;
                 }
                 Ok(Message::Pong(_)) => {
-                    // Ignore pong messages
+                    // Already recorded in last_seen
                 }
-                Ok(Message::Close(_)) => {
+                Ok(Message::Close(frame)) => {
+                    if connection_pin::is_rejection(&frame) {
+                        window_events::emit(&window_clone, "connection-pin-rejected", &target_ip_clone);
+                    }
                     println!("Chat connection closed by {}", target_ip_clone);
                     break;
                 }
@@ -286,62 +528,274 @@ pub async fn connect_to_chat(target_ip: String, window: Window, app: AppHandle)
             conns.remove(&target_ip_clone);
         }
 
-        let _ = window_clone.emit("chat-disconnected", &target_ip_clone);
+        window_events::emit(&window_clone, "chat-disconnected", &target_ip_clone);
+
+        if auto_reconnect && !intentional.load(Ordering::SeqCst) {
+            spawn_reconnect(target_ip_clone, compress, window_clone, app);
+        }
     });
 
     Ok(())
 }
 
-#[tauri::command]
-pub async fn send_chat_message(target_ip: String, content: String, app: AppHandle) -> Result<(), String> {
+/// Retry an outgoing chat connection that dropped unexpectedly, with exponential backoff.
+/// Stops on success, after RECONNECT_ATTEMPTS, or when disconnect_chat is called for the peer.
+fn spawn_reconnect(target_ip: String, compress: Option<bool>, window: Window, app: AppHandle) {
+    RECONNECTING.lock().unwrap().push(target_ip.clone());
+    tokio::spawn(async move {
+        let mut delay = RECONNECT_DELAY;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            window_events::emit(&window, "chat-reconnecting", ChatReconnecting {
+                peer_ip: target_ip.clone(),
+                attempt,
+                max_attempts: RECONNECT_ATTEMPTS,
+                delay_ms: delay.as_millis() as u64,
+            });
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+
+            if !RECONNECTING.lock().unwrap().contains(&target_ip) {
+                return;
+            }
+            // Boxed to break the recursive future type (the new connection may reconnect again)
+            let connect: std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), TransferError>> + Send>> =
+                Box::pin(connect_to_chat(target_ip.clone(), Some(true), compress, window.clone(), app.clone()));
+            match connect.await {
+                Ok(()) => {
+                    println!("Reconnected chat to {}", target_ip);
+                    return;
+                }
+                Err(e) => eprintln!("Chat reconnect attempt {} to {} failed: {}", attempt, target_ip, e),
+            }
+        }
+        RECONNECTING.lock().unwrap().retain(|ip| ip != &target_ip);
+        window_events::emit(&window, "chat-reconnect-failed", &target_ip);
+    });
+}
+
+/// Seal and send a message over an open chat session, dropping the session if the write fails.
+/// The plaintext message is added to the chat history once sent.
+async fn send_on_session(app: &AppHandle, target_ip: &str, message: &ChatMessage) -> Result<(), TransferError> {
     let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
 
     // Get writer Arc without holding the lock
-    let writer = {
+    let (writer, compressed, salt) = {
         let conns = connections.lock().await;
-        let connection = conns.get(&target_ip)
-            .ok_or_else(|| format!("Not connected to {}", target_ip))?;
-        connection.writer.clone()
+        let connection = conns.get(target_ip)
+            .ok_or_else(|| TransferError::NotFound(format!("Not connected to {}", target_ip)))?;
+        (connection.writer.clone(), connection.compressed, connection.salt)
     };
 
+    let json = serde_json::to_string(&seal_message(message, salt.as_ref())?)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize message: {}", e)))?;
+
+    let result = {
+        let mut w = writer.lock().await;
+        w.send(crate::network::compression::text_message(json, compressed)).await
+    };
+
+    match result {
+        Ok(_) => {
+            crate::network::chat_history::record(app, target_ip, "sent", message);
+            Ok(())
+        }
+        Err(e) => {
+            // Remove dead connection
+            let mut conns = connections.lock().await;
+            conns.remove(target_ip);
+            Err(TransferError::ConnectionFailed(format!("连接已断开: {}", e), None))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn send_chat_message(target_ip: String, content: String, app: AppHandle) -> Result<(), TransferError> {
+    check_message_len(&content)?;
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
+
     let local_ip = crate::network::transfer::get_local_ip()
         .unwrap_or_else(|_| "unknown".to_string());
 
     let message = ChatMessage {
         content,
         from_ip: local_ip,
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64,
+        timestamp: now_millis(),
+        ack_id: None,
+        encrypted: false,
+        attachment: None,
     };
 
-    let json = serde_json::to_string(&message)
-        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    send_on_session(&app, &target_ip, &message).await
+}
+
+/// Name, MIME type and content of a file to attach, checked against MAX_ATTACHMENT_SIZE.
+#[cfg(not(target_os = "android"))]
+async fn read_attachment(_app: &AppHandle, path: &str) -> Result<(String, String, Vec<u8>), TransferError> {
+    let path = Path::new(path);
+    let name = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| TransferError::InvalidInput(format!("Invalid attachment path: {}", path.display())))?;
+    let size = tokio::fs::metadata(path).await
+        .map_err(|e| TransferError::Io(format!("Failed to read attachment: {}", e)))?
+        .len();
+    check_attachment_size(size)?;
+    let data = tokio::fs::read(path).await
+        .map_err(|e| TransferError::Io(format!("Failed to read attachment: {}", e)))?;
+    Ok((name, mime_guess::from_path(path).first_or_octet_stream().to_string(), data))
+}
 
-    let result = {
-        let mut w = writer.lock().await;
-        w.send(Message::Text(json)).await
+/// Android: read a content:// URI through the storage plugin.
+#[cfg(target_os = "android")]
+async fn read_attachment(app: &AppHandle, uri: &str) -> Result<(String, String, Vec<u8>), TransferError> {
+    let storage = app.state::<crate::android_storage::AndroidStorage>();
+    let (name, size) = storage.get_file_info(uri.to_string())
+        .map_err(|e| TransferError::Io(format!("Failed to read attachment: {}", e)))?;
+    check_attachment_size(size)?;
+    let mime = storage.get_mime_type(uri.to_string())
+        .unwrap_or_else(|_| mime_guess::from_path(&name).first_or_octet_stream().to_string());
+
+    let reader = storage.open_uri_reader(uri.to_string());
+    let mut data = Vec::with_capacity(size as usize);
+    let mut buf = Vec::new();
+    loop {
+        let read = reader.read_chunk(data.len() as u64, ATTACHMENT_READ_CHUNK, &mut buf)
+            .map_err(|e| TransferError::Io(format!("Failed to read attachment: {}", e)))?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read]);
+        // The provider's reported size may be stale
+        check_attachment_size(data.len() as u64)?;
+    }
+    Ok((name, mime, data))
+}
+
+/// Send a small file (up to 5MB) inline over an open chat session. `uri` is a file path, or a
+/// content:// URI on Android. Larger files are rejected and should be sent through the file transfer instead.
+#[tauri::command]
+pub async fn send_chat_attachment(target_ip: String, uri: String, app: AppHandle) -> Result<(), TransferError> {
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
+    let (name, mime, data) = read_attachment(&app, &uri).await?;
+
+    let local_ip = crate::network::transfer::get_local_ip()
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    let message = ChatMessage {
+        content: String::new(),
+        from_ip: local_ip,
+        timestamp: now_millis(),
+        ack_id: None,
+        encrypted: false,
+        attachment: Some(ChatAttachment {
+            name,
+            mime,
+            data_base64: general_purpose::STANDARD.encode(&data),
+        }),
     };
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            // Remove dead connection
-            let mut conns = connections.lock().await;
-            conns.remove(&target_ip);
-            Err(format!("连接已断开: {}", e))
+    send_on_session(&app, &target_ip, &message).await
+}
+
+/// Save a received attachment into the active save profile directory (or Downloads),
+/// renaming it if a file with the same name exists. Returns the saved path.
+#[tauri::command]
+pub async fn save_chat_attachment(data_base64: String, name: String, app: AppHandle) -> Result<String, TransferError> {
+    let name = crate::network::transfer::sanitize_relative_path(&name)
+        .and_then(|path| path.rsplit('/').next().map(str::to_string))
+        .ok_or_else(|| TransferError::InvalidInput(format!("Invalid attachment name: {}", name)))?;
+    let data = general_purpose::STANDARD.decode(&data_base64)
+        .map_err(|e| TransferError::InvalidInput(format!("Invalid attachment data: {}", e)))?;
+
+    let dir = match crate::network::save_profiles::active_profile_dir() {
+        Some(dir) => dir,
+        None => crate::network::transfer::get_download_dir(app)?,
+    };
+    let mut path = Path::new(&dir).join(name);
+    if path.exists() {
+        path = crate::network::collision::unique_path(&path)
+            .ok_or_else(|| TransferError::Io(format!("No free file name for {}", path.display())))?;
+    }
+    tokio::fs::write(&path, data).await
+        .map_err(|e| TransferError::Io(format!("Failed to save attachment: {}", e)))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Send a single chat message over a short-lived connection without opening a chat session.
+/// When `wait_for_ack` is set, waits for the peer to confirm delivery before closing.
+#[tauri::command]
+pub async fn send_chat_once(target_ip: String, content: String, wait_for_ack: Option<bool>, app: AppHandle) -> Result<(), TransferError> {
+    check_message_len(&content)?;
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
+    let wait_for_ack = wait_for_ack.unwrap_or(false);
+    let url = format!("ws://{}:{}/?{}", target_ip, ports::peer(&target_ip, Service::Chat), ONCE_QUERY);
+    let mut request = url.into_client_request()
+        .map_err(|e| TransferError::InvalidInput(format!("Invalid chat URL: {}", e)))?;
+    let offered_salt = chat_crypto::offer_salt(&mut request);
+
+    let (mut ws_stream, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .map_err(|e| {
+            let reason = ConnectFailureReason::from_ws_error(&e);
+            TransferError::ConnectionFailed(format!("Failed to connect to chat: {}", e), Some(reason))
+        })?;
+    if let Some(auth) = connection_pin::auth_message() {
+        ws_stream.send(auth).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send connection PIN: {}", e), None))?;
+    }
+    let salt = chat_crypto::connected_salt(offered_salt, &response);
+    let (mut writer, mut reader) = ws_stream.split();
+
+    let local_ip = crate::network::transfer::get_local_ip()
+        .unwrap_or_else(|_| "unknown".to_string());
+    let timestamp = now_millis();
+    let ack_id = wait_for_ack.then(|| format!("{}-{}", local_ip, timestamp));
+
+    let message = ChatMessage {
+        content,
+        from_ip: local_ip,
+        timestamp,
+        ack_id: ack_id.clone(),
+        encrypted: false,
+        attachment: None,
+    };
+    let json = serde_json::to_string(&seal_message(&message, salt.as_ref())?)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize message: {}", e)))?;
+
+    writer.send(Message::Text(json)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send message: {}", e), None))?;
+
+    if let Some(id) = ack_id {
+        let acked = tokio::time::timeout(ACK_TIMEOUT, async {
+            while let Some(Ok(msg)) = reader.next().await {
+                if let Message::Text(text) = msg {
+                    if serde_json::from_str::<ChatAck>(&text).is_ok_and(|a| a.ack == id) {
+                        return true;
+                    }
+                }
+            }
+            false
+        }).await.unwrap_or(false);
+
+        if !acked {
+            let _ = writer.send(Message::Close(None)).await;
+            return Err(TransferError::Timeout("Peer did not acknowledge the message".into()));
         }
     }
+
+    let _ = writer.send(Message::Close(None)).await;
+    crate::network::chat_history::record(&app, &target_ip, "sent", &message);
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn disconnect_chat(target_ip: String, app: AppHandle) -> Result<(), String> {
+pub async fn disconnect_chat(target_ip: String, app: AppHandle) -> Result<(), TransferError> {
     let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
 
+    RECONNECTING.lock().unwrap().retain(|ip| ip != &target_ip);
     let mut conns = connections.lock().await;
 
     if let Some(connection) = conns.remove(&target_ip) {
+        connection.intentional.store(true, Ordering::SeqCst);
         // Try to send close frame, but don't fail if it errors (connection might already be dead)
         let _ = connection.writer.lock().await.send(Message::Close(None)).await;
     }
@@ -350,20 +804,78 @@ pub async fn disconnect_chat(target_ip: String, app: AppHandle) -> Result<(), St
 }
 
 #[tauri::command]
-pub async fn stop_chat_server() -> Result<(), String> {
+pub async fn stop_chat_server() -> Result<(), TransferError> {
     CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
     Ok(())
 }
 
 #[tauri::command]
-pub async fn disconnect_all_chats(app: AppHandle) -> Result<(), String> {
+pub async fn disconnect_all_chats(app: AppHandle) -> Result<(), TransferError> {
     let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
+    RECONNECTING.lock().unwrap().clear();
     let mut conns = connections.lock().await;
 
     // Close all connections
     for (_ip, connection) in conns.drain() {
+        connection.intentional.store(true, Ordering::SeqCst);
         let _ = connection.writer.lock().await.send(Message::Close(None)).await;
     }
 
     Ok(())
 }
+
+/// Set the maximum chat message size in bytes (default 64KB), applied to sent and received messages.
+#[tauri::command]
+pub fn set_chat_message_limit(bytes: usize) -> Result<(), TransferError> {
+    if bytes == 0 {
+        return Err(TransferError::InvalidInput("Chat message limit must be greater than 0".into()));
+    }
+    MESSAGE_LIMIT.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Serve chat on `listener`, already bound to the newly configured port. Established chats keep their connections.
+pub(crate) async fn rebind(window: Window, app: AppHandle, listener: std::net::TcpListener) -> Result<(), TransferError> {
+    if !CHAT_SERVER_RUNNING.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    if let Some(task) = CHAT_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    let listener = tokio::net::TcpListener::from_std(listener)
+        .map_err(|e| TransferError::Io(format!("Failed to take over listener: {}", e)))?;
+    serve(window, app, Some(listener)).await
+}
+
+/// Forcibly stop the chat server and drop every connection, even if the running flag is stale.
+pub(crate) async fn force_reset(app: &AppHandle) {
+    CHAT_SERVER_RUNNING.store(false, Ordering::Relaxed);
+    if let Some(task) = CHAT_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+
+    let connections: ChatConnections = app.state::<ChatConnections>().inner().clone();
+    RECONNECTING.lock().unwrap().clear();
+    let mut conns = connections.lock().await;
+    for (_ip, connection) in conns.drain() {
+        connection.intentional.store(true, Ordering::SeqCst);
+        let _ = connection.writer.lock().await.send(Message::Close(None)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_length_is_checked_in_bytes() {
+        assert!(check_message_len("").is_ok());
+        assert!(check_message_len(&"a".repeat(DEFAULT_MESSAGE_LIMIT)).is_ok());
+        let err = check_message_len(&"a".repeat(DEFAULT_MESSAGE_LIMIT + 1)).unwrap_err();
+        assert_eq!(err.code(), "invalid_input");
+        // Three bytes per character, so fewer characters than the limit can still be too long
+        let wide = "字".repeat(DEFAULT_MESSAGE_LIMIT / 3 + 1);
+        assert!(wide.chars().count() < DEFAULT_MESSAGE_LIMIT);
+        assert!(check_message_len(&wide).is_err());
+    }
+}
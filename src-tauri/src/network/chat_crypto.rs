@@ -0,0 +1,269 @@
+use base64::{engine::general_purpose, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{LazyLock, Mutex};
+use tokio_tungstenite::tungstenite::handshake::client::{Request as ClientRequest, Response as ClientResponse};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderValue};
+
+use crate::network::error::TransferError;
+
+// Each side of a chat connection sends a random salt in the handshake; the key for the
+// connection is derived from the shared pairing secret and both salts
+const SALT_HEADER: &str = "x-lan-transfer-chat-salt";
+const SALT_LEN: usize = 16;
+const KEY_ITERATIONS: u32 = 100_000;
+
+/// Salts of one chat connection: the connecting side's followed by the accepting side's
+pub(crate) type SessionSalt = [u8; 2 * SALT_LEN];
+
+// Shared pairing secret; None sends and accepts plaintext only
+static CHAT_SECRET: Mutex<Option<String>> = Mutex::new(None);
+// Keys already derived from the secret, by salt; cleared when the secret changes
+static DERIVED_KEYS: LazyLock<Mutex<HashMap<Vec<u8>, [u8; 32]>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+// Salt for sealing chat history at rest, picked once per secret and stored with each entry
+static HISTORY_SALT: Mutex<Option<[u8; SALT_LEN]>> = Mutex::new(None);
+
+fn derive_key(secret: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(KEY_ITERATIONS).unwrap(),
+        salt,
+        secret.as_bytes(),
+        &mut key,
+    );
+    key
+}
+
+/// Key for `salt` under the current secret, or None when no secret is set.
+fn key_for(salt: &[u8]) -> Option<[u8; 32]> {
+    let secret = CHAT_SECRET.lock().unwrap().clone()?;
+    if let Some(key) = DERIVED_KEYS.lock().unwrap().get(salt) {
+        return Some(*key);
+    }
+    let key = derive_key(&secret, salt);
+    // Skip caching if the secret changed while deriving
+    if CHAT_SECRET.lock().unwrap().as_ref() == Some(&secret) {
+        DERIVED_KEYS.lock().unwrap().insert(salt.to_vec(), key);
+    }
+    Some(key)
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("key length matches algorithm"))
+}
+
+fn new_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new().fill(&mut salt).expect("system random source available");
+    salt
+}
+
+fn read_salt(headers: &HeaderMap) -> Option<[u8; SALT_LEN]> {
+    let value = headers.get(SALT_HEADER)?.to_str().ok()?;
+    general_purpose::STANDARD.decode(value).ok()?.try_into().ok()
+}
+
+fn salt_header(salt: &[u8; SALT_LEN]) -> HeaderValue {
+    HeaderValue::from_str(&general_purpose::STANDARD.encode(salt)).expect("base64 is a valid header value")
+}
+
+fn session_salt(client: [u8; SALT_LEN], server: [u8; SALT_LEN]) -> SessionSalt {
+    let mut salt = [0u8; 2 * SALT_LEN];
+    salt[..SALT_LEN].copy_from_slice(&client);
+    salt[SALT_LEN..].copy_from_slice(&server);
+    salt
+}
+
+/// Client side: add this connection's salt to the handshake request. Pass it to `connected_salt` afterwards.
+pub(crate) fn offer_salt(request: &mut ClientRequest) -> [u8; SALT_LEN] {
+    let salt = new_salt();
+    request.headers_mut().insert(SALT_HEADER, salt_header(&salt));
+    salt
+}
+
+/// Client side: combine our salt with the server's. None when the server did not send one.
+pub(crate) fn connected_salt(offered: [u8; SALT_LEN], response: &ClientResponse) -> Option<SessionSalt> {
+    read_salt(response.headers()).map(|server| session_salt(offered, server))
+}
+
+/// Server side: answer the client's salt with our own. None when the client did not send one.
+pub(crate) fn accept_salt(request: &Request, response: &mut Response) -> Option<SessionSalt> {
+    let client = read_salt(request.headers())?;
+    let server = new_salt();
+    response.headers_mut().insert(SALT_HEADER, salt_header(&server));
+    Some(session_salt(client, server))
+}
+
+pub(crate) fn enabled() -> bool {
+    CHAT_SECRET.lock().unwrap().is_some()
+}
+
+/// nonce || ciphertext || tag
+fn seal_with(key: &[u8; 32], content: &str) -> Result<Vec<u8>, String> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce_bytes)
+        .map_err(|_| "Failed to generate nonce".to_string())?;
+
+    let mut in_out = content.as_bytes().to_vec();
+    aead_key(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::empty(), &mut in_out)
+        .map_err(|_| "Failed to encrypt message".to_string())?;
+
+    let mut sealed = nonce_bytes.to_vec();
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+fn open_with(key: &[u8; 32], sealed: &[u8]) -> Result<String, String> {
+    if sealed.len() < NONCE_LEN {
+        return Err("Invalid encrypted message: too short".to_string());
+    }
+
+    let (nonce_bytes, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+        .map_err(|_| "Invalid encrypted message nonce".to_string())?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = aead_key(key)
+        .open_in_place(nonce, Aad::empty(), &mut in_out)
+        .map_err(|_| "Failed to decrypt message (wrong chat key?)".to_string())?;
+
+    String::from_utf8(plaintext.to_vec())
+        .map_err(|e| format!("Decrypted message is not valid UTF-8: {}", e))
+}
+
+/// Seal message content with the connection's key: base64(nonce || ciphertext || tag).
+/// Returns None when no secret is set, and an error when the peer did not exchange a salt.
+pub(crate) fn seal(salt: Option<&SessionSalt>, content: &str) -> Option<Result<String, String>> {
+    if !enabled() {
+        return None;
+    }
+    let Some(salt) = salt else {
+        return Some(Err("Peer does not support encrypted chat (no key salt in the handshake)".to_string()));
+    };
+    let key = key_for(salt)?;
+    Some(seal_with(&key, content).map(|sealed| general_purpose::STANDARD.encode(sealed)))
+}
+
+/// Open content sealed by the peer on the same connection.
+pub(crate) fn open(salt: Option<&SessionSalt>, sealed: &str) -> Result<String, String> {
+    let salt = salt.ok_or_else(|| "Received an encrypted message without a key salt in the handshake".to_string())?;
+    let key = key_for(salt)
+        .ok_or_else(|| "Received an encrypted message but no chat key is set".to_string())?;
+    let data = general_purpose::STANDARD.decode(sealed)
+        .map_err(|e| format!("Invalid encrypted message: {}", e))?;
+    open_with(&key, &data)
+}
+
+/// Seal content for storage: base64(salt || nonce || ciphertext || tag). Returns None when no secret is set.
+pub(crate) fn seal_at_rest(content: &str) -> Option<Result<String, String>> {
+    if !enabled() {
+        return None;
+    }
+    let salt = *HISTORY_SALT.lock().unwrap().get_or_insert_with(new_salt);
+    let key = key_for(&salt)?;
+    Some(seal_with(&key, content).map(|sealed| general_purpose::STANDARD.encode([&salt[..], &sealed].concat())))
+}
+
+/// Open content stored by `seal_at_rest` under the current secret.
+pub(crate) fn open_at_rest(sealed: &str) -> Result<String, String> {
+    let data = general_purpose::STANDARD.decode(sealed)
+        .map_err(|e| format!("Invalid encrypted message: {}", e))?;
+    if data.len() < SALT_LEN {
+        return Err("Invalid encrypted message: too short".to_string());
+    }
+    let (salt, sealed) = data.split_at(SALT_LEN);
+    let key = key_for(salt).ok_or_else(|| "No chat key is set".to_string())?;
+    open_with(&key, sealed)
+}
+
+/// Set the shared chat secret established during pairing (both peers must use the same one).
+/// Pass None or an empty string to go back to plaintext chat.
+#[tauri::command]
+pub async fn set_chat_key(secret: Option<String>) -> Result<(), TransferError> {
+    *CHAT_SECRET.lock().unwrap() = secret.filter(|s| !s.is_empty());
+    DERIVED_KEYS.lock().unwrap().clear();
+    *HISTORY_SALT.lock().unwrap() = None;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn chat_encryption_enabled() -> Result<bool, TransferError> {
+    Ok(enabled())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_content_round_trips() {
+        let key = derive_key("pairing secret", &session_salt([1; SALT_LEN], [2; SALT_LEN]));
+        for content in ["", "hello", "你好，世界"] {
+            let sealed = seal_with(&key, content).unwrap();
+            assert_ne!(sealed, content.as_bytes());
+            assert_eq!(open_with(&key, &sealed).unwrap(), content);
+        }
+        // A fresh nonce for every message
+        assert_ne!(seal_with(&key, "hello").unwrap(), seal_with(&key, "hello").unwrap());
+    }
+
+    #[test]
+    fn other_salt_or_secret_cannot_open() {
+        let salt = session_salt([1; SALT_LEN], [2; SALT_LEN]);
+        let key = derive_key("pairing secret", &salt);
+        let sealed = seal_with(&key, "hello").unwrap();
+
+        assert!(open_with(&derive_key("pairing secret", &session_salt([1; SALT_LEN], [3; SALT_LEN])), &sealed).is_err());
+        assert!(open_with(&derive_key("other secret", &salt), &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_with(&key, &tampered).is_err());
+        assert!(open_with(&key, &[0; NONCE_LEN - 1]).is_err());
+    }
+
+    // The only test that touches the global secret
+    #[test]
+    fn sealing_follows_the_current_secret() {
+        let salt = session_salt([4; SALT_LEN], [5; SALT_LEN]);
+        *CHAT_SECRET.lock().unwrap() = Some("pairing secret".to_string());
+
+        let sealed = seal(Some(&salt), "hello").unwrap().unwrap();
+        assert_eq!(open(Some(&salt), &sealed).unwrap(), "hello");
+        assert!(seal(None, "hello").unwrap().is_err());
+        assert!(open(Some(&session_salt([4; SALT_LEN], [6; SALT_LEN])), &sealed).is_err());
+
+        let stored = seal_at_rest("hello").unwrap().unwrap();
+        assert_eq!(open_at_rest(&stored).unwrap(), "hello");
+
+        *CHAT_SECRET.lock().unwrap() = None;
+        assert!(seal(Some(&salt), "hello").is_none());
+        assert!(open(Some(&salt), &sealed).is_err());
+        assert!(open_at_rest(&stored).is_err());
+    }
+
+    #[test]
+    fn handshake_salts_give_both_sides_the_same_salt() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = "ws://10.0.0.2:7879".into_client_request().unwrap();
+        let offered = offer_salt(&mut request);
+        let mut response = Response::new(());
+        let server_salt = accept_salt(&request, &mut response).unwrap();
+        let client_salt = connected_salt(offered, &response.map(|_| None)).unwrap();
+        assert_eq!(client_salt, server_salt);
+
+        // Each connection gets its own salt
+        let mut again = "ws://10.0.0.2:7879".into_client_request().unwrap();
+        assert_ne!(offer_salt(&mut again), offered);
+
+        // Peers without salt support
+        let plain = "ws://10.0.0.2:7879".into_client_request().unwrap();
+        assert!(accept_salt(&plain, &mut Response::new(())).is_none());
+        assert!(connected_salt(offered, &ClientResponse::new(None)).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::network::chat::ChatMessage;
+use crate::network::chat_crypto;
+use crate::network::error::TransferError;
+
+const HISTORY_FILE: &str = "chat_history.jsonl";
+
+// Appends and rewrites must not interleave
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// One stored chat message
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatHistoryEntry {
+    /// Device id announced in discovery when known, otherwise the IP, so history survives DHCP and name changes
+    pub peer: String,
+    pub peer_ip: String,
+    /// "sent" or "received"
+    pub direction: String,
+    /// Attachment data is not stored, only its name and type. Content is sealed with the chat key
+    /// while one is set (`encrypted` is true) and opened again when read back with the same key
+    pub message: ChatMessage,
+}
+
+/// History key for a peer: its discovered device id, or the IP when unknown
+fn peer_key(peer_ip: &str) -> String {
+    crate::network::transfer::discovered_device_id(peer_ip).unwrap_or_else(|| peer_ip.to_string())
+}
+
+/// Open entries stored while a chat key was set. Entries the current key cannot open stay sealed.
+fn open_entry(mut entry: ChatHistoryEntry) -> ChatHistoryEntry {
+    if entry.message.encrypted {
+        if let Ok(content) = chat_crypto::open_at_rest(&entry.message.content) {
+            entry.message.content = content;
+            entry.message.encrypted = false;
+        }
+    }
+    entry
+}
+
+fn append_entry(app: &AppHandle, entry: &ChatHistoryEntry) -> Result<(), String> {
+    let path = crate::config::config_path(app, HISTORY_FILE)?;
+    let line = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize chat message: {}", e))?;
+
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    writeln!(file, "{}", line)
+        .map_err(|e| format!("Failed to write chat history: {}", e))
+}
+
+/// Read every entry; the caller must hold HISTORY_LOCK
+fn load_entries(path: &std::path::Path) -> Vec<ChatHistoryEntry> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    // Skip lines that fail to parse (e.g. half-written on a crash)
+    std::io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Store a sent or received message. Written in the background so the socket loop never waits on disk.
+pub(crate) fn record(app: &AppHandle, peer_ip: &str, direction: &str, message: &ChatMessage) {
+    let mut message = message.clone();
+    if let Some(attachment) = message.attachment.as_mut() {
+        attachment.data_base64.clear();
+    }
+    // Keep the stored copy encrypted at rest while a chat key is set
+    message.encrypted = false;
+    match chat_crypto::seal_at_rest(&message.content) {
+        Some(Ok(sealed)) => {
+            message.content = sealed;
+            message.encrypted = true;
+        }
+        Some(Err(e)) => {
+            eprintln!("Failed to record chat message: {}", e);
+            return;
+        }
+        None => {}
+    }
+    let entry = ChatHistoryEntry {
+        peer: peer_key(peer_ip),
+        peer_ip: peer_ip.to_string(),
+        direction: direction.to_string(),
+        message,
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = append_entry(&app, &entry) {
+            eprintln!("Failed to record chat message: {}", e);
+        }
+    });
+}
+
+/// Get the last `limit` messages exchanged with a peer, oldest first.
+/// Messages are matched by the peer's device id, so they are included even if its IP has changed.
+#[tauri::command]
+pub async fn get_chat_history(peer_ip: String, limit: usize, app: AppHandle) -> Result<Vec<ChatHistoryEntry>, TransferError> {
+    let path = crate::config::config_path(&app, HISTORY_FILE).map_err(TransferError::Io)?;
+    let key = peer_key(&peer_ip);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = HISTORY_LOCK.lock().unwrap();
+        let mut entries: Vec<ChatHistoryEntry> = load_entries(&path)
+            .into_iter()
+            .filter(|e| e.peer == key)
+            .collect();
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+        entries.into_iter().map(open_entry).collect()
+    }).await.map_err(|e| TransferError::Other(format!("Failed to read chat history: {}", e)))
+}
+
+/// Delete the stored conversation with a peer (matched the same way as get_chat_history).
+#[tauri::command]
+pub async fn clear_chat_history(peer_ip: String, app: AppHandle) -> Result<(), TransferError> {
+    let path = crate::config::config_path(&app, HISTORY_FILE).map_err(TransferError::Io)?;
+    let key = peer_key(&peer_ip);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = HISTORY_LOCK.lock().unwrap();
+        let kept: Vec<String> = load_entries(&path)
+            .into_iter()
+            .filter(|e| e.peer != key)
+            .filter_map(|e| serde_json::to_string(&e).ok())
+            .collect();
+        let contents = kept.iter().map(|line| format!("{}\n", line)).collect::<String>();
+        std::fs::write(&path, contents)
+            .map_err(|e| TransferError::Io(format!("Failed to clear chat history: {}", e)))
+    }).await.map_err(|e| TransferError::Other(format!("Failed to clear chat history: {}", e)))?
+}
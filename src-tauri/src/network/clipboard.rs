@@ -1,26 +1,188 @@
+#[cfg(not(target_os = "android"))]
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, Manager, Window};
-use tokio::net::TcpListener;
+use tauri::{AppHandle, Manager, Window};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{accept_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{accept_hdr_async, MaybeTlsStream, WebSocketStream};
 use futures_util::{SinkExt, StreamExt};
-use socket2::{Socket, Domain, Type, Protocol};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use crate::network::connection_pin;
+use crate::network::keepalive;
+use crate::network::ports::{self, Service};
+use crate::network::error::TransferError;
+use crate::network::transfer::ConnectFailureReason;
+use crate::network::window_events;
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+// Bounds for set_clipboard_poll_interval: faster busy-loops, slower effectively stops syncing
+const MIN_POLL_INTERVAL_MS: u64 = 100;
+const MAX_POLL_INTERVAL_MS: u64 = 60_000;
+const HISTORY_LEN: usize = 20;
+const DEFAULT_MAX_SIZE: usize = 1024 * 1024;
+// Room for the JSON envelope around the content in a received frame
+const FRAME_OVERHEAD: usize = 64 * 1024;
+
+// Kinds beyond text are negotiated in the handshake; Android peers only handle text
+const KINDS_HEADER: &str = "x-lan-transfer-clipboard-kinds";
+const RICH_KINDS: &str = "image,files";
+
+/// What a clipboard message carries
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipboardKind {
+    #[default]
+    Text,
+    Image,
+    Files,
+}
 
-const CLIPBOARD_PORT: u16 = 7880;
-const POLL_INTERVAL_MS: u64 = 500;
+/// Clipboard image, PNG-encoded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClipboardImage {
+    pub width: u32,
+    pub height: u32,
+    pub png_base64: String,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ClipboardMessage {
+    /// Text for `Text`, one path per line for `Files`, empty for `Image`
     pub content: String,
     pub from_ip: String,
     pub timestamp: i64,
     pub hash: String,
+    /// Missing from older peers, which only sync text
+    #[serde(default)]
+    pub kind: ClipboardKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<ClipboardImage>,
+    /// What the text is: "text/plain", "text/html" or "text/uri-list" ("image/png" for images).
+    /// Unknown or missing types are treated as plain text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Markup for "text/html"; `content` holds the plain-text version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+/// Clipboard content read locally for syncing
+enum LocalClipboard {
+    Text(String),
+    /// Rich text: plain-text version and HTML markup
+    #[cfg(not(target_os = "android"))]
+    Html(String, String),
+    #[cfg(not(target_os = "android"))]
+    Files(Vec<std::path::PathBuf>),
+    #[cfg(not(target_os = "android"))]
+    Image(arboard::ImageData<'static>),
+}
+
+impl LocalClipboard {
+    /// Text that would be sent; empty for images
+    fn text(&self) -> std::borrow::Cow<'_, str> {
+        match self {
+            LocalClipboard::Text(text) => text.into(),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Html(text, _) => text.into(),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Files(paths) => files_content(paths).into(),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Image(_) => "".into(),
+        }
+    }
+
+    /// Hash used to detect changes and suppress echoes; images hash the raw pixels so polling never encodes PNG
+    fn hash(&self) -> String {
+        match self {
+            LocalClipboard::Text(text) => compute_hash(text),
+            // Same as plain text, so a receiver that pastes the text does not echo it back
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Html(text, _) => compute_hash(text),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Files(paths) => compute_hash(&files_content(paths)),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Image(image) => {
+                let mut hasher = DefaultHasher::new();
+                (image.width, image.height, image.bytes.as_ref()).hash(&mut hasher);
+                format!("{:x}", hasher.finish())
+            }
+        }
+    }
+
+    fn into_message(self, hash: String) -> Result<ClipboardMessage, String> {
+        let (kind, content, image, content_type, html) = match self {
+            LocalClipboard::Text(text) => {
+                let content_type = sniff_text_type(&text).to_string();
+                (ClipboardKind::Text, text, None, Some(content_type), None)
+            }
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Html(text, markup) => {
+                (ClipboardKind::Text, text, None, Some("text/html".to_string()), Some(markup))
+            }
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Files(paths) => (ClipboardKind::Files, files_content(&paths), None, None, None),
+            #[cfg(not(target_os = "android"))]
+            LocalClipboard::Image(image) => {
+                let png_data = encode_png(&image)?;
+                let image = ClipboardImage {
+                    width: image.width as u32,
+                    height: image.height as u32,
+                    png_base64: general_purpose::STANDARD.encode(png_data),
+                };
+                (ClipboardKind::Image, String::new(), Some(image), Some("image/png".to_string()), None)
+            }
+        };
+        Ok(ClipboardMessage {
+            content,
+            from_ip: crate::network::transfer::get_local_ip()
+                .unwrap_or_else(|_| "unknown".to_string()),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as i64,
+            hash,
+            kind,
+            image,
+            content_type,
+            html,
+        })
+    }
+}
+
+/// A single URL is sent as "text/uri-list" so receivers can offer to open it; anything else is plain text.
+fn sniff_text_type(text: &str) -> &'static str {
+    let text = text.trim();
+    let is_url = ["http://", "https://", "ftp://", "file://"].iter().any(|scheme| text.starts_with(scheme))
+        && !text.contains(char::is_whitespace);
+    if is_url { "text/uri-list" } else { "text/plain" }
+}
+
+#[cfg(not(target_os = "android"))]
+fn files_content(paths: &[std::path::PathBuf]) -> String {
+    paths.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>().join("\n")
+}
+
+/// Client side: advertise image and file-list support (desktop only).
+fn offer_kinds(request: &mut tokio_tungstenite::tungstenite::handshake::client::Request) {
+    if cfg!(not(target_os = "android")) {
+        request.headers_mut().insert(KINDS_HEADER, HeaderValue::from_static(RICH_KINDS));
+    }
+}
+
+/// Server side: accept image and file-list messages when both peers support them.
+fn negotiate_kinds(request: &Request, response: &mut Response) -> bool {
+    if cfg!(target_os = "android") || !request.headers().contains_key(KINDS_HEADER) {
+        return false;
+    }
+    response.headers_mut().insert(KINDS_HEADER, HeaderValue::from_static(RICH_KINDS));
+    true
 }
 
 pub(crate) enum ClipboardWsWriter {
@@ -37,26 +199,201 @@ impl ClipboardWsWriter {
     }
 }
 
+impl keepalive::PingWriter for ClipboardWsWriter {
+    async fn ping(&mut self) -> bool {
+        self.send(Message::Ping(Vec::new())).await.is_ok()
+    }
+}
+
 pub struct ClipboardConnection {
     #[allow(dead_code)]
     pub ip: String,
     pub writer: Arc<Mutex<ClipboardWsWriter>>,
+    /// Deflate negotiated in the handshake
+    pub compressed: bool,
+    /// Peer accepts image and file-list messages; text-only peers are skipped for those
+    pub rich_kinds: bool,
 }
 
 pub type ClipboardConnections = Arc<Mutex<HashMap<String, ClipboardConnection>>>;
 
+/// Which way clipboard changes flow: the mode chosen with set_clipboard_mode,
+/// or in clipboard_sync_status the effective direction given the running state
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    Off,
+    SendOnly,
+    ReceiveOnly,
+    Both,
+}
+
+impl SyncDirection {
+    fn sends(self) -> bool {
+        matches!(self, SyncDirection::SendOnly | SyncDirection::Both)
+    }
+
+    fn receives(self) -> bool {
+        matches!(self, SyncDirection::ReceiveOnly | SyncDirection::Both)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ClipboardSyncStatus {
+    pub polling: bool,
+    pub server_running: bool,
+    pub connections: usize,
+    pub direction: SyncDirection,
+}
+
 static CLIPBOARD_SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+// Accept loop task, kept so a forced reset can tear down the listener
+static CLIPBOARD_SERVER_TASK: std::sync::Mutex<Option<tokio::task::AbortHandle>> = std::sync::Mutex::new(None);
 static CLIPBOARD_POLLING_RUNNING: AtomicBool = AtomicBool::new(false);
+// Broadcasts in progress; each also has a cancel flag registered under its id with the transfer sends
+static ACTIVE_BROADCASTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+// Read on every polling iteration so changes apply without restarting polling
+static POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(DEFAULT_POLL_INTERVAL_MS);
+// Directions allowed by set_clipboard_mode
+static CLIPBOARD_MODE: std::sync::Mutex<SyncDirection> = std::sync::Mutex::new(SyncDirection::Both);
+// Content matching any of these is never broadcast (set_clipboard_exclude_patterns)
+static EXCLUDE_PATTERNS: std::sync::Mutex<Vec<regex::Regex>> = std::sync::Mutex::new(Vec::new());
+// Largest text (or file list) broadcast to peers, in bytes
+static CLIPBOARD_MAX_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_SIZE);
+
+/// Whether the clipboard listener is running
+pub(crate) fn server_running() -> bool {
+    CLIPBOARD_SERVER_RUNNING.load(Ordering::Relaxed)
+}
+
+/// Payload of `clipboard-skipped-too-large`
+#[derive(Serialize, Clone, Debug)]
+struct ClipboardTooLarge {
+    size: usize,
+    limit: usize,
+}
+
+/// Whether the clipboard owner marked the content as sensitive. Password managers on Windows set
+/// ExcludeClipboardContentFromMonitorProcessing; other platforms don't expose the hint through arboard.
+#[cfg(windows)]
+fn clipboard_concealed() -> bool {
+    clipboard_win::register_format("ExcludeClipboardContentFromMonitorProcessing")
+        .is_some_and(|format| clipboard_win::is_format_avail(format.get()))
+}
+
+#[cfg(not(windows))]
+fn clipboard_concealed() -> bool {
+    false
+}
+
+/// Check outgoing content against the concealed hint and the exclude patterns;
+/// emits `clipboard-skipped-excluded` and returns true when it must not be synced.
+fn is_excluded(window: &Window, content: &str) -> bool {
+    let excluded = clipboard_concealed()
+        || EXCLUDE_PATTERNS.lock().unwrap().iter().any(|pattern| pattern.is_match(content));
+    if excluded {
+        println!("Skipping clipboard sync: content is excluded");
+        window_events::emit(window, "clipboard-skipped-excluded", ());
+    }
+    excluded
+}
+
+/// Check the size of outgoing content (text, or the base64 PNG of an image) against the size limit;
+/// emits `clipboard-skipped-too-large` and returns false when over it.
+fn within_size_limit(window: &Window, size: usize) -> bool {
+    let limit = CLIPBOARD_MAX_SIZE.load(Ordering::Relaxed);
+    if size <= limit {
+        return true;
+    }
+    println!("Skipping clipboard sync: {} bytes exceeds the {} byte limit", size, limit);
+    window_events::emit(window, "clipboard-skipped-too-large", ClipboardTooLarge { size, limit });
+    false
+}
+
+/// Images are only encoded when the message is built, so their size is checked afterwards.
+fn image_within_size_limit(window: &Window, message: &ClipboardMessage) -> bool {
+    message.image.as_ref().is_none_or(|image| within_size_limit(window, image.png_base64.len()))
+}
+
+/// Largest frame a compressed connection may inflate to: escaped content within the size limit plus the envelope.
+fn max_frame_size() -> usize {
+    CLIPBOARD_MAX_SIZE.load(Ordering::Relaxed).saturating_mul(2) + FRAME_OVERHEAD
+}
 
 // Last known clipboard hash to prevent echo
 static LAST_CLIPBOARD_HASH: std::sync::Mutex<String> = std::sync::Mutex::new(String::new());
 
+// Recently sent and received clipboard values, newest first
+static CLIPBOARD_HISTORY: std::sync::Mutex<VecDeque<ClipboardHistoryEntry>> = std::sync::Mutex::new(VecDeque::new());
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ClipboardHistoryEntry {
+    /// "sent" or "received"
+    pub direction: String,
+    pub message: ClipboardMessage,
+}
+
+/// Add a sent or received value to the history, skipping it when it repeats the latest entry.
+fn record_history(direction: &str, message: &ClipboardMessage) {
+    let mut history = CLIPBOARD_HISTORY.lock().unwrap();
+    if history.front().is_some_and(|latest| latest.message.hash == message.hash) {
+        return;
+    }
+    history.push_front(ClipboardHistoryEntry {
+        direction: direction.to_string(),
+        message: message.clone(),
+    });
+    history.truncate(HISTORY_LEN);
+}
+
+fn clipboard_mode() -> SyncDirection {
+    *CLIPBOARD_MODE.lock().unwrap()
+}
+
 fn compute_hash(content: &str) -> String {
     let mut hasher = DefaultHasher::new();
     content.hash(&mut hasher);
     format!("{:x}", hasher.finish())
 }
 
+/// Send a serialized clipboard message to every connected peer that supports its kind.
+/// The broadcast gets its own id, announced in `clipboard-broadcast-started` and cancellable with
+/// cancel_clipboard_broadcast or cancel_file_sending; returns false if it was cancelled.
+async fn broadcast_clipboard(window: &Window, connections: &ClipboardConnections, json: &str, kind: ClipboardKind) -> bool {
+    let broadcast_id = format!("clipboard-{}", crate::network::transfer::new_transfer_id());
+    let cancel = crate::network::transfer::register_send_cancel(&broadcast_id);
+    ACTIVE_BROADCASTS.lock().unwrap().push(broadcast_id.clone());
+    window_events::emit(window, "clipboard-broadcast-started", &broadcast_id);
+
+    let completed = send_to_peers(connections, json, kind, &cancel.flag).await;
+    ACTIVE_BROADCASTS.lock().unwrap().retain(|id| *id != broadcast_id);
+    if !completed {
+        println!("Clipboard broadcast {} cancelled", broadcast_id);
+    }
+    completed
+}
+
+/// Per-peer loop of a broadcast, checking the cancel flag before each peer
+async fn send_to_peers(connections: &ClipboardConnections, json: &str, kind: ClipboardKind, cancelled: &AtomicBool) -> bool {
+    let conns = connections.lock().await;
+    for (ip, connection) in conns.iter() {
+        if cancelled.load(Ordering::SeqCst) {
+            return false;
+        }
+        if kind != ClipboardKind::Text && !connection.rich_kinds {
+            continue;
+        }
+
+        let mut w = connection.writer.lock().await;
+        let msg = crate::network::compression::text_message(json.to_string(), connection.compressed);
+        if let Err(e) = w.send(msg).await {
+            eprintln!("Failed to send clipboard to {}: {}", ip, e);
+        }
+    }
+
+    true
+}
+
 /// Get system clipboard content (desktop only, Android uses plugin)
 #[cfg(not(target_os = "android"))]
 fn get_clipboard_content() -> Result<String, String> {
@@ -83,22 +420,133 @@ fn set_clipboard_content(_content: &str) -> Result<(), String> {
     Err("Use set_system_clipboard command for Android".to_string())
 }
 
+#[cfg(not(target_os = "android"))]
+fn encode_png(image: &arboard::ImageData) -> Result<Vec<u8>, String> {
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| format!("Failed to encode PNG: {}", e))?;
+        writer.write_image_data(&image.bytes).map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    }
+    Ok(png_data)
+}
+
+/// Decode a PNG produced by encode_png back into RGBA pixels.
+#[cfg(not(target_os = "android"))]
+fn decode_png(png_data: &[u8]) -> Result<arboard::ImageData<'static>, String> {
+    let decoder = png::Decoder::new(png_data);
+    let mut reader = decoder.read_info().map_err(|e| format!("Failed to decode PNG: {}", e))?;
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).map_err(|e| format!("Failed to decode PNG: {}", e))?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err("Unsupported clipboard image format".to_string());
+    }
+    bytes.truncate(info.buffer_size());
+    Ok(arboard::ImageData {
+        width: info.width as usize,
+        height: info.height as usize,
+        bytes: bytes.into(),
+    })
+}
+
+/// Read an image from the system clipboard and encode it as PNG (desktop only).
+/// Returns Ok(None) when the clipboard holds no image.
+#[cfg(not(target_os = "android"))]
+fn get_clipboard_image_png() -> Result<Option<Vec<u8>>, String> {
+    use arboard::{Clipboard, Error as ClipboardError};
+    let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to access clipboard: {}", e))?;
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(ClipboardError::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(format!("Failed to get clipboard image: {}", e)),
+    };
+    encode_png(&image).map(Some)
+}
+
+/// Read the clipboard for syncing: a file list, then text, then an image (desktop only).
+/// Returns Ok(None) when the clipboard is empty.
+#[cfg(not(target_os = "android"))]
+fn read_local_clipboard() -> Result<Option<LocalClipboard>, String> {
+    use arboard::{Clipboard, Error as ClipboardError};
+    let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to access clipboard: {}", e))?;
+    // Not every platform exposes file lists; treat errors as "no files"
+    if let Ok(paths) = clipboard.get().file_list() {
+        if !paths.is_empty() {
+            return Ok(Some(LocalClipboard::Files(paths)));
+        }
+    }
+    if let Ok(text) = clipboard.get_text() {
+        if !text.is_empty() {
+            return match clipboard.get().html() {
+                Ok(html) if !html.is_empty() => Ok(Some(LocalClipboard::Html(text, html))),
+                _ => Ok(Some(LocalClipboard::Text(text))),
+            };
+        }
+    }
+    match clipboard.get_image() {
+        Ok(image) => Ok(Some(LocalClipboard::Image(image))),
+        Err(ClipboardError::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(format!("Failed to get clipboard image: {}", e)),
+    }
+}
+
+/// Put a received message on the system clipboard with the setter matching its kind (desktop only).
+/// File lists are only set as files when every path exists here; otherwise they are pasted as text.
+#[cfg(not(target_os = "android"))]
+fn apply_clipboard_message(msg: &ClipboardMessage) -> Result<(), String> {
+    use arboard::Clipboard;
+    match msg.kind {
+        ClipboardKind::Text => match &msg.html {
+            Some(html) if msg.content_type.as_deref() == Some("text/html") => {
+                let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to access clipboard: {}", e))?;
+                clipboard.set_html(html.as_str(), Some(msg.content.as_str()))
+                    .map_err(|e| format!("Failed to set clipboard HTML: {}", e))
+            }
+            _ => set_clipboard_content(&msg.content),
+        },
+        ClipboardKind::Image => {
+            let image = msg.image.as_ref().ok_or_else(|| "Clipboard image missing".to_string())?;
+            let png_data = general_purpose::STANDARD.decode(&image.png_base64)
+                .map_err(|e| format!("Invalid clipboard image: {}", e))?;
+            let image = decode_png(&png_data)?;
+            let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to access clipboard: {}", e))?;
+            clipboard.set_image(image).map_err(|e| format!("Failed to set clipboard image: {}", e))
+        }
+        ClipboardKind::Files => {
+            let paths: Vec<std::path::PathBuf> = msg.content.lines().map(std::path::PathBuf::from).collect();
+            if !paths.iter().all(|p| p.exists()) {
+                return set_clipboard_content(&msg.content);
+            }
+            let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to access clipboard: {}", e))?;
+            clipboard.set().file_list(&paths).map_err(|e| format!("Failed to set clipboard files: {}", e))
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+fn get_clipboard_image_png() -> Result<Option<Vec<u8>>, String> {
+    // The Android plugin only exposes clipboard text
+    Ok(None)
+}
+
 #[tauri::command]
-pub async fn get_system_clipboard(app: AppHandle) -> Result<String, String> {
+pub async fn get_system_clipboard(app: AppHandle) -> Result<String, TransferError> {
     #[cfg(target_os = "android")]
     {
         let storage = app.state::<crate::android_storage::AndroidStorage>();
-        return storage.get_clipboard();
+        return storage.get_clipboard().map_err(TransferError::Io);
     }
     #[cfg(not(target_os = "android"))]
     {
         let _ = app;
-        get_clipboard_content()
+        get_clipboard_content().map_err(TransferError::Io)
     }
 }
 
 #[tauri::command]
-pub async fn set_system_clipboard(content: String, app: AppHandle) -> Result<(), String> {
+pub async fn set_system_clipboard(content: String, app: AppHandle) -> Result<(), TransferError> {
     // Update last hash to prevent echo
     if let Ok(mut hash) = LAST_CLIPBOARD_HASH.lock() {
         *hash = compute_hash(&content);
@@ -107,17 +555,22 @@ pub async fn set_system_clipboard(content: String, app: AppHandle) -> Result<(),
     #[cfg(target_os = "android")]
     {
         let storage = app.state::<crate::android_storage::AndroidStorage>();
-        return storage.set_clipboard(content);
+        return storage.set_clipboard(content).map_err(TransferError::Io);
     }
     #[cfg(not(target_os = "android"))]
     {
         let _ = app;
-        set_clipboard_content(&content)
+        set_clipboard_content(&content).map_err(TransferError::Io)
     }
 }
 
 #[tauri::command]
-pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<(), String> {
+pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<(), TransferError> {
+    serve(window, app, None).await
+}
+
+/// Start the clipboard server on `listener` if the caller already bound one, otherwise bind the configured port
+async fn serve(window: Window, app: AppHandle, listener: Option<tokio::net::TcpListener>) -> Result<(), TransferError> {
     if CLIPBOARD_SERVER_RUNNING.load(Ordering::Relaxed) {
         return Ok(());
     }
@@ -126,54 +579,20 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
 
     let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
 
-    tokio::spawn(async move {
-        let addr = format!("0.0.0.0:{}", CLIPBOARD_PORT);
-
-        let socket = match Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Failed to create clipboard socket: {}", e);
-                CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
-                let _ = window.emit("clipboard-server-error", format!("Failed to create socket: {}", e));
-                return;
-            }
-        };
-
-        if let Err(e) = socket.set_reuse_address(true) {
-            eprintln!("Failed to set SO_REUSEADDR: {}", e);
-        }
-
-        #[cfg(not(windows))]
-        if let Err(e) = socket.set_reuse_port(true) {
-            eprintln!("Failed to set SO_REUSEPORT: {}", e);
-        }
-
-        if let Err(e) = socket.set_nonblocking(true) {
-            eprintln!("Failed to set nonblocking: {}", e);
-        }
+    let task = tokio::spawn(async move {
+        let addr = format!("0.0.0.0:{}", ports::local(Service::Clipboard));
 
         let sock_addr: std::net::SocketAddr = addr.parse().unwrap();
-        if let Err(e) = socket.bind(&sock_addr.into()) {
-            eprintln!("Failed to bind clipboard server: {}", e);
-            CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
-            let _ = window.emit("clipboard-server-error", format!("Failed to bind: {}", e));
-            return;
-        }
-
-        if let Err(e) = socket.listen(128) {
-            eprintln!("Failed to listen: {}", e);
-            CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
-            let _ = window.emit("clipboard-server-error", format!("Failed to listen: {}", e));
-            return;
-        }
-
-        let std_listener: std::net::TcpListener = socket.into();
-        let listener = match TcpListener::from_std(std_listener) {
+        let bound = match listener {
+            Some(listener) => Ok(listener),
+            None => crate::network::listener::bind_with_retry(sock_addr, "clipboard", &CLIPBOARD_SERVER_RUNNING, &window).await,
+        };
+        let listener = match bound {
             Ok(l) => l,
             Err(e) => {
-                eprintln!("Failed to convert listener: {}", e);
+                eprintln!("Failed to start clipboard server: {}", e);
                 CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
-                let _ = window.emit("clipboard-server-error", format!("Failed to convert listener: {}", e));
+                window_events::emit(&window, "clipboard-server-error", format!("Failed to start clipboard server: {}", e));
                 return;
             }
         };
@@ -199,7 +618,15 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
                         let window_clone = window.clone();
 
                         tokio::spawn(async move {
-                            let ws_stream = match accept_async(stream).await {
+                            let mut compressed = false;
+                            let mut rich_kinds = false;
+                            #[allow(clippy::result_large_err)]
+                            let negotiate = |req: &Request, mut resp: Response| {
+                                compressed = crate::network::compression::negotiate(req, &mut resp);
+                                rich_kinds = negotiate_kinds(req, &mut resp);
+                                Ok(resp)
+                            };
+                            let ws_stream = match accept_hdr_async(stream, negotiate).await {
                                 Ok(ws) => ws,
                                 Err(e) => {
                                     eprintln!("WebSocket handshake failed: {}", e);
@@ -207,8 +634,12 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
                                 }
                             };
 
-                            let (writer, mut reader) = ws_stream.split();
+                            let (mut writer, mut reader) = ws_stream.split();
+                            if !connection_pin::authorize(&mut reader, &mut writer, &peer_ip).await {
+                                return;
+                            }
                             let writer = Arc::new(Mutex::new(ClipboardWsWriter::Plain(writer)));
+                            keepalive::spawn(Arc::downgrade(&writer));
 
                             // Store connection
                             {
@@ -216,16 +647,23 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
                                 conns.insert(peer_ip.clone(), ClipboardConnection {
                                     ip: peer_ip.clone(),
                                     writer: writer.clone(),
+                                    compressed,
+                                    rich_kinds,
                                 });
                             }
 
-                            let _ = window_clone.emit("clipboard-connected", &peer_ip);
+                            window_events::emit(&window_clone, "clipboard-connected", &peer_ip);
 
                             // Listen for messages
                             while let Some(msg_result) = reader.next().await {
+                                let msg_result = msg_result.map(|m| crate::network::compression::inflate_message(m, compressed, max_frame_size()));
                                 match msg_result {
+                                    // PIN sent to a server without one configured
+                                    Ok(Message::Text(text)) if connection_pin::is_auth_message(&text) => {}
                                     Ok(Message::Text(text)) => {
                                         match serde_json::from_str::<ClipboardMessage>(&text) {
+                                            // Receiving disabled by the clipboard mode
+                                            Ok(_) if !clipboard_mode().receives() => {}
                                             Ok(clip_msg) => {
                                                 // Update last hash to prevent echo
                                                 if let Ok(mut hash) = LAST_CLIPBOARD_HASH.lock() {
@@ -234,11 +672,12 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
 
                                                 // Set local clipboard
                                                 #[cfg(not(target_os = "android"))]
-                                                {
-                                                    let _ = set_clipboard_content(&clip_msg.content);
+                                                if let Err(e) = apply_clipboard_message(&clip_msg) {
+                                                    eprintln!("Failed to apply clipboard from {}: {}", peer_ip, e);
                                                 }
 
-                                                let _ = window_clone.emit("clipboard-received", clip_msg);
+                                                record_history("received", &clip_msg);
+                                                window_events::emit(&window_clone, "clipboard-received", clip_msg);
                                             }
                                             Err(e) => {
                                                 eprintln!("Failed to parse clipboard message: {}", e);
@@ -271,7 +710,7 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
                                 conns.remove(&peer_ip);
                             }
 
-                            let _ = window_clone.emit("clipboard-disconnected", &peer_ip);
+                            window_events::emit(&window_clone, "clipboard-disconnected", &peer_ip);
                         });
                     }
                 }
@@ -286,18 +725,49 @@ pub async fn start_clipboard_server(window: Window, app: AppHandle) -> Result<()
 
         println!("Clipboard server stopped");
     });
+    *CLIPBOARD_SERVER_TASK.lock().unwrap() = Some(task.abort_handle());
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn stop_clipboard_server() -> Result<(), String> {
+pub async fn stop_clipboard_server() -> Result<(), TransferError> {
     CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
     Ok(())
 }
 
+/// Serve clipboard sync on `listener`, already bound to the newly configured port. Open sync connections stay up.
+pub(crate) async fn rebind(window: Window, app: AppHandle, listener: std::net::TcpListener) -> Result<(), TransferError> {
+    if !CLIPBOARD_SERVER_RUNNING.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    if let Some(task) = CLIPBOARD_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    let listener = tokio::net::TcpListener::from_std(listener)
+        .map_err(|e| TransferError::Io(format!("Failed to take over listener: {}", e)))?;
+    serve(window, app, Some(listener)).await
+}
+
+/// Forcibly stop the clipboard server and drop every connection, even if the running flag is stale.
+pub(crate) async fn force_reset(app: &AppHandle) {
+    CLIPBOARD_SERVER_RUNNING.store(false, Ordering::Relaxed);
+    if let Some(task) = CLIPBOARD_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+
+    let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
+    let mut conns = connections.lock().await;
+    for (_ip, connection) in conns.drain() {
+        let _ = connection.writer.lock().await.send(Message::Close(None)).await;
+    }
+}
+
+/// Connect to a peer's clipboard server. With `compress`, permessage-deflate is offered for this
+/// connection (off by default).
 #[tauri::command]
-pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHandle) -> Result<(), String> {
+pub async fn connect_to_clipboard(target_ip: String, compress: Option<bool>, window: Window, app: AppHandle) -> Result<(), TransferError> {
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
     let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
 
     // Check if already connected
@@ -308,15 +778,29 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
         }
     }
 
-    let url = format!("ws://{}:{}", target_ip, CLIPBOARD_PORT);
+    let url = format!("ws://{}:{}", target_ip, ports::peer(&target_ip, Service::Clipboard));
+
+    let mut request = url.into_client_request()
+        .map_err(|e| TransferError::InvalidInput(format!("Invalid clipboard URL: {}", e)))?;
+    crate::network::compression::offer(&mut request, compress.unwrap_or(false));
+    offer_kinds(&mut request);
 
-    let ws_stream = tokio_tungstenite::connect_async(&url)
+    let (mut ws_stream, response) = tokio_tungstenite::connect_async(request)
         .await
-        .map_err(|e| format!("Failed to connect to clipboard: {}", e))?
-        .0;
+        .map_err(|e| {
+            let reason = ConnectFailureReason::from_ws_error(&e);
+            TransferError::ConnectionFailed(format!("Failed to connect to clipboard: {}", e), Some(reason))
+        })?;
+    if let Some(auth) = connection_pin::auth_message() {
+        ws_stream.send(auth).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send connection PIN: {}", e), None))?;
+    }
+    let compressed = crate::network::compression::accepted(&response);
+    let rich_kinds = cfg!(not(target_os = "android")) && response.headers().contains_key(KINDS_HEADER);
 
     let (writer, mut reader) = ws_stream.split();
     let writer = Arc::new(Mutex::new(ClipboardWsWriter::Tls(writer)));
+    keepalive::spawn(Arc::downgrade(&writer));
 
     // Store connection
     {
@@ -324,10 +808,12 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
         conns.insert(target_ip.clone(), ClipboardConnection {
             ip: target_ip.clone(),
             writer: writer.clone(),
+            compressed,
+            rich_kinds,
         });
     }
 
-    let _ = window.emit("clipboard-connected", &target_ip);
+    window_events::emit(&window, "clipboard-connected", &target_ip);
 
     let connections_clone = connections.clone();
     let target_ip_clone = target_ip.clone();
@@ -336,9 +822,12 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
     let writer_clone = writer.clone();
     tokio::spawn(async move {
         while let Some(msg_result) = reader.next().await {
+            let msg_result = msg_result.map(|m| crate::network::compression::inflate_message(m, compressed, max_frame_size()));
             match msg_result {
                 Ok(Message::Text(text)) => {
                     match serde_json::from_str::<ClipboardMessage>(&text) {
+                        // Receiving disabled by the clipboard mode
+                        Ok(_) if !clipboard_mode().receives() => {}
                         Ok(clip_msg) => {
                             // Update last hash to prevent echo
                             if let Ok(mut hash) = LAST_CLIPBOARD_HASH.lock() {
@@ -347,11 +836,12 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
 
                             // Set local clipboard
                             #[cfg(not(target_os = "android"))]
-                            {
-                                let _ = set_clipboard_content(&clip_msg.content);
+                            if let Err(e) = apply_clipboard_message(&clip_msg) {
+                                eprintln!("Failed to apply clipboard from {}: {}", target_ip_clone, e);
                             }
 
-                            let _ = window_clone.emit("clipboard-received", clip_msg);
+                            record_history("received", &clip_msg);
+                            window_events::emit(&window_clone, "clipboard-received", clip_msg);
                         }
                         Err(e) => {
                             eprintln!("Failed to parse clipboard message: {}", e);
@@ -362,7 +852,10 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
                     let _ = writer_clone.lock().await.send(Message::Pong(data)).await;
                 }
                 Ok(Message::Pong(_)) => {}
-                Ok(Message::Close(_)) => {
+                Ok(Message::Close(frame)) => {
+                    if connection_pin::is_rejection(&frame) {
+                        window_events::emit(&window_clone, "connection-pin-rejected", &target_ip_clone);
+                    }
                     println!("Clipboard connection closed by {}", target_ip_clone);
                     break;
                 }
@@ -384,14 +877,14 @@ pub async fn connect_to_clipboard(target_ip: String, window: Window, app: AppHan
             conns.remove(&target_ip_clone);
         }
 
-        let _ = window_clone.emit("clipboard-disconnected", &target_ip_clone);
+        window_events::emit(&window_clone, "clipboard-disconnected", &target_ip_clone);
     });
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn disconnect_clipboard(target_ip: String, app: AppHandle) -> Result<(), String> {
+pub async fn disconnect_clipboard(target_ip: String, app: AppHandle) -> Result<(), TransferError> {
     let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
 
     let mut conns = connections.lock().await;
@@ -404,7 +897,7 @@ pub async fn disconnect_clipboard(target_ip: String, app: AppHandle) -> Result<(
 }
 
 #[tauri::command]
-pub async fn disconnect_all_clipboards(app: AppHandle) -> Result<(), String> {
+pub async fn disconnect_all_clipboards(app: AppHandle) -> Result<(), TransferError> {
     let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
     let mut conns = connections.lock().await;
 
@@ -416,51 +909,134 @@ pub async fn disconnect_all_clipboards(app: AppHandle) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn send_clipboard_content(app: AppHandle) -> Result<(), String> {
-    let content = get_system_clipboard(app.clone()).await?;
+pub async fn send_clipboard_content(window: Window, app: AppHandle) -> Result<(), TransferError> {
+    #[cfg(not(target_os = "android"))]
+    let local = read_local_clipboard().map_err(TransferError::Io)?;
+    #[cfg(target_os = "android")]
+    let local = Some(get_system_clipboard(app.clone()).await?)
+        .filter(|text| !text.is_empty())
+        .map(LocalClipboard::Text);
 
-    if content.is_empty() {
-        return Err("Clipboard is empty".to_string());
+    let Some(local) = local else {
+        return Err(TransferError::NotFound("Clipboard is empty".into()));
+    };
+    if !within_size_limit(&window, local.text().len()) {
+        return Err(TransferError::InvalidInput("Clipboard content exceeds the size limit".into()));
+    }
+    if is_excluded(&window, &local.text()) {
+        return Err(TransferError::InvalidInput("Clipboard content is excluded from sync".into()));
     }
 
-    let hash = compute_hash(&content);
+    let hash = local.hash();
+    let message = local.into_message(hash.clone()).map_err(TransferError::Io)?;
+    if !image_within_size_limit(&window, &message) {
+        return Err(TransferError::InvalidInput("Clipboard content exceeds the size limit".into()));
+    }
 
     // Update last hash to prevent echo
     if let Ok(mut last_hash) = LAST_CLIPBOARD_HASH.lock() {
-        *last_hash = hash.clone();
+        *last_hash = hash;
     }
 
-    let local_ip = crate::network::transfer::get_local_ip()
-        .unwrap_or_else(|_| "unknown".to_string());
+    let json = serde_json::to_string(&message)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize message: {}", e)))?;
+
+    let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
+
+    if !broadcast_clipboard(&window, &connections, &json, message.kind).await {
+        return Err(TransferError::Cancelled("Cancelled by user".into()));
+    }
+    record_history("sent", &message);
+
+    Ok(())
+}
 
-    let message = ClipboardMessage {
-        content,
-        from_ip: local_ip,
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64,
-        hash,
+/// Send the current clipboard to a peer as a file through the transfer protocol
+/// (text becomes a .txt file, images a .png file).
+#[tauri::command]
+pub async fn send_clipboard_as_file(target_ip: String, window: Window, app: AppHandle) -> Result<String, TransferError> {
+    let target_ip = crate::network::transfer::resolve_target(&target_ip)?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let text = get_system_clipboard(app).await.unwrap_or_default();
+    let (file_name, mime, data) = if !text.is_empty() {
+        (format!("clipboard-{}.txt", timestamp), "text/plain", text.into_bytes())
+    } else if let Some(png_data) = get_clipboard_image_png().map_err(TransferError::Io)? {
+        (format!("clipboard-{}.png", timestamp), "image/png", png_data)
+    } else {
+        return Err(TransferError::NotFound("Clipboard is empty".into()));
     };
 
-    let json = serde_json::to_string(&message)
-        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    crate::network::transfer::send_bytes_as_file(&window, &target_ip, &file_name, mime, data).await
+}
 
-    let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
-    let conns = connections.lock().await;
+/// Choose which way clipboard sync works: "Both" (default), "SendOnly", "ReceiveOnly" or "Off".
+/// Polling stops broadcasting when sending is off; received content is ignored when receiving is off.
+#[tauri::command]
+pub async fn set_clipboard_mode(mode: String) -> Result<(), TransferError> {
+    let mode = match mode.to_ascii_lowercase().replace('_', "").as_str() {
+        "both" => SyncDirection::Both,
+        "sendonly" => SyncDirection::SendOnly,
+        "receiveonly" => SyncDirection::ReceiveOnly,
+        "off" => SyncDirection::Off,
+        _ => return Err(TransferError::InvalidInput(format!("Unknown clipboard mode: {}", mode))),
+    };
+    *CLIPBOARD_MODE.lock().unwrap() = mode;
+    Ok(())
+}
 
-    for (ip, connection) in conns.iter() {
-        let mut w = connection.writer.lock().await;
-        if let Err(e) = w.send(Message::Text(json.clone())).await {
-            eprintln!("Failed to send clipboard to {}: {}", ip, e);
-        }
+/// Set how often clipboard polling checks for changes, in milliseconds (default 500).
+/// Must be between 100ms and 60s; takes effect on the next check without restarting polling.
+#[tauri::command]
+pub async fn set_clipboard_poll_interval(ms: u64) -> Result<(), TransferError> {
+    if !(MIN_POLL_INTERVAL_MS..=MAX_POLL_INTERVAL_MS).contains(&ms) {
+        return Err(TransferError::InvalidInput(format!(
+            "Poll interval must be between {} and {} ms",
+            MIN_POLL_INTERVAL_MS, MAX_POLL_INTERVAL_MS
+        )));
     }
+    POLL_INTERVAL_MS.store(ms, Ordering::Relaxed);
+    Ok(())
+}
 
+/// Never broadcast clipboard content matching any of these regular expressions (replaces the current list).
+/// Content a password manager marks as concealed is skipped as well where the platform exposes the hint.
+#[tauri::command]
+pub async fn set_clipboard_exclude_patterns(patterns: Vec<String>) -> Result<(), TransferError> {
+    let patterns = patterns.iter()
+        .map(|p| regex::Regex::new(p).map_err(|e| TransferError::InvalidInput(format!("Invalid exclude pattern {}: {}", p, e))))
+        .collect::<Result<Vec<_>, _>>()?;
+    *EXCLUDE_PATTERNS.lock().unwrap() = patterns;
     Ok(())
 }
 
+/// Set the largest clipboard text broadcast to peers, in bytes (default 1MB). Larger content is skipped.
 #[tauri::command]
-pub async fn start_clipboard_polling(window: Window, app: AppHandle) -> Result<(), String> {
+pub async fn set_clipboard_max_size(bytes: usize) -> Result<(), TransferError> {
+    CLIPBOARD_MAX_SIZE.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Cancel the broadcast with this id (from `clipboard-broadcast-started`), or every broadcast in progress when None.
+#[tauri::command]
+pub async fn cancel_clipboard_broadcast(broadcast_id: Option<String>) -> Result<(), TransferError> {
+    match broadcast_id {
+        Some(id) => crate::network::transfer::cancel_file_sending(id),
+        None => {
+            let active = ACTIVE_BROADCASTS.lock().unwrap().clone();
+            for id in active {
+                let _ = crate::network::transfer::cancel_file_sending(id);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn start_clipboard_polling(window: Window, app: AppHandle) -> Result<(), TransferError> {
     if CLIPBOARD_POLLING_RUNNING.load(Ordering::Relaxed) {
         return Ok(());
     }
@@ -476,25 +1052,22 @@ pub async fn start_clipboard_polling(window: Window, app: AppHandle) -> Result<(
         let mut last_content_hash = String::new();
 
         while CLIPBOARD_POLLING_RUNNING.load(Ordering::Relaxed) {
-            tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS)).await;
+            tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS.load(Ordering::Relaxed))).await;
 
             // Get current clipboard content
             #[cfg(not(target_os = "android"))]
-            let content_result = get_clipboard_content();
+            let content_result = read_local_clipboard();
 
             #[cfg(target_os = "android")]
-            let content_result = storage.get_clipboard();
+            let content_result = storage.get_clipboard()
+                .map(|text| Some(text).filter(|t| !t.is_empty()).map(LocalClipboard::Text));
 
-            let content = match content_result {
-                Ok(c) => c,
-                Err(_) => continue,
+            let local = match content_result {
+                Ok(Some(local)) => local,
+                _ => continue,
             };
 
-            if content.is_empty() {
-                continue;
-            }
-
-            let current_hash = compute_hash(&content);
+            let current_hash = local.hash();
 
             // Skip if same as last known hash (to prevent echo)
             if let Ok(last_hash) = LAST_CLIPBOARD_HASH.lock() {
@@ -510,40 +1083,44 @@ pub async fn start_clipboard_polling(window: Window, app: AppHandle) -> Result<(
 
             last_content_hash = current_hash.clone();
 
+            if !clipboard_mode().sends()
+                || !within_size_limit(&window, local.text().len())
+                || is_excluded(&window, &local.text())
+            {
+                continue;
+            }
+
+            let message = match local.into_message(current_hash.clone()) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Failed to prepare clipboard: {}", e);
+                    continue;
+                }
+            };
+            if !image_within_size_limit(&window, &message) {
+                continue;
+            }
+
             // Update last hash
             if let Ok(mut hash) = LAST_CLIPBOARD_HASH.lock() {
-                *hash = current_hash.clone();
+                *hash = current_hash;
             }
 
             // Broadcast to all connections
-            let local_ip = crate::network::transfer::get_local_ip()
-                .unwrap_or_else(|_| "unknown".to_string());
-
-            let message = ClipboardMessage {
-                content: content.clone(),
-                from_ip: local_ip,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
-                hash: current_hash,
-            };
 
             let json = match serde_json::to_string(&message) {
                 Ok(j) => j,
                 Err(_) => continue,
             };
 
-            let conns = connections.lock().await;
-            for (ip, connection) in conns.iter() {
-                let mut w = connection.writer.lock().await;
-                if let Err(e) = w.send(Message::Text(json.clone())).await {
-                    eprintln!("Failed to broadcast clipboard to {}: {}", ip, e);
-                }
+            if !broadcast_clipboard(&window, &connections, &json, message.kind).await {
+                window_events::emit(&window, "clipboard-broadcast-cancelled", &message.hash);
+                continue;
             }
 
             // Emit local event for UI update
-            let _ = window.emit("clipboard-sent", &message);
+            record_history("sent", &message);
+            window_events::emit(&window, "clipboard-sent", &message);
         }
 
         println!("Clipboard polling stopped");
@@ -553,7 +1130,86 @@ pub async fn start_clipboard_polling(window: Window, app: AppHandle) -> Result<(
 }
 
 #[tauri::command]
-pub async fn stop_clipboard_polling() -> Result<(), String> {
+pub async fn stop_clipboard_polling() -> Result<(), TransferError> {
     CLIPBOARD_POLLING_RUNNING.store(false, Ordering::Relaxed);
     Ok(())
 }
+
+/// Report the actual clipboard sync state so a reloaded UI doesn't have to guess.
+/// Polling broadcasts local changes (send); the server or any open connection delivers remote ones (receive).
+#[tauri::command]
+pub async fn clipboard_sync_status(app: AppHandle) -> Result<ClipboardSyncStatus, TransferError> {
+    let connections: ClipboardConnections = app.state::<ClipboardConnections>().inner().clone();
+    let connections = connections.lock().await.len();
+
+    let polling = CLIPBOARD_POLLING_RUNNING.load(Ordering::Relaxed);
+    let server_running = CLIPBOARD_SERVER_RUNNING.load(Ordering::Relaxed);
+    let mode = clipboard_mode();
+    let sending = polling && mode.sends();
+    let receiving = (server_running || connections > 0) && mode.receives();
+
+    let direction = match (sending, receiving) {
+        (true, true) => SyncDirection::Both,
+        (true, false) => SyncDirection::SendOnly,
+        (false, true) => SyncDirection::ReceiveOnly,
+        (false, false) => SyncDirection::Off,
+    };
+
+    Ok(ClipboardSyncStatus {
+        polling,
+        server_running,
+        connections,
+        direction,
+    })
+}
+
+/// List the last clipboard values sent or received (up to 20), newest first.
+#[tauri::command]
+pub async fn get_clipboard_history() -> Result<Vec<ClipboardHistoryEntry>, TransferError> {
+    Ok(CLIPBOARD_HISTORY.lock().unwrap().iter().cloned().collect())
+}
+
+/// Put a history entry (index as returned by get_clipboard_history) back on the system clipboard.
+/// The echo hash is updated so polling does not broadcast it again.
+#[tauri::command]
+pub async fn apply_clipboard_history(index: usize, app: AppHandle) -> Result<(), TransferError> {
+    let message = CLIPBOARD_HISTORY.lock().unwrap()
+        .get(index)
+        .map(|entry| entry.message.clone())
+        .ok_or_else(|| TransferError::NotFound(format!("Clipboard history entry {} not found", index)))?;
+
+    if message.kind == ClipboardKind::Text && message.html.is_none() {
+        return set_system_clipboard(message.content, app).await;
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        if let Ok(mut hash) = LAST_CLIPBOARD_HASH.lock() {
+            *hash = message.hash.clone();
+        }
+        apply_clipboard_message(&message).map_err(TransferError::Io)
+    }
+    #[cfg(target_os = "android")]
+    {
+        Err(TransferError::Unsupported("Only text can be restored on this device".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_urls_are_sent_as_uri_lists() {
+        for url in ["https://example.com/a?b=c", "  http://10.0.0.2:8080/\n", "ftp://host/file", "file:///tmp/a.txt"] {
+            assert_eq!(sniff_text_type(url), "text/uri-list", "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn other_text_is_plain() {
+        for text in ["", "hello", "see https://example.com", "https://a.com https://b.com", "mailto:someone@example.com", "www.example.com"] {
+            assert_eq!(sniff_text_type(text), "text/plain", "{:?}", text);
+        }
+    }
+}
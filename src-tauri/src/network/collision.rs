@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+
+/// 接收的文件与保存目录中已有文件同名时的处理方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// 覆盖已有文件
+    Overwrite,
+    /// 在扩展名前加上 ` (1)`、` (2)` 等保存为新文件
+    #[default]
+    Rename,
+    /// 保留已有文件，丢弃收到的文件
+    Skip,
+}
+
+static POLICY: Mutex<CollisionPolicy> = Mutex::new(CollisionPolicy::Rename);
+
+// 加序号时最多尝试的次数
+const MAX_RENAME_ATTEMPTS: u32 = 10_000;
+
+/// `file-renamed` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct FileRenamed {
+    /// 发送端给出的文件名
+    pub original: String,
+    /// 实际保存的文件名
+    pub renamed: String,
+    pub peer_ip: String,
+}
+
+pub(crate) fn policy() -> CollisionPolicy {
+    *POLICY.lock().unwrap()
+}
+
+pub(crate) fn set_policy(policy: CollisionPolicy) {
+    *POLICY.lock().unwrap() = policy;
+}
+
+/// 第 n 个候选文件名：`photo.jpg` -> `photo (n).jpg`，没有扩展名时直接追加
+pub(crate) fn numbered_name(name: &str, n: u32) -> String {
+    let path = Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!("{} ({}).{}", stem.to_string_lossy(), n, ext.to_string_lossy()),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// 依次尝试候选文件名，返回第一个 taken 为 false 的
+pub(crate) fn first_free_name(name: &str, mut taken: impl FnMut(&str) -> bool) -> Option<String> {
+    (1..=MAX_RENAME_ATTEMPTS)
+        .map(|n| numbered_name(name, n))
+        .find(|candidate| !taken(candidate))
+}
+
+/// 桌面端/非 SAF：同目录下第一个不存在的候选路径
+pub(crate) fn unique_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let free = first_free_name(&name, |candidate| path.with_file_name(candidate).exists())?;
+    Some(path.with_file_name(free))
+}
+
+/// 桌面端/非 SAF：按同名文件策略决定最终保存路径，None 表示跳过
+pub(crate) fn resolve_path(path: &Path) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path.to_path_buf());
+    }
+    match policy() {
+        CollisionPolicy::Overwrite => Some(path.to_path_buf()),
+        CollisionPolicy::Rename => unique_path(path),
+        CollisionPolicy::Skip => None,
+    }
+}
+
+/// Android SAF：元数据到达时按同名文件策略决定最终文件名（覆盖时留到完成后再删除已有文档），None 表示跳过
+#[cfg(target_os = "android")]
+pub(crate) fn plan_document_name(storage: &AndroidStorage, dir_uri: &str, name: &str) -> Option<String> {
+    if policy() == CollisionPolicy::Overwrite {
+        return Some(name.to_string());
+    }
+    resolve_document_name(storage, dir_uri, name)
+}
+
+/// Android SAF：按同名文件策略决定目录中的最终文件名（覆盖时先删除已有文档），None 表示跳过
+#[cfg(target_os = "android")]
+pub(crate) fn resolve_document_name(storage: &AndroidStorage, dir_uri: &str, name: &str) -> Option<String> {
+    let existing = match storage.find_document(dir_uri.to_string(), name.to_string()) {
+        Ok(Some(uri)) => uri,
+        Ok(None) => return Some(name.to_string()),
+        Err(e) => {
+            eprintln!("Failed to check for existing file {}: {}", name, e);
+            return Some(name.to_string());
+        }
+    };
+    match policy() {
+        CollisionPolicy::Overwrite => {
+            if let Err(e) = storage.delete_document(existing) {
+                eprintln!("Failed to delete existing file {}: {}", name, e);
+            }
+            Some(name.to_string())
+        }
+        CollisionPolicy::Rename => first_free_name(name, |candidate| {
+            storage.find_document(dir_uri.to_string(), candidate.to_string()).ok().flatten().is_some()
+        }),
+        CollisionPolicy::Skip => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_name_goes_before_the_extension() {
+        assert_eq!(numbered_name("photo.jpg", 1), "photo (1).jpg");
+        assert_eq!(numbered_name("archive.tar.gz", 2), "archive.tar (2).gz");
+        assert_eq!(numbered_name("README", 3), "README (3)");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc (1)");
+    }
+
+    #[test]
+    fn first_free_name_skips_taken_candidates() {
+        let taken = ["notes (1).txt", "notes (2).txt"];
+        assert_eq!(
+            first_free_name("notes.txt", |candidate| taken.contains(&candidate)).as_deref(),
+            Some("notes (3).txt"),
+        );
+        assert_eq!(first_free_name("notes.txt", |_| false).as_deref(), Some("notes (1).txt"));
+        assert_eq!(first_free_name("notes.txt", |_| true), None);
+    }
+}
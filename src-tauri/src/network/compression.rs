@@ -0,0 +1,219 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io::{Read, Write};
+use tokio_tungstenite::tungstenite::handshake::client::Request as ClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+
+// Chat and clipboard negotiate RFC 7692 permessage-deflate per connection through
+// Sec-WebSocket-Extensions. Both sides compress every message independently (no context
+// takeover) with the default 15-bit window. tungstenite 0.21 rejects frames with RSV1 set,
+// so compressed messages travel as binary frames instead of RSV1-flagged text frames.
+const EXTENSIONS_HEADER: &str = "sec-websocket-extensions";
+const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+const DEFLATE_PARAMS: &str = "permessage-deflate; client_no_context_takeover; server_no_context_takeover";
+// Sync-flush trailer that RFC 7692 strips from each compressed message
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The permessage-deflate offers in an extensions header, each as its list of parameters.
+fn deflate_offers(headers: &HeaderMap) -> Vec<Vec<String>> {
+    headers
+        .get_all(EXTENSIONS_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|extension| {
+            let mut parts = extension.split(';').map(|p| p.trim());
+            (parts.next()? == PERMESSAGE_DEFLATE)
+                .then(|| parts.filter(|p| !p.is_empty()).map(|p| p.to_ascii_lowercase()).collect())
+        })
+        .collect()
+}
+
+/// Whether an offer can be served with independent messages and a 15-bit window.
+fn acceptable(params: &[String]) -> bool {
+    params.iter().all(|param| {
+        let (name, value) = param.split_once('=').map_or((param.as_str(), None), |(n, v)| (n.trim(), Some(v.trim().trim_matches('"'))));
+        match name {
+            "client_no_context_takeover" | "server_no_context_takeover" => value.is_none(),
+            // The client may receive with a smaller window only if we compress with one
+            "server_max_window_bits" => value == Some("15"),
+            "client_max_window_bits" => value.is_none_or(|bits| bits.parse::<u8>().is_ok_and(|bits| (8..=15).contains(&bits))),
+            _ => false,
+        }
+    })
+}
+
+/// Client side: offer permessage-deflate in the handshake request when `compress` is set for this connection.
+pub(crate) fn offer(request: &mut ClientRequest, compress: bool) {
+    if compress {
+        request.headers_mut().append(EXTENSIONS_HEADER, HeaderValue::from_static(DEFLATE_PARAMS));
+    }
+}
+
+/// Client side: whether the server accepted permessage-deflate for this connection.
+pub(crate) fn accepted(response: &tokio_tungstenite::tungstenite::handshake::client::Response) -> bool {
+    !deflate_offers(response.headers()).is_empty()
+}
+
+/// Server side: accept the client's permessage-deflate offer if it has one we can serve.
+/// Returns whether it was negotiated.
+pub(crate) fn negotiate(request: &Request, response: &mut Response) -> bool {
+    if !deflate_offers(request.headers()).iter().any(|params| acceptable(params)) {
+        return false;
+    }
+    response.headers_mut().insert(EXTENSIONS_HEADER, HeaderValue::from_static(DEFLATE_PARAMS));
+    true
+}
+
+/// Compress one message as RFC 7692 describes: raw deflate, sync flushed, trailer removed.
+fn deflate(data: &[u8]) -> Result<Vec<u8>, flate2::CompressError> {
+    let mut compress = Compress::new(Compression::fast(), false);
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity().max(64));
+        }
+        let consumed = compress.total_in() as usize;
+        compress.compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)?;
+        // The flush is complete once all input is consumed and output space is left over
+        if compress.total_in() as usize == data.len() && out.len() < out.capacity() {
+            break;
+        }
+    }
+    if out.ends_with(&DEFLATE_TAIL) {
+        out.truncate(out.len() - DEFLATE_TAIL.len());
+    }
+    Ok(out)
+}
+
+/// Build an outgoing text message, deflated into a binary frame on compressed connections.
+pub(crate) fn text_message(text: String, compressed: bool) -> Message {
+    if !compressed {
+        return Message::Text(text);
+    }
+    match deflate(text.as_bytes()) {
+        Ok(data) => Message::Binary(data),
+        Err(e) => {
+            eprintln!("Failed to compress message, sending uncompressed: {}", e);
+            Message::Text(text)
+        }
+    }
+}
+
+/// Inflate one RFC 7692 message, or None if it inflates past `limit` bytes.
+fn inflate(data: &[u8], limit: usize) -> Result<Option<Vec<u8>>, flate2::DecompressError> {
+    let input = [data, &DEFLATE_TAIL].concat();
+    let mut decompress = Decompress::new(false);
+    let mut out = Vec::with_capacity(data.len().saturating_mul(4).min(limit.saturating_add(1)).max(64));
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve((limit.saturating_add(1) - out.len()).min(out.len()).max(64));
+        }
+        let (consumed, produced) = (decompress.total_in() as usize, out.len());
+        let status = decompress.decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)?;
+        if out.len() > limit {
+            return Ok(None);
+        }
+        let done = decompress.total_in() as usize == input.len() && out.len() < out.capacity();
+        let stalled = decompress.total_in() as usize == consumed && out.len() == produced;
+        if status == Status::StreamEnd || done || stalled {
+            return Ok(Some(out));
+        }
+    }
+}
+
+/// Turn an incoming deflated binary frame back into a text message; other messages pass through.
+/// A frame that inflates past `limit` bytes comes back as a Close, so the caller drops the connection.
+pub(crate) fn inflate_message(msg: Message, compressed: bool, limit: usize) -> Message {
+    match msg {
+        Message::Binary(data) if compressed => match inflate(&data, limit) {
+            Ok(Some(text)) => match String::from_utf8(text) {
+                Ok(text) => Message::Text(text),
+                Err(e) => {
+                    eprintln!("Decompressed message is not valid UTF-8: {}", e);
+                    Message::Binary(data)
+                }
+            },
+            Ok(None) => {
+                eprintln!("Compressed message inflates past the {} byte limit, closing the connection", limit);
+                Message::Close(None)
+            }
+            Err(e) => {
+                eprintln!("Failed to decompress message: {}", e);
+                Message::Binary(data)
+            }
+        },
+        other => other,
+    }
+}
+
+/// Gzip one file chunk for transfers sent with `compress`.
+pub(crate) fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::fast());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Decompress a chunk produced by `gzip`, reading at most `limit + 1` bytes so the caller can
+/// tell a chunk that expands past `limit` apart from one that fits.
+pub(crate) fn gunzip(data: &[u8], limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len().saturating_mul(2).min(usize::try_from(limit).unwrap_or(usize::MAX)));
+    GzDecoder::new(data).take(limit.saturating_add(1)).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    fn request_offering(extensions: &str) -> Request {
+        Request::builder().header(EXTENSIONS_HEADER, extensions).body(()).unwrap()
+    }
+
+    #[test]
+    fn negotiates_only_offers_it_can_serve() {
+        let mut offered = "ws://10.0.0.2:7880".into_client_request().unwrap();
+        offer(&mut offered, true);
+        let mut response = Response::new(());
+        assert!(negotiate(&offered, &mut response));
+        let client_response = response.map(|_| None::<Vec<u8>>);
+        assert!(accepted(&client_response));
+
+        // No offer, or one asking for a smaller server window or an unknown parameter
+        for extensions in ["x-webkit-deflate-frame", "permessage-deflate; server_max_window_bits=10", "permessage-deflate; foo"] {
+            let mut response = Response::new(());
+            assert!(!negotiate(&request_offering(extensions), &mut response), "{}", extensions);
+            assert!(!response.headers().contains_key(EXTENSIONS_HEADER));
+        }
+        // Browser-style offer with a fallback
+        let mut response = Response::new(());
+        assert!(negotiate(&request_offering("permessage-deflate; client_max_window_bits, permessage-deflate"), &mut response));
+
+        let mut plain = "ws://10.0.0.2:7880".into_client_request().unwrap();
+        offer(&mut plain, false);
+        assert!(!plain.headers().contains_key(EXTENSIONS_HEADER));
+    }
+
+    #[test]
+    fn compressed_text_round_trips() {
+        let text = "fn main() { println!(\"hello\"); }\n".repeat(200);
+        let msg = text_message(text.clone(), true);
+        let Message::Binary(data) = &msg else { panic!("expected a binary frame") };
+        assert!(data.len() < text.len() / 10);
+        assert!(!data.ends_with(&DEFLATE_TAIL));
+        assert_eq!(inflate_message(msg, true, text.len()), Message::Text(text.clone()));
+
+        assert_eq!(text_message(text.clone(), false), Message::Text(text.clone()));
+        assert_eq!(inflate_message(text_message(String::new(), true), true, 0), Message::Text(String::new()));
+    }
+
+    #[test]
+    fn message_inflating_past_the_limit_closes() {
+        let text = "a".repeat(10_000);
+        assert_eq!(inflate_message(text_message(text, true), true, 9_999), Message::Close(None));
+    }
+}
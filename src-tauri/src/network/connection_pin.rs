@@ -0,0 +1,128 @@
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+use crate::network::error::TransferError;
+
+// PIN 不匹配时关闭连接使用的 Close code
+pub(crate) const PIN_REJECTED_CODE: u16 = 4006;
+// 服务端等待连接方发送 PIN 的时间
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+const PIN_LEN: usize = 6;
+
+// 传输、聊天、剪贴板服务共用的连接 PIN，None 表示不校验
+static CONNECTION_PIN: Mutex<Option<String>> = Mutex::new(None);
+
+/// 连接方建立连接后发送的第一条消息：`{"type":"auth","pin":"123456"}`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AuthMessage {
+    Auth { pin: String },
+}
+
+/// 逐字节比较全部内容，耗时与不匹配的位置无关
+fn pins_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 连接方：设置了 PIN 时需在连接后首先发送的消息
+pub(crate) fn auth_message() -> Option<Message> {
+    let pin = CONNECTION_PIN.lock().unwrap().clone()?;
+    serde_json::to_string(&AuthMessage::Auth { pin }).ok().map(Message::Text)
+}
+
+#[tauri::command]
+/// 前端直接建立的 WebSocket 连接（桌面端发送文件）需要首先发送的 PIN 消息，未设置 PIN 时为 None
+pub fn get_connection_auth_frame() -> Option<String> {
+    match auth_message()? {
+        Message::Text(text) => Some(text),
+        _ => None,
+    }
+}
+
+/// 未设置 PIN 的服务端收到的 PIN 消息，直接忽略
+pub(crate) fn is_auth_message(text: &str) -> bool {
+    serde_json::from_str::<AuthMessage>(text).is_ok()
+}
+
+/// 连接方：对端是否因 PIN 不匹配关闭了连接
+pub(crate) fn is_rejection(frame: &Option<CloseFrame>) -> bool {
+    frame.as_ref().is_some_and(|frame| u16::from(frame.code) == PIN_REJECTED_CODE)
+}
+
+/// 服务端：设置了 PIN 时读取连接上的第一条消息并校验，不匹配或超时未收到时以 4006 关闭。
+/// 返回是否可以继续处理该连接
+pub(crate) async fn authorize<R, W>(read: &mut R, write: &mut W, peer_ip: &str) -> bool
+where
+    R: Stream<Item = Result<Message, WsError>> + Unpin,
+    W: Sink<Message> + Unpin,
+{
+    let Some(expected) = CONNECTION_PIN.lock().unwrap().clone() else {
+        return true;
+    };
+    let pin = match tokio::time::timeout(AUTH_TIMEOUT, read.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => match serde_json::from_str::<AuthMessage>(&text) {
+            Ok(AuthMessage::Auth { pin }) => Some(pin),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+    if pin.is_some_and(|pin| pins_match(&pin, &expected)) {
+        return true;
+    }
+
+    println!("Rejecting connection from {}: wrong or missing PIN", peer_ip);
+    let _ = write.send(Message::Close(Some(CloseFrame {
+        code: PIN_REJECTED_CODE.into(),
+        reason: "Invalid PIN".into(),
+    }))).await;
+    false
+}
+
+#[tauri::command]
+/// 设置传输、聊天、剪贴板服务共用的 6 位数字连接 PIN，空字符串表示不再校验。
+/// 两端需设置相同的 PIN，连接方发送的 PIN 不匹配时服务端以 Close code 4006 关闭连接
+pub fn set_connection_pin(pin: String) -> Result<(), TransferError> {
+    let pin = pin.trim();
+    if pin.is_empty() {
+        *CONNECTION_PIN.lock().unwrap() = None;
+        return Ok(());
+    }
+    if pin.len() != PIN_LEN || !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(TransferError::InvalidInput(format!("PIN must be {} digits", PIN_LEN)));
+    }
+    *CONNECTION_PIN.lock().unwrap() = Some(pin.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_match_compares_whole_pin() {
+        assert!(pins_match("123456", "123456"));
+        assert!(!pins_match("123456", "123457"));
+        assert!(!pins_match("123456", "12345"));
+        assert!(!pins_match("", "123456"));
+    }
+
+    #[test]
+    fn set_connection_pin_validates_input() {
+        assert!(set_connection_pin("12345".into()).is_err());
+        assert!(set_connection_pin("1234567".into()).is_err());
+        assert!(set_connection_pin("12a456".into()).is_err());
+        assert!(CONNECTION_PIN.lock().unwrap().is_none());
+
+        set_connection_pin(" 123456 ".into()).unwrap();
+        assert_eq!(CONNECTION_PIN.lock().unwrap().as_deref(), Some("123456"));
+        assert!(get_connection_auth_frame().is_some_and(|frame| is_auth_message(&frame)));
+
+        set_connection_pin(String::new()).unwrap();
+        assert!(CONNECTION_PIN.lock().unwrap().is_none());
+        assert!(get_connection_auth_frame().is_none());
+    }
+}
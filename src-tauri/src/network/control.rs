@@ -0,0 +1,254 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, Window};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_async, WebSocketStream};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use crate::network::connection_pin;
+use crate::network::error::TransferError;
+use crate::network::ports::{self, Service};
+use crate::network::window_events;
+
+/// An open control channel; outgoing frames are queued to the channel's writer task
+pub struct ControlChannel {
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+pub type ControlChannels = Arc<Mutex<HashMap<String, ControlChannel>>>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ControlEvent {
+    pub channel_id: String,
+    pub ip: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ControlReceived {
+    pub channel_id: String,
+    pub ip: String,
+    /// Parsed JSON when the peer sent valid JSON, otherwise the raw text
+    pub message: serde_json::Value,
+}
+
+static CONTROL_SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+// Accept loop task, kept so stopping or a forced reset can tear down the listener
+static CONTROL_SERVER_TASK: std::sync::Mutex<Option<tokio::task::AbortHandle>> = std::sync::Mutex::new(None);
+static NEXT_CHANNEL_ID: AtomicU64 = AtomicU64::new(1);
+
+fn new_channel_id() -> String {
+    format!("ctl-{}", NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Register a connected WebSocket as a control channel and spawn tasks pumping it until either side closes.
+async fn attach_channel<S>(
+    mut writer: SplitSink<WebSocketStream<S>, Message>,
+    mut reader: SplitStream<WebSocketStream<S>>,
+    channel_id: String,
+    ip: String,
+    channels: ControlChannels,
+    window: Window,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+
+    channels.lock().await.insert(channel_id.clone(), ControlChannel {
+        sender: tx.clone(),
+    });
+
+    // Writer task: exits once every sender is dropped or a close frame has been sent
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let is_close = matches!(msg, Message::Close(_));
+            if writer.send(msg).await.is_err() || is_close {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some(msg_result) = reader.next().await {
+            match msg_result {
+                // A PIN sent to a server without one configured
+                Ok(Message::Text(text)) if connection_pin::is_auth_message(&text) => {}
+                Ok(Message::Text(text)) => {
+                    let message = serde_json::from_str(&text)
+                        .unwrap_or(serde_json::Value::String(text));
+                    window_events::emit(&window, "control-received", ControlReceived {
+                        channel_id: channel_id.clone(),
+                        ip: ip.clone(),
+                        message,
+                    });
+                }
+                Ok(Message::Ping(data)) => {
+                    let _ = tx.send(Message::Pong(data));
+                }
+                Ok(Message::Close(_)) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Control channel {} error: {}", channel_id, e);
+                    break;
+                }
+            }
+        }
+
+        channels.lock().await.remove(&channel_id);
+        window_events::emit(&window, "control-closed", ControlEvent { channel_id, ip });
+    });
+}
+
+/// Listen for inbound control channels from peers.
+#[tauri::command]
+pub async fn start_control_server(port: Option<u16>, window: Window, app: AppHandle) -> Result<(), TransferError> {
+    if CONTROL_SERVER_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let port = port.unwrap_or_else(|| ports::local(Service::Control));
+    let listener = match TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            CONTROL_SERVER_RUNNING.store(false, Ordering::SeqCst);
+            return Err(TransferError::Io(format!("Failed to bind control server: {}", e)));
+        }
+    };
+    // Recorded only once bound, so a failed start keeps the previous port
+    ports::set_local(Service::Control, port);
+    spawn_accept_loop(listener, window, app);
+    Ok(())
+}
+
+fn spawn_accept_loop(listener: TcpListener, window: Window, app: AppHandle) {
+    println!("Control server listening on port {}", ports::local(Service::Control));
+
+    let channels: ControlChannels = app.state::<ControlChannels>().inner().clone();
+
+    let task = tokio::spawn(async move {
+        while CONTROL_SERVER_RUNNING.load(Ordering::SeqCst) {
+            let (stream, addr) = match listener.accept().await {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Failed to accept control connection: {}", e);
+                    continue;
+                }
+            };
+
+            let channels = channels.clone();
+            let window = window.clone();
+            tokio::spawn(async move {
+                let ws_stream = match accept_async(stream).await {
+                    Ok(ws) => ws,
+                    Err(e) => {
+                        eprintln!("Control handshake failed: {}", e);
+                        return;
+                    }
+                };
+
+                let ip = addr.ip().to_string();
+                let (mut writer, mut reader) = ws_stream.split();
+                if !connection_pin::authorize(&mut reader, &mut writer, &ip).await {
+                    return;
+                }
+
+                let channel_id = new_channel_id();
+                window_events::emit(&window, "control-opened", ControlEvent {
+                    channel_id: channel_id.clone(),
+                    ip: ip.clone(),
+                });
+                attach_channel(writer, reader, channel_id, ip, channels, window).await;
+            });
+        }
+    });
+    *CONTROL_SERVER_TASK.lock().unwrap() = Some(task.abort_handle());
+}
+
+/// Serve control channels on `listener`, already bound to the newly configured port. Open channels stay up.
+pub(crate) fn rebind(window: Window, app: AppHandle, listener: std::net::TcpListener) -> Result<(), TransferError> {
+    if !CONTROL_SERVER_RUNNING.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let listener = TcpListener::from_std(listener)
+        .map_err(|e| TransferError::Io(format!("Failed to take over listener: {}", e)))?;
+    if let Some(task) = CONTROL_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    spawn_accept_loop(listener, window, app);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_control_server() -> Result<(), TransferError> {
+    CONTROL_SERVER_RUNNING.store(false, Ordering::SeqCst);
+    // Abort the accept loop so the listener is released now, not on the next inbound connection
+    if let Some(task) = CONTROL_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    Ok(())
+}
+
+/// Open a control channel to a peer and return its channel ID.
+#[tauri::command]
+pub async fn open_control_channel(ip: String, port: Option<u16>, window: Window, app: AppHandle) -> Result<String, TransferError> {
+    let ip = crate::network::transfer::resolve_target(&ip)?;
+    let url = format!("ws://{}:{}", ip, port.unwrap_or_else(|| ports::peer(&ip, Service::Control)));
+
+    let (ws_stream, _) = tokio_tungstenite::connect_async(&url)
+        .await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to open control channel: {}", e), None))?;
+    let (mut writer, reader) = ws_stream.split();
+    if let Some(auth) = connection_pin::auth_message() {
+        writer.send(auth).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to open control channel: {}", e), None))?;
+    }
+
+    let channels: ControlChannels = app.state::<ControlChannels>().inner().clone();
+    let channel_id = new_channel_id();
+
+    attach_channel(writer, reader, channel_id.clone(), ip, channels, window).await;
+
+    Ok(channel_id)
+}
+
+/// Send a JSON control message over an open channel.
+#[tauri::command]
+pub async fn send_control(channel_id: String, json: serde_json::Value, app: AppHandle) -> Result<(), TransferError> {
+    let channels: ControlChannels = app.state::<ControlChannels>().inner().clone();
+    let text = serde_json::to_string(&json)
+        .map_err(|e| TransferError::InvalidInput(format!("Failed to serialize control message: {}", e)))?;
+
+    let conns = channels.lock().await;
+    let channel = conns.get(&channel_id)
+        .ok_or_else(|| TransferError::NotFound(format!("No control channel {}", channel_id)))?;
+    channel.sender.send(Message::Text(text))
+        .map_err(|_| TransferError::ConnectionFailed(format!("Control channel {} is closed", channel_id), None))
+}
+
+#[tauri::command]
+pub async fn close_control_channel(channel_id: String, app: AppHandle) -> Result<(), TransferError> {
+    let channels: ControlChannels = app.state::<ControlChannels>().inner().clone();
+
+    if let Some(channel) = channels.lock().await.get(&channel_id) {
+        let _ = channel.sender.send(Message::Close(None));
+    }
+
+    Ok(())
+}
+
+/// Forcibly stop the control server and close every open channel.
+pub(crate) async fn force_reset(app: &AppHandle) {
+    CONTROL_SERVER_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(task) = CONTROL_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+
+    let channels: ControlChannels = app.state::<ControlChannels>().inner().clone();
+    for (_id, channel) in channels.lock().await.drain() {
+        let _ = channel.sender.send(Message::Close(None));
+    }
+}
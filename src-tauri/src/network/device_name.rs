@@ -0,0 +1,86 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::network::error::TransferError;
+
+const DEVICE_NAME_FILE: &str = "device_name.json";
+const DEVICE_ID_FILE: &str = "device_id.json";
+const DEVICE_ID_BYTES: usize = 16;
+
+// 本机设备 ID，首次使用时从文件加载或生成
+static DEVICE_ID: Mutex<Option<String>> = Mutex::new(None);
+
+// 设备名最长字节数（紧凑发现格式的字段长度前缀为 u8）
+const MAX_NAME_LEN: usize = 255;
+
+#[derive(Serialize, Deserialize, Default)]
+struct DeviceName {
+    /// 用户设置的设备名，未设置时使用系统主机名
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// 去掉旧版文本发现格式（FILETRANSFER:IP:HOSTNAME:INSTANCE_ID）中的分隔符 `:` 与控制字符，
+/// 否则旧版本接收端按 `:` 拆分时会解析错位
+pub(crate) fn sanitize(name: &str) -> String {
+    let name: String = name.chars()
+        .filter(|c| *c != ':' && !c.is_control())
+        .collect();
+    let name = name.trim();
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].trim_end().to_string()
+}
+
+/// 用户设置的设备名（设备发现启动时读取），未设置时为 None
+pub(crate) fn custom_name(app: &AppHandle) -> Option<String> {
+    let device_name: DeviceName = crate::config::load_json(app, DEVICE_NAME_FILE);
+    device_name.name.map(|name| sanitize(&name)).filter(|name| !name.is_empty())
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DeviceId {
+    #[serde(default)]
+    id: Option<String>,
+}
+
+/// 本机的设备 ID：首次调用时随机生成并保存，之后不随 IP 或设备名变化，随设备发现广播，
+/// 对端据此识别同一台设备（如按设备保存聊天记录）
+pub(crate) fn device_id(app: &AppHandle) -> Option<String> {
+    let mut cached = DEVICE_ID.lock().unwrap();
+    if cached.is_none() {
+        let stored: DeviceId = crate::config::load_json(app, DEVICE_ID_FILE);
+        *cached = match stored.id {
+            Some(id) => Some(id),
+            None => {
+                let mut bytes = [0u8; DEVICE_ID_BYTES];
+                SystemRandom::new().fill(&mut bytes).ok()?;
+                let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                if let Err(e) = crate::config::save_json(app, DEVICE_ID_FILE, &DeviceId { id: Some(id.clone()) }) {
+                    eprintln!("Failed to save device id: {}", e);
+                }
+                Some(id)
+            }
+        };
+    }
+    cached.clone()
+}
+
+#[tauri::command]
+/// 设置设备发现中广播的设备名，空字符串恢复使用系统主机名。下次启动设备发现时生效
+pub fn set_device_name(name: String, app: AppHandle) -> Result<String, TransferError> {
+    let name = sanitize(&name);
+    let device_name = DeviceName { name: (!name.is_empty()).then(|| name.clone()) };
+    crate::config::save_json(&app, DEVICE_NAME_FILE, &device_name).map_err(TransferError::Io)?;
+    Ok(name)
+}
+
+#[tauri::command]
+/// 查询用户设置的设备名，未设置时为 None
+pub fn get_device_name(app: AppHandle) -> Option<String> {
+    custom_name(&app)
+}
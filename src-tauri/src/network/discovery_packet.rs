@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::network::error::TransferError;
+use crate::network::ports::ServicePorts;
+
+// 文本格式: FILETRANSFER:{"ip":..,"hostname":..,"instance_id":..,"ports":{..}}
+// 旧版本为 FILETRANSFER:IP:HOSTNAME:INSTANCE_ID[:传输端口,聊天端口,剪贴板端口]，
+// 设备名中含 `:` 时无法正确拆分；接收端暂时仍兼容旧格式
+const TEXT_PREFIX: &str = "FILETRANSFER:";
+
+// 紧凑二进制格式: "LT" + 版本字节 + IPv4(4 字节) + 长度前缀(u8)的主机名 + 长度前缀(u8)的实例 ID
+// [+ 传输/聊天/剪贴板端口(各 u16 大端)]。端口附加在末尾，旧版本解析时会忽略
+const BINARY_MAGIC: [u8; 2] = *b"LT";
+const BINARY_VERSION: u8 = 1;
+
+// 默认发送文本格式以兼容旧版本；接收端始终能解析两种格式
+static COMPACT_DISCOVERY: AtomicBool = AtomicBool::new(false);
+
+/// 设备发现广播的内容
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Announce {
+    pub ip: String,
+    pub hostname: String,
+    pub instance_id: String,
+    /// 各服务端口，旧版本的广播中没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<ServicePorts>,
+    /// 启用传输加密时的证书指纹（紧凑格式与旧版本中没有，对端按明文连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_fingerprint: Option<String>,
+    /// 不随 IP 变化的设备 ID（紧凑格式与旧版本中没有）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// IPv6 广播中发送接口的链路本地地址（紧凑格式与 IPv4 广播中没有）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<String>,
+}
+
+/// 追加长度前缀的字段，超过 255 字节时按字符边界截断
+fn push_field(buf: &mut Vec<u8>, value: &str) {
+    let mut end = value.len().min(u8::MAX as usize);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    buf.push(end as u8);
+    buf.extend_from_slice(&value.as_bytes()[..end]);
+}
+
+fn read_field<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    let len = *data.get(*pos)? as usize;
+    let field = data.get(*pos + 1..*pos + 1 + len)?;
+    *pos += 1 + len;
+    std::str::from_utf8(field).ok()
+}
+
+fn encode_binary(announce: &Announce) -> Option<Vec<u8>> {
+    let ip: Ipv4Addr = announce.ip.parse().ok()?;
+    let mut buf = Vec::with_capacity(9 + announce.hostname.len() + announce.instance_id.len());
+    buf.extend_from_slice(&BINARY_MAGIC);
+    buf.push(BINARY_VERSION);
+    buf.extend_from_slice(&ip.octets());
+    push_field(&mut buf, &announce.hostname);
+    push_field(&mut buf, &announce.instance_id);
+    if let Some(ports) = &announce.ports {
+        for port in [ports.transfer, ports.chat, ports.clipboard] {
+            buf.extend_from_slice(&port.to_be_bytes());
+        }
+    }
+    Some(buf)
+}
+
+fn decode_binary(data: &[u8]) -> Option<Announce> {
+    if data.get(..2)? != BINARY_MAGIC {
+        return None;
+    }
+    // 未知版本直接忽略，新版本可在此扩展
+    if *data.get(2)? != BINARY_VERSION {
+        return None;
+    }
+    let octets: [u8; 4] = data.get(3..7)?.try_into().ok()?;
+    let mut pos = 7;
+    let hostname = read_field(data, &mut pos)?.to_string();
+    let instance_id = read_field(data, &mut pos)?.to_string();
+    let port_at = |i: usize| data.get(pos + i * 2..pos + i * 2 + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let ports = match (port_at(0), port_at(1), port_at(2)) {
+        (Some(transfer), Some(chat), Some(clipboard)) => Some(ServicePorts { transfer, chat, clipboard }),
+        _ => None,
+    };
+    Some(Announce {
+        ip: Ipv4Addr::from(octets).to_string(),
+        hostname,
+        instance_id,
+        ports,
+        tls_fingerprint: None,
+        device_id: None,
+        ipv6: None,
+    })
+}
+
+fn parse_ports(value: &str) -> Option<ServicePorts> {
+    let mut ports = value.split(',').map(|p| p.trim().parse::<u16>().ok());
+    Some(ServicePorts {
+        transfer: ports.next()??,
+        chat: ports.next()??,
+        clipboard: ports.next()??,
+    })
+}
+
+fn decode_text(data: &[u8]) -> Option<Announce> {
+    let rest = std::str::from_utf8(data).ok()?.strip_prefix(TEXT_PREFIX)?;
+    if rest.starts_with('{') {
+        return serde_json::from_str(rest).ok();
+    }
+
+    // 旧版本的冒号分隔格式
+    let parts: Vec<&str> = rest.split(':').collect();
+    if parts.len() < 3 {
+        return None;
+    }
+    Some(Announce {
+        ip: parts[0].to_string(),
+        hostname: parts[1].to_string(),
+        instance_id: parts[2].to_string(),
+        ports: parts.get(3).and_then(|p| parse_ports(p)),
+        tls_fingerprint: None,
+        device_id: None,
+        ipv6: None,
+    })
+}
+
+/// 按当前设置编码广播包（紧凑格式无法编码时回退为文本格式）
+pub(crate) fn encode(announce: &Announce) -> Vec<u8> {
+    if COMPACT_DISCOVERY.load(Ordering::Relaxed) {
+        if let Some(buf) = encode_binary(announce) {
+            return buf;
+        }
+    }
+    let json = serde_json::to_string(announce).unwrap_or_default();
+    format!("{}{}", TEXT_PREFIX, json).into_bytes()
+}
+
+/// 解析任一格式的广播包
+pub(crate) fn decode(data: &[u8]) -> Option<Announce> {
+    decode_binary(data).or_else(|| decode_text(data))
+}
+
+#[tauri::command]
+/// 设置设备发现广播是否使用紧凑二进制格式（更小的包，省电；需对端为支持该格式的版本）
+pub async fn set_compact_discovery(enabled: bool) -> Result<(), TransferError> {
+    COMPACT_DISCOVERY.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_compact_discovery() -> Result<bool, TransferError> {
+    Ok(COMPACT_DISCOVERY.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announce(hostname: &str, ports: Option<ServicePorts>) -> Announce {
+        Announce {
+            ip: "192.168.1.20".to_string(),
+            hostname: hostname.to_string(),
+            instance_id: "abc123".to_string(),
+            ports,
+            tls_fingerprint: None,
+            device_id: None,
+            ipv6: None,
+        }
+    }
+
+    #[test]
+    fn binary_round_trip_without_ports() {
+        let original = announce("laptop", None);
+        let buf = encode_binary(&original).unwrap();
+        assert_eq!(decode(&buf), Some(original));
+    }
+
+    #[test]
+    fn binary_round_trip_with_ports() {
+        let original = announce("laptop", Some(ServicePorts { transfer: 7878, chat: 7879, clipboard: 7880 }));
+        let buf = encode_binary(&original).unwrap();
+        assert_eq!(decode(&buf), Some(original));
+    }
+
+    #[test]
+    fn binary_ignores_partial_ports() {
+        let with_ports = announce("laptop", Some(ServicePorts { transfer: 7878, chat: 7879, clipboard: 7880 }));
+        let mut buf = encode_binary(&with_ports).unwrap();
+        buf.truncate(buf.len() - 1);
+        assert_eq!(decode_binary(&buf), Some(announce("laptop", None)));
+    }
+
+    #[test]
+    fn binary_rejects_truncated_packets() {
+        let buf = encode_binary(&announce("laptop", None)).unwrap();
+        for len in 0..buf.len() {
+            assert_eq!(decode_binary(&buf[..len]), None, "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn binary_rejects_length_prefix_past_end() {
+        let mut buf = encode_binary(&announce("laptop", None)).unwrap();
+        // 主机名的长度前缀紧跟在 IPv4 之后
+        buf[7] = u8::MAX;
+        assert_eq!(decode_binary(&buf), None);
+    }
+
+    #[test]
+    fn binary_rejects_unknown_version() {
+        let mut buf = encode_binary(&announce("laptop", None)).unwrap();
+        buf[2] = BINARY_VERSION + 1;
+        assert_eq!(decode_binary(&buf), None);
+    }
+
+    #[test]
+    fn binary_truncates_long_hostname_at_char_boundary() {
+        // 3 字节字符：255 恰好落在字符边界上；前面多一个 ASCII 字符时需回退到 253
+        let hostname = "设".repeat(100);
+        let buf = encode_binary(&announce(&hostname, None)).unwrap();
+        let decoded = decode_binary(&buf).unwrap();
+        assert_eq!(decoded.hostname.len(), 255);
+        assert!(hostname.starts_with(&decoded.hostname));
+
+        let hostname = format!("a{}", "设".repeat(100));
+        let decoded = decode_binary(&encode_binary(&announce(&hostname, None)).unwrap()).unwrap();
+        assert_eq!(decoded.hostname.len(), 253);
+        assert!(hostname.starts_with(&decoded.hostname));
+    }
+
+    #[test]
+    fn binary_requires_ipv4() {
+        let mut v6 = announce("laptop", None);
+        v6.ip = "fe80::1".to_string();
+        assert_eq!(encode_binary(&v6), None);
+    }
+
+    #[test]
+    fn legacy_colon_format() {
+        assert_eq!(
+            decode(b"FILETRANSFER:192.168.1.20:laptop:abc123"),
+            Some(announce("laptop", None)),
+        );
+        assert_eq!(
+            decode(b"FILETRANSFER:192.168.1.20:laptop:abc123:7878,7879,7880"),
+            Some(announce("laptop", Some(ServicePorts { transfer: 7878, chat: 7879, clipboard: 7880 }))),
+        );
+        assert_eq!(decode(b"FILETRANSFER:192.168.1.20:laptop"), None);
+    }
+
+    #[test]
+    fn json_format() {
+        let mut original = announce("my:laptop", Some(ServicePorts { transfer: 7878, chat: 7879, clipboard: 7880 }));
+        original.tls_fingerprint = Some("00ff".to_string());
+        let json = format!("{}{}", TEXT_PREFIX, serde_json::to_string(&original).unwrap());
+        assert_eq!(decode(json.as_bytes()), Some(original));
+
+        let legacy = br#"FILETRANSFER:{"ip":"192.168.1.20","hostname":"laptop","instance_id":"abc123"}"#;
+        assert_eq!(decode(legacy), Some(announce("laptop", None)));
+        assert_eq!(decode(b"FILETRANSFER:{\"ip\":"), None);
+    }
+
+    #[test]
+    fn rejects_unrelated_packets() {
+        assert_eq!(decode(b""), None);
+        assert_eq!(decode(b"HELLO"), None);
+    }
+}
@@ -0,0 +1,55 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::network::error::TransferError;
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+/// `transfer-insufficient-space` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct InsufficientSpace {
+    pub peer_ip: String,
+    pub file_name: String,
+    /// 还需写入的字节数
+    pub required: u64,
+    pub available: u64,
+}
+
+/// 目录所在卷中应用可用的字节数（Android 上可为 SAF tree Uri）
+fn free_space(app: &AppHandle, dir: &str) -> Result<u64, String> {
+    #[cfg(target_os = "android")]
+    {
+        // SAF 目录与普通路径都交给插件按所在存储卷查询
+        return app.state::<AndroidStorage>().get_free_space(dir.to_string());
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = app;
+        // 目录可能尚未创建，按最近的已存在上级目录查询
+        let path = std::path::Path::new(dir).ancestors().find(|p| p.exists())
+            .ok_or_else(|| format!("Path does not exist: {}", dir))?;
+        fs2::available_space(path)
+            .map_err(|e| format!("Failed to query free space for {}: {}", path.display(), e))
+    }
+}
+
+/// 保存目录所在卷的可用空间，无法查询时返回 None（调用方此时不做检查）
+pub(crate) fn available_space(app: &AppHandle, save_dir: &str) -> Option<u64> {
+    match free_space(app, save_dir) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            eprintln!("Failed to query free space for {}: {}", save_dir, e);
+            None
+        }
+    }
+}
+
+#[tauri::command]
+/// 查询目录所在存储卷中应用可用的字节数（Android 上可传 SAF tree Uri），供前端显示保存目录的剩余空间。
+/// 无法查询时返回错误而不是 0
+pub fn get_free_space(path: String, app: AppHandle) -> Result<u64, TransferError> {
+    free_space(&app, &path).map_err(TransferError::Io)
+}
@@ -0,0 +1,93 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use crate::network::transfer::ConnectFailureReason;
+
+/// 传输、聊天、剪贴板命令返回给前端的错误，序列化为 `{"code": "...", "message": "..."}`。
+/// 前端按 code 区分错误类型，message 仅用于显示。各变体在错误产生处构造，不按错误信息推断
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    /// 本端取消
+    Cancelled(String),
+    /// 对端（接收端或发送端）取消
+    CancelledByPeer(String),
+    /// 对端拒绝（包括 PIN 不匹配、元数据或续传被拒）
+    Rejected(String),
+    /// 接收端空间或配额不足
+    InsufficientSpace(String),
+    /// 无法连接对端或连接中断；建立连接失败时带有具体原因（序列化为 reason 字段）
+    ConnectionFailed(String, Option<ConnectFailureReason>),
+    /// 目标设备当前未被发现
+    DeviceNotFound(String),
+    /// 指定的任务、请求、通道等不存在
+    NotFound(String),
+    /// 等待对端回复超时
+    Timeout(String),
+    /// 参数无效或超出限制
+    InvalidInput(String),
+    /// 当前平台不支持
+    Unsupported(String),
+    /// 本地文件读写失败
+    Io(String),
+    Other(String),
+}
+
+impl TransferError {
+    /// 前端据此区分错误类型的代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransferError::Cancelled(_) => "cancelled",
+            TransferError::CancelledByPeer(_) => "cancelled_by_peer",
+            TransferError::Rejected(_) => "rejected",
+            TransferError::InsufficientSpace(_) => "insufficient_space",
+            TransferError::ConnectionFailed(..) => "connection_failed",
+            TransferError::DeviceNotFound(_) => "device_not_found",
+            TransferError::NotFound(_) => "not_found",
+            TransferError::Timeout(_) => "timeout",
+            TransferError::InvalidInput(_) => "invalid_input",
+            TransferError::Unsupported(_) => "unsupported",
+            TransferError::Io(_) => "io",
+            TransferError::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            TransferError::Cancelled(message)
+            | TransferError::CancelledByPeer(message)
+            | TransferError::Rejected(message)
+            | TransferError::InsufficientSpace(message)
+            | TransferError::ConnectionFailed(message, _)
+            | TransferError::DeviceNotFound(message)
+            | TransferError::NotFound(message)
+            | TransferError::Timeout(message)
+            | TransferError::InvalidInput(message)
+            | TransferError::Unsupported(message)
+            | TransferError::Io(message)
+            | TransferError::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl Serialize for TransferError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let reason = match self {
+            TransferError::ConnectionFailed(_, reason) => *reason,
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("TransferError", 2 + reason.is_some() as usize)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        if let Some(reason) = reason {
+            state.serialize_field("reason", &reason)?;
+        }
+        state.end()
+    }
+}
@@ -0,0 +1,114 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use crate::network::error::TransferError;
+
+/// 发送文件夹时的 include / exclude glob 过滤，按 relative_path（含根目录名）匹配
+#[derive(Default)]
+pub(crate) struct FolderFilter {
+    /// None 表示发送全部文件
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    /// exclude 中以 `/**` 结尾的模式去掉该后缀后的形式：匹配的目录整体跳过，不再进入遍历
+    prune: GlobSet,
+}
+
+fn build_set<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<GlobSet, TransferError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| TransferError::InvalidInput(format!("Invalid pattern {:?}: {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| TransferError::InvalidInput(format!("Invalid patterns: {}", e)))
+}
+
+impl FolderFilter {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Result<Self, TransferError> {
+        let include: Vec<&str> = include.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+        let exclude: Vec<&str> = exclude.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+        Ok(FolderFilter {
+            include: if include.is_empty() { None } else { Some(build_set(include)?) },
+            exclude: build_set(exclude.iter().copied())?,
+            prune: build_set(exclude.iter().map(|p| p.strip_suffix("/**").unwrap_or(p)))?,
+        })
+    }
+
+    /// 是否设置了 include（此时不再单独发送空目录）
+    pub(crate) fn has_include(&self) -> bool {
+        self.include.is_some()
+    }
+
+    /// 目录是否整体排除（如 `**/node_modules/**` 匹配的 node_modules 目录）
+    pub(crate) fn skips_dir(&self, relative_path: &str) -> bool {
+        self.prune.is_match(relative_path)
+    }
+
+    /// 已列出的条目是否需要发送（Android 的文件列表无法在遍历时过滤）
+    #[cfg(any(target_os = "android", test))]
+    pub(crate) fn keeps(&self, relative_path: &str, is_dir: bool) -> bool {
+        if is_dir {
+            !self.has_include() && !self.skips_dir(relative_path)
+        } else {
+            self.matches_file(relative_path)
+        }
+    }
+
+    /// 文件是否需要发送
+    pub(crate) fn matches_file(&self, relative_path: &str) -> bool {
+        !self.exclude.is_match(relative_path)
+            && self.include.as_ref().is_none_or(|include| include.is_match(relative_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> FolderFilter {
+        let strings = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        FolderFilter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn exclude_with_trailing_globstar_prunes_the_directory() {
+        let filter = filter(&[], &["**/node_modules/**", "**/*.log"]);
+        assert!(filter.skips_dir("project/node_modules"));
+        assert!(filter.skips_dir("project/app/node_modules"));
+        assert!(!filter.skips_dir("project/src"));
+        // 只排除文件的模式不会跳过目录
+        assert!(!filter.skips_dir("project/logs"));
+
+        assert!(!filter.matches_file("project/node_modules/pkg/index.js"));
+        assert!(!filter.matches_file("project/debug.log"));
+        assert!(filter.matches_file("project/src/main.rs"));
+    }
+
+    #[test]
+    fn include_limits_files_and_drops_empty_dirs() {
+        let filter = filter(&["**/*.rs", " "], &["**/target/**"]);
+        assert!(filter.has_include());
+        assert!(filter.keeps("project/src/main.rs", false));
+        assert!(!filter.keeps("project/README.md", false));
+        // exclude 优先于 include
+        assert!(!filter.keeps("project/target/build.rs", false));
+        // 设置 include 时不单独发送空目录
+        assert!(!filter.keeps("project/empty", true));
+    }
+
+    #[test]
+    fn empty_filter_keeps_everything_except_pruned_dirs() {
+        let everything = filter(&[" "], &[]);
+        assert!(!everything.has_include());
+        assert!(everything.keeps("project/any/file.bin", false));
+        assert!(everything.keeps("project/empty", true));
+
+        let pruned = filter(&[], &["**/.git/**"]);
+        assert!(!pruned.keeps("project/.git", true));
+        assert!(pruned.keeps("project/docs", true));
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected() {
+        let result = FolderFilter::new(&["[".to_string()], &[]);
+        assert!(matches!(result, Err(TransferError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,82 @@
+use ring::digest::{Context, Digest, SHA256};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+use tauri::{AppHandle, Window};
+
+use crate::network::error::TransferError;
+use crate::network::transfer::{is_within_save_dir, sanitize_relative_path, SendOptions};
+
+/// 增量同步时发送端提交的文件条目（sha256 只对接收端要求进一步比较的文件提供）
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct DiffEntry {
+    pub index: u32,
+    pub relative_path: String,
+    pub size: u64,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// 摘要转为小写十六进制
+pub(crate) fn hex_digest(digest: Digest) -> String {
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 计算文件的 SHA-256（小写十六进制）
+pub(crate) fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0u8; 256 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buffer[..n]);
+    }
+    Ok(hex_digest(context.finish()))
+}
+
+/// 接收端：与保存目录中的同名文件比较。
+/// 返回 (有变化的序号, 大小相同、需要发送端提供 sha256 才能判断的序号)
+pub(crate) fn diff_against_dir(save_dir: &str, files: &[DiffEntry]) -> (Vec<u32>, Vec<u32>) {
+    let mut changed = Vec::new();
+    let mut needs_hash = Vec::new();
+
+    for entry in files {
+        let existing = sanitize_relative_path(&entry.relative_path)
+            .map(|rel| Path::new(save_dir).join(rel))
+            .filter(|path| is_within_save_dir(Path::new(save_dir), path))
+            .filter(|path| std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() == entry.size));
+
+        match (existing, &entry.sha256) {
+            (Some(path), Some(hash)) => {
+                if !sha256_file(&path).is_ok_and(|local| local.eq_ignore_ascii_case(hash)) {
+                    changed.push(entry.index);
+                }
+            }
+            (Some(_), None) => needs_hash.push(entry.index),
+            (None, _) => changed.push(entry.index),
+        }
+    }
+
+    (changed, needs_hash)
+}
+
+#[tauri::command]
+/// 桌面端：增量同步文件夹，只发送接收端缺失或内容有变化的文件
+/// （未变化的文件数通过 transfer-batch-started 的 unchanged 字段报告）
+pub async fn sync_folder_desktop(
+    folder_path: String,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let options = SendOptions {
+        changed_only: true,
+        ..options.unwrap_or_default()
+    };
+    crate::network::transfer::send_folder_desktop(folder_path, target_ip, Some(options), port, window, app).await
+}
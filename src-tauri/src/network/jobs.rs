@@ -0,0 +1,120 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::network::error::TransferError;
+
+/// 任务类型
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// 已提交、尚未开始传输数据的发送（连接、清单协商、续传查询阶段）
+    SendQueued,
+    SendActive,
+    ReceiveActive,
+    /// 等待用户在清单中选择文件的接收
+    ReceiveHeld,
+}
+
+/// 任务状态
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    /// 已请求取消，等待传输循环退出
+    Cancelling,
+}
+
+/// 发送、接收与等待中的传输的统一视图
+#[derive(Serialize, Clone, Debug)]
+pub struct Job {
+    /// 发送为 batch_id，接收为连接 ID，等待选择的清单为其 batch_id
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub peer_ip: String,
+    pub file_name: Option<String>,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub started_at: u64,
+}
+
+// 当前存在的任务（id -> 任务）
+static JOBS: LazyLock<Mutex<HashMap<String, Job>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 任务登记句柄，释放（包括任务被中止）时移除对应任务
+pub(crate) struct JobHandle {
+    id: String,
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        JOBS.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 登记一个任务，返回的句柄存活期间任务出现在 list_all_jobs 中
+pub(crate) fn register(id: &str, kind: JobKind, peer_ip: &str) -> JobHandle {
+    let status = match kind {
+        JobKind::SendQueued | JobKind::ReceiveHeld => JobStatus::Pending,
+        JobKind::SendActive | JobKind::ReceiveActive => JobStatus::Running,
+    };
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    JOBS.lock().unwrap().insert(id.to_string(), Job {
+        id: id.to_string(),
+        kind,
+        status,
+        peer_ip: peer_ip.to_string(),
+        file_name: None,
+        bytes_done: 0,
+        bytes_total: 0,
+        started_at,
+    });
+    JobHandle { id: id.to_string() }
+}
+
+/// 更新任务当前文件的进度；排队中的发送收到首个进度后转为进行中
+pub(crate) fn update_progress(id: &str, file_name: &str, bytes_done: u64, bytes_total: u64) {
+    let mut jobs = JOBS.lock().unwrap();
+    let Some(job) = jobs.get_mut(id) else {
+        return;
+    };
+    if job.kind == JobKind::SendQueued {
+        job.kind = JobKind::SendActive;
+    }
+    if job.status == JobStatus::Pending {
+        job.status = JobStatus::Running;
+    }
+    if job.file_name.as_deref() != Some(file_name) {
+        job.file_name = Some(file_name.to_string());
+    }
+    job.bytes_done = bytes_done;
+    job.bytes_total = bytes_total;
+}
+
+#[tauri::command]
+/// 列出所有排队、进行中和等待确认的传输任务（按开始时间排序）
+pub fn list_all_jobs() -> Vec<Job> {
+    let mut jobs: Vec<Job> = JOBS.lock().unwrap().values().cloned().collect();
+    jobs.sort_by(|a, b| a.started_at.cmp(&b.started_at).then_with(|| a.id.cmp(&b.id)));
+    jobs
+}
+
+#[tauri::command]
+/// 取消指定任务：发送/接收设置该任务的取消标志，等待选择的清单按全部拒绝回复
+pub fn cancel_job(id: String) -> Result<(), TransferError> {
+    let kind = {
+        let mut jobs = JOBS.lock().unwrap();
+        let job = jobs.get_mut(&id).ok_or_else(|| TransferError::NotFound(format!("No job with id {}", id)))?;
+        job.status = JobStatus::Cancelling;
+        job.kind
+    };
+
+    match kind {
+        JobKind::SendQueued | JobKind::SendActive => crate::network::transfer::cancel_file_sending(id),
+        JobKind::ReceiveActive => crate::network::transfer::cancel_file_receiving(id),
+        JobKind::ReceiveHeld => crate::network::transfer::respond_to_manifest(id, Vec::new()),
+    }
+}
@@ -0,0 +1,98 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use futures_util::future::Fuse;
+use futures_util::FutureExt;
+use tokio::sync::{oneshot, Mutex, Notify};
+
+use crate::network::error::TransferError;
+
+const DEFAULT_KEEPALIVE_SECS: u64 = 15;
+// A peer that sends nothing (not even a pong) for this long is treated as gone
+const PONG_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Ping interval for chat and clipboard connections, 0 disables keepalive
+static KEEPALIVE_INTERVAL_SECS: AtomicU64 = AtomicU64::new(DEFAULT_KEEPALIVE_SECS);
+// Wakes keepalive tasks when the interval changes, so parked tasks resume once it is re-enabled
+static INTERVAL_CHANGED: Notify = Notify::const_new();
+
+/// A connection writer the keepalive task can ping
+pub(crate) trait PingWriter: Send + 'static {
+    /// Send a ping frame; false once the connection can no longer be written to
+    fn ping(&mut self) -> impl Future<Output = bool> + Send;
+}
+
+/// Current keepalive interval, or None when keepalive is disabled.
+/// Read on every tick so changes apply to connections that are already open.
+pub fn interval() -> Option<Duration> {
+    match KEEPALIVE_INTERVAL_SECS.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Periodically ping the peer so idle connections survive NAT/firewall timeouts.
+/// Exits once the connection's writer has been dropped or a ping fails, and parks
+/// without polling while keepalive is disabled.
+pub(crate) fn spawn<W: PingWriter>(writer: Weak<Mutex<W>>) {
+    tokio::spawn(run(writer, None));
+}
+
+/// Like [`spawn`], but the returned receiver fires when nothing (not even a pong) has arrived
+/// since `last_seen` for longer than the pong timeout, so the reader loop can drop the peer.
+pub(crate) fn spawn_with_liveness<W: PingWriter>(writer: Weak<Mutex<W>>, last_seen: Arc<AtomicI64>) -> Fuse<oneshot::Receiver<()>> {
+    let (dead_tx, dead_rx) = oneshot::channel();
+    tokio::spawn(run(writer, Some((last_seen, dead_tx))));
+    // Fused so the reader loop can keep polling it after the task exits without firing
+    dead_rx.fuse()
+}
+
+async fn run<W: PingWriter>(writer: Weak<Mutex<W>>, mut liveness: Option<(Arc<AtomicI64>, oneshot::Sender<()>)>) {
+    loop {
+        // Registered before reading the interval so a change in between is not missed
+        let changed = INTERVAL_CHANGED.notified();
+        let Some(interval) = interval() else {
+            changed.await;
+            continue;
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = changed => continue,
+        }
+
+        let Some(writer) = writer.upgrade() else { break; };
+        if let Some((last_seen, _)) = &liveness {
+            // Allow at least two missed pings with long intervals
+            let timeout = PONG_TIMEOUT.max(interval * 2);
+            if now_millis() - last_seen.load(Ordering::Relaxed) > timeout.as_millis() as i64 {
+                if let Some((_, dead_tx)) = liveness.take() {
+                    let _ = dead_tx.send(());
+                }
+                break;
+            }
+        }
+        if !writer.lock().await.ping().await {
+            break;
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn set_keepalive_interval(seconds: u64) -> Result<(), TransferError> {
+    KEEPALIVE_INTERVAL_SECS.store(seconds, Ordering::Relaxed);
+    INTERVAL_CHANGED.notify_waiters();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_keepalive_interval() -> Result<u64, TransferError> {
+    Ok(KEEPALIVE_INTERVAL_SECS.load(Ordering::Relaxed))
+}
@@ -0,0 +1,87 @@
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::Window;
+use tokio::net::TcpListener;
+use socket2::{Socket, Domain, Type, Protocol};
+use crate::network::window_events;
+
+const BIND_MAX_ATTEMPTS: u32 = 5;
+const BIND_INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BindRetry {
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub error: String,
+}
+
+/// Create a listening socket with SO_REUSEADDR (and SO_REUSEPORT where available) to allow quick restarts.
+pub(crate) fn bind_reusable(addr: SocketAddr) -> Result<TcpListener, String> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .map_err(|e| format!("Failed to create socket: {}", e))?;
+
+    if let Err(e) = socket.set_reuse_address(true) {
+        eprintln!("Failed to set SO_REUSEADDR: {}", e);
+    }
+
+    #[cfg(not(windows))]
+    if let Err(e) = socket.set_reuse_port(true) {
+        eprintln!("Failed to set SO_REUSEPORT: {}", e);
+    }
+
+    if let Err(e) = socket.set_nonblocking(true) {
+        eprintln!("Failed to set nonblocking: {}", e);
+    }
+
+    socket.bind(&addr.into())
+        .map_err(|e| format!("Failed to bind: {}", e))?;
+    socket.listen(128)
+        .map_err(|e| format!("Failed to listen: {}", e))?;
+
+    let std_listener: std::net::TcpListener = socket.into();
+    TcpListener::from_std(std_listener)
+        .map_err(|e| format!("Failed to convert listener: {}", e))
+}
+
+/// Bind a server socket, retrying with exponential backoff when the port is still held
+/// (e.g. by the previous instance during a quick restart). Emits `<service>-server-retrying`
+/// before each retry and gives up early if `running` is cleared in the meantime.
+pub(crate) async fn bind_with_retry(
+    addr: SocketAddr,
+    service: &str,
+    running: &AtomicBool,
+    window: &Window,
+) -> Result<TcpListener, String> {
+    let mut delay = BIND_INITIAL_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        let error = match bind_reusable(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => e,
+        };
+
+        if attempt >= BIND_MAX_ATTEMPTS {
+            return Err(format!("{} (gave up after {} attempts)", error, attempt));
+        }
+
+        eprintln!("{} server bind attempt {} failed: {}, retrying in {:?}", service, attempt, error, delay);
+        window_events::emit(window, &format!("{}-server-retrying", service), BindRetry {
+            attempt,
+            max_attempts: BIND_MAX_ATTEMPTS,
+            delay_ms: delay.as_millis() as u64,
+            error,
+        });
+
+        tokio::time::sleep(delay).await;
+        if !running.load(Ordering::Relaxed) {
+            return Err("Server was stopped while retrying".to_string());
+        }
+
+        delay *= 2;
+        attempt += 1;
+    }
+}
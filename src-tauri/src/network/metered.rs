@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Window};
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+const METERED_POLICY_FILE: &str = "metered_policy.json";
+// 暂停时重新检查网络类型的间隔
+const METERED_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 当前网络类型（仅 Android 可检测，桌面端始终为 Unknown）
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+pub enum ConnectionType {
+    Wifi,
+    Cellular,
+    Ethernet,
+    None,
+    Unknown,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConnectionInfo {
+    pub connection_type: ConnectionType,
+    /// 系统认为当前网络是否按流量计费（蜂窝网络或被标记为计费的 Wi-Fi）
+    pub metered: bool,
+}
+
+/// 计费网络下的发送策略
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MeteredPolicy {
+    #[default]
+    Allow,
+    /// 等待切换到非计费网络后再开始
+    Pause,
+    /// 直接拒绝
+    Refuse,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct MeteredSettings {
+    #[serde(default)]
+    policy: MeteredPolicy,
+}
+
+/// `transfer-blocked-metered` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct TransferBlockedMetered {
+    pub batch_id: String,
+    pub connection_type: ConnectionType,
+    /// "paused" 或 "refused"
+    pub action: &'static str,
+}
+
+// 配置文件中的策略，首次使用时加载
+static METERED_POLICY: Mutex<Option<MeteredPolicy>> = Mutex::new(None);
+
+fn policy(app: &AppHandle) -> MeteredPolicy {
+    let mut policy = METERED_POLICY.lock().unwrap();
+    *policy.get_or_insert_with(|| crate::config::load_json::<MeteredSettings>(app, METERED_POLICY_FILE).policy)
+}
+
+fn connection_info(app: &AppHandle) -> ConnectionInfo {
+    #[cfg(target_os = "android")]
+    {
+        let storage = app.state::<AndroidStorage>();
+        match storage.get_connection_type() {
+            Ok(res) => {
+                let connection_type = match res.connection_type.as_str() {
+                    "wifi" => ConnectionType::Wifi,
+                    "cellular" => ConnectionType::Cellular,
+                    "ethernet" => ConnectionType::Ethernet,
+                    "none" => ConnectionType::None,
+                    _ => ConnectionType::Unknown,
+                };
+                return ConnectionInfo { connection_type, metered: res.metered };
+            }
+            Err(e) => eprintln!("Failed to get connection type: {}", e),
+        }
+    }
+
+    let _ = app;
+    ConnectionInfo { connection_type: ConnectionType::Unknown, metered: false }
+}
+
+/// 发送前按计费网络策略检查：拒绝时返回错误，暂停时等待直到网络不再计费或用户取消。
+/// allow_metered 为单次发送的覆盖选项
+pub(crate) async fn wait_until_allowed(
+    window: &Window,
+    app: &AppHandle,
+    batch_id: &str,
+    allow_metered: bool,
+) -> Result<(), TransferError> {
+    let policy = policy(app);
+    if allow_metered || policy == MeteredPolicy::Allow {
+        return Ok(());
+    }
+
+    let mut info = connection_info(app);
+    if !info.metered {
+        return Ok(());
+    }
+
+    let action = if policy == MeteredPolicy::Refuse { "refused" } else { "paused" };
+    window_events::emit(window, "transfer-blocked-metered", TransferBlockedMetered {
+        batch_id: batch_id.to_string(),
+        connection_type: info.connection_type,
+        action,
+    });
+    if policy == MeteredPolicy::Refuse {
+        return Err(TransferError::Other("Blocked on metered connection".to_string()));
+    }
+
+    println!("Send {} paused on metered connection", batch_id);
+    while info.metered {
+        tokio::time::sleep(METERED_RECHECK_INTERVAL).await;
+        if crate::network::transfer::send_cancel_requested(batch_id) {
+            return Err(TransferError::Cancelled("Cancelled by user".to_string()));
+        }
+        info = connection_info(app);
+    }
+    println!("Send {} resumed on unmetered connection", batch_id);
+    Ok(())
+}
+
+#[tauri::command]
+/// 查询当前网络类型及是否计费
+pub fn get_connection_type(app: AppHandle) -> ConnectionInfo {
+    connection_info(&app)
+}
+
+#[tauri::command]
+/// 设置计费网络下的发送策略（单次发送可用 allow_metered 覆盖），保存后重启仍然生效
+pub fn set_metered_policy(policy: MeteredPolicy, app: AppHandle) -> Result<(), TransferError> {
+    crate::config::save_json(&app, METERED_POLICY_FILE, &MeteredSettings { policy }).map_err(TransferError::Io)?;
+    *METERED_POLICY.lock().unwrap() = Some(policy);
+    Ok(())
+}
+
+#[tauri::command]
+/// 查询计费网络下的发送策略
+pub fn get_metered_policy(app: AppHandle) -> MeteredPolicy {
+    policy(&app)
+}
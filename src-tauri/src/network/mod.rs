@@ -1,3 +1,37 @@
 pub mod transfer;
+pub mod discovery_packet;
 pub mod chat;
-pub mod clipboard;
\ No newline at end of file
+pub mod chat_crypto;
+pub mod clipboard;
+pub mod control;
+pub mod keepalive;
+pub mod compression;
+pub mod listener;
+pub mod save_profiles;
+pub mod receive_quota;
+pub mod batch_progress;
+pub mod partials;
+pub mod transfer_history;
+pub mod jobs;
+pub mod receive_zip;
+pub mod peer_trust;
+pub mod folder_sync;
+pub mod window_events;
+pub mod socket_buffers;
+pub mod pull_tokens;
+pub mod size_units;
+pub mod throughput;
+pub mod metered;
+pub mod ports;
+pub mod transfer_service;
+pub mod collision;
+pub mod device_name;
+pub mod transfer_queue;
+pub mod rate_limit;
+pub mod disk_space;
+pub mod transfer_tls;
+pub mod connection_pin;
+pub mod chat_history;
+pub mod folder_filter;
+pub mod multi_send;
+pub mod error;
@@ -0,0 +1,196 @@
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Window};
+
+use crate::network::jobs;
+use crate::network::transfer::{self, SendOptions, SendSource};
+use crate::network::transfer_queue::QueueStatus;
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+// multicast-transfer-progress 事件的发送间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 多目标发送中单个目标的状态，也是 send_files_to_many 的返回值
+#[derive(Serialize, Clone, Debug)]
+pub struct TargetProgress {
+    pub target_ip: String,
+    pub status: QueueStatus,
+    /// 当前（或最后一次）发送的批次 ID
+    pub batch_id: Option<String>,
+    pub file_name: Option<String>,
+    /// 当前文件的进度
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    /// 失败原因
+    pub error: Option<String>,
+}
+
+/// `multicast-transfer-progress` 事件内容
+#[derive(Serialize, Clone, Debug)]
+struct MulticastProgress<'a> {
+    fanout_id: &'a str,
+    targets: &'a [TargetProgress],
+}
+
+/// 要发送的内容：Android 为文件 URI；桌面端的文件夹各自发送一次，普通文件合为一次发送
+fn sources_for(paths: Vec<String>) -> Result<Vec<SendSource>, TransferError> {
+    #[cfg(target_os = "android")]
+    {
+        Ok(vec![SendSource::FilesAndroid { uris: paths }])
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let mut sources = Vec::new();
+        let mut files = Vec::new();
+        for path in paths {
+            let path_ref = std::path::Path::new(&path);
+            if path_ref.is_dir() {
+                sources.push(SendSource::FolderDesktop { folder_path: path });
+            } else if path_ref.is_file() {
+                files.push(path);
+            } else {
+                return Err(TransferError::NotFound(format!("No such file or directory: {}", path)));
+            }
+        }
+        if !files.is_empty() {
+            sources.insert(0, SendSource::FilesDesktop { paths: files });
+        }
+        Ok(sources)
+    }
+}
+
+fn emit_progress(window: &Window, fanout_id: &str, targets: &Mutex<Vec<TargetProgress>>) {
+    let mut targets = targets.lock().unwrap();
+    // 进行中的目标取其发送任务的当前进度
+    let jobs = jobs::list_all_jobs();
+    for target in targets.iter_mut().filter(|t| t.status == QueueStatus::Active) {
+        if let Some(job) = jobs.iter().find(|job| Some(&job.id) == target.batch_id.as_ref()) {
+            target.file_name = job.file_name.clone();
+            target.bytes_done = job.bytes_done;
+            target.bytes_total = job.bytes_total;
+        }
+    }
+    window_events::emit(window, "multicast-transfer-progress", MulticastProgress { fanout_id, targets: &targets });
+}
+
+/// 向一个目标依次发送全部内容，复用单目标的发送流程
+#[allow(clippy::too_many_arguments)]
+async fn send_to_target(
+    index: usize,
+    fanout_id: &str,
+    sources: &[SendSource],
+    options: &SendOptions,
+    targets: &Mutex<Vec<TargetProgress>>,
+    cancelled: &AtomicBool,
+    window: &Window,
+    app: &AppHandle,
+) {
+    let target_ip = targets.lock().unwrap()[index].target_ip.clone();
+    for (n, source) in sources.iter().enumerate() {
+        if cancelled.load(Ordering::SeqCst) {
+            targets.lock().unwrap()[index].status = QueueStatus::Cancelled;
+            emit_progress(window, fanout_id, targets);
+            return;
+        }
+        let batch_id = format!("{}-{}-{}", fanout_id, index, n);
+        {
+            let mut targets = targets.lock().unwrap();
+            targets[index].status = QueueStatus::Active;
+            targets[index].batch_id = Some(batch_id.clone());
+        }
+        emit_progress(window, fanout_id, targets);
+
+        let mut options = options.clone();
+        options.batch_id = Some(batch_id);
+        // 各目标按设备发现中公布的端口连接
+        let result = transfer::send_source(source.clone(), target_ip.clone(), None, options, window.clone(), app.clone()).await;
+        if let Err(e) = result {
+            {
+                let mut targets = targets.lock().unwrap();
+                if let TransferError::Cancelled(_) = e {
+                    targets[index].status = QueueStatus::Cancelled;
+                } else {
+                    targets[index].status = QueueStatus::Failed;
+                    targets[index].error = Some(e.to_string());
+                }
+            }
+            emit_progress(window, fanout_id, targets);
+            return;
+        }
+    }
+    targets.lock().unwrap()[index].status = QueueStatus::Done;
+    emit_progress(window, fanout_id, targets);
+}
+
+#[tauri::command]
+/// 同时向多个设备发送相同内容（Android 为文件 URI，桌面端为文件或文件夹路径），各目标并行发送，
+/// 期间定时发送 `multicast-transfer-progress` 事件报告每个目标的状态。
+/// 某个目标失败不影响其他目标；以事件中的 fanout_id 调用 cancel_file_sending 可取消全部目标。
+/// 返回每个目标（IP）的最终状态
+pub async fn send_files_to_many(
+    paths: Vec<String>,
+    target_ips: Vec<String>,
+    options: Option<SendOptions>,
+    window: Window,
+    app: AppHandle,
+) -> Result<HashMap<String, TargetProgress>, TransferError> {
+    if paths.is_empty() {
+        return Err(TransferError::InvalidInput("No files to send".into()));
+    }
+    let mut seen = HashSet::new();
+    let mut target_ips = target_ips;
+    target_ips.retain(|ip| seen.insert(ip.clone()));
+    if target_ips.is_empty() {
+        return Err(TransferError::InvalidInput("No target devices".into()));
+    }
+    let sources = sources_for(paths)?;
+    let options = options.unwrap_or_default();
+
+    let fanout_id = transfer::new_transfer_id();
+    let cancel = transfer::register_send_cancel(&fanout_id);
+    let targets = Mutex::new(target_ips.iter().map(|ip| TargetProgress {
+        target_ip: ip.clone(),
+        status: QueueStatus::Pending,
+        batch_id: None,
+        file_name: None,
+        bytes_done: 0,
+        bytes_total: 0,
+        error: None,
+    }).collect::<Vec<_>>());
+    emit_progress(&window, &fanout_id, &targets);
+
+    // 等待全部目标结束，期间定时汇总进度
+    {
+        let sends = join_all((0..target_ips.len()).map(|index| {
+            send_to_target(index, &fanout_id, &sources, &options, &targets, &cancel.flag, &window, &app)
+        }));
+        tokio::pin!(sends);
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = &mut sends => break,
+                _ = ticker.tick() => {
+                    if cancel.flag.load(Ordering::SeqCst) {
+                        // 取消各目标进行中的发送（尚未登记的发送开始前会检查 fan-out 的取消标志）
+                        let batch_ids: Vec<String> = targets.lock().unwrap().iter()
+                            .filter(|t| t.status == QueueStatus::Active)
+                            .filter_map(|t| t.batch_id.clone())
+                            .collect();
+                        for batch_id in batch_ids {
+                            let _ = transfer::cancel_file_sending(batch_id);
+                        }
+                    }
+                    emit_progress(&window, &fanout_id, &targets);
+                }
+            }
+        }
+    }
+
+    Ok(targets.into_inner().unwrap().into_iter().map(|t| (t.target_ip.clone(), t)).collect())
+}
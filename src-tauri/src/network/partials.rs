@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Window};
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+/// 接收中的文件先写入带此后缀的临时文件，完整接收后再重命名为最终文件名
+pub(crate) const PARTIAL_SUFFIX: &str = ".lt-part";
+
+/// 连接中断后保留的临时文件旁写入的续传状态文件（`<name>.lt-part.lt-resume`），重启后仍可续传
+pub(crate) const RESUME_STATE_SUFFIX: &str = ".lt-resume";
+
+// 超过该时间未更新的临时文件视为崩溃或强制退出的残留
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+// 进行中的接收正在写入的临时文件（本地路径或 SAF 文档 URI）；传输停滞很久时也不能清理
+static ACTIVE_PARTIALS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// 接收期间登记正在写入的临时文件，释放时取消登记
+pub(crate) struct ActivePartial(String);
+
+impl ActivePartial {
+    pub(crate) fn register(key: impl Into<String>) -> Self {
+        let key = key.into();
+        ACTIVE_PARTIALS.lock().unwrap().push(key.clone());
+        ActivePartial(key)
+    }
+}
+
+impl Drop for ActivePartial {
+    fn drop(&mut self) {
+        let mut active = ACTIVE_PARTIALS.lock().unwrap();
+        if let Some(pos) = active.iter().position(|key| *key == self.0) {
+            active.swap_remove(pos);
+        }
+    }
+}
+
+fn is_active(key: &str) -> bool {
+    ACTIVE_PARTIALS.lock().unwrap().iter().any(|active| active == key)
+}
+
+/// `partials-cleaned` 事件内容
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PartialsCleanup {
+    pub files_removed: usize,
+    pub bytes_reclaimed: u64,
+}
+
+#[cfg(target_os = "android")]
+pub(crate) fn partial_name(name: &str) -> String {
+    format!("{}{}", name, PARTIAL_SUFFIX)
+}
+
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// 续传状态：临时文件所属文件声明的大小，续传时须与发送端的元数据一致
+#[derive(Serialize, Deserialize)]
+struct ResumeState {
+    size: u64,
+}
+
+fn resume_state_path(partial: &Path) -> PathBuf {
+    let mut state = partial.as_os_str().to_owned();
+    state.push(RESUME_STATE_SUFFIX);
+    PathBuf::from(state)
+}
+
+/// 保留临时文件以便续传，记录其所属文件的大小
+pub(crate) async fn keep_for_resume(partial: &Path, size: u64) {
+    let state = serde_json::to_vec(&ResumeState { size }).unwrap_or_default();
+    if let Err(e) = tokio::fs::write(resume_state_path(partial), state).await {
+        eprintln!("Failed to save resume state for {}: {}", partial.display(), e);
+    }
+}
+
+/// 临时文件保留时记录的文件大小，未保留时为 None
+pub(crate) async fn kept_size(partial: &Path) -> Option<u64> {
+    let state = tokio::fs::read(resume_state_path(partial)).await.ok()?;
+    serde_json::from_slice::<ResumeState>(&state).ok().map(|state| state.size)
+}
+
+/// 删除续传状态，返回删除前记录的文件大小
+pub(crate) async fn take_resume_state(partial: &Path) -> Option<u64> {
+    let size = kept_size(partial).await;
+    forget_resume(partial).await;
+    size
+}
+
+pub(crate) async fn forget_resume(partial: &Path) {
+    let _ = tokio::fs::remove_file(resume_state_path(partial)).await;
+}
+
+/// 递归删除目录中过期的临时文件（不跟随符号链接），跳过进行中接收的临时文件
+fn sweep_dir(dir: &Path, summary: &mut PartialsCleanup) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            sweep_dir(&path, summary);
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // 对应的临时文件已不存在时删除残留的续传状态
+        if file_type.is_file() && name.ends_with(RESUME_STATE_SUFFIX) {
+            let partial = path.with_file_name(&name[..name.len() - RESUME_STATE_SUFFIX.len()]);
+            if !partial.exists() {
+                let _ = std::fs::remove_file(&path);
+            }
+            continue;
+        }
+        if !file_type.is_file() || !name.ends_with(PARTIAL_SUFFIX) {
+            continue;
+        }
+        if is_active(&path.to_string_lossy()) {
+            continue;
+        }
+
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let stale = meta.modified().ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= PARTIAL_MAX_AGE);
+        if !stale {
+            continue;
+        }
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                let _ = std::fs::remove_file(resume_state_path(&path));
+                println!("Removed orphaned partial file {}", path.display());
+                summary.files_removed += 1;
+                summary.bytes_reclaimed += meta.len();
+            }
+            Err(e) => eprintln!("Failed to remove partial file {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Android: 遍历 SAF 目录树删除过期的临时文件（提供方不返回修改时间的文件保留）
+#[cfg(target_os = "android")]
+fn sweep_tree(tree_uri: &str, app: &AppHandle, summary: &mut PartialsCleanup) {
+    let storage = app.state::<AndroidStorage>();
+    let files = match storage.list_folder_contents(tree_uri.to_string()) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Failed to list {}: {}", tree_uri, e);
+            return;
+        }
+    };
+
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+
+    for file in files {
+        if file.is_dir || !file.name.ends_with(PARTIAL_SUFFIX) || file.last_modified <= 0 {
+            continue;
+        }
+        if now_ms - file.last_modified < PARTIAL_MAX_AGE.as_millis() as i64 || is_active(&file.uri) {
+            continue;
+        }
+
+        match storage.delete_document(file.uri) {
+            Ok(()) => {
+                println!("Removed orphaned partial file {}", file.relative_path);
+                summary.files_removed += 1;
+                summary.bytes_reclaimed += file.size;
+            }
+            Err(e) => eprintln!("Failed to remove partial file {}: {}", file.relative_path, e),
+        }
+    }
+}
+
+/// 清理当前保存目录及所有保存配置目录中的残留临时文件，并通知前端回收的空间
+pub(crate) async fn sweep(window: &Window, app: &AppHandle) -> Result<PartialsCleanup, TransferError> {
+    let mut dirs = crate::network::save_profiles::profile_dirs(app);
+    dirs.push(crate::network::transfer::current_save_dir());
+    dirs.retain(|d| !d.is_empty());
+    dirs.sort();
+    dirs.dedup();
+
+    let app = app.clone();
+    let summary = tokio::task::spawn_blocking(move || {
+        let mut summary = PartialsCleanup::default();
+        for dir in &dirs {
+            if dir.starts_with("content://") {
+                #[cfg(target_os = "android")]
+                sweep_tree(dir, &app, &mut summary);
+                continue;
+            }
+            sweep_dir(Path::new(dir), &mut summary);
+        }
+        let _ = app;
+        summary
+    })
+    .await
+    .map_err(|e| TransferError::Other(format!("Failed to clean up partial files: {}", e)))?;
+
+    if summary.files_removed > 0 {
+        println!("Reclaimed {} bytes from {} partial files", summary.bytes_reclaimed, summary.files_removed);
+    }
+    window_events::emit(window, "partials-cleaned", summary.clone());
+    Ok(summary)
+}
+
+#[tauri::command]
+/// 手动清理保存目录中崩溃或强制退出残留的临时文件
+pub async fn cleanup_partials(window: Window, app: AppHandle) -> Result<PartialsCleanup, TransferError> {
+    sweep(&window, &app).await
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::network::error::TransferError;
+
+const TRUST_FILE: &str = "peer_trust.json";
+
+// 内存中的信任设置，首次查询时从文件加载，set_peer_trust 写入后失效
+static TRUST_CACHE: Mutex<Option<BTreeMap<String, TrustLevel>>> = Mutex::new(None);
+
+/// 对端的信任级别，决定接收时是否需要用户确认
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustLevel {
+    /// 拒绝该对端发来的所有文件
+    Untrusted,
+    /// 清单需用户选择，直接发来的文件需用户接受（未设置的对端默认如此）
+    #[default]
+    Ask,
+    /// 清单与文件自动全部接受
+    AutoAccept,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PeerTrust {
+    /// 对端 IP 或设备名（设备名小写保存）-> 信任级别
+    #[serde(default)]
+    peers: BTreeMap<String, TrustLevel>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PeerTrustEntry {
+    pub peer: String,
+    pub trust_level: TrustLevel,
+}
+
+/// 规范化对端标识：IP 原样保存，设备名忽略大小写
+fn peer_key(peer: &str) -> String {
+    let peer = peer.trim();
+    if peer.parse::<std::net::IpAddr>().is_ok() {
+        peer.to_string()
+    } else {
+        peer.to_lowercase()
+    }
+}
+
+/// 查询对端的信任级别：优先按 IP，其次按已发现的设备名（IP 变化后仍然有效）
+pub(crate) fn trust_level(app: &AppHandle, peer_ip: &str) -> TrustLevel {
+    let mut cache = TRUST_CACHE.lock().unwrap();
+    let peers = cache.get_or_insert_with(|| crate::config::load_json::<PeerTrust>(app, TRUST_FILE).peers);
+    if let Some(level) = peers.get(peer_ip) {
+        return *level;
+    }
+    crate::network::transfer::discovered_hostname(peer_ip)
+        .and_then(|hostname| peers.get(&hostname.to_lowercase()).copied())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+/// 设置对端（IP 或设备名）的信任级别；设为 ask 即恢复默认
+pub fn set_peer_trust(peer: String, trust_level: TrustLevel, app: AppHandle) -> Result<(), TransferError> {
+    let key = peer_key(&peer);
+    if key.is_empty() {
+        return Err(TransferError::InvalidInput("Peer must not be empty".to_string()));
+    }
+
+    let mut trust: PeerTrust = crate::config::load_json(&app, TRUST_FILE);
+    if trust_level == TrustLevel::Ask {
+        trust.peers.remove(&key);
+    } else {
+        trust.peers.insert(key, trust_level);
+    }
+    let saved = crate::config::save_json(&app, TRUST_FILE, &trust).map_err(TransferError::Io);
+    *TRUST_CACHE.lock().unwrap() = None;
+    saved
+}
+
+#[tauri::command]
+/// 列出设置过信任级别的对端
+pub fn list_peer_trust(app: AppHandle) -> Vec<PeerTrustEntry> {
+    let trust: PeerTrust = crate::config::load_json(&app, TRUST_FILE);
+    trust.peers.into_iter()
+        .map(|(peer, trust_level)| PeerTrustEntry { peer, trust_level })
+        .collect()
+}
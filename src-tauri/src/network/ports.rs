@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicU16, Ordering};
+use tauri::{AppHandle, Window};
+
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+// 各服务的默认端口（未从设备发现获知对端端口时也按此连接）
+pub const DEFAULT_TRANSFER_PORT: u16 = 7878;
+pub const DEFAULT_CHAT_PORT: u16 = 7879;
+pub const DEFAULT_CLIPBOARD_PORT: u16 = 7880;
+pub const DEFAULT_CONTROL_PORT: u16 = 7881;
+
+// 本机各服务当前监听的端口
+static TRANSFER_PORT: AtomicU16 = AtomicU16::new(DEFAULT_TRANSFER_PORT);
+static CHAT_PORT: AtomicU16 = AtomicU16::new(DEFAULT_CHAT_PORT);
+static CLIPBOARD_PORT: AtomicU16 = AtomicU16::new(DEFAULT_CLIPBOARD_PORT);
+static CONTROL_PORT: AtomicU16 = AtomicU16::new(DEFAULT_CONTROL_PORT);
+
+tokio::task_local! {
+    // 当前发送任务指定的对端传输端口（发送命令的 port 参数）
+    static PEER_TRANSFER_PORT: Option<u16>;
+}
+
+/// 可换端口的服务
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Service {
+    Transfer,
+    Chat,
+    Clipboard,
+    Control,
+}
+
+const ALL_SERVICES: [Service; 4] = [Service::Transfer, Service::Chat, Service::Clipboard, Service::Control];
+
+/// 一台设备各服务的端口，随设备发现广播
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServicePorts {
+    pub transfer: u16,
+    pub chat: u16,
+    pub clipboard: u16,
+}
+
+impl Default for ServicePorts {
+    fn default() -> Self {
+        ServicePorts {
+            transfer: DEFAULT_TRANSFER_PORT,
+            chat: DEFAULT_CHAT_PORT,
+            clipboard: DEFAULT_CLIPBOARD_PORT,
+        }
+    }
+}
+
+impl ServicePorts {
+    fn get(&self, service: Service) -> u16 {
+        match service {
+            Service::Transfer => self.transfer,
+            Service::Chat => self.chat,
+            Service::Clipboard => self.clipboard,
+            // 控制通道端口不随发现广播，对端按默认端口连接
+            Service::Control => DEFAULT_CONTROL_PORT,
+        }
+    }
+}
+
+/// `server-bound` 与 `server-rebound` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerPort {
+    pub service: Service,
+    pub port: u16,
+}
+
+fn port_static(service: Service) -> &'static AtomicU16 {
+    match service {
+        Service::Transfer => &TRANSFER_PORT,
+        Service::Chat => &CHAT_PORT,
+        Service::Clipboard => &CLIPBOARD_PORT,
+        Service::Control => &CONTROL_PORT,
+    }
+}
+
+/// 本机该服务当前的监听端口
+pub(crate) fn local(service: Service) -> u16 {
+    port_static(service).load(Ordering::SeqCst)
+}
+
+/// 记录本机该服务实际监听的端口
+pub(crate) fn set_local(service: Service, port: u16) {
+    port_static(service).store(port, Ordering::SeqCst);
+}
+
+/// 本机所有服务的端口（用于设备发现广播）
+pub(crate) fn local_ports() -> ServicePorts {
+    ServicePorts {
+        transfer: local(Service::Transfer),
+        chat: local(Service::Chat),
+        clipboard: local(Service::Clipboard),
+    }
+}
+
+/// 在指定对端传输端口下执行发送：其中连接传输服务时使用该端口（None 时按发现结果或默认端口）
+pub(crate) async fn with_peer_port<F: Future>(port: Option<u16>, fut: F) -> F::Output {
+    PEER_TRANSFER_PORT.scope(port, fut).await
+}
+
+/// 连接对端时使用的端口：优先使用发送时指定的端口，其次是对端在发现广播中公布的端口，否则使用默认端口
+pub(crate) fn peer(ip: &str, service: Service) -> u16 {
+    if service == Service::Transfer {
+        if let Some(port) = PEER_TRANSFER_PORT.try_with(|port| *port).ok().flatten() {
+            return port;
+        }
+    }
+    crate::network::transfer::discovered_ports(ip)
+        .unwrap_or_default()
+        .get(service)
+}
+
+#[tauri::command]
+/// 查询本机各服务当前监听的端口
+pub fn get_service_ports() -> ServicePorts {
+    local_ports()
+}
+
+/// 按服务的监听方式绑定新端口
+fn bind(service: Service, port: u16) -> std::io::Result<TcpListener> {
+    match service {
+        Service::Transfer => crate::network::transfer::bind_transfer_listener(port),
+        Service::Chat | Service::Clipboard | Service::Control => {
+            crate::network::listener::bind_reusable(SocketAddr::from(([0, 0, 0, 0], port)))
+                .map_err(std::io::Error::other)?
+                .into_std()
+        }
+    }
+}
+
+#[tauri::command]
+/// 运行时将某个服务换到新端口：停止旧监听（已建立的连接保持不变，进行中的接收继续完成），
+/// 在新端口重新监听，并在下一次设备发现广播中公布新端口。服务未运行时只记录端口，下次启动生效
+pub async fn rebind_server(service: Service, new_port: u16, window: Window, app: AppHandle) -> Result<u16, TransferError> {
+    if new_port == 0 {
+        return Err(TransferError::InvalidInput("Port must be between 1 and 65535".to_string()));
+    }
+    if new_port == local(service) {
+        return Ok(new_port);
+    }
+    if ALL_SERVICES.iter().any(|&other| other != service && local(other) == new_port) {
+        return Err(TransferError::InvalidInput(format!("Port {} is already used by another service", new_port)));
+    }
+
+    // 只绑定一次并把监听交给服务：先试绑再释放、由服务重新绑定时，端口可能在两者之间被占用。
+    // 绑定失败时旧监听与记录的端口都保持不变
+    let listener = bind(service, new_port)
+        .map_err(|e| TransferError::Io(format!("Failed to bind port {}: {}", new_port, e)))?;
+
+    let old_port = port_static(service).swap(new_port, Ordering::SeqCst);
+    match service {
+        Service::Transfer => crate::network::transfer::rebind_websocket_server(window.clone(), app, listener),
+        Service::Chat => crate::network::chat::rebind(window.clone(), app, listener).await?,
+        Service::Clipboard => crate::network::clipboard::rebind(window.clone(), app, listener).await?,
+        Service::Control => crate::network::control::rebind(window.clone(), app, listener)?,
+    }
+    println!("{:?} server moved from port {} to {}", service, old_port, new_port);
+
+    window_events::emit(&window, "server-rebound", ServerPort { service, port: new_port });
+    Ok(new_port)
+}
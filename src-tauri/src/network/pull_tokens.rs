@@ -0,0 +1,141 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Window};
+
+use crate::network::error::TransferError;
+use crate::network::transfer::{SendOptions, SendSource};
+
+// 令牌默认有效期与允许的最长有效期
+const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+const MAX_TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const TOKEN_BYTES: usize = 16;
+
+/// 登记的待拉取文件集
+struct PullOffer {
+    /// 令牌只能由该 IP 兑换
+    peer_ip: String,
+    source: SendSource,
+    options: SendOptions,
+    expires_at: Instant,
+}
+
+// 发送端登记的文件集（令牌 -> 文件集），令牌使用一次即失效
+static PULL_OFFERS: LazyLock<Mutex<HashMap<String, PullOffer>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PullToken {
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+fn new_token() -> Result<String, TransferError> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    SystemRandom::new().fill(&mut bytes)
+        .map_err(|_| TransferError::Other("Failed to generate token".to_string()))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// 两个 IP 文本是否为同一地址（忽略 IPv4 映射的 IPv6 等写法差异）
+fn same_ip(a: &str, b: &str) -> bool {
+    match (a.parse::<std::net::IpAddr>(), b.parse::<std::net::IpAddr>()) {
+        (Ok(a), Ok(b)) => a.to_canonical() == b.to_canonical(),
+        _ => a == b,
+    }
+}
+
+/// 发送端：收到拉取请求时兑换令牌（一次性，过期无效），返回要推送的文件集。
+/// 令牌只能由登记时指定的对端兑换；其他 IP 的请求被拒绝且不会使令牌失效
+pub(crate) fn redeem(token: &str, peer_ip: &str) -> Result<(SendSource, SendOptions), String> {
+    let mut offers = PULL_OFFERS.lock().unwrap();
+    let now = Instant::now();
+    offers.retain(|_, offer| offer.expires_at > now);
+    let token = token.trim();
+    let issued_to = offers.get(token)
+        .map(|offer| offer.peer_ip.as_str())
+        .ok_or_else(|| "Invalid or expired token".to_string())?;
+    if !same_ip(issued_to, peer_ip) {
+        return Err("Token was issued to another device".to_string());
+    }
+    offers.remove(token)
+        .map(|offer| (offer.source, offer.options))
+        .ok_or_else(|| "Invalid or expired token".to_string())
+}
+
+#[tauri::command]
+/// 发送端：为 peer（IP 或设备名）登记一组待拉取的文件，返回一次性令牌（ttl_secs 默认 10 分钟，最长 24 小时）。
+/// 令牌只接受来自该对端的拉取请求
+pub fn create_pull_token(
+    peer: String,
+    source: SendSource,
+    options: Option<SendOptions>,
+    ttl_secs: Option<u64>,
+    app: AppHandle,
+) -> Result<PullToken, TransferError> {
+    let peer_ip = crate::network::transfer::resolve_target(&peer)?;
+    let missing = crate::network::transfer_history::missing_sources(&source, &app);
+    if !missing.is_empty() {
+        return Err(TransferError::NotFound(format!("Source files do not exist: {}", missing.join(", "))));
+    }
+
+    let ttl = ttl_secs.map(Duration::from_secs).unwrap_or(DEFAULT_TOKEN_TTL);
+    if ttl.is_zero() || ttl > MAX_TOKEN_TTL {
+        return Err(TransferError::InvalidInput(format!("Token lifetime must be between 1 and {} seconds", MAX_TOKEN_TTL.as_secs())));
+    }
+
+    let token = new_token()?;
+    PULL_OFFERS.lock().unwrap().insert(token.clone(), PullOffer {
+        peer_ip,
+        source,
+        options: options.unwrap_or_default(),
+        expires_at: Instant::now() + ttl,
+    });
+    Ok(PullToken { token, expires_in_secs: ttl.as_secs() })
+}
+
+#[tauri::command]
+/// 发送端：撤销尚未使用的令牌
+pub fn revoke_pull_token(token: String) -> Result<(), TransferError> {
+    PULL_OFFERS.lock().unwrap().remove(token.trim())
+        .map(|_| ())
+        .ok_or_else(|| TransferError::NotFound(format!("No pull token {}", token)))
+}
+
+#[tauri::command]
+/// 拉取方：凭令牌请求对端推送文件，文件随后按普通接收流程到达（需已启动接收服务）
+pub async fn pull_transfer(ip: String, token: String, window: Window) -> Result<(), TransferError> {
+    let ip = crate::network::transfer::resolve_target(&ip)?;
+    crate::network::transfer::request_pull(&window, &ip, token.trim()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer_for(peer_ip: &str) -> String {
+        let token = new_token().unwrap();
+        PULL_OFFERS.lock().unwrap().insert(token.clone(), PullOffer {
+            peer_ip: peer_ip.to_string(),
+            source: SendSource::FolderDesktop { folder_path: "/tmp".to_string() },
+            options: SendOptions::default(),
+            expires_at: Instant::now() + DEFAULT_TOKEN_TTL,
+        });
+        token
+    }
+
+    #[test]
+    fn token_is_redeemed_once_by_its_peer() {
+        let token = offer_for("192.168.1.20");
+        assert!(redeem(&token, "192.168.1.20").is_ok());
+        assert!(redeem(&token, "192.168.1.20").is_err());
+    }
+
+    #[test]
+    fn token_from_another_peer_is_rejected_and_kept() {
+        let token = offer_for("192.168.1.20");
+        assert!(redeem(&token, "192.168.1.21").is_err());
+        assert!(redeem(&token, "::ffff:192.168.1.20").is_ok());
+    }
+}
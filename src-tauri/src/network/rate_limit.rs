@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::network::error::TransferError;
+
+// 所有发送合计的速率上限（字节/秒），0 表示不限速
+static SEND_RATE_LIMIT: AtomicU64 = AtomicU64::new(0);
+// 下一块数据最早的发送时间，各发送共用，限制的是总速率
+static NEXT_SEND_AT: Mutex<Option<Instant>> = Mutex::new(None);
+// 进行中发送各自的速率上限（批次 ID -> 字节/秒，0 表示不限速），set_transfer_rate_limit 可按批次调整
+static BATCH_LIMITS: LazyLock<Mutex<HashMap<String, Arc<AtomicU64>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) fn set_limit(bytes_per_sec: Option<u64>) {
+    SEND_RATE_LIMIT.store(bytes_per_sec.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// 按上限为 bytes 预留发送时间，返回本块需要等待的时长
+fn reserve(next: &mut Option<Instant>, bytes: u64, limit: u64, now: Instant) -> Duration {
+    // 空闲期间不积累额度，避免恢复发送时突发占满链路
+    let start = next.filter(|at| *at > now).unwrap_or(now);
+    *next = Some(start + Duration::from_secs_f64(bytes as f64 / limit as f64));
+    start - now
+}
+
+/// 全局上限下本块需要等待的时长（每次读取上限，发送中调整立即生效）
+fn reserve_global(bytes: u64) -> Duration {
+    let limit = SEND_RATE_LIMIT.load(Ordering::SeqCst);
+    if limit == 0 {
+        return Duration::ZERO;
+    }
+    reserve(&mut NEXT_SEND_AT.lock().unwrap(), bytes, limit, Instant::now())
+}
+
+/// 每发送一块数据后调用：按全局上限等到下一块可以发送的时间
+pub(crate) async fn pace(bytes: u64) {
+    let wait = reserve_global(bytes);
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// 单次发送自己的速率上限（SendOptions::max_bytes_per_sec），与全局上限同时生效，只限制这一次发送。
+/// 上限按批次 ID 登记，发送中可用 set_transfer_rate_limit 调整或取消，结束（drop）时注销
+pub(crate) struct SendPacer {
+    batch_id: String,
+    limit: Arc<AtomicU64>,
+    next: Option<Instant>,
+}
+
+impl SendPacer {
+    pub(crate) fn new(batch_id: &str, bytes_per_sec: Option<u64>) -> Self {
+        let limit = Arc::new(AtomicU64::new(bytes_per_sec.unwrap_or(0)));
+        BATCH_LIMITS.lock().unwrap().insert(batch_id.to_string(), limit.clone());
+        SendPacer { batch_id: batch_id.to_string(), limit, next: None }
+    }
+
+    /// 每发送一块数据后调用：等到全局上限与本次上限都允许发送下一块
+    pub(crate) async fn pace(&mut self, bytes: u64) {
+        let global = reserve_global(bytes);
+        // 每块读取上限，发送中调整立即生效
+        let limit = self.limit.load(Ordering::SeqCst);
+        let own = if limit > 0 {
+            reserve(&mut self.next, bytes, limit, Instant::now())
+        } else {
+            Duration::ZERO
+        };
+        let wait = global.max(own);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Drop for SendPacer {
+    fn drop(&mut self) {
+        let mut limits = BATCH_LIMITS.lock().unwrap();
+        // 同一批次 ID 已被新的发送登记时保留新的上限
+        if limits.get(&self.batch_id).is_some_and(|limit| Arc::ptr_eq(limit, &self.limit)) {
+            limits.remove(&self.batch_id);
+        }
+    }
+}
+
+/// 调整进行中发送的速率上限，返回该批次是否正在发送
+fn set_batch_limit(batch_id: &str, bytes_per_sec: Option<u64>) -> bool {
+    match BATCH_LIMITS.lock().unwrap().get(batch_id) {
+        Some(limit) => {
+            limit.store(bytes_per_sec.unwrap_or(0), Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+#[tauri::command]
+/// 设置发送速率上限（字节/秒），None 取消限速。指定 batch_id 时只调整该次进行中的发送自己的上限，
+/// 否则设置所有发送合计的上限。进行中的发送从下一块数据起按新上限发送
+pub fn set_transfer_rate_limit(bytes_per_sec: Option<u64>, batch_id: Option<String>) -> Result<(), TransferError> {
+    if bytes_per_sec == Some(0) {
+        return Err(TransferError::InvalidInput("Rate limit must be greater than 0".to_string()));
+    }
+    match batch_id {
+        Some(batch_id) => {
+            if !set_batch_limit(&batch_id, bytes_per_sec) {
+                return Err(TransferError::NotFound(format!("No active send with id {}", batch_id)));
+            }
+        }
+        None => set_limit(bytes_per_sec),
+    }
+    Ok(())
+}
+
+#[tauri::command]
+/// 查询当前的发送速率上限，None 表示不限速
+pub fn get_transfer_rate_limit() -> Option<u64> {
+    Some(SEND_RATE_LIMIT.load(Ordering::SeqCst)).filter(|limit| *limit > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_spaces_chunks_by_the_limit() {
+        let now = Instant::now();
+        let mut next = None;
+        // 第一块立即发送，之后每块按 bytes / limit 排队
+        assert_eq!(reserve(&mut next, 1000, 1000, now), Duration::ZERO);
+        assert_eq!(next, Some(now + Duration::from_secs(1)));
+        assert_eq!(reserve(&mut next, 500, 1000, now), Duration::from_secs(1));
+        assert_eq!(next, Some(now + Duration::from_millis(1500)));
+        assert_eq!(reserve(&mut next, 500, 1000, now + Duration::from_millis(500)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reserve_does_not_bank_idle_time() {
+        let now = Instant::now();
+        let mut next = Some(now);
+        let later = now + Duration::from_secs(10);
+        assert_eq!(reserve(&mut next, 2000, 1000, later), Duration::ZERO);
+        assert_eq!(next, Some(later + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn batch_limit_can_be_changed_while_sending() {
+        let pacer = SendPacer::new("rate-test", Some(1000));
+        assert!(set_batch_limit("rate-test", Some(5000)));
+        assert_eq!(pacer.limit.load(Ordering::SeqCst), 5000);
+        assert!(set_batch_limit("rate-test", None));
+        assert_eq!(pacer.limit.load(Ordering::SeqCst), 0);
+        drop(pacer);
+        assert!(!set_batch_limit("rate-test", Some(1000)));
+    }
+}
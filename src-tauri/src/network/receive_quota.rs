@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Window};
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+const INDEX_FILE: &str = "received_index.json";
+
+// 索引读写需串行，避免多个并发接收同时改写文件
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ReceivedEntry {
+    /// 桌面端为文件路径，Android SAF 为 document Uri
+    location: String,
+    name: String,
+    size: u64,
+    received_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ReceivedIndex {
+    /// 0 表示不限制
+    #[serde(default)]
+    quota_bytes: u64,
+    #[serde(default)]
+    files: Vec<ReceivedEntry>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EvictedFile {
+    pub name: String,
+    pub location: String,
+    pub size: u64,
+}
+
+fn entry_exists(entry: &ReceivedEntry) -> bool {
+    // SAF 文档无法廉价检查是否存在，交给删除时处理
+    entry.location.starts_with("content://") || std::path::Path::new(&entry.location).exists()
+}
+
+fn delete_entry(entry: &ReceivedEntry, app: &AppHandle) -> Result<(), String> {
+    if entry.location.starts_with("content://") {
+        #[cfg(target_os = "android")]
+        return app.state::<AndroidStorage>().delete_document(entry.location.clone());
+        #[cfg(not(target_os = "android"))]
+        {
+            let _ = app;
+            return Err("Content URIs can only be deleted on Android".to_string());
+        }
+    }
+    std::fs::remove_file(&entry.location)
+        .map_err(|e| format!("Failed to delete {}: {}", entry.location, e))
+}
+
+/// 按接收时间从旧到新删除文件，直到总大小不超过配额（最新接收的文件始终保留）
+fn enforce_quota(index: &mut ReceivedIndex, window: &Window, app: &AppHandle) {
+    // 用户已手动删除的文件不再计入
+    index.files.retain(entry_exists);
+
+    if index.quota_bytes == 0 {
+        return;
+    }
+
+    index.files.sort_by_key(|e| e.received_at);
+    let mut total: u64 = index.files.iter().map(|e| e.size).sum();
+
+    while total > index.quota_bytes && index.files.len() > 1 {
+        let entry = index.files.remove(0);
+        total = total.saturating_sub(entry.size);
+
+        if let Err(e) = delete_entry(&entry, app) {
+            eprintln!("Failed to evict {}: {}", entry.name, e);
+            continue;
+        }
+        println!("Evicted {} ({} bytes) to stay within receive quota", entry.name, entry.size);
+        window_events::emit(window, "file-evicted", EvictedFile {
+            name: entry.name,
+            location: entry.location,
+            size: entry.size,
+        });
+    }
+}
+
+/// 记录一个接收完成的文件，并在超出配额时淘汰最旧的文件。
+/// 索引读写和删除文件都是阻塞操作，放到阻塞线程池执行，不占用接收连接的异步线程
+pub(crate) async fn record_received(location: String, name: String, size: u64, window: &Window, app: &AppHandle) {
+    let (window, app) = (window.clone(), app.clone());
+    let recorded = tokio::task::spawn_blocking(move || record_received_blocking(location, name, size, &window, &app)).await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to record received file: {}", e);
+    }
+}
+
+fn record_received_blocking(location: String, name: String, size: u64, window: &Window, app: &AppHandle) {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index: ReceivedIndex = crate::config::load_json(app, INDEX_FILE);
+
+    // 同名覆盖时只保留最新记录
+    index.files.retain(|e| e.location != location);
+    index.files.push(ReceivedEntry {
+        location,
+        name,
+        size,
+        received_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64,
+    });
+
+    enforce_quota(&mut index, window, app);
+
+    if let Err(e) = crate::config::save_json(app, INDEX_FILE, &index) {
+        eprintln!("Failed to save received index: {}", e);
+    }
+}
+
+#[tauri::command]
+/// 设置接收目录配额（字节，0 表示不限制），立即按新配额淘汰旧文件
+pub async fn set_receive_quota(bytes: u64, window: Window, app: AppHandle) -> Result<(), TransferError> {
+    tokio::task::spawn_blocking(move || {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        let mut index: ReceivedIndex = crate::config::load_json(&app, INDEX_FILE);
+        index.quota_bytes = bytes;
+        enforce_quota(&mut index, &window, &app);
+        crate::config::save_json(&app, INDEX_FILE, &index).map_err(TransferError::Io)
+    }).await.map_err(|e| TransferError::Other(e.to_string()))?
+}
+
+#[tauri::command]
+/// 获取当前接收目录配额（字节，0 表示不限制）
+pub fn get_receive_quota(app: AppHandle) -> u64 {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let index: ReceivedIndex = crate::config::load_json(&app, INDEX_FILE);
+    index.quota_bytes
+}
@@ -0,0 +1,171 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::network::batch_progress::ReceivedFile;
+use crate::network::partials::partial_path;
+
+// 接收端选项：带 batch_id 的批次打包为单个 zip，而不是散落的文件
+static PACKAGE_AS_ZIP: AtomicBool = AtomicBool::new(false);
+
+/// 正在写入的批次压缩包
+struct ZipBatch {
+    writer: ZipWriter<BufWriter<File>>,
+    /// 最终路径（写入期间使用其临时文件）
+    path: PathBuf,
+    files: u32,
+    bytes: u64,
+    /// 已追加的文件，完成后记入批次进度
+    entries: Vec<ReceivedFile>,
+}
+
+/// 已完成的批次压缩包
+pub(crate) struct FinishedZip {
+    pub path: PathBuf,
+    pub files: u32,
+    /// 原始（未压缩）字节数
+    pub bytes: u64,
+    /// 包内的文件，location 为压缩包路径
+    pub entries: Vec<ReceivedFile>,
+}
+
+// 正在打包的批次（batch_id -> 压缩包）；同一批次的文件经不同连接依次到达
+static ZIP_BATCHES: LazyLock<Mutex<HashMap<String, ZipBatch>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// `folder-received` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct FolderReceived {
+    pub batch_id: String,
+    pub path: String,
+    pub files: u32,
+    pub bytes: u64,
+    pub peer_ip: String,
+}
+
+#[tauri::command]
+/// 设置接收的批次是否打包为单个 zip
+pub fn set_package_as_zip(enabled: bool) {
+    PACKAGE_AS_ZIP.store(enabled, Ordering::SeqCst);
+}
+
+#[tauri::command]
+/// 查询接收的批次是否打包为 zip
+pub fn get_package_as_zip() -> bool {
+    PACKAGE_AS_ZIP.load(Ordering::SeqCst)
+}
+
+pub(crate) fn enabled() -> bool {
+    PACKAGE_AS_ZIP.load(Ordering::SeqCst)
+}
+
+/// 批次中单个文件在追加到压缩包前的接收位置
+pub(crate) fn staging_path(save_dir: &str, batch_id: &str, index: u32) -> PathBuf {
+    let safe_id: String = batch_id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    Path::new(save_dir).join(format!(".{}-{}", safe_id, index))
+}
+
+/// 压缩包名：批次的顶层文件夹名，没有时使用 batch_id；同名文件已存在时追加序号
+fn unique_zip_path(save_dir: &str, stem: &str) -> PathBuf {
+    let dir = Path::new(save_dir);
+    let mut candidate = dir.join(format!("{}.zip", stem));
+    let mut n = 1;
+    while candidate.exists() || partial_path(&candidate).exists() {
+        candidate = dir.join(format!("{} ({}).zip", stem, n));
+        n += 1;
+    }
+    candidate
+}
+
+/// 批次的压缩包，首个条目到达时创建
+fn batch_zip<'a>(batches: &'a mut HashMap<String, ZipBatch>, save_dir: &str, batch_id: &str, entry_name: &str) -> Result<&'a mut ZipBatch, String> {
+    if !batches.contains_key(batch_id) {
+        let stem = entry_name.split('/').next()
+            .filter(|root| entry_name.contains('/') && !root.is_empty())
+            .unwrap_or(batch_id);
+        let path = unique_zip_path(save_dir, stem);
+        let file = File::create(partial_path(&path))
+            .map_err(|e| format!("Failed to create zip {}: {}", path.display(), e))?;
+        batches.insert(batch_id.to_string(), ZipBatch {
+            writer: ZipWriter::new(BufWriter::new(file)),
+            path,
+            files: 0,
+            bytes: 0,
+            entries: Vec::new(),
+        });
+    }
+    Ok(batches.get_mut(batch_id).unwrap())
+}
+
+/// 将一个已完整接收的文件追加到批次压缩包（首个文件时创建压缩包），随后删除该文件
+pub(crate) fn append(save_dir: &str, batch_id: &str, entry_name: &str, source: &Path, received: ReceivedFile) -> Result<(), String> {
+    let mut batches = ZIP_BATCHES.lock().unwrap();
+    let batch = batch_zip(&mut batches, save_dir, batch_id, entry_name)?;
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    batch.writer.start_file(entry_name, options)
+        .map_err(|e| format!("Failed to add {} to zip: {}", entry_name, e))?;
+    let mut input = File::open(source)
+        .map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let copied = std::io::copy(&mut input, &mut batch.writer)
+        .map_err(|e| format!("Failed to write {} to zip: {}", entry_name, e))?;
+    batch.files += 1;
+    batch.bytes += copied;
+    batch.entries.push(received);
+
+    drop(input);
+    let _ = std::fs::remove_file(source);
+    Ok(())
+}
+
+/// 将批次中的空目录作为目录条目写入压缩包（发送端在文件之前发送空目录）
+pub(crate) fn append_dir(save_dir: &str, batch_id: &str, dir_name: &str) -> Result<(), String> {
+    let mut batches = ZIP_BATCHES.lock().unwrap();
+    let batch = batch_zip(&mut batches, save_dir, batch_id, dir_name)?;
+    batch.writer.add_directory(dir_name, SimpleFileOptions::default())
+        .map_err(|e| format!("Failed to add directory {} to zip: {}", dir_name, e))
+}
+
+/// 批次最后一个文件到达后写入目录并改回最终文件名
+pub(crate) fn finish(batch_id: &str) -> Result<FinishedZip, String> {
+    let batch = ZIP_BATCHES.lock().unwrap().remove(batch_id)
+        .ok_or_else(|| format!("No zip in progress for batch {}", batch_id))?;
+
+    let partial = partial_path(&batch.path);
+    let mut out = batch.writer.finish()
+        .map_err(|e| format!("Failed to finish zip: {}", e))?;
+    std::io::Write::flush(&mut out)
+        .map_err(|e| format!("Failed to finish zip: {}", e))?;
+    drop(out);
+
+    std::fs::rename(&partial, &batch.path)
+        .map_err(|e| format!("Failed to rename zip {}: {}", batch.path.display(), e))?;
+    let location = batch.path.to_string_lossy().to_string();
+    let entries = batch.entries.into_iter()
+        .map(|entry| ReceivedFile { location: Some(location.clone()), ..entry })
+        .collect();
+    Ok(FinishedZip { path: batch.path, files: batch.files, bytes: batch.bytes, entries })
+}
+
+/// 批次取消或中断：丢弃未完成的压缩包
+pub(crate) fn abort(batch_id: &str) {
+    let Some(batch) = ZIP_BATCHES.lock().unwrap().remove(batch_id) else {
+        return;
+    };
+    let partial = partial_path(&batch.path);
+    drop(batch);
+    if let Err(e) = std::fs::remove_file(&partial) {
+        eprintln!("Failed to remove incomplete zip {}: {}", partial.display(), e);
+    } else {
+        println!("Removed incomplete zip: {}", batch_id);
+    }
+}
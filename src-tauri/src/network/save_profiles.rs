@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::network::error::TransferError;
+
+const PROFILES_FILE: &str = "save_profiles.json";
+
+#[derive(Serialize, Deserialize, Default)]
+struct SaveProfiles {
+    #[serde(default)]
+    active: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SaveProfile {
+    pub name: String,
+    pub dir: String,
+    pub active: bool,
+}
+
+// 当前激活配置对应的保存目录；None 表示使用 start_websocket_server 传入的目录
+static ACTIVE_PROFILE_DIR: Mutex<Option<String>> = Mutex::new(None);
+
+/// 从配置文件恢复激活的保存目录（接收服务启动时调用）
+pub(crate) fn load_active_profile(app: &AppHandle) {
+    let profiles: SaveProfiles = crate::config::load_json(app, PROFILES_FILE);
+    let dir = profiles.active.and_then(|name| profiles.profiles.get(&name).cloned());
+    *ACTIVE_PROFILE_DIR.lock().unwrap() = dir;
+}
+
+/// 激活配置的保存目录（每个新连接到来时读取，切换后无需重启服务器）
+pub(crate) fn active_profile_dir() -> Option<String> {
+    ACTIVE_PROFILE_DIR.lock().unwrap().clone()
+}
+
+/// 所有配置的保存目录（清理残留临时文件等场景使用）
+pub(crate) fn profile_dirs(app: &AppHandle) -> Vec<String> {
+    let profiles: SaveProfiles = crate::config::load_json(app, PROFILES_FILE);
+    profiles.profiles.into_values().collect()
+}
+
+fn validate_dir(dir: &str) -> Result<(), TransferError> {
+    // Android SAF tree Uri 无法在 Rust 端校验
+    if dir.starts_with("content://") {
+        return Ok(());
+    }
+    if !std::path::Path::new(dir).is_dir() {
+        return Err(TransferError::InvalidInput(format!("Not a directory: {}", dir)));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+/// 添加（或更新）一个命名的保存目录配置
+pub fn add_save_profile(name: String, dir: String, app: AppHandle) -> Result<(), TransferError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(TransferError::InvalidInput("Profile name must not be empty".to_string()));
+    }
+    validate_dir(&dir)?;
+
+    let mut profiles: SaveProfiles = crate::config::load_json(&app, PROFILES_FILE);
+    if profiles.active.as_deref() == Some(name.as_str()) {
+        *ACTIVE_PROFILE_DIR.lock().unwrap() = Some(dir.clone());
+    }
+    profiles.profiles.insert(name, dir);
+    crate::config::save_json(&app, PROFILES_FILE, &profiles).map_err(TransferError::Io)
+}
+
+#[tauri::command]
+/// 删除保存目录配置（若为当前激活配置则回退到默认目录）
+pub fn remove_save_profile(name: String, app: AppHandle) -> Result<(), TransferError> {
+    let mut profiles: SaveProfiles = crate::config::load_json(&app, PROFILES_FILE);
+    if profiles.profiles.remove(&name).is_none() {
+        return Err(TransferError::NotFound(format!("Profile not found: {}", name)));
+    }
+    if profiles.active.as_deref() == Some(name.as_str()) {
+        profiles.active = None;
+        *ACTIVE_PROFILE_DIR.lock().unwrap() = None;
+    }
+    crate::config::save_json(&app, PROFILES_FILE, &profiles).map_err(TransferError::Io)
+}
+
+#[tauri::command]
+/// 列出所有保存目录配置
+pub fn list_save_profiles(app: AppHandle) -> Vec<SaveProfile> {
+    let profiles: SaveProfiles = crate::config::load_json(&app, PROFILES_FILE);
+    profiles.profiles.iter().map(|(name, dir)| SaveProfile {
+        name: name.clone(),
+        dir: dir.clone(),
+        active: profiles.active.as_deref() == Some(name.as_str()),
+    }).collect()
+}
+
+#[tauri::command]
+/// 切换激活的保存目录配置，传 None 恢复使用默认保存目录。对之后的新连接立即生效
+pub fn set_active_save_profile(name: Option<String>, app: AppHandle) -> Result<(), TransferError> {
+    let mut profiles: SaveProfiles = crate::config::load_json(&app, PROFILES_FILE);
+    let dir = match &name {
+        Some(name) => Some(profiles.profiles.get(name).cloned()
+            .ok_or_else(|| TransferError::NotFound(format!("Profile not found: {}", name)))?),
+        None => None,
+    };
+    profiles.active = name;
+    crate::config::save_json(&app, PROFILES_FILE, &profiles).map_err(TransferError::Io)?;
+    *ACTIVE_PROFILE_DIR.lock().unwrap() = dir;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// 进度中可读大小使用的单位制
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeUnits {
+    /// 1024 进制：KiB、MiB、GiB
+    #[default]
+    Binary,
+    /// 1000 进制：KB、MB、GB
+    Decimal,
+}
+
+static SIZE_UNITS: Mutex<SizeUnits> = Mutex::new(SizeUnits::Binary);
+
+const BINARY_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+const DECIMAL_UNITS: [&str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
+
+/// 按当前单位制格式化字节数，例如 "1.2 GiB"
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    let (base, units) = match *SIZE_UNITS.lock().unwrap() {
+        SizeUnits::Binary => (1024.0, BINARY_UNITS),
+        SizeUnits::Decimal => (1000.0, DECIMAL_UNITS),
+    };
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= base && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+/// 格式化传输速度，例如 "35.4 MiB/s"
+pub(crate) fn format_speed(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec.max(0.0) as u64))
+}
+
+#[tauri::command]
+/// 设置进度事件中可读大小的单位制
+pub fn set_size_units(units: SizeUnits) {
+    *SIZE_UNITS.lock().unwrap() = units;
+}
+
+#[tauri::command]
+/// 查询进度事件中可读大小的单位制
+pub fn get_size_units() -> SizeUnits {
+    *SIZE_UNITS.lock().unwrap()
+}
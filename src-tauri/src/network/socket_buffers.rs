@@ -0,0 +1,236 @@
+use serde::Serialize;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::net::SocketAddr;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::network::error::TransferError;
+
+// 可设置的缓冲区大小范围
+const MIN_BUFFER_SIZE: usize = 4 * 1024;
+const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+// 传输连接的 SO_RCVBUF / SO_SNDBUF，0 表示使用系统默认值
+static RECV_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+static SEND_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+// 固定源端口被短暂占用（上一个连接尚未释放）时的重试次数与初始间隔
+const SOURCE_PORT_ATTEMPTS: u32 = 5;
+const SOURCE_PORT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+tokio::task_local! {
+    // 当前发送任务要求的本地源端口（SendOptions::source_port）
+    static SOURCE_PORT: Option<u16>;
+}
+
+/// 在指定源端口下执行发送：其中建立的所有出站连接都从该端口发起。
+/// 同一端口同时只能有一个连接，因此该发送中的连接会依次建立，多个使用同一端口的发送不能并行
+pub(crate) async fn with_source_port<F: Future>(port: Option<u16>, fut: F) -> F::Output {
+    SOURCE_PORT.scope(port, fut).await
+}
+
+/// 请求的缓冲区大小与系统实际分配的大小（Linux 上内核通常会翻倍并受 rmem_max/wmem_max 限制）
+#[derive(Serialize, Clone, Debug)]
+pub struct SocketBufferSizes {
+    /// None 表示使用系统默认值
+    pub requested_recv: Option<usize>,
+    pub requested_send: Option<usize>,
+    pub granted_recv: Option<usize>,
+    pub granted_send: Option<usize>,
+}
+
+fn configured(size: usize) -> Option<usize> {
+    (size > 0).then_some(size)
+}
+
+fn validate(size: Option<usize>, name: &str) -> Result<usize, TransferError> {
+    match size {
+        None => Ok(0),
+        Some(size) if (MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&size) => Ok(size),
+        Some(size) => Err(TransferError::InvalidInput(format!(
+            "{} buffer size {} is out of range ({}..={} bytes)",
+            name, size, MIN_BUFFER_SIZE, MAX_BUFFER_SIZE
+        ))),
+    }
+}
+
+/// 将配置的缓冲区大小应用到套接字（未配置时保持系统默认值）
+fn apply(socket: SockRef<'_>) {
+    if let Some(size) = configured(RECV_BUFFER_SIZE.load(Ordering::SeqCst)) {
+        if let Err(e) = socket.set_recv_buffer_size(size) {
+            eprintln!("Failed to set SO_RCVBUF to {}: {}", size, e);
+        }
+    }
+    if let Some(size) = configured(SEND_BUFFER_SIZE.load(Ordering::SeqCst)) {
+        if let Err(e) = socket.set_send_buffer_size(size) {
+            eprintln!("Failed to set SO_SNDBUF to {}: {}", size, e);
+        }
+    }
+}
+
+/// 用临时套接字探测当前配置下系统实际分配的大小
+fn probe() -> SocketBufferSizes {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).ok();
+    if let Some(socket) = &socket {
+        apply(SockRef::from(socket));
+    }
+    SocketBufferSizes {
+        requested_recv: configured(RECV_BUFFER_SIZE.load(Ordering::SeqCst)),
+        requested_send: configured(SEND_BUFFER_SIZE.load(Ordering::SeqCst)),
+        granted_recv: socket.as_ref().and_then(|s| s.recv_buffer_size().ok()),
+        granted_send: socket.as_ref().and_then(|s| s.send_buffer_size().ok()),
+    }
+}
+
+/// 绑定传输服务的监听套接字（非阻塞的标准库监听，可交给任意运行时）。
+/// 缓冲区需在 listen 前设置，接受的连接会继承该设置
+pub(crate) fn bind_listener(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // 与 TcpListener::bind 一致：非 Windows 平台允许快速重新绑定
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    // IPv6 地址同时接受 IPv4 连接（双栈）
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    apply(SockRef::from(&socket));
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+fn new_socket(addr: SocketAddr, source_port: Option<u16>) -> std::io::Result<TcpSocket> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    apply(SockRef::from(&socket));
+    if let Some(port) = source_port {
+        // 允许绑定仍处于 TIME_WAIT 的源端口
+        socket.set_reuseaddr(true)?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            (std::net::Ipv4Addr::UNSPECIFIED, port).into()
+        } else {
+            (std::net::Ipv6Addr::UNSPECIFIED, port).into()
+        };
+        socket.bind(local)?;
+    }
+    Ok(socket)
+}
+
+/// 建立出站传输连接，连接前应用缓冲区设置；发送指定了源端口时从该端口发起，
+/// 端口被短暂占用时按指数退避重试
+pub(crate) async fn connect(addr: SocketAddr) -> std::io::Result<TcpStream> {
+    let source_port = SOURCE_PORT.try_with(|port| *port).ok().flatten();
+    let mut delay = SOURCE_PORT_RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        let result = match new_socket(addr, source_port) {
+            Ok(socket) => socket.connect(addr).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if source_port.is_some()
+                && matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable)
+                && attempt < SOURCE_PORT_ATTEMPTS =>
+            {
+                eprintln!("Source port {:?} is busy ({}), retrying in {:?}", source_port, e, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[tauri::command]
+/// 设置传输连接的接收/发送缓冲区大小（字节，None 恢复系统默认值），
+/// 对之后建立的连接和重启后的接收服务生效。返回系统实际分配的大小
+pub fn set_socket_buffers(recv_bytes: Option<usize>, send_bytes: Option<usize>) -> Result<SocketBufferSizes, TransferError> {
+    let recv = validate(recv_bytes, "Receive")?;
+    let send = validate(send_bytes, "Send")?;
+    RECV_BUFFER_SIZE.store(recv, Ordering::SeqCst);
+    SEND_BUFFER_SIZE.store(send, Ordering::SeqCst);
+    Ok(probe())
+}
+
+#[tauri::command]
+/// 查询当前的缓冲区设置及系统实际分配的大小
+pub fn get_socket_buffers() -> SocketBufferSizes {
+    probe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn validates_buffer_sizes() {
+        assert_eq!(validate(None, "Receive").unwrap(), 0);
+        assert_eq!(validate(Some(MIN_BUFFER_SIZE), "Receive").unwrap(), MIN_BUFFER_SIZE);
+        assert_eq!(validate(Some(MAX_BUFFER_SIZE), "Send").unwrap(), MAX_BUFFER_SIZE);
+        assert!(validate(Some(MIN_BUFFER_SIZE - 1), "Receive").is_err());
+        assert!(validate(Some(MAX_BUFFER_SIZE + 1), "Send").is_err());
+    }
+
+    /// 通过 bind_listener / connect 建立的连接发送 total 字节，返回吞吐量（MiB/s）
+    async fn loopback_throughput(total: usize) -> f64 {
+        let listener = TcpListener::from_std(bind_listener("127.0.0.1:0".parse().unwrap()).unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reader = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 256 * 1024];
+            let mut received = 0;
+            loop {
+                match stream.read(&mut buf).await.unwrap() {
+                    0 => break received,
+                    n => received += n,
+                }
+            }
+        });
+
+        let mut stream = connect(addr).await.unwrap();
+        let chunk = vec![0x5au8; 256 * 1024];
+        let start = Instant::now();
+        for _ in 0..total / chunk.len() {
+            stream.write_all(&chunk).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+        assert_eq!(reader.await.unwrap(), total);
+        total as f64 / start.elapsed().as_secs_f64() / (1024.0 * 1024.0)
+    }
+
+    /// 结束时（包括断言失败）恢复原来的缓冲区设置，不影响同一进程中的其他测试
+    struct RestoreBuffers(usize, usize);
+
+    impl Drop for RestoreBuffers {
+        fn drop(&mut self) {
+            RECV_BUFFER_SIZE.store(self.0, Ordering::SeqCst);
+            SEND_BUFFER_SIZE.store(self.1, Ordering::SeqCst);
+        }
+    }
+
+    /// 基准：不同缓冲区设置下的吞吐量。回环没有链路延迟，差异远小于高带宽时延积的局域网，
+    /// 在目标网络上比较需在两台设备间实测。运行：
+    /// cargo test --release throughput_by_buffer_size -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn throughput_by_buffer_size() {
+        const TOTAL: usize = 2 * 1024 * 1024 * 1024;
+        let _restore = RestoreBuffers(RECV_BUFFER_SIZE.load(Ordering::SeqCst), SEND_BUFFER_SIZE.load(Ordering::SeqCst));
+        for size in [None, Some(64 * 1024), Some(256 * 1024), Some(4 * 1024 * 1024), Some(16 * 1024 * 1024)] {
+            let granted = set_socket_buffers(size, size).unwrap();
+            let throughput = loopback_throughput(TOTAL).await;
+            println!(
+                "requested {:?}: granted recv {:?} / send {:?}, {:.0} MiB/s",
+                size, granted.granted_recv, granted.granted_send, throughput
+            );
+        }
+    }
+}
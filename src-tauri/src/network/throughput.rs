@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Window;
+
+use crate::network::error::TransferError;
+use crate::network::window_events;
+
+// 默认与允许的最小统计周期
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+// 所有传输累计收发的字节数，由收发循环更新
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+// 统计任务，保留句柄以便停止或换用新的统计周期
+static THROUGHPUT_TASK: Mutex<Option<tokio::task::AbortHandle>> = Mutex::new(None);
+
+/// `network-throughput` 事件内容：上一周期内所有传输的收发总量
+#[derive(Serialize, Clone, Debug)]
+pub struct NetworkThroughput {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// 实际统计时长（毫秒）
+    pub interval_ms: u64,
+    pub sent_per_sec: f64,
+    pub received_per_sec: f64,
+}
+
+pub(crate) fn add_sent(bytes: u64) {
+    BYTES_SENT.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn add_received(bytes: u64) {
+    BYTES_RECEIVED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+#[tauri::command]
+/// 开始周期性发送 `network-throughput` 事件（interval_ms 默认 500）；已在运行时按新周期重新开始
+pub async fn start_throughput_events(interval_ms: Option<u64>, window: Window) -> Result<(), TransferError> {
+    let interval = interval_ms.map(Duration::from_millis).unwrap_or(DEFAULT_INTERVAL);
+    if interval < MIN_INTERVAL {
+        return Err(TransferError::InvalidInput(format!("Interval must be at least {} ms", MIN_INTERVAL.as_millis())));
+    }
+
+    let task = tokio::spawn(async move {
+        let mut last_sent = BYTES_SENT.load(Ordering::Relaxed);
+        let mut last_received = BYTES_RECEIVED.load(Ordering::Relaxed);
+        let mut last_tick = Instant::now();
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let sent = BYTES_SENT.load(Ordering::Relaxed);
+            let received = BYTES_RECEIVED.load(Ordering::Relaxed);
+            let elapsed = last_tick.elapsed();
+            last_tick = Instant::now();
+
+            let bytes_sent = sent.wrapping_sub(last_sent);
+            let bytes_received = received.wrapping_sub(last_received);
+            last_sent = sent;
+            last_received = received;
+
+            let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+            window_events::emit(&window, "network-throughput", NetworkThroughput {
+                bytes_sent,
+                bytes_received,
+                interval_ms: elapsed.as_millis() as u64,
+                sent_per_sec: bytes_sent as f64 / seconds,
+                received_per_sec: bytes_received as f64 / seconds,
+            });
+        }
+    });
+    if let Some(previous) = THROUGHPUT_TASK.lock().unwrap().replace(task.abort_handle()) {
+        previous.abort();
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+/// 停止发送 `network-throughput` 事件
+pub fn stop_throughput_events() {
+    if let Some(task) = THROUGHPUT_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+}
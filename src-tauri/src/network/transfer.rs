@@ -1,24 +1,47 @@
-use std::path::PathBuf;
-use tauri::{Window, AppHandle, Emitter};
+use std::path::{Path, PathBuf};
+use tauri::{Window, AppHandle};
 #[cfg(target_os = "android")]
-use crate::android_storage::AndroidStorage;
+use crate::android_storage::{AndroidStorage, UriReader};
 #[cfg(target_os = "android")]
 use base64::{engine::general_purpose, Engine as _};
-#[cfg(target_os = "android")]
 use tauri::Manager;
-use tokio::net::TcpListener;
 use tokio_tungstenite::{accept_async_with_config};
 use tokio_tungstenite::tungstenite::protocol::{Message, WebSocketConfig, CloseFrame};
 use futures_util::{StreamExt, SinkExt};
 use serde::{Deserialize, Serialize};
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
-use std::net::{UdpSocket, Ipv4Addr, SocketAddrV4};
-use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::TcpListener;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use std::net::{UdpSocket, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use socket2::{Socket, Domain, Type, Protocol};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use ring::digest::{self, Context, SHA256};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::oneshot;
+use crate::network::batch_progress::ReceivedFile;
+use crate::network::discovery_packet;
+use crate::network::jobs::{self, JobKind};
+use crate::network::receive_zip;
+use crate::network::peer_trust::{self, TrustLevel};
+use crate::network::folder_sync::{self, DiffEntry};
+use crate::network::folder_filter::FolderFilter;
+use crate::network::error::TransferError;
+use crate::network::window_events;
+use crate::network::size_units;
+use crate::network::socket_buffers;
+use crate::network::throughput;
+use crate::network::metered;
+use crate::network::ports::{self, Service};
+use crate::network::transfer_service;
+use crate::network::collision::{self, CollisionPolicy};
+use crate::network::compression;
+use crate::network::rate_limit;
+use crate::network::disk_space;
+use crate::network::device_name;
+use crate::network::transfer_tls;
+use crate::network::connection_pin;
 
 #[derive(Deserialize)]
 struct FileMeta {
@@ -31,313 +54,2552 @@ struct FileMeta {
     total: u32,
     #[serde(default)]
     relative_path: Option<String>,
+    #[serde(default)]
+    batch_id: Option<String>,
+    #[serde(default)]
+    mime: Option<String>,
+    /// 发送方附带的简短备注
+    #[serde(default)]
+    note: Option<String>,
+    /// 续传：接收端已有的部分数据长度，发送端从该位置开始发送
+    #[serde(default)]
+    resume_from: u64,
+    /// 发送前已知的整个文件的 SHA-256（小写十六进制），接收端据此校验（不用于续传）
+    #[serde(default)]
+    sha256: Option<String>,
+    /// 发送端边读边计算 SHA-256，在数据之后通过 Checksum 消息发送
+    #[serde(default)]
+    checksum_follows: bool,
+    /// 数据块经 gzip 压缩，接收端解压后写入
+    #[serde(default)]
+    compress: bool,
+    /// 源文件的修改时间（Unix 秒），接收完成后应用到保存的文件
+    #[serde(default)]
+    mtime: Option<i64>,
+    /// 空目录：没有数据帧，接收端按 relative_path 创建目录
+    #[serde(default)]
+    is_dir: bool,
 }
 
-/// Sanitize relative path to prevent path traversal attacks.
-/// Returns None if the path is invalid or attempts directory traversal.
-fn sanitize_relative_path(path: &str) -> Option<String> {
-    if path.is_empty() {
-        return None;
-    }
-
-    // Normalize path separators
-    let normalized = path.replace('\\', "/");
+/// 文件的修改时间（Unix 秒），早于 1970 年或无法获取时为 None
+fn unix_mtime(metadata: &std::fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    i64::try_from(modified.as_secs()).ok()
+}
 
-    // Split into components and validate each
-    let mut components: Vec<&str> = Vec::new();
-    for component in normalized.split('/') {
-        match component {
-            "" | "." => continue,  // Skip empty and current dir
-            ".." => return None,   // Reject parent dir traversal
-            c if c.contains('\0') => return None,  // Reject null bytes
-            c => components.push(c),
-        }
+/// 将接收完成的文件的修改时间设为源文件的时间（失败时只记录日志）
+fn apply_mtime(path: &Path, mtime: i64) {
+    if let Err(e) = filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(mtime, 0)) {
+        eprintln!("Failed to set modification time of {}: {}", path.display(), e);
     }
+}
 
-    if components.is_empty() {
-        return None;
+// 文件备注的最大长度（字符数）
+const NOTE_MAX_CHARS: usize = 200;
+
+/// 清理备注：控制字符替换为空格、去除首尾空白并截断长度，空备注返回 None
+fn sanitize_note(note: &str) -> Option<String> {
+    let cleaned: String = note
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(NOTE_MAX_CHARS)
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() {
+        None
+    } else {
+        Some(cleaned.to_string())
     }
+}
 
-    Some(components.join("/"))
+/// 清单中的单个文件条目
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestEntry {
+    index: u32,
+    name: String,
+    size: u64,
+    #[serde(default)]
+    relative_path: Option<String>,
 }
 
-#[derive(Serialize, Clone, Debug)]
-struct FileProgress {
-    file_name: String,
-    bytes_received: u64,
-    total_bytes: u64,
-    percentage: f64,
+/// 控制消息：与 FileMeta 共用 Text 帧，通过 `type` 字段区分（FileMeta 没有该字段）
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlMessage {
+    /// 发送端先发送文件清单，由接收端挑选需要的文件
+    Manifest { batch_id: String, files: Vec<ManifestEntry> },
+    /// 接收端回复同意接收的文件序号
+    ManifestResponse { batch_id: String, accepted: Vec<u32> },
+    /// 发送端续传前查询批次中接收端已完成的文件
+    BatchStatusQuery { batch_id: String },
+    /// 接收端回复已完成文件（相对路径或文件名）
+    BatchStatus { batch_id: String, completed: Vec<String> },
+    /// 发送端查询接收端支持的协议扩展
+    CapabilitiesQuery,
+    /// 接收端回复支持的协议扩展
+    Capabilities { capabilities: Vec<String> },
+    /// 稀疏传输：代替数据帧，表示接下来 len 字节全为 0
+    ZeroFill { len: u64 },
+    /// 接收端确认文件已完整写入（在关闭连接之前发送）
+    Complete { name: String, bytes: u64 },
+    /// 增量同步：发送端询问哪些文件与接收端保存目录中的不同
+    DiffQuery { files: Vec<DiffEntry> },
+    /// 接收端回复有变化的文件，以及大小相同、需要 sha256 进一步比较的文件
+    DiffResult { changed: Vec<u32>, needs_hash: Vec<u32> },
+    /// 拉取：请求方凭令牌请求对端把登记的文件推送过来
+    /// port 为拉取方接收服务的端口（旧版本不带，按发现结果或默认端口推送）
+    PullRequest {
+        token: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+    },
+    /// 对端回复令牌是否有效（有效时随后通过普通发送流程推送文件）
+    PullResponse { accepted: bool, error: Option<String> },
+    /// 时钟比较：请求方附带本机发送时间（Unix 毫秒）
+    TimeQuery { sent_at_ms: i64 },
+    /// 对端回复收到请求时自己的时间
+    TimeReply { sent_at_ms: i64, peer_time_ms: i64 },
+    /// 续传：发送端在文件元数据之前询问接收端是否保留了该文件上次中断时的部分数据
+    ResumeQuery { name: String, relative_path: Option<String>, size: u64 },
+    /// 接收端回复可续传的位置（0 表示从头发送）；收到带续传位置的元数据后也以此确认续传
+    ResumeOffset { offset: u64 },
+    /// 发送端在文件数据之后发送本次发送数据的 SHA-256（续传时只包含本次发送的部分）
+    Checksum { sha256: String },
+    /// 同一连接发送多个文件时，发送端在每个文件之后发送；接收端处理完该文件后原样回复
+    EndOfFile,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Device {
-    ip: String,
-    hostname: String,
-    last_seen: u64,
+/// 本端作为接收端支持的协议扩展（旧版本接收端不响应查询，视为不支持任何扩展）
+const RECEIVER_CAPABILITIES: &[&str] = &["manifest", "batch_status", "sparse", "diff", "pull", "clock", "resume_offset", "checksum", "multi_file", "gzip", "empty_dirs"];
+
+// 稀疏发送：按块检测全零数据，连续全零达到该长度才用 ZeroFill 代替
+const SPARSE_BLOCK_SIZE: usize = 4 * 1024;
+const SPARSE_MIN_RUN: usize = 64 * 1024;
+
+/// 稀疏发送时缓冲区拆分出的片段
+enum SparseSegment<'a> {
+    Data(&'a [u8]),
+    Zeros(u64),
 }
 
-type DeviceList = Arc<Mutex<HashMap<String, Device>>>;
+/// 将缓冲区拆分为数据段和足够长的全零段
+fn split_sparse(buf: &[u8]) -> Vec<SparseSegment<'_>> {
+    let mut segments = Vec::new();
+    let mut data_start = 0;
+    let mut zero_start: Option<usize> = None;
+    let mut pos = 0;
+
+    while pos < buf.len() {
+        let end = (pos + SPARSE_BLOCK_SIZE).min(buf.len());
+        if buf[pos..end].iter().all(|&b| b == 0) {
+            zero_start.get_or_insert(pos);
+        } else if let Some(start) = zero_start.take() {
+            if pos - start >= SPARSE_MIN_RUN {
+                if start > data_start {
+                    segments.push(SparseSegment::Data(&buf[data_start..start]));
+                }
+                segments.push(SparseSegment::Zeros((pos - start) as u64));
+                data_start = pos;
+            }
+        }
+        pos = end;
+    }
 
-// 全局状态：防止服务重复启动
-static DISCOVERY_RUNNING: AtomicBool = AtomicBool::new(false);
-static WEBSOCKET_RUNNING: AtomicBool = AtomicBool::new(false);
-// 取消发送标志
-static CANCEL_SENDING: AtomicBool = AtomicBool::new(false);
-// 取消接收标志
-static CANCEL_RECEIVING: AtomicBool = AtomicBool::new(false);
-// 当前保存目录（可在服务器运行期间更新）
-static CURRENT_SAVE_DIR: Mutex<String> = Mutex::new(String::new());
+    if let Some(start) = zero_start {
+        if buf.len() - start >= SPARSE_MIN_RUN {
+            if start > data_start {
+                segments.push(SparseSegment::Data(&buf[data_start..start]));
+            }
+            segments.push(SparseSegment::Zeros((buf.len() - start) as u64));
+            data_start = buf.len();
+        }
+    }
+    if data_start < buf.len() {
+        segments.push(SparseSegment::Data(&buf[data_start..]));
+    }
 
-#[tauri::command]
-/// 取消正在进行的文件发送
-pub fn cancel_file_sending() {
-    CANCEL_SENDING.store(true, Ordering::SeqCst);
+    segments
 }
 
-#[tauri::command]
-/// 取消正在进行的文件接收
-pub fn cancel_file_receiving() {
-    CANCEL_RECEIVING.store(true, Ordering::SeqCst);
+/// `transfer-batch-started` 事件内容，前端保存 batch_id 以便中断后续传
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchStarted {
+    pub batch_id: String,
+    pub total: u32,
+    /// 续传时因接收端已完成而跳过的文件数
+    pub skipped: u32,
+    /// 增量同步时因内容未变化而跳过的文件数
+    pub unchanged: u32,
+}
+
+/// `transfer-send-started` 事件内容：发送命令登记取消标志后立即发出，
+/// 前端据此在命令返回前用 cancel_file_sending 取消
+#[derive(Serialize, Clone, Debug)]
+pub struct SendStarted {
+    pub batch_id: String,
+    pub target_ip: String,
+}
+
+/// 清单中所有文件的父目录（已清理的相对路径，去重）
+fn manifest_parent_dirs(files: &[ManifestEntry]) -> Vec<String> {
+    let mut dirs: Vec<String> = files.iter()
+        .filter_map(|f| f.relative_path.as_deref().and_then(sanitize_relative_path))
+        .filter_map(|p| Path::new(&p).parent().map(|d| d.to_string_lossy().replace('\\', "/")))
+        .filter(|d| !d.is_empty())
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
 }
 
 #[tauri::command]
-/// 启动设备发现服务
-pub fn start_discovery(window: Window, app: tauri::AppHandle) {
-    // 防止重复启动
-    if DISCOVERY_RUNNING.swap(true, Ordering::SeqCst) {
-        println!("Discovery service already running");
-        return;
+/// 预先创建一批子目录（Android 通过 SAF 一次性创建并缓存，避免接收时逐个查找）
+pub fn precreate_dirs(tree_uri: String, relative_dirs: Vec<String>, app: AppHandle) -> Result<(), TransferError> {
+    let relative_dirs: Vec<String> = relative_dirs.iter()
+        .filter_map(|d| sanitize_relative_path(d))
+        .collect();
+    if relative_dirs.is_empty() {
+        return Ok(());
     }
 
-    // Get device name before spawning thread
     #[cfg(target_os = "android")]
-    let device_name = {
-        use tauri::Manager;
-        app.state::<crate::android_storage::AndroidStorage>()
-            .get_device_name()
-            .unwrap_or_else(|_| "Android".to_string())
-    };
-    #[cfg(not(target_os = "android"))]
-    let device_name = {
-        let _ = app;
-        hostname::get()
-            .unwrap_or_else(|_| "Unknown".into())
-            .to_string_lossy()
-            .to_string()
-    };
+    if tree_uri.starts_with("content://") {
+        let storage = app.state::<AndroidStorage>();
+        let dirs = storage.precreate_dirs(tree_uri.clone(), relative_dirs).map_err(TransferError::Io)?;
+        let mut cache = SAF_DIR_CACHE.lock().unwrap();
+        for (relative_dir, uri) in dirs {
+            cache.insert((tree_uri.clone(), relative_dir), uri);
+        }
+        return Ok(());
+    }
 
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async move {
-            if let Err(e) = run_discovery_service(window, device_name).await {
-                eprintln!("Discovery service error: {}", e);
-                DISCOVERY_RUNNING.store(false, Ordering::SeqCst);
-            }
-        });
-    });
+    let _ = app;
+    let root = Path::new(&tree_uri);
+    for relative_dir in relative_dirs {
+        let dir = root.join(&relative_dir);
+        if !is_within_save_dir(root, &dir) {
+            return Err(TransferError::InvalidInput(format!("Directory outside save directory: {}", relative_dir)));
+        }
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| TransferError::Io(format!("Failed to create directory {}: {}", relative_dir, e)))?;
+    }
+    Ok(())
 }
 
-async fn run_discovery_service(window: Window, hostname: String) -> Result<(), Box<dyn std::error::Error>> {
-    let devices: DeviceList = Arc::new(Mutex::new(HashMap::new()));
-    let local_ip = get_local_ip()?;
+/// `transfer-manifest` 事件内容，前端据此让用户勾选文件
+#[derive(Serialize, Clone, Debug)]
+struct ManifestRequest {
+    batch_id: String,
+    peer_ip: String,
+    files: Vec<ManifestEntry>,
+}
 
-    // 生成唯一实例 ID（用进程 ID�?
-    let instance_id = std::process::id().to_string();
+/// `transfer-request` 事件内容：连接上的首个文件到达，等待用户接受或拒绝
+#[derive(Serialize, Clone, Debug)]
+struct TransferRequest {
+    connection_id: String,
+    peer_ip: String,
+    file_name: String,
+    size: u64,
+    /// 批次文件总数（单个文件时为 0）
+    total: u32,
+    batch_id: Option<String>,
+}
 
-    // 组播地址�?39.x.x.x 为管理范围组播地址�?
-    let multicast_addr: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 88);
+/// 检查目标路径是否位于保存目录内。
+/// 对路径中已存在的最深祖先做 canonicalize（会解析符号链接），再判断是否仍以保存目录为前缀。
+pub(crate) fn is_within_save_dir(save_dir: &Path, target: &Path) -> bool {
+    let Ok(root) = std::fs::canonicalize(save_dir) else {
+        return false;
+    };
 
-    // 解析本机 IP 为 Ipv4Addr，用于指定组播发送接口
-    let local_ipv4: Ipv4Addr = local_ip.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let mut existing = Some(target);
+    while let Some(path) = existing {
+        // symlink_metadata 不跟随链接，悬空的符号链接也视为已存在
+        if std::fs::symlink_metadata(path).is_ok() {
+            return match std::fs::canonicalize(path) {
+                Ok(resolved) => resolved.starts_with(&root),
+                Err(_) => false,
+            };
+        }
+        existing = path.parent();
+    }
 
-    // 使用 socket2 创建可重用的 UDP socket
-    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-    socket.set_reuse_address(true)?;
-    #[cfg(unix)]
-    socket.set_reuse_port(true)?;
-    socket.set_nonblocking(true)?;
-    socket.set_multicast_ttl_v4(255)?;
-    // 显式指定组播发送接口，避免 Windows 多网卡时发到错误接口
-    socket.set_multicast_if_v4(&local_ipv4)?;
+    false
+}
 
-    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 37821);
-    socket.bind(&addr.into())?;
+/// `file-receive-bad-metadata` 事件内容
+#[derive(Serialize, Clone, Debug)]
+struct BadMetadata {
+    peer_ip: String,
+    raw: String,
+    error: String,
+}
 
-    // 加入组播�?
-    socket.join_multicast_v4(&multicast_addr, &local_ipv4)?;
+// 事件中保留的原始元数据长度
+const BAD_METADATA_PREVIEW_CHARS: usize = 256;
+// 元数据无法解析时关闭连接使用的 Close code
+const BAD_METADATA_CODE: u16 = 4400;
+
+/// 解析发送端的文件元数据，失败时返回 `file-receive-bad-metadata` 事件内容（原始内容截断到预览长度）
+fn parse_file_meta(peer_ip: &str, json_str: &str) -> Result<FileMeta, BadMetadata> {
+    serde_json::from_str::<FileMeta>(json_str).map_err(|e| BadMetadata {
+        peer_ip: peer_ip.to_string(),
+        raw: json_str.chars().take(BAD_METADATA_PREVIEW_CHARS).collect(),
+        error: e.to_string(),
+    })
+}
 
-    // 转换为标准库�?UdpSocket
-    let socket: UdpSocket = socket.into();
+// 元数据内容不合理（文件名、序号或大小）时关闭连接使用的 Close code
+const INVALID_METADATA_CODE: u16 = 4406;
+// 同名文件策略为跳过且已有同名文件时关闭连接使用的 Close code，发送端据此跳过该文件继续发送
+const FILE_EXISTS_CODE: u16 = 4412;
+// 文件名的最大长度（字节），与常见文件系统一致
+const MAX_FILE_NAME_BYTES: usize = 255;
+// 一个批次中的最大文件数
+const MAX_BATCH_FILES: u32 = 1_000_000;
+// 默认接受的单个文件最大大小：1 TiB
+const DEFAULT_MAX_RECEIVE_FILE_SIZE: u64 = 1 << 40;
+// 接受的单个文件最大大小，可用 set_max_receive_file_size 调整
+static MAX_RECEIVE_FILE_SIZE: AtomicU64 = AtomicU64::new(DEFAULT_MAX_RECEIVE_FILE_SIZE);
+
+/// `transfer-rejected-invalid` 事件内容
+#[derive(Serialize, Clone, Debug)]
+struct InvalidMetadata {
+    peer_ip: String,
+    name: String,
+    reason: String,
+}
 
-    let multicast_target = SocketAddrV4::new(multicast_addr, 37821);
+/// Windows 保留的设备名（不区分大小写，带扩展名同样保留），在 Windows 上无法作为文件名保存
+fn is_reserved_windows_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or("").trim_end_matches(' ').to_ascii_uppercase();
+    match stem.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => ["COM", "LPT"].iter().any(|prefix| {
+            stem.strip_prefix(prefix).is_some_and(|n| n.len() == 1 && matches!(n.as_bytes()[0], b'1'..=b'9'))
+        }),
+    }
+}
 
-    // 克隆 socket 用于发�?
-    let socket_send = socket.try_clone()?;
-    let instance_id_clone = instance_id.clone();
-    let devices_for_send = devices.clone();
+/// 绝对路径（`/`、`\` 开头或带盘符）
+fn is_absolute_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with(['/', '\\']) || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
 
-    // 任务1：定期发送组播 + 单播回复已知设备
-    // (格式: FILETRANSFER:IP:HOSTNAME:INSTANCE_ID)
-    tokio::spawn(async move {
-        loop {
-            let msg = format!("FILETRANSFER:{}:{}:{}", local_ip, hostname, instance_id_clone);
-            // 组播发送
-            let _ = socket_send.send_to(msg.as_bytes(), multicast_target);
-            // 单播发送给所有已知设备（解决路由器组播单向不通的问题）
-            if let Ok(known) = devices_for_send.lock() {
-                for device in known.values() {
-                    if let Ok(ip) = device.ip.parse::<Ipv4Addr>() {
-                        let target = SocketAddrV4::new(ip, 37821);
-                        let _ = socket_send.send_to(msg.as_bytes(), target);
-                    }
-                }
-            }
-            tokio::time::sleep(Duration::from_secs(3)).await;
+/// 检查发送端声明的元数据：文件名不得包含路径分隔符、控制字符或 Windows 保留名，
+/// 相对路径不得为绝对路径或包含 `..`，序号、文件数与大小需在合理范围内
+fn validate_meta(meta: &FileMeta) -> Result<(), String> {
+    let name = meta.name.as_str();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("empty file name".to_string());
+    }
+    if name.len() > MAX_FILE_NAME_BYTES {
+        return Err(format!("file name longer than {} bytes", MAX_FILE_NAME_BYTES));
+    }
+    if name.contains(['/', '\\']) {
+        return Err("file name contains a path separator".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("file name contains control characters".to_string());
+    }
+    if is_reserved_windows_name(name) {
+        return Err("file name is a reserved device name".to_string());
+    }
+    if let Some(path) = meta.relative_path.as_deref() {
+        if path.chars().any(char::is_control) {
+            return Err("relative path contains control characters".to_string());
         }
-    });
-
-    // 任务2：接收组播并更新设备列表
-    let devices_clone = devices.clone();
-    let window_clone = window.clone();
-
-    tokio::spawn(async move {
-        let mut buf = [0u8; 1024];
-        loop {
-            match socket.recv_from(&mut buf) {
-                Ok((len, _)) => {
-                    if let Ok(msg) = std::str::from_utf8(&buf[..len]) {
-                        if msg.starts_with("FILETRANSFER:") {
-                            let parts: Vec<&str> = msg.strip_prefix("FILETRANSFER:").unwrap().split(':').collect();
-                            if parts.len() >= 3 {
-                                let ip = parts[0].to_string();
-                                let hostname = parts[1].to_string();
-                                let remote_instance_id = parts[2];
-
-                                // 用实�?ID 判断是否是自己（而不�?IP�?
-                                if remote_instance_id != instance_id {
-                                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-                                    let device = Device {
-                                        ip: ip.clone(),
-                                        hostname,
-                                        last_seen: now,
-                                    };
-
-                                    let mut devices = devices_clone.lock().unwrap();
-                                    devices.insert(ip.clone(), device);
-
-                                    // 发送更新到前端
-                                    let device_list: Vec<Device> = devices.values().cloned().collect();
-                                    let _ = window_clone.emit("devices-updated", device_list);
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // 非阻塞模式下没有数据，正常情�?
-                }
-                Err(e) => {
-                    eprintln!("UDP recv error: {}", e);
-                }
-            }
-            tokio::time::sleep(Duration::from_millis(100)).await;
+        if is_absolute_path(path) {
+            return Err("relative path is absolute".to_string());
         }
-    });
-
-    // 任务3：定期清理过期设备（30秒未响应�?
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-            let mut devices = devices.lock().unwrap();
-            devices.retain(|_, device| now - device.last_seen < 30);
-
-            let device_list: Vec<Device> = devices.values().cloned().collect();
-            let _ = window.emit("devices-updated", device_list);
+        let mut components = path.split(['/', '\\']);
+        if components.clone().any(|c| c == "..") {
+            return Err("relative path escapes the save directory".to_string());
         }
-    });
-
-    // 保持运行
-    loop {
-        tokio::time::sleep(Duration::from_secs(3600)).await;
+        if components.any(is_reserved_windows_name) {
+            return Err("relative path contains a reserved device name".to_string());
+        }
+    }
+    if meta.total > MAX_BATCH_FILES {
+        return Err(format!("batch of {} files exceeds the limit of {}", meta.total, MAX_BATCH_FILES));
     }
+    if meta.total > 0 && meta.index >= meta.total {
+        return Err(format!("file index {} out of range for {} files", meta.index, meta.total));
+    }
+    let max_size = MAX_RECEIVE_FILE_SIZE.load(Ordering::Relaxed);
+    if meta.size > max_size {
+        return Err(format!("file size {} exceeds the limit of {} bytes", meta.size, max_size));
+    }
+    if meta.resume_from > meta.size {
+        return Err(format!("resume offset {} beyond file size {}", meta.resume_from, meta.size));
+    }
+    Ok(())
 }
 
 #[tauri::command]
-/// 获取系统下载目录
-pub fn get_download_dir(app: AppHandle) -> Result<String, String> {
-    // Android: 直接使用标准下载目录
-    #[cfg(target_os = "android")]
-    {
-        return Ok("/storage/emulated/0/Download".to_string());
+/// 设置接收端接受的单个文件最大大小（字节），声明更大的文件以 Close code 4406 拒绝。None 恢复默认（1 TiB）
+pub fn set_max_receive_file_size(bytes: Option<u64>) -> Result<(), TransferError> {
+    if bytes == Some(0) {
+        return Err(TransferError::InvalidInput("Maximum file size must be greater than 0".into()));
     }
+    MAX_RECEIVE_FILE_SIZE.store(bytes.unwrap_or(DEFAULT_MAX_RECEIVE_FILE_SIZE), Ordering::Relaxed);
+    Ok(())
+}
 
-    // 桌面端：使用系统下载目录
-    #[allow(unreachable_code)]
-    if let Some(dir) = dirs::download_dir().or_else(|| dirs::home_dir()) {
-        return Ok(dir.to_string_lossy().to_string());
+#[tauri::command]
+/// 设置同时处理的接收连接数上限（默认 4），超出的连接排队等待，等待期间仍回应 ping
+pub fn set_max_concurrent_receives(n: usize) -> Result<(), TransferError> {
+    if n == 0 {
+        return Err(TransferError::InvalidInput("Concurrent receive limit must be at least 1".into()));
+    }
+    let mut limit = MAX_CONCURRENT_RECEIVES.lock().unwrap();
+    if n > *limit {
+        RECEIVE_PERMITS.add_permits(n - *limit);
+    } else if n < *limit {
+        // 先收回空闲的许可，其余在进行中的连接结束后收回
+        let excess = *limit - n;
+        let remaining = excess - RECEIVE_PERMITS.forget_permits(excess);
+        if remaining > 0 {
+            tauri::async_runtime::spawn(async move {
+                if let Ok(permits) = RECEIVE_PERMITS.acquire_many(remaining as u32).await {
+                    permits.forget();
+                }
+            });
+        }
     }
+    println!("Max concurrent receives set to {}", n);
+    *limit = n;
+    Ok(())
+}
 
-    let _ = app; // 桌面端避�?unused 警告
-    Err("无法获取下载目录".to_string())
+/// `file-receiving-details` 事件内容，与 `file-receiving`（仅文件名）同时发送。
+/// peer_ip 为连接的实际对端地址，同机调试时可用 connection_id 区分
+#[derive(Serialize, Clone, Debug)]
+pub struct FileReceiving {
+    pub name: String,
+    pub peer_ip: String,
+    pub connection_id: String,
+    pub note: Option<String>,
+    /// 文件在批次中的序号（从 0 开始），total 为 0 表示发送端未提供
+    pub index: u32,
+    pub total: u32,
+    pub size: u64,
 }
 
-#[tauri::command]
-/// 获取本机局域网IP地址
-pub fn get_local_ip() -> Result<String, String> {
-    // 通过连接到外部地址（不实际发送数据）来获取本机IP
-    let socket = UdpSocket::bind("0.0.0.0:0")
-        .map_err(|e| format!("Failed to bind socket: {}", e))?;
+/// 发送端 `file-sending` / `file-sent` 事件内容
+#[derive(Serialize, Clone, Debug)]
+pub struct FileSending {
+    pub name: String,
+    /// 文件在本次发送中的序号（从 0 开始）
+    pub index: u32,
+    pub total: u32,
+    pub size: u64,
+}
 
-    socket.connect("8.8.8.8:80")
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+/// 取消已实际生效（传输循环已退出、残留文件已清理）后的确认事件
+#[derive(Serialize, Clone, Debug)]
+pub struct TransferCancelled {
+    /// 与取消时传入的 ID 一致：发送为 cancel_file_sending 的批次 ID，接收为 cancel_file_receiving 的连接 ID
+    pub transfer_id: String,
+    /// "send" 或 "receive"
+    pub direction: &'static str,
+    pub file_name: Option<String>,
+    pub bytes_transferred: u64,
+}
 
-    let local_addr = socket.local_addr()
-        .map_err(|e| format!("Failed to get local address: {}", e))?;
+fn emit_transfer_cancelled(
+    window: &Window,
+    transfer_id: &str,
+    direction: &'static str,
+    file_name: Option<String>,
+    bytes_transferred: u64,
+) {
+    window_events::emit(window, "transfer-cancelled", TransferCancelled {
+        transfer_id: transfer_id.to_string(),
+        direction,
+        file_name,
+        bytes_transferred,
+    });
+}
 
-    Ok(local_addr.ip().to_string())
+/// `file-sent-unconfirmed` 事件内容：连接正常关闭但未收到接收端的 complete 确认；
+/// 接收端因同名文件跳过时以同样内容发送 `file-send-skipped`
+#[derive(Serialize, Clone, Debug)]
+pub struct TransferUnconfirmed {
+    pub transfer_id: String,
+    pub file_name: String,
 }
 
-#[tauri::command]
-/// 弹出文件夹选择对话框并返回路径字符�?
-pub async fn select_folder(app: AppHandle) -> Result<Option<String>, String> {
-    #[cfg(not(target_os = "android"))]
-    {
-        use tauri_plugin_dialog::DialogExt;
+/// 发送端：接收端对一个文件的处理结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Receipt {
+    /// 收到 complete 确认
+    Confirmed,
+    /// 连接正常关闭但没有确认（旧版本接收端不发送确认）
+    Unconfirmed,
+    /// 接收端已有同名文件且策略为跳过，以 FILE_EXISTS_CODE 关闭了连接
+    Skipped,
+}
 
-        // 使用 blocking 版本适合异步命令
-        let folder_path = app.dialog().file().blocking_pick_folder();
+impl Receipt {
+    fn from_confirmed(confirmed: bool) -> Self {
+        if confirmed { Receipt::Confirmed } else { Receipt::Unconfirmed }
+    }
+}
 
-        match folder_path {
-            Some(fp) => {
-                let pathbuf = fp.into_path().map_err(|e| e.to_string())?;
-                Ok(Some(pathbuf.to_string_lossy().to_string()))
-            }
-            None => Ok(None),
+/// 发送端：写入失败后检查接收端的 Close 码（可能仍在接收缓冲区中）。
+/// 接收端跳过了该文件时返回 Skipped，其他错误码转换为对应错误，否则返回 fallback
+async fn receiver_closed(
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    fallback: TransferError,
+) -> Result<Receipt, TransferError> {
+    if let Ok(Some(Ok(Message::Close(Some(frame))))) =
+        tokio::time::timeout(Duration::from_millis(500), read.next()).await
+    {
+        let code: u16 = frame.code.into();
+        if code == FILE_EXISTS_CODE {
+            return Ok(Receipt::Skipped);
+        }
+        if let Some(err) = receiver_close_error(code) {
+            return Err(err);
         }
     }
+    Err(fallback)
+}
 
-    // Android: 使用 SAF 选择文件夹并返回 tree Uri
+/// 发送端：发送 Close 后读取接收端的回复直到对方关闭。
+/// 返回接收端的处理结果；接收端以错误码关闭或超时未关闭时返回对应错误
+async fn await_receiver_close(
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+) -> Result<Receipt, TransferError> {
+    tokio::time::timeout(CONTROL_REPLY_TIMEOUT, async {
+        let mut confirmed = false;
+        while let Some(msg) = read.next().await {
+            match msg {
+                Ok(Message::Text(text)) => {
+                    if let Ok(ControlMessage::Complete { .. }) = serde_json::from_str::<ControlMessage>(&text) {
+                        confirmed = true;
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    if let Some(frame) = frame {
+                        let code: u16 = frame.code.into();
+                        if code == FILE_EXISTS_CODE {
+                            return Ok(Receipt::Skipped);
+                        }
+                        if let Some(err) = receiver_close_error(code) {
+                            return Err(err);
+                        }
+                    }
+                    break;
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        Ok(Receipt::from_confirmed(confirmed))
+    }).await.map_err(|_| TransferError::Timeout("Timed out waiting for receiver to close the connection".to_string()))?
+}
+
+/// 发送端：一个文件的数据已发送完。复用连接时发送 EndOfFile 并等待接收端处理完该文件，
+/// 否则关闭连接。返回接收端的处理结果；接收端以错误码关闭或超时未回复时返回对应错误
+async fn finish_file(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    keep_open: bool,
+) -> Result<Receipt, TransferError> {
+    if !keep_open {
+        if let Err(e) = write.send(Message::Close(None)).await {
+            return receiver_closed(read, TransferError::ConnectionFailed(format!("Failed to close connection: {}", e), None)).await;
+        }
+        return await_receiver_close(read).await;
+    }
+
+    let end = serde_json::to_string(&ControlMessage::EndOfFile)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize end of file: {}", e)))?;
+    if let Err(e) = write.send(Message::Text(end)).await {
+        return receiver_closed(read, TransferError::ConnectionFailed(format!("Failed to send end of file: {}", e), None)).await;
+    }
+
+    tokio::time::timeout(CONTROL_REPLY_TIMEOUT, async {
+        let mut confirmed = false;
+        while let Some(msg) = read.next().await {
+            match msg {
+                Ok(Message::Text(text)) => match serde_json::from_str::<ControlMessage>(&text) {
+                    Ok(ControlMessage::Complete { .. }) => confirmed = true,
+                    Ok(ControlMessage::EndOfFile) => return Ok(Receipt::from_confirmed(confirmed)),
+                    _ => {}
+                },
+                Ok(Message::Close(frame)) => {
+                    let code: Option<u16> = frame.map(|frame| frame.code.into());
+                    if code == Some(FILE_EXISTS_CODE) {
+                        return Ok(Receipt::Skipped);
+                    }
+                    if let Some(err) = code.and_then(receiver_close_error) {
+                        return Err(err);
+                    }
+                    break;
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        Err(TransferError::ConnectionFailed("Connection closed by receiver".to_string(), None))
+    }).await.map_err(|_| TransferError::Timeout("Timed out waiting for receiver to finish the file".to_string()))?
+}
+
+/// 接收端支持时返回要创建的空目录，否则忽略（旧版本接收端会把目录当作空文件保存）
+async fn empty_dirs_to_send(window: &Window, target_ip: &str, dirs: Vec<String>) -> Vec<String> {
+    if dirs.is_empty() || supports(window, target_ip, "empty_dirs").await {
+        return dirs;
+    }
+    println!("{} does not support empty directories, skipping {} of them", target_ip, dirs.len());
+    Vec::new()
+}
+
+/// 发送端：让接收端创建一个空目录（元数据标记 is_dir，没有数据帧）。返回接收端的处理结果
+async fn send_empty_dir(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    relative_path: &str,
+    batch_id: &str,
+    note: &Option<String>,
+    total: u32,
+    keep_open: bool,
+) -> Result<Receipt, TransferError> {
+    let name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+    // total 为批次中的文件数，接收端据此判断能否把目录写入批次压缩包
+    let meta = serde_json::json!({
+        "name": name,
+        "relative_path": relative_path,
+        "batch_id": batch_id,
+        "note": note,
+        "total": total,
+        "is_dir": true,
+    });
+    let meta_str = serde_json::to_string(&meta)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize metadata: {}", e)))?;
+    write.send(Message::Text(meta_str)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send metadata: {}", e), None))?;
+    finish_file(write, read, keep_open).await
+}
+
+/// 未收到确认时提醒用户文件可能不完整（旧版本接收端不发送确认）；接收端跳过时通知前端
+fn report_receipt(window: &Window, receipt: Receipt, transfer_id: &str, file_name: &str) {
+    let event = match receipt {
+        Receipt::Confirmed => return,
+        Receipt::Unconfirmed => {
+            println!("No completion ack for {}, receiver may not have the complete file", file_name);
+            "file-sent-unconfirmed"
+        }
+        Receipt::Skipped => {
+            println!("Receiver skipped {}: a file with the same name already exists", file_name);
+            "file-send-skipped"
+        }
+    };
+    window_events::emit(window, event, TransferUnconfirmed {
+        transfer_id: transfer_id.to_string(),
+        file_name: file_name.to_string(),
+    });
+}
+
+/// Sanitize relative path to prevent path traversal attacks.
+/// Returns None if the path is invalid or attempts directory traversal.
+pub(crate) fn sanitize_relative_path(path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+
+    // Normalize path separators
+    let normalized = path.replace('\\', "/");
+
+    // Split into components and validate each
+    let mut components: Vec<&str> = Vec::new();
+    for component in normalized.split('/') {
+        match component {
+            "" | "." => continue,  // Skip empty and current dir
+            ".." => return None,   // Reject parent dir traversal
+            c if c.contains('\0') => return None,  // Reject null bytes
+            c => components.push(c),
+        }
+    }
+
+    if components.is_empty() {
+        return None;
+    }
+
+    Some(components.join("/"))
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct FileProgress {
+    file_name: String,
+    bytes_received: u64,
+    total_bytes: u64,
+    percentage: f64,
+    /// 最近几次进度之间的速度（字节/秒）
+    speed_bytes_per_sec: f64,
+    /// 按当前速度估计的剩余时间，速度未知时为 None
+    eta_seconds: Option<f64>,
+    /// 按 size_units 设置格式化的大小与速度，收发两端显示一致
+    received_human: String,
+    total_human: String,
+    speed_human: String,
+}
+
+impl FileProgress {
+    /// rate 为当前文件的速度窗口，每次生成进度时记录一次
+    fn new(file_name: String, bytes_received: u64, total_bytes: u64, rate: &mut ProgressRate) -> Self {
+        let speed = rate.sample(bytes_received);
+        let remaining = total_bytes.saturating_sub(bytes_received);
+        let eta_seconds = if remaining == 0 {
+            Some(0.0)
+        } else if speed > 0.0 {
+            Some(remaining as f64 / speed)
+        } else {
+            None
+        };
+        FileProgress {
+            file_name,
+            bytes_received,
+            total_bytes,
+            percentage: (bytes_received as f64 / total_bytes as f64) * 100.0,
+            speed_bytes_per_sec: speed,
+            eta_seconds,
+            received_human: size_units::format_bytes(bytes_received),
+            total_human: size_units::format_bytes(total_bytes),
+            speed_human: size_units::format_speed(speed),
+        }
+    }
+}
+
+/// `folder-transfer-progress` 事件内容：文件夹发送的总体进度（当前文件的进度仍见 file-transfer-progress）
+#[derive(Serialize, Clone, Debug)]
+struct FolderProgress {
+    batch_id: String,
+    files_done: u32,
+    files_total: u32,
+    bytes_done: u64,
+    bytes_total: u64,
+    percentage: f64,
+}
+
+impl FolderProgress {
+    fn new(batch_id: &str, files_total: u32, bytes_total: u64) -> Self {
+        FolderProgress {
+            batch_id: batch_id.to_string(),
+            files_done: 0,
+            files_total,
+            bytes_done: 0,
+            bytes_total,
+            percentage: 0.0,
+        }
+    }
+
+    /// 当前文件已发送 current_bytes 字节时的总体进度
+    fn emit(&self, window: &Window, current_bytes: u64) {
+        let bytes_done = self.bytes_done + current_bytes;
+        let percentage = if self.bytes_total > 0 {
+            (bytes_done as f64 / self.bytes_total as f64) * 100.0
+        } else {
+            (self.files_done as f64 / self.files_total.max(1) as f64) * 100.0
+        };
+        window_events::emit(window, "folder-transfer-progress", FolderProgress {
+            bytes_done,
+            percentage,
+            ..self.clone()
+        });
+    }
+
+    fn file_done(&mut self, window: &Window, size: u64) {
+        self.files_done += 1;
+        self.bytes_done += size;
+        self.emit(window, 0);
+    }
+}
+
+// 速度按最近几次进度的滑动窗口计算，反映当前而非整个文件的平均速度
+const RATE_WINDOW_SAMPLES: usize = 5;
+
+/// 单个文件的速度窗口：最近几次进度的 (时间, 已传输字节数)
+struct ProgressRate {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl ProgressRate {
+    /// start_bytes 为开始传输时已有的字节数（续传时不为 0）
+    fn new(start_bytes: u64) -> Self {
+        ProgressRate { samples: VecDeque::from([(Instant::now(), start_bytes)]) }
+    }
+
+    /// 记录一次进度，返回窗口内的速度（字节/秒）
+    fn sample(&mut self, bytes: u64) -> f64 {
+        let now = Instant::now();
+        self.samples.push_back((now, bytes));
+        while self.samples.len() > RATE_WINDOW_SAMPLES + 1 {
+            self.samples.pop_front();
+        }
+        let (since, start_bytes) = self.samples[0];
+        let seconds = now.duration_since(since).as_secs_f64();
+        if seconds > 0.0 { bytes.saturating_sub(start_bytes) as f64 / seconds } else { 0.0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Device {
+    ip: String,
+    hostname: String,
+    last_seen: u64,
+    /// 对端公布的服务端口
+    #[serde(default)]
+    ports: Option<ports::ServicePorts>,
+    /// 通过 IPv6 链路本地地址发现时所在接口的 scope id（连接时需要）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope_id: Option<u32>,
+    /// 对端启用传输加密时公布的证书指纹，连接时据此校验
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tls_fingerprint: Option<String>,
+    /// 对端公布的设备 ID（旧版本没有）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    device_id: Option<String>,
+}
+
+type DeviceList = Arc<Mutex<HashMap<String, Device>>>;
+
+// 当前发现的设备（IP -> 设备），发送时按设备名解析目标 IP
+static DISCOVERED_DEVICES: LazyLock<DeviceList> = LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
+
+/// 解析发送目标：IP 原样返回，否则按设备名（忽略大小写）在已发现设备中查找当前 IP。
+/// 同名设备有多个 IP 时取最近一次出现的
+pub(crate) fn resolve_target(target: &str) -> Result<String, TransferError> {
+    let target = target.trim();
+    if target.parse::<std::net::IpAddr>().is_ok() {
+        return Ok(target.to_string());
+    }
+
+    DISCOVERED_DEVICES.lock().unwrap()
+        .values()
+        .filter(|d| d.hostname.eq_ignore_ascii_case(target))
+        .max_by_key(|d| d.last_seen)
+        .map(|d| d.ip.clone())
+        .ok_or_else(|| TransferError::DeviceNotFound(format!("{} is not currently discovered", target)))
+}
+
+/// 已发现设备中该 IP 对应的设备名
+pub(crate) fn discovered_hostname(ip: &str) -> Option<String> {
+    DISCOVERED_DEVICES.lock().unwrap().get(ip).map(|d| d.hostname.clone())
+}
+
+/// 已发现设备公布的设备 ID
+pub(crate) fn discovered_device_id(ip: &str) -> Option<String> {
+    DISCOVERED_DEVICES.lock().unwrap().get(ip).and_then(|d| d.device_id.clone())
+}
+
+/// IPv6 链路本地地址补上发现该设备时记录的接口 scope id，否则无法连接
+pub(crate) fn scoped_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) if v6.ip().is_unicast_link_local() && v6.scope_id() == 0 => {
+            let scope_id = DISCOVERED_DEVICES.lock().unwrap()
+                .get(&v6.ip().to_string())
+                .and_then(|d| d.scope_id)
+                .unwrap_or(0);
+            SocketAddr::V6(SocketAddrV6::new(*v6.ip(), v6.port(), 0, scope_id))
+        }
+        addr => addr,
+    }
+}
+
+/// 已发现设备在广播中公布的服务端口（旧版本不公布端口时为 None）
+pub(crate) fn discovered_ports(ip: &str) -> Option<ports::ServicePorts> {
+    DISCOVERED_DEVICES.lock().unwrap().get(ip).and_then(|d| d.ports)
+}
+
+/// 已发现设备公布的传输证书指纹（未启用加密或旧版本时为 None）
+pub(crate) fn discovered_tls_fingerprint(ip: &str) -> Option<String> {
+    DISCOVERED_DEVICES.lock().unwrap().get(ip).and_then(|d| d.tls_fingerprint.clone())
+}
+
+// 全局状态：防止服务重复启动
+// 设备发现默认使用的组播组与端口
+const MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 88);
+const DISCOVERY_PORT: u16 = 37821;
+// 默认组播组对应的 IPv6 链路本地组播组（端口与 IPv4 相同）
+const MULTICAST_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x7878);
+// 发现广播的周期
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(3);
+// 当前使用的组播组与端口（start_discovery 可指定，需各设备一致才能互相发现）
+static DISCOVERY_GROUP: Mutex<SocketAddrV4> = Mutex::new(SocketAddrV4::new(MULTICAST_GROUP, DISCOVERY_PORT));
+// 组播自检时监听的时长（略长于发现广播的 3 秒周期，以便看到其他设备）
+const MULTICAST_TEST_LISTEN: Duration = Duration::from_millis(3500);
+
+static DISCOVERY_RUNNING: AtomicBool = AtomicBool::new(false);
+// 设备发现的运行代数：stop_discovery 时加一，旧代的收发任务检测到后退出
+static DISCOVERY_GENERATION: AtomicU64 = AtomicU64::new(0);
+static WEBSOCKET_RUNNING: AtomicBool = AtomicBool::new(false);
+// 服务任务句柄，供 force_reset_servers 中止
+static DISCOVERY_TASK: Mutex<Option<tokio::task::AbortHandle>> = Mutex::new(None);
+static WEBSOCKET_SERVER_TASK: Mutex<Option<tokio::task::AbortHandle>> = Mutex::new(None);
+// 通知接收服务的监听循环退出，附带的 Sender 在监听端口释放后回复
+static WEBSOCKET_STOP: Mutex<Option<oneshot::Sender<oneshot::Sender<()>>>> = Mutex::new(None);
+// stop_websocket_server 等待监听端口释放的最长时间
+const WEBSOCKET_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+// 正在处理的接收连接数，优雅停止时等待其归零
+static ACTIVE_RECEIVES: AtomicUsize = AtomicUsize::new(0);
+// 进行中的接收全部结束时唤醒等待者
+static RECEIVES_FINISHED: tokio::sync::Notify = tokio::sync::Notify::const_new();
+// 同时处理的接收连接数上限，超出的连接排队等待许可
+const DEFAULT_MAX_CONCURRENT_RECEIVES: usize = 4;
+static MAX_CONCURRENT_RECEIVES: Mutex<usize> = Mutex::new(DEFAULT_MAX_CONCURRENT_RECEIVES);
+static RECEIVE_PERMITS: LazyLock<Arc<tokio::sync::Semaphore>> =
+    LazyLock::new(|| Arc::new(tokio::sync::Semaphore::new(DEFAULT_MAX_CONCURRENT_RECEIVES)));
+// 优雅停止的截止时间：监听停止后，服务线程在此之前保留运行时，让进行中的接收完成
+static DRAIN_DEADLINE: Mutex<Option<std::time::Instant>> = Mutex::new(None);
+// 优雅停止的默认等待时间
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 接收连接存活期间计数，任务结束（包括被中止）时自动减一
+struct ActiveReceiveGuard;
+
+impl ActiveReceiveGuard {
+    fn new() -> Self {
+        ACTIVE_RECEIVES.fetch_add(1, Ordering::SeqCst);
+        ActiveReceiveGuard
+    }
+}
+
+impl Drop for ActiveReceiveGuard {
+    fn drop(&mut self) {
+        if ACTIVE_RECEIVES.fetch_sub(1, Ordering::SeqCst) == 1 {
+            RECEIVES_FINISHED.notify_waiters();
+        }
+    }
+}
+
+/// 等待进行中的接收全部结束
+async fn wait_receives_finished() {
+    loop {
+        // 先登记等待再检查计数，避免错过两者之间的唤醒
+        let finished = RECEIVES_FINISHED.notified();
+        if ACTIVE_RECEIVES.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        finished.await;
+    }
+}
+
+/// 优雅停止期间是否仍需等待进行中的接收
+fn draining() -> bool {
+    let deadline = *DRAIN_DEADLINE.lock().unwrap();
+    deadline.is_some_and(|d| std::time::Instant::now() < d) && ACTIVE_RECEIVES.load(Ordering::SeqCst) > 0
+}
+type CancelRegistry = LazyLock<Mutex<HashMap<String, Arc<AtomicBool>>>>;
+// 进行中发送的取消标志（batch_id -> 标志）
+static SEND_CANCELS: CancelRegistry = LazyLock::new(|| Mutex::new(HashMap::new()));
+// 进行中接收的取消标志（连接 ID -> 标志）
+static RECEIVE_CANCELS: CancelRegistry = LazyLock::new(|| Mutex::new(HashMap::new()));
+// 当前保存目录（可在服务器运行期间更新）
+static CURRENT_SAVE_DIR: Mutex<String> = Mutex::new(String::new());
+
+/// start_websocket_server 传入的默认保存目录
+pub(crate) fn current_save_dir() -> String {
+    CURRENT_SAVE_DIR.lock().unwrap().clone()
+}
+// 传输/批次 ID 序号
+static NEXT_TRANSFER_SEQ: AtomicU64 = AtomicU64::new(0);
+// Android：已创建的 SAF 子目录 ((保存目录 tree Uri, 相对路径) -> 子目录 tree Uri)
+#[cfg(target_os = "android")]
+static SAF_DIR_CACHE: LazyLock<Mutex<HashMap<(String, String), String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+// 等待用户选择的清单（batch_id -> 回复通道）
+static PENDING_MANIFESTS: LazyLock<Mutex<HashMap<String, oneshot::Sender<Vec<u32>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// 等待用户决定是否接收的连接（connection_id -> 回复通道）
+static PENDING_TRANSFERS: LazyLock<Mutex<HashMap<String, oneshot::Sender<bool>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+// 用户已接受（或已通过清单选择）的批次 ((对端 IP, batch_id) -> 接受时间)，同批次的后续连接不再询问
+static ACCEPTED_BATCHES: LazyLock<Mutex<HashMap<(String, String), Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// 接收端等待用户勾选清单的时间，超时视为全部拒绝
+const MANIFEST_RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+// 接收端等待用户接受传输的时间，超时视为拒绝
+const TRANSFER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// 等待用户接受期间最多缓存的数据帧字节数，超出后暂停读取由 TCP 背压限制发送端
+const PENDING_APPROVAL_BUFFER: usize = 4 * 1024 * 1024;
+// 已接受的批次在该时间内的后续连接不再询问
+const ACCEPTED_BATCH_TTL: Duration = Duration::from_secs(60 * 60);
+// 发送端等待清单回复的时间（略长于接收端，避免先于接收端超时）
+const MANIFEST_WAIT_TIMEOUT: Duration = Duration::from_secs(75);
+// 控制请求（批次进度、能力查询等）等待回复的时间
+const CONTROL_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+// 增量同步的哈希比较需要接收端读取文件，等待时间更长
+const DIFF_HASH_REPLY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+// 时钟比较的采样次数，取往返时间最短的一次
+const CLOCK_SAMPLES: usize = 4;
+// 对端能力查询结果的缓存时间，过期后重新握手查询
+const CAPABILITIES_TTL: Duration = Duration::from_secs(60);
+// 已查询的对端能力（IP -> (查询时间, 能力列表)）
+type CapabilityCache = HashMap<String, (Instant, Vec<String>)>;
+static PEER_CAPABILITIES: LazyLock<Mutex<CapabilityCache>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 生成进程内唯一的传输/批次 ID
+pub(crate) fn new_transfer_id() -> String {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let seq = NEXT_TRANSFER_SEQ.fetch_add(1, Ordering::SeqCst);
+    format!("{:x}-{}", millis, seq)
+}
+
+/// 单个传输的取消标志登记，释放（包括任务被中止）时从登记表中移除
+pub(crate) struct CancelGuard {
+    registry: &'static CancelRegistry,
+    id: String,
+    pub(crate) flag: Arc<AtomicBool>,
+}
+
+impl CancelGuard {
+    fn register(registry: &'static CancelRegistry, id: &str) -> Self {
+        let flag = Arc::new(AtomicBool::new(false));
+        registry.lock().unwrap().insert(id.to_string(), flag.clone());
+        CancelGuard { registry, id: id.to_string(), flag }
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        // 同一 ID 已被新的传输重新登记时保留新的标志
+        if registry.get(&self.id).is_some_and(|flag| Arc::ptr_eq(flag, &self.flag)) {
+            registry.remove(&self.id);
+        }
+    }
+}
+
+/// 该传输的取消标志（未登记时返回不会被设置的新标志）
+fn cancel_flag(registry: &CancelRegistry, id: &str) -> Arc<AtomicBool> {
+    registry.lock().unwrap().get(id).cloned().unwrap_or_default()
+}
+
+fn request_cancel(registry: &CancelRegistry, id: &str) -> bool {
+    match registry.lock().unwrap().get(id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 登记一个组合发送（如多目标发送）的取消标志，cancel_file_sending 传入该 ID 即可设置
+pub(crate) fn register_send_cancel(id: &str) -> CancelGuard {
+    CancelGuard::register(&SEND_CANCELS, id)
+}
+
+/// 该发送是否已被取消（发送开始前的等待阶段使用）
+pub(crate) fn send_cancel_requested(batch_id: &str) -> bool {
+    cancel_flag(&SEND_CANCELS, batch_id).load(Ordering::SeqCst)
+}
+
+/// 取消所有进行中的发送与接收
+pub(crate) fn cancel_all_transfers() {
+    for registry in [&SEND_CANCELS, &RECEIVE_CANCELS] {
+        for flag in registry.lock().unwrap().values() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[tauri::command]
+/// 取消指定的文件发送（transfer_id 为发送命令返回的 ID，进行中时也可从 list_all_jobs 获取），
+/// 不影响同时进行的其他发送
+pub fn cancel_file_sending(transfer_id: String) -> Result<(), TransferError> {
+    if request_cancel(&SEND_CANCELS, &transfer_id) {
+        Ok(())
+    } else {
+        Err(TransferError::NotFound(format!("No active send with id {}", transfer_id)))
+    }
+}
+
+#[tauri::command]
+/// 取消指定连接上的文件接收（transfer_id 为接收任务的连接 ID，见 list_all_jobs 与 transfer-request 事件）
+pub fn cancel_file_receiving(transfer_id: String) -> Result<(), TransferError> {
+    if request_cancel(&RECEIVE_CANCELS, &transfer_id) {
+        Ok(())
+    } else {
+        Err(TransferError::NotFound(format!("No active receive with id {}", transfer_id)))
+    }
+}
+
+/// 连接失败的具体原因，便于前端给出对应的处理建议
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConnectFailureReason {
+    /// 对方端口未监听（接收端未开启）
+    ConnectionRefused,
+    /// 连接超时（多为防火墙丢包）
+    Timeout,
+    /// 无路由（不在同一网络）
+    Unreachable,
+    /// 地址解析失败
+    DnsError,
+    Other,
+}
+
+impl ConnectFailureReason {
+    pub(crate) fn from_ws_error(e: &tokio_tungstenite::tungstenite::Error) -> Self {
+        use std::io::ErrorKind;
+        use tokio_tungstenite::tungstenite::Error as WsError;
+
+        match e {
+            WsError::Io(io) => match io.kind() {
+                ErrorKind::ConnectionRefused => Self::ConnectionRefused,
+                ErrorKind::TimedOut => Self::Timeout,
+                ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable
+                | ErrorKind::AddrNotAvailable => Self::Unreachable,
+                _ => {
+                    let msg = io.to_string().to_lowercase();
+                    if msg.contains("lookup") || msg.contains("resolve") || msg.contains("name or service") {
+                        Self::DnsError
+                    } else {
+                        Self::Other
+                    }
+                }
+            },
+            WsError::Url(_) => Self::DnsError,
+            _ => Self::Other,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::ConnectionRefused => "Connection refused: make sure the receiver is in receive mode",
+            Self::Timeout => "Connection timed out: check the firewall on the receiving device",
+            Self::Unreachable => "Device unreachable: make sure both devices are on the same network",
+            Self::DnsError => "Could not resolve the device address",
+            Self::Other => "Failed to connect",
+        }
+    }
+}
+
+/// `connect-failed` 事件内容
+#[derive(Serialize, Clone, Debug)]
+struct ConnectFailure {
+    target_ip: String,
+    reason: ConnectFailureReason,
+    message: String,
+}
+
+/// `transfer-retrying` 事件内容：发送连接失败，等待后重试
+#[derive(Serialize, Clone, Debug)]
+struct TransferRetrying {
+    transfer_id: String,
+    target_ip: String,
+    /// 即将进行的是第几次尝试（首次连接为第 1 次）
+    attempt: u32,
+    max_attempts: u32,
+    delay_ms: u64,
+    reason: ConnectFailureReason,
+}
+
+type ClientWsStream = tokio_tungstenite::WebSocketStream<
+    tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+>;
+
+// 建立连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// 发送连接失败时的默认重试次数，以及首次重试前的等待时间（之后每次翻倍）
+const DEFAULT_CONNECT_RETRIES: u32 = 3;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+// Android 发送：每次通过插件读取 1MB，按 256KB 帧发送
+#[cfg(any(target_os = "android", test))]
+const ANDROID_READ_CHUNK_SIZE: i32 = 1024 * 1024;
+#[cfg(any(target_os = "android", test))]
+const SEND_FRAME_SIZE: usize = 256 * 1024;
+// 发送进度事件的最小间隔
+#[cfg(target_os = "android")]
+const PROGRESS_EMIT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 连接到接收端的传输服务，失败时区分原因并发送 `connect-failed` 事件
+async fn connect_peer(window: &Window, target_ip: &str) -> Result<ClientWsStream, TransferError> {
+    match try_connect_peer(target_ip).await? {
+        Ok(ws_stream) => Ok(ws_stream),
+        Err((reason, detail)) => Err(report_connect_failure(window, target_ip, reason, &detail)),
+    }
+}
+
+/// 发送文件时建立连接：失败后按 250ms、500ms、1s…… 退避重试（retries 次，默认 3），
+/// 每次重试前发送 `transfer-retrying` 事件。等待期间可以取消
+async fn connect_peer_with_retry(
+    window: &Window,
+    target_ip: &str,
+    transfer_id: &str,
+    retries: Option<u32>,
+    cancelled: &AtomicBool,
+) -> Result<ClientWsStream, TransferError> {
+    let max_attempts = retries.unwrap_or(DEFAULT_CONNECT_RETRIES) + 1;
+    let mut delay = CONNECT_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let (reason, detail) = match try_connect_peer(target_ip).await? {
+            Ok(ws_stream) => return Ok(ws_stream),
+            Err(failure) => failure,
+        };
+        if attempt >= max_attempts {
+            return Err(report_connect_failure(window, target_ip, reason, &detail));
+        }
+
+        attempt += 1;
+        println!("Connecting to {} failed ({}), retrying in {:?} ({}/{})", target_ip, detail, delay, attempt, max_attempts);
+        window_events::emit(window, "transfer-retrying", TransferRetrying {
+            transfer_id: transfer_id.to_string(),
+            target_ip: target_ip.to_string(),
+            attempt,
+            max_attempts,
+            delay_ms: delay.as_millis() as u64,
+            reason,
+        });
+
+        let retry_at = Instant::now() + delay;
+        while Instant::now() < retry_at {
+            if cancelled.load(Ordering::SeqCst) {
+                emit_transfer_cancelled(window, transfer_id, "send", None, 0);
+                return Err(TransferError::Cancelled("Cancelled by user".into()));
+            }
+            tokio::time::sleep(retry_at.saturating_duration_since(Instant::now()).min(Duration::from_millis(50))).await;
+        }
+        delay *= 2;
+    }
+}
+
+/// 发送 `connect-failed` 事件，返回给调用方的错误（带有失败原因）
+fn report_connect_failure(window: &Window, target_ip: &str, reason: ConnectFailureReason, detail: &str) -> TransferError {
+    let failure = ConnectFailure {
+        target_ip: target_ip.to_string(),
+        reason,
+        message: reason.message().to_string(),
+    };
+    window_events::emit(window, "connect-failed", &failure);
+
+    TransferError::ConnectionFailed(format!("Failed to connect to {}: {} ({})", target_ip, failure.message, detail), Some(reason))
+}
+
+/// 尝试一次连接。地址无效时返回外层错误，连接失败时返回失败原因与详细信息
+async fn try_connect_peer(target_ip: &str) -> Result<Result<ClientWsStream, (ConnectFailureReason, String)>, TransferError> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    use tokio_tungstenite::{client_async, client_async_tls_with_config, Connector, MaybeTlsStream};
+
+    let port = ports::peer(target_ip, Service::Transfer);
+    // 两端都启用加密时使用 wss://，只信任对端在发现广播中公布的证书
+    let fingerprint = transfer_tls::peer_fingerprint(target_ip);
+    let scheme = if fingerprint.is_some() { "wss" } else { "ws" };
+
+    // 手动建立 TCP 连接，以便应用配置的套接字缓冲区大小
+    let connect = async {
+        // 不是 IP 地址时按主机名解析（未被发现的设备、.local 名称等）
+        let addr = match target_ip.parse::<std::net::IpAddr>() {
+            Ok(ip) => std::net::SocketAddr::from((ip, port)),
+            Err(_) => tokio::net::lookup_host((target_ip, port)).await.map_err(WsError::Io)?
+                .next()
+                .ok_or_else(|| WsError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("failed to lookup address for {}", target_ip),
+                )))?,
+        };
+        let request = format!("{}://{}", scheme, addr).into_client_request()?;
+        let stream = socket_buffers::connect(scoped_addr(addr)).await.map_err(WsError::Io)?;
+        let (mut ws_stream, _) = match fingerprint {
+            Some(fingerprint) => {
+                let connector = Connector::Rustls(transfer_tls::client_config(&fingerprint));
+                client_async_tls_with_config(request, stream, None, Some(connector)).await?
+            }
+            None => client_async(request, MaybeTlsStream::Plain(stream)).await?,
+        };
+        // 设置了连接 PIN 时首先发送，接收端校验通过后才处理后续消息
+        if let Some(auth) = connection_pin::auth_message() {
+            ws_stream.send(auth).await?;
+        }
+        Ok(ws_stream)
+    };
+
+    Ok(match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+        Ok(Ok(ws_stream)) => Ok(ws_stream),
+        Ok(Err(e)) => Err((ConnectFailureReason::from_ws_error(&e), e.to_string())),
+        Err(_) => Err((ConnectFailureReason::Timeout, "no response".to_string())),
+    })
+}
+
+#[tauri::command]
+/// 接收端：回复文件清单，选择要接收的文件序号（空列表表示全部拒绝）
+pub fn respond_to_manifest(batch_id: String, accepted: Vec<u32>) -> Result<(), TransferError> {
+    let sender = PENDING_MANIFESTS.lock().unwrap().remove(&batch_id)
+        .ok_or_else(|| TransferError::NotFound(format!("No pending manifest for batch {}", batch_id)))?;
+    sender.send(accepted).map_err(|_| TransferError::Timeout("Manifest request already expired".into()))
+}
+
+#[tauri::command]
+/// 接收端：回复 `transfer-request`，接受或拒绝该连接发来的文件
+pub fn respond_to_transfer(accept: bool, connection_id: String) -> Result<(), TransferError> {
+    let sender = PENDING_TRANSFERS.lock().unwrap().remove(&connection_id)
+        .ok_or_else(|| TransferError::NotFound(format!("No pending transfer request for connection {}", connection_id)))?;
+    sender.send(accept).map_err(|_| TransferError::Timeout("Transfer request already expired".into()))
+}
+
+/// 记录用户已接受的批次
+fn remember_accepted_batch(peer_ip: &str, batch_id: &str) {
+    let mut accepted = ACCEPTED_BATCHES.lock().unwrap();
+    accepted.retain(|_, at| at.elapsed() < ACCEPTED_BATCH_TTL);
+    accepted.insert((peer_ip.to_string(), batch_id.to_string()), Instant::now());
+}
+
+fn batch_accepted(peer_ip: &str, batch_id: &str) -> bool {
+    ACCEPTED_BATCHES.lock().unwrap()
+        .get(&(peer_ip.to_string(), batch_id.to_string()))
+        .is_some_and(|at| at.elapsed() < ACCEPTED_BATCH_TTL)
+}
+
+/// 发送端：在随后发送文件的同一连接上先发送文件清单，等待接收端选择，返回被接受的文件序号。
+/// 全部被拒绝时关闭连接
+async fn negotiate_manifest(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    batch_id: &str,
+    files: Vec<ManifestEntry>,
+) -> Result<Vec<u32>, TransferError> {
+    let manifest = ControlMessage::Manifest { batch_id: batch_id.to_string(), files };
+    let manifest_str = serde_json::to_string(&manifest)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize manifest: {}", e)))?;
+
+    write.send(Message::Text(manifest_str)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send manifest: {}", e), None))?;
+
+    let accepted = loop {
+        let msg = tokio::time::timeout(MANIFEST_WAIT_TIMEOUT, read.next()).await
+            .map_err(|_| TransferError::Timeout("Timed out waiting for receiver to choose files".to_string()))?;
+
+        match msg {
+            Some(Ok(Message::Text(text))) => {
+                if let Ok(ControlMessage::ManifestResponse { batch_id: id, accepted }) =
+                    serde_json::from_str::<ControlMessage>(&text)
+                {
+                    if id == batch_id {
+                        break accepted;
+                    }
+                }
+            }
+            Some(Ok(Message::Close(frame))) => {
+                let code: Option<u16> = frame.map(|f| f.code.into());
+                return Err(code.and_then(receiver_close_error).unwrap_or_else(|| {
+                    TransferError::ConnectionFailed("Connection closed before receiver chose files".to_string(), None)
+                }));
+            }
+            None => {
+                return Err(TransferError::ConnectionFailed("Connection closed before receiver chose files".to_string(), None));
+            }
+            Some(Err(e)) => {
+                return Err(TransferError::ConnectionFailed(format!("Failed to read manifest response: {}", e), None));
+            }
+            Some(Ok(_)) => {}
+        }
+    };
+
+    if accepted.is_empty() {
+        let _ = write.send(Message::Close(None)).await;
+        return Err(TransferError::Rejected("Rejected by receiver".to_string()));
+    }
+
+    Ok(accepted)
+}
+
+/// 发送端：通过单独的连接发送一条控制消息，等待接收端的对应回复。
+/// 旧版本接收端不支持时会超时或断开，返回 None
+async fn control_request<T>(
+    window: &Window,
+    target_ip: &str,
+    request: ControlMessage,
+    reply_timeout: Duration,
+    extract: impl Fn(ControlMessage) -> Option<T>,
+) -> Option<T> {
+    let ws_stream = match connect_peer(window, target_ip).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            eprintln!("Control request failed: {}", e);
+            return None;
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    let request_str = serde_json::to_string(&request).ok()?;
+    write.send(Message::Text(request_str)).await.ok()?;
+
+    let reply = tokio::time::timeout(reply_timeout, async {
+        while let Some(Ok(msg)) = read.next().await {
+            if let Message::Text(text) = msg {
+                if let Some(reply) = serde_json::from_str::<ControlMessage>(&text).ok().and_then(&extract) {
+                    return Some(reply);
+                }
+            }
+        }
+        None
+    }).await.ok().flatten();
+
+    let _ = write.send(Message::Close(None)).await;
+    reply
+}
+
+/// 发送端：查询接收端在该批次中已完成的文件（不支持时返回空列表，即全部重新发送）
+async fn query_batch_status(window: &Window, target_ip: &str, batch_id: &str) -> Vec<String> {
+    let query = ControlMessage::BatchStatusQuery { batch_id: batch_id.to_string() };
+    control_request(window, target_ip, query, CONTROL_REPLY_TIMEOUT, |reply| match reply {
+        ControlMessage::BatchStatus { batch_id: id, completed } if id == batch_id => Some(completed),
+        _ => None,
+    }).await.unwrap_or_default()
+}
+
+/// 发送端：查询接收端支持的协议扩展（成功的回复缓存一段时间，旧版本不支持查询时为空列表）
+async fn query_capabilities(window: &Window, target_ip: &str) -> Vec<String> {
+    if let Some((queried_at, capabilities)) = PEER_CAPABILITIES.lock().unwrap().get(target_ip) {
+        if queried_at.elapsed() < CAPABILITIES_TTL {
+            return capabilities.clone();
+        }
+    }
+
+    let Some(capabilities) = control_request(window, target_ip, ControlMessage::CapabilitiesQuery, CONTROL_REPLY_TIMEOUT, |reply| match reply {
+        ControlMessage::Capabilities { capabilities } => Some(capabilities),
+        _ => None,
+    }).await else {
+        // 查询失败或超时不缓存，下次重新查询，以免一次超时让之后的发送都退回基础协议
+        return Vec::new();
+    };
+    PEER_CAPABILITIES.lock().unwrap().insert(target_ip.to_string(), (Instant::now(), capabilities.clone()));
+    capabilities
+}
+
+/// 发送端：在文件连接上询问接收端已保留的部分数据长度（不支持或没有可续传的部分文件时为 0）
+async fn query_resume_offset(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    name: &str,
+    relative_path: Option<&str>,
+    size: u64,
+) -> u64 {
+    let query = ControlMessage::ResumeQuery {
+        name: name.to_string(),
+        relative_path: relative_path.map(str::to_string),
+        size,
+    };
+    let Ok(text) = serde_json::to_string(&query) else {
+        return 0;
+    };
+    if write.send(Message::Text(text)).await.is_err() {
+        return 0;
+    }
+
+    let offset = tokio::time::timeout(CONTROL_REPLY_TIMEOUT, async {
+        while let Some(Ok(msg)) = read.next().await {
+            if let Message::Text(text) = msg {
+                if let Ok(ControlMessage::ResumeOffset { offset }) = serde_json::from_str(&text) {
+                    return Some(offset);
+                }
+            }
+        }
+        None
+    }).await.ok().flatten().unwrap_or(0);
+    offset.min(size)
+}
+
+/// 发送端：发送文件元数据，返回实际的起始位置。续传时等待接收端确认；
+/// 接收端以 4409 拒绝续传时重新连接，从头发送该文件
+#[allow(clippy::too_many_arguments)]
+async fn send_file_meta(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    read: &mut futures_util::stream::SplitStream<ClientWsStream>,
+    mut meta: serde_json::Value,
+    window: &Window,
+    target_ip: &str,
+    batch_id: &str,
+    retries: Option<u32>,
+    cancelled: &AtomicBool,
+) -> Result<u64, TransferError> {
+    let resume_from = meta["resume_from"].as_u64().unwrap_or(0);
+    send_meta_frame(write, &meta).await?;
+    if resume_from == 0 || resume_accepted(read).await? {
+        return Ok(resume_from);
+    }
+
+    println!("Receiver rejected resume of {}, resending from the start", meta["name"]);
+    meta["resume_from"] = 0.into();
+    let (new_write, new_read) = connect_peer_with_retry(window, target_ip, batch_id, retries, cancelled).await?.split();
+    *write = new_write;
+    *read = new_read;
+    send_meta_frame(write, &meta).await?;
+    Ok(0)
+}
+
+async fn send_meta_frame(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    meta: &serde_json::Value,
+) -> Result<(), TransferError> {
+    let meta_str = serde_json::to_string(meta)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize metadata: {}", e)))?;
+    write.send(Message::Text(meta_str)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send metadata: {}", e), None))
+}
+
+/// 发送端：等待接收端确认续传（回复 ResumeOffset）。以 4409 关闭时返回 false，其他错误码按原样返回
+async fn resume_accepted(read: &mut futures_util::stream::SplitStream<ClientWsStream>) -> Result<bool, TransferError> {
+    tokio::time::timeout(CONTROL_REPLY_TIMEOUT, async {
+        while let Some(msg) = read.next().await {
+            match msg {
+                Ok(Message::Text(text)) => {
+                    if let Ok(ControlMessage::ResumeOffset { .. }) = serde_json::from_str::<ControlMessage>(&text) {
+                        return Ok(true);
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    let code: Option<u16> = frame.map(|f| f.code.into());
+                    return match code {
+                        Some(4409) => Ok(false),
+                        code => Err(code.and_then(receiver_close_error).unwrap_or_else(|| {
+                            TransferError::ConnectionFailed("Connection closed before receiver confirmed the resume".to_string(), None)
+                        })),
+                    };
+                }
+                Ok(_) => {}
+                Err(e) => return Err(TransferError::ConnectionFailed(format!("Failed to read resume confirmation: {}", e), None)),
+            }
+        }
+        Err(TransferError::ConnectionFailed("Connection closed before receiver confirmed the resume".to_string(), None))
+    }).await.map_err(|_| TransferError::Timeout("Timed out waiting for receiver to confirm the resume".to_string()))?
+}
+
+/// 发送端：文件数据帧，启用压缩时以 gzip 压缩（进度与校验和仍按压缩前的数据计算）
+fn binary_frame(data: &[u8], compress: bool) -> Result<Message, TransferError> {
+    if !compress {
+        return Ok(Message::Binary(data.to_vec()));
+    }
+    compression::gzip(data)
+        .map(Message::Binary)
+        .map_err(|e| TransferError::Other(format!("Failed to compress chunk: {}", e)))
+}
+
+/// 发送端分块发送的结束方式
+#[cfg(any(target_os = "android", test))]
+#[derive(Debug)]
+enum ChunksEnd {
+    /// 数据已全部发送
+    Done,
+    /// 用户取消，连接尚未关闭
+    Cancelled,
+    /// 写入失败（连接断开），由调用方检查接收端的 Close 码
+    WriteFailed(TransferError),
+}
+
+/// 发送端（Android）：每次通过 read_chunk 读取一大块到 buffer，再拆成较小的帧发送。
+/// 每帧之前检查取消，保证一次桥接调用读取大块数据时取消仍能及时生效；
+/// bytes_sent 从续传位置开始累加，每帧发送后以其调用 progress
+#[cfg(any(target_os = "android", test))]
+#[allow(clippy::too_many_arguments)]
+async fn send_chunks<W>(
+    write: &mut W,
+    mut read_chunk: impl FnMut(u64, &mut Vec<u8>) -> Result<usize, TransferError>,
+    buffer: &mut Vec<u8>,
+    size: u64,
+    bytes_sent: &mut u64,
+    compress: bool,
+    cancelled: &AtomicBool,
+    mut hasher: Option<&mut Context>,
+    pacer: &mut rate_limit::SendPacer,
+    mut progress: impl FnMut(u64),
+) -> Result<ChunksEnd, TransferError>
+where
+    W: futures_util::Sink<Message> + Unpin,
+    W::Error: std::fmt::Display,
+{
+    let mut offset = *bytes_sent;
+    loop {
+        let bytes_read = read_chunk(offset, buffer)?;
+        if bytes_read == 0 {
+            return Ok(ChunksEnd::Done);
+        }
+        offset += bytes_read as u64;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(buffer);
+        }
+
+        for frame_data in buffer.chunks(SEND_FRAME_SIZE) {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(ChunksEnd::Cancelled);
+            }
+            if let Err(e) = write.send(binary_frame(frame_data, compress)?).await {
+                return Ok(ChunksEnd::WriteFailed(TransferError::ConnectionFailed(format!("Failed to send chunk: {}", e), None)));
+            }
+            *bytes_sent += frame_data.len() as u64;
+            throughput::add_sent(frame_data.len() as u64);
+            pacer.pace(frame_data.len() as u64).await;
+            progress(*bytes_sent);
+        }
+
+        if *bytes_sent >= size {
+            return Ok(ChunksEnd::Done);
+        }
+    }
+}
+
+/// 续传时把已传输的前 len 字节计入摘要，使校验和覆盖整个文件；读完后 reader 恰好位于 len 处
+async fn hash_prefix<R: tokio::io::AsyncRead + Unpin>(reader: &mut R, len: u64, mut update: impl FnMut(&[u8])) -> std::io::Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = vec![0u8; 256 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let want = remaining.min(buffer.len() as u64) as usize;
+        let n = reader.read(&mut buffer[..want]).await?;
+        if n == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "File is shorter than the resume offset"));
+        }
+        update(&buffer[..n]);
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+/// Android: 同 hash_prefix，从 content:// URI 读取已传输的部分
+#[cfg(target_os = "android")]
+fn hash_uri_prefix(
+    reader: &UriReader,
+    len: u64,
+    hasher: &mut Context,
+    buf: &mut Vec<u8>,
+) -> Result<(), TransferError> {
+    let mut offset: u64 = 0;
+    while offset < len {
+        let size = (len - offset).min(ANDROID_READ_CHUNK_SIZE as u64) as i32;
+        let bytes_read = reader.read_chunk(offset, size, buf)
+            .map_err(|e| TransferError::Io(format!("Failed to read chunk: {}", e)))?;
+        if bytes_read == 0 {
+            return Err(TransferError::Io("File is shorter than the resume offset".to_string()));
+        }
+        hasher.update(&buf[..bytes_read]);
+        offset += bytes_read as u64;
+    }
+    Ok(())
+}
+
+/// 发送端：在文件数据之后发送整个文件的 SHA-256（续传时包括已传输的部分）
+async fn send_checksum(
+    write: &mut futures_util::stream::SplitSink<ClientWsStream, Message>,
+    hasher: Context,
+) -> Result<(), TransferError> {
+    let message = ControlMessage::Checksum { sha256: folder_sync::hex_digest(hasher.finish()) };
+    let text = serde_json::to_string(&message)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize checksum: {}", e)))?;
+    write.send(Message::Text(text)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send checksum: {}", e), None))
+}
+
+/// 当前 Unix 时间（毫秒）
+fn unix_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+/// 两台设备的时钟差
+#[derive(Serialize, Clone, Debug)]
+pub struct ClockSkew {
+    /// 对端时钟减本机时钟（毫秒），正数表示对端的时钟更快
+    pub offset_ms: i64,
+    /// 用于计算的那次往返时间，偏差的误差不超过其一半
+    pub rtt_ms: i64,
+}
+
+/// 在已建立的连接上做几次类似 NTP 的时间交换，取往返时间最短的一次计算偏差
+async fn exchange_clock(ws_stream: &mut ClientWsStream) -> Option<ClockSkew> {
+    let mut best: Option<ClockSkew> = None;
+    for _ in 0..CLOCK_SAMPLES {
+        let sent_at = unix_millis();
+        let query = serde_json::to_string(&ControlMessage::TimeQuery { sent_at_ms: sent_at }).ok()?;
+        if ws_stream.send(Message::Text(query)).await.is_err() {
+            break;
+        }
+
+        let peer_time = tokio::time::timeout(CONTROL_REPLY_TIMEOUT, async {
+            while let Some(Ok(msg)) = ws_stream.next().await {
+                if let Message::Text(text) = msg {
+                    if let Ok(ControlMessage::TimeReply { sent_at_ms, peer_time_ms }) = serde_json::from_str(&text) {
+                        if sent_at_ms == sent_at {
+                            return Some(peer_time_ms);
+                        }
+                    }
+                }
+            }
+            None
+        }).await.ok().flatten();
+        // 旧版本不回复，不必再试
+        let Some(peer_time) = peer_time else {
+            break;
+        };
+
+        let received_at = unix_millis();
+        let sample = ClockSkew {
+            // 假设往返路径对称，对端回复时本机时间约为发送与收到的中点
+            offset_ms: peer_time - (sent_at + received_at) / 2,
+            rtt_ms: received_at - sent_at,
+        };
+        if best.as_ref().is_none_or(|b| sample.rtt_ms < b.rtt_ms) {
+            best = Some(sample);
+        }
+    }
+    best
+}
+
+/// 能力名称的别名映射到协议中使用的名称（例如续传对应 batch_status）
+fn capability_name(capability: &str) -> String {
+    let capability = capability.trim().to_lowercase();
+    match capability.as_str() {
+        "resume" => "batch_status".to_string(),
+        "sync" | "changed_only" => "diff".to_string(),
+        _ => capability,
+    }
+}
+
+/// 发送端：接收端是否支持某项协议扩展
+async fn supports(window: &Window, target_ip: &str, capability: &str) -> bool {
+    let capability = capability_name(capability);
+    query_capabilities(window, target_ip).await.contains(&capability)
+}
+
+/// 发送端：增量同步时询问接收端哪些文件有变化，返回需要发送的序号。
+/// 先只比较大小，大小相同的文件再计算 sha256 比较；接收端不支持时发送全部文件
+async fn query_changed_files(window: &Window, target_ip: &str, files: &[FolderFile]) -> Vec<u32> {
+    let all: Vec<u32> = (0..files.len() as u32).collect();
+    let entries: Vec<DiffEntry> = files.iter().enumerate().map(|(index, f)| DiffEntry {
+        index: index as u32,
+        relative_path: f.relative_path.clone(),
+        size: f.size,
+        sha256: None,
+    }).collect();
+
+    let query = ControlMessage::DiffQuery { files: entries.clone() };
+    let Some((mut changed, needs_hash)) = control_request(window, target_ip, query, CONTROL_REPLY_TIMEOUT, |reply| match reply {
+        ControlMessage::DiffResult { changed, needs_hash } => Some((changed, needs_hash)),
+        _ => None,
+    }).await else {
+        println!("Receiver does not support folder diff, sending all files");
+        return all;
+    };
+    if needs_hash.is_empty() {
+        return changed;
+    }
+
+    // 只为大小相同的文件计算哈希
+    let paths: Vec<(DiffEntry, String)> = needs_hash.iter()
+        .filter_map(|&i| Some((entries.get(i as usize)?.clone(), files[i as usize].path.clone())))
+        .collect();
+    let hashed = tokio::task::spawn_blocking(move || {
+        paths.into_iter().map(|(mut entry, path)| {
+            entry.sha256 = folder_sync::sha256_file(Path::new(&path)).ok();
+            entry
+        }).collect::<Vec<_>>()
+    }).await.unwrap_or_default();
+    // 无法计算哈希的文件视为有变化
+    changed.extend(hashed.iter().filter(|e| e.sha256.is_none()).map(|e| e.index));
+    let hashed: Vec<DiffEntry> = hashed.into_iter().filter(|e| e.sha256.is_some()).collect();
+    if hashed.is_empty() {
+        return changed;
+    }
+
+    let query = ControlMessage::DiffQuery { files: hashed.clone() };
+    match control_request(window, target_ip, query, DIFF_HASH_REPLY_TIMEOUT, |reply| match reply {
+        ControlMessage::DiffResult { changed, .. } => Some(changed),
+        _ => None,
+    }).await {
+        Some(differs) => changed.extend(differs),
+        None => changed.extend(hashed.iter().map(|e| e.index)),
+    }
+    changed
+}
+
+/// 拉取方：凭令牌请求对端推送文件，返回对端是否接受
+pub(crate) async fn request_pull(window: &Window, target_ip: &str, token: &str) -> Result<(), TransferError> {
+    if !WEBSOCKET_RUNNING.load(Ordering::SeqCst) {
+        return Err(TransferError::NotFound("Receive server is not running".to_string()));
+    }
+    let request = ControlMessage::PullRequest {
+        token: token.to_string(),
+        port: Some(ports::local(Service::Transfer)),
+    };
+    let reply = control_request(window, target_ip, request, CONTROL_REPLY_TIMEOUT, |reply| match reply {
+        ControlMessage::PullResponse { accepted, error } => Some((accepted, error)),
+        _ => None,
+    }).await;
+
+    match reply {
+        Some((true, _)) => Ok(()),
+        Some((false, error)) => Err(TransferError::Rejected(error.unwrap_or_else(|| "Pull request rejected".to_string()))),
+        None => Err(TransferError::Timeout(format!("{} did not answer the pull request", target_ip))),
+    }
+}
+
+/// 将接收端的 Close 状态码转换为发送端错误
+fn receiver_close_error(code: u16) -> Option<TransferError> {
+    let rejected = |message: &str| Some(TransferError::Rejected(message.to_string()));
+    match code {
+        4001 => Some(TransferError::CancelledByPeer("Cancelled by receiver".to_string())),
+        4003 => rejected("Rejected by receiver"),
+        4005 => Some(TransferError::InsufficientSpace("Not enough free space on the receiving device".to_string())),
+        4006 => rejected("Connection PIN rejected by receiver"),
+        BAD_METADATA_CODE => rejected("Metadata rejected by receiver"),
+        INVALID_METADATA_CODE => rejected("Invalid file name, index or size rejected by receiver"),
+        4409 => rejected("Resume rejected by receiver, retry to resend the file from the start"),
+        4422 => Some(TransferError::Other("Checksum mismatch, the file was corrupted in transit".to_string())),
+        _ => None,
+    }
+}
+
+/// 按接收端选择的序号过滤待发送列表
+fn retain_accepted<T>(items: Vec<T>, accepted: &[u32]) -> Vec<T> {
+    items.into_iter()
+        .enumerate()
+        .filter(|(i, _)| accepted.contains(&(*i as u32)))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// 接收端：通知前端有清单到达，等待用户选择（超时视为全部拒绝）
+async fn await_manifest_decision(
+    window: &Window,
+    peer_ip: &str,
+    batch_id: String,
+    files: Vec<ManifestEntry>,
+) -> Vec<u32> {
+    let file_count = files.len() as u32;
+    let (tx, rx) = oneshot::channel();
+    PENDING_MANIFESTS.lock().unwrap().insert(batch_id.clone(), tx);
+
+    window_events::emit(window, "transfer-manifest", ManifestRequest {
+        batch_id: batch_id.clone(),
+        peer_ip: peer_ip.to_string(),
+        files,
+    });
+
+    let accepted = match tokio::time::timeout(MANIFEST_RESPONSE_TIMEOUT, rx).await {
+        Ok(Ok(accepted)) => accepted,
+        _ => {
+            println!("Manifest {} not answered in time, rejecting", batch_id);
+            Vec::new()
+        }
+    };
+    PENDING_MANIFESTS.lock().unwrap().remove(&batch_id);
+
+    accepted.into_iter().filter(|i| *i < file_count).collect()
+}
+
+/// 接收端：通知前端有文件到达，等待用户接受（超时视为拒绝）
+async fn await_transfer_decision(window: &Window, request: TransferRequest) -> bool {
+    let connection_id = request.connection_id.clone();
+    let (tx, rx) = oneshot::channel();
+    PENDING_TRANSFERS.lock().unwrap().insert(connection_id.clone(), tx);
+
+    window_events::emit(window, "transfer-request", request);
+
+    let accepted = match tokio::time::timeout(TRANSFER_REQUEST_TIMEOUT, rx).await {
+        Ok(Ok(accepted)) => accepted,
+        _ => {
+            println!("Transfer request {} not answered in time, rejecting", connection_id);
+            false
+        }
+    };
+    PENDING_TRANSFERS.lock().unwrap().remove(&connection_id);
+    accepted
+}
+
+/// 等待确认期间发送端已断开：撤回请求并通知前端关闭对话框
+fn withdraw_transfer_request(window: &Window, connection_id: &str) {
+    PENDING_TRANSFERS.lock().unwrap().remove(connection_id);
+    window_events::emit(window, "transfer-request-withdrawn", connection_id);
+}
+
+/// 解析发现服务的组播组与端口（省略时使用默认值）。组播地址必须是管理范围组播地址（239.0.0.0/8）
+fn parse_discovery_group(multicast_addr: Option<&str>, port: Option<u16>) -> Result<SocketAddrV4, TransferError> {
+    let group = match multicast_addr {
+        Some(addr) => addr.trim().parse::<Ipv4Addr>()
+            .map_err(|_| TransferError::InvalidInput(format!("Invalid multicast address: {}", addr)))?,
+        None => MULTICAST_GROUP,
+    };
+    if group.octets()[0] != 239 {
+        return Err(TransferError::InvalidInput(format!(
+            "{} is not an administratively scoped multicast address (239.0.0.0/8)", group
+        )));
+    }
+    let port = port.unwrap_or(DISCOVERY_PORT);
+    if port == 0 {
+        return Err(TransferError::InvalidInput("Discovery port must be between 1 and 65535".to_string()));
+    }
+    Ok(SocketAddrV4::new(group, port))
+}
+
+#[tauri::command]
+/// 启动设备发现服务。interfaces 指定用于组播收发的本机 IP（来自 list_local_ips，可多个），
+/// 不指定时使用默认出口 IP。multicast_addr / port 指定组播组与端口（默认 239.255.77.88:37821），
+/// 用于避开被过滤的组播组或隔离不同的部署，只有相同设置的设备才能互相发现。
+/// 运行中以不同的组播组或端口调用时会重启发现服务
+pub fn start_discovery(
+    interfaces: Option<Vec<String>>,
+    multicast_addr: Option<String>,
+    port: Option<u16>,
+    window: Window,
+    app: tauri::AppHandle,
+) -> Result<(), TransferError> {
+    let group = parse_discovery_group(multicast_addr.as_deref(), port)?;
+
+    // 运行中换了组播组：停止当前服务后按新设置重新启动，否则新设置不会生效
+    if DISCOVERY_RUNNING.load(Ordering::SeqCst) && *DISCOVERY_GROUP.lock().unwrap() != group {
+        println!("Discovery group changed to {}, restarting discovery", group);
+        stop_discovery(window.clone());
+    }
+
+    // 防止重复启动
+    if DISCOVERY_RUNNING.swap(true, Ordering::SeqCst) {
+        println!("Discovery service already running");
+        return Ok(());
+    }
+    *DISCOVERY_GROUP.lock().unwrap() = group;
+    let generation = DISCOVERY_GENERATION.load(Ordering::SeqCst);
+
+    let interfaces: Vec<Ipv4Addr> = interfaces.unwrap_or_default()
+        .iter()
+        .filter_map(|ip| match ip.parse::<Ipv4Addr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                eprintln!("Ignoring invalid discovery interface: {}", ip);
+                None
+            }
+        })
+        .collect();
+
+    // Get device name before spawning thread（优先使用用户设置的设备名）
+    let custom_name = device_name::custom_name(&app);
+    #[cfg(target_os = "android")]
+    let device_name = custom_name.unwrap_or_else(|| {
+        use tauri::Manager;
+        app.state::<crate::android_storage::AndroidStorage>()
+            .get_device_name()
+            .map(|name| device_name::sanitize(&name))
+            .unwrap_or_else(|_| "Android".to_string())
+    });
+    #[cfg(not(target_os = "android"))]
+    let device_name = custom_name.unwrap_or_else(|| {
+        device_name::sanitize(&hostname::get()
+            .unwrap_or_else(|_| "Unknown".into())
+            .to_string_lossy())
+    });
+
+    let device_id = device_name::device_id(&app);
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let task = tokio::spawn(async move {
+                // 错误转为 String，使任务结果满足 Send
+                run_discovery_service(window, device_name, device_id, interfaces, group, generation).await.map_err(|e| e.to_string())
+            });
+            *DISCOVERY_TASK.lock().unwrap() = Some(task.abort_handle());
+            match task.await {
+                Ok(Err(e)) => {
+                    eprintln!("Discovery service error: {}", e);
+                    DISCOVERY_RUNNING.store(false, Ordering::SeqCst);
+                }
+                Err(e) if e.is_cancelled() => println!("Discovery service aborted"),
+                _ => {}
+            }
+        });
+    });
+    Ok(())
+}
+
+async fn run_discovery_service(
+    window: Window,
+    hostname: String,
+    device_id: Option<String>,
+    interfaces: Vec<Ipv4Addr>,
+    group: SocketAddrV4,
+    generation: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // stop_discovery 之后各任务退出循环
+    let active = move || DISCOVERY_GENERATION.load(Ordering::SeqCst) == generation;
+    let devices: DeviceList = DISCOVERED_DEVICES.clone();
+    devices.lock().unwrap().clear();
+
+    // 生成唯一实例 ID（用进程 ID�?
+    let instance_id = std::process::id().to_string();
+
+    // 组播地址�?39.x.x.x 为管理范围组播地址�?
+    let multicast_addr: Ipv4Addr = *group.ip();
+
+    // 组播收发使用的接口：未指定时用默认出口 IP
+    let interfaces = if interfaces.is_empty() {
+        vec![get_local_ip()?.parse().unwrap_or(Ipv4Addr::UNSPECIFIED)]
+    } else {
+        interfaces
+    };
+
+    // 使用 socket2 创建可重用的 UDP socket
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    // 显式指定组播发送接口，避免 Windows 多网卡时发到错误接口
+    socket.set_multicast_if_v4(&interfaces[0])?;
+
+    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, group.port());
+    socket.bind(&addr.into())?;
+
+    // 在每个接口上加入组播组（多网卡时可同时发现各网络中的设备）
+    let mut joined = 0;
+    for iface in &interfaces {
+        match socket.join_multicast_v4(&multicast_addr, iface) {
+            Ok(()) => joined += 1,
+            Err(e) => eprintln!("Failed to join multicast group on {}: {}", iface, e),
+        }
+    }
+    if joined == 0 {
+        return Err("Failed to join multicast group on any interface".into());
+    }
+    println!("Discovery running on interfaces: {:?}", interfaces);
+
+    // 转换为标准库�?UdpSocket
+    let socket: UdpSocket = socket.into();
+
+    let multicast_target = group;
+
+    // 克隆 socket 用于发�?
+    let socket_send = socket.try_clone()?;
+    let instance_id_clone = instance_id.clone();
+    let devices_for_send = devices.clone();
+
+    // IPv6 链路本地发现与 IPv4 并行，失败（例如系统未启用 IPv6）不影响 IPv4 发现
+    let group_v6 = SocketAddrV6::new(discovery_group_v6(*group.ip()), group.port(), 0, 0);
+    let v6 = run_discovery_v6(window.clone(), hostname.clone(), instance_id.clone(), device_id.clone(), interfaces[0].to_string(), group_v6, generation);
+    tokio::spawn(async move {
+        if let Err(e) = v6.await {
+            eprintln!("IPv6 discovery unavailable: {}", e);
+        }
+    });
+
+    // 任务1：定期发送组播 + 单播回复已知设备
+    // (文本或紧凑二进制格式，见 discovery_packet)
+    tokio::spawn(async move {
+        let announce_for = |ip: &Ipv4Addr| discovery_packet::encode(&discovery_packet::Announce {
+            ip: ip.to_string(),
+            hostname: hostname.clone(),
+            instance_id: instance_id_clone.clone(),
+            // 每次广播时读取，换端口后下一轮即公布新端口
+            ports: Some(ports::local_ports()),
+            tls_fingerprint: transfer_tls::local_fingerprint(),
+            device_id: device_id.clone(),
+            ipv6: None,
+        });
+        while active() {
+            // 每个接口各发一次，广播该网络中可达的本机 IP
+            for iface in &interfaces {
+                let msg = announce_for(iface);
+                // 组播发送
+                if interfaces.len() > 1 {
+                    let _ = socket2::SockRef::from(&socket_send).set_multicast_if_v4(iface);
+                }
+                let _ = socket_send.send_to(&msg, multicast_target);
+            }
+            // 单播发送给所有已知设备（解决路由器组播单向不通的问题）
+            let msg = announce_for(&interfaces[0]);
+            if let Ok(known) = devices_for_send.lock() {
+                for device in known.values() {
+                    if let Ok(ip) = device.ip.parse::<Ipv4Addr>() {
+                        let target = SocketAddrV4::new(ip, group.port());
+                        let _ = socket_send.send_to(&msg, target);
+                    }
+                }
+            }
+            tokio::time::sleep(ANNOUNCE_INTERVAL).await;
+        }
+    });
+
+    // 任务2：接收组播并更新设备列表
+    let devices_clone = devices.clone();
+    let window_clone = window.clone();
+
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while active() {
+            match socket.recv_from(&mut buf) {
+                Ok((len, _)) => {
+                    if let Some(announce) = discovery_packet::decode(&buf[..len]) {
+                        // 用实例 ID 判断是否是自己（而不是 IP）
+                        if announce.instance_id != instance_id {
+                            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                            let ip = announce.ip;
+                            let device = Device {
+                                ip: ip.clone(),
+                                hostname: announce.hostname,
+                                last_seen: now,
+                                ports: announce.ports,
+                                scope_id: None,
+                                tls_fingerprint: announce.tls_fingerprint,
+                                device_id: announce.device_id,
+                            };
+
+                            let mut devices = devices_clone.lock().unwrap();
+                            devices.insert(ip, device);
+
+                            // 发送更新到前端
+                            let device_list: Vec<Device> = devices.values().cloned().collect();
+                            window_events::emit(&window_clone, "devices-updated", device_list);
+                        }
+                    }
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // 非阻塞模式下没有数据，正常情�?
+                }
+                Err(e) => {
+                    eprintln!("UDP recv error: {}", e);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+
+    // 任务3：定期清理过期设备（30秒未响应�?
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            if !active() {
+                break;
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            // 先在锁内拆分出过期设备，释放锁后再通知前端
+            let (lost, device_list) = {
+                let mut devices = devices.lock().unwrap();
+                let mut lost: Vec<Device> = Vec::new();
+                devices.retain(|_, device| {
+                    let alive = now.saturating_sub(device.last_seen) < 30;
+                    if !alive {
+                        lost.push(device.clone());
+                    }
+                    alive
+                });
+                (lost, devices.values().cloned().collect::<Vec<Device>>())
+            };
+
+            if !lost.is_empty() {
+                window_events::emit(&window, "device-lost", lost);
+            }
+            window_events::emit(&window, "devices-updated", device_list);
+        }
+    });
+
+    // 保持运行，直到 stop_discovery
+    while active() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    println!("Discovery service stopped");
+    Ok(())
+}
+
+/// 与 IPv4 组播组对应的 IPv6 链路本地组播组：默认组沿用 ff02::7878 以兼容旧版本，
+/// 其他组将 IPv4 地址嵌入低 32 位，使只有相同设置的设备互相发现
+fn discovery_group_v6(group: Ipv4Addr) -> Ipv6Addr {
+    if group == MULTICAST_GROUP {
+        return MULTICAST_GROUP_V6;
+    }
+    let [a, b, c, d] = group.octets();
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, u16::from_be_bytes([a, b]), u16::from_be_bytes([c, d]))
+}
+
+/// 本机有 IPv6 链路本地地址的接口：(接口序号即 scope id, 链路本地地址)
+fn link_local_interfaces() -> Vec<(u32, Ipv6Addr)> {
+    let mut scopes: Vec<(u32, Ipv6Addr)> = Vec::new();
+    for iface in if_addrs::get_if_addrs().unwrap_or_default() {
+        let (Some(index), std::net::IpAddr::V6(ip)) = (iface.index, iface.ip()) else {
+            continue;
+        };
+        if iface.is_loopback() || !ip.is_unicast_link_local() || scopes.iter().any(|(i, _)| *i == index) {
+            continue;
+        }
+        scopes.push((index, ip));
+    }
+    scopes
+}
+
+/// IPv6 链路本地组播发现：在每个有链路本地地址的接口上加入组播组并定期广播，接收其他设备的广播，
+/// 结果合并到同一设备列表。广播中的 ip 为本机 IPv4 地址，ipv6 为发送接口的链路本地地址；
+/// 对端已通过该 IPv4 地址发现时不重复添加，否则以其 IPv6 地址加入，并记录所在接口的 scope id
+async fn run_discovery_v6(
+    window: Window,
+    hostname: String,
+    instance_id: String,
+    device_id: Option<String>,
+    announce_ip: String,
+    group: SocketAddrV6,
+    generation: u64,
+) -> std::io::Result<()> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, group.port(), 0, 0).into())?;
+
+    // 链路本地组播需在每个接口上分别加入和发送；找不到接口时由系统选择默认接口（scope 0）
+    let mut scopes: Vec<(u32, Option<Ipv6Addr>)> = link_local_interfaces().into_iter()
+        .filter(|(index, _)| match socket.join_multicast_v6(group.ip(), *index) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Failed to join IPv6 multicast group on interface {}: {}", index, e);
+                false
+            }
+        })
+        .map(|(index, ip)| (index, Some(ip)))
+        .collect();
+    if scopes.is_empty() {
+        socket.join_multicast_v6(group.ip(), 0)?;
+        scopes.push((0, None));
+    }
+    let socket: UdpSocket = socket.into();
+    println!("IPv6 discovery running on [{}]:{} (interfaces {:?})", group.ip(), group.port(),
+        scopes.iter().map(|(index, _)| *index).collect::<Vec<_>>());
+
+    let mut next_announce = Instant::now();
+    let mut buf = [0u8; 1024];
+
+    while DISCOVERY_GENERATION.load(Ordering::SeqCst) == generation {
+        if Instant::now() >= next_announce {
+            for &(scope_id, link_local) in &scopes {
+                let msg = discovery_packet::encode(&discovery_packet::Announce {
+                    ip: announce_ip.clone(),
+                    hostname: hostname.clone(),
+                    instance_id: instance_id.clone(),
+                    ports: Some(ports::local_ports()),
+                    tls_fingerprint: transfer_tls::local_fingerprint(),
+                    device_id: device_id.clone(),
+                    ipv6: link_local.map(|ip| ip.to_string()),
+                });
+                if let Err(e) = socket2::SockRef::from(&socket).set_multicast_if_v6(scope_id) {
+                    eprintln!("Failed to select IPv6 multicast interface {}: {}", scope_id, e);
+                    continue;
+                }
+                let _ = socket.send_to(&msg, SocketAddrV6::new(*group.ip(), group.port(), 0, scope_id));
+            }
+            next_announce = Instant::now() + ANNOUNCE_INTERVAL;
+        }
+
+        match socket.recv_from(&mut buf) {
+            Ok((len, SocketAddr::V6(source))) => {
+                let Some(announce) = discovery_packet::decode(&buf[..len]) else {
+                    continue;
+                };
+                if announce.instance_id == instance_id {
+                    continue;
+                }
+                let scope_id = source.scope_id();
+                // 收到来自未加入接口（例如新接入的网络）的广播时，之后也在该接口上收发
+                if scope_id != 0 && !scopes.iter().any(|(index, _)| *index == scope_id) {
+                    let sock = socket2::SockRef::from(&socket);
+                    if let Err(e) = sock.join_multicast_v6(group.ip(), scope_id) {
+                        eprintln!("Failed to join IPv6 multicast group on interface {}: {}", scope_id, e);
+                    }
+                    let link_local = link_local_interfaces().into_iter()
+                        .find(|(index, _)| *index == scope_id)
+                        .map(|(_, ip)| ip);
+                    scopes.push((scope_id, link_local));
+                }
+
+                let mut devices = DISCOVERED_DEVICES.lock().unwrap();
+                if devices.contains_key(&announce.ip) {
+                    continue;
+                }
+                // 优先使用对端公布的链路本地地址，旧版本没有时使用发送方地址
+                let ip = announce.ipv6.as_deref()
+                    .and_then(|ip| ip.parse::<Ipv6Addr>().ok())
+                    .filter(|ip| ip.is_unicast_link_local())
+                    .unwrap_or(*source.ip())
+                    .to_string();
+                devices.insert(ip.clone(), Device {
+                    ip,
+                    hostname: announce.hostname,
+                    last_seen: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                    ports: announce.ports,
+                    scope_id: (scope_id != 0).then_some(scope_id),
+                    tls_fingerprint: announce.tls_fingerprint,
+                    device_id: announce.device_id,
+                });
+                let device_list: Vec<Device> = devices.values().cloned().collect();
+                window_events::emit(&window, "devices-updated", device_list);
+            }
+            Ok(_) => {}
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => {
+                eprintln!("IPv6 discovery recv error: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+/// 停止设备发现：广播、接收与过期清理任务在下一轮循环时退出并释放组播套接字，清空设备列表。
+/// 之后再次调用 start_discovery 会重新选择接口（切换网络后使用新的本机 IP）并重新加入组播组
+pub fn stop_discovery(window: Window) {
+    if !DISCOVERY_RUNNING.load(Ordering::SeqCst) {
+        return;
+    }
+    // 先使当前服务失效再清除运行标志：之后启动的服务取得新的 generation，不会被误判为已停止
+    DISCOVERY_GENERATION.fetch_add(1, Ordering::SeqCst);
+    if !DISCOVERY_RUNNING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    if let Some(task) = DISCOVERY_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    DISCOVERED_DEVICES.lock().unwrap().clear();
+    window_events::emit(&window, "devices-updated", Vec::<Device>::new());
+    println!("Discovery service stopping");
+}
+
+#[tauri::command]
+/// 手动添加设备（组播跨 VLAN 或被交换机过滤时无法发现对端）。设备名暂用 IP，
+/// 加入后发现服务会向其单播广播，对端回复后更新为实际设备名与端口。
+/// 一直没有回复的设备与其他设备一样 30 秒后过期，需要时重新添加。
+/// 单播广播只经 IPv4 发送，因此只接受 IPv4 地址（IPv6 设备由链路本地发现自动找到）
+pub fn add_manual_device(ip: String, window: Window) -> Result<(), TransferError> {
+    let addr: std::net::IpAddr = ip.trim().parse()
+        .map_err(|e| TransferError::InvalidInput(format!("Invalid IP address {}: {}", ip, e)))?;
+    let std::net::IpAddr::V4(addr) = addr else {
+        return Err(TransferError::InvalidInput(format!("Manual devices must have an IPv4 address: {}", addr)));
+    };
+    if addr.is_unspecified() || addr.is_multicast() {
+        return Err(TransferError::InvalidInput(format!("Invalid device address: {}", addr)));
+    }
+
+    let ip = addr.to_string();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut devices = DISCOVERED_DEVICES.lock().unwrap();
+    devices.entry(ip.clone())
+        .and_modify(|device| device.last_seen = now)
+        .or_insert_with(|| Device {
+            ip: ip.clone(),
+            hostname: ip.clone(),
+            last_seen: now,
+            ports: None,
+            scope_id: None,
+            tls_fingerprint: None,
+            device_id: None,
+        });
+    println!("Manually added device {}", ip);
+
+    let device_list: Vec<Device> = devices.values().cloned().collect();
+    window_events::emit(&window, "devices-updated", device_list);
+    Ok(())
+}
+
+/// `test_multicast` 的诊断结果
+#[derive(Serialize, Clone, Debug)]
+pub struct MulticastTestResult {
+    /// 探测包能否发往组播组
+    pub can_send: bool,
+    /// 能否收到自己发出的探测包（组播环回可用说明本机组播收发正常）
+    pub can_receive_own: bool,
+    /// 监听期间看到的其他设备数量
+    pub peers_seen: usize,
+}
+
+#[tauri::command]
+/// 组播连通性自检：向组播组发送带标记的探测包，短暂监听自己的回环以及其他设备的发现广播
+pub async fn test_multicast() -> Result<MulticastTestResult, TransferError> {
+    let local_ip = get_local_ip()?;
+    let local_ipv4: Ipv4Addr = local_ip.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+    // 检测发现服务当前使用的组播组
+    let group = *DISCOVERY_GROUP.lock().unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+            .map_err(|e| format!("Failed to create socket: {}", e))?;
+        socket.set_reuse_address(true)
+            .map_err(|e| format!("Failed to set SO_REUSEADDR: {}", e))?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)
+            .map_err(|e| format!("Failed to set SO_REUSEPORT: {}", e))?;
+        socket.set_multicast_loop_v4(true)
+            .map_err(|e| format!("Failed to enable multicast loopback: {}", e))?;
+        let _ = socket.set_multicast_if_v4(&local_ipv4);
+
+        // 与发现服务共用端口（SO_REUSEADDR），才能收到其他设备的广播
+        let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, group.port());
+        socket.bind(&addr.into())
+            .map_err(|e| format!("Failed to bind discovery port: {}", e))?;
+        socket.join_multicast_v4(group.ip(), &local_ipv4)
+            .map_err(|e| format!("Failed to join multicast group: {}", e))?;
+
+        let socket: UdpSocket = socket.into();
+        socket.set_read_timeout(Some(Duration::from_millis(200)))
+            .map_err(|e| format!("Failed to set read timeout: {}", e))?;
+
+        // 探测包使用不同的前缀，其他设备的发现服务会忽略它
+        let probe = format!("FILETRANSFER-PROBE:{}:{}", local_ip, new_transfer_id());
+        let can_send = socket
+            .send_to(probe.as_bytes(), group)
+            .is_ok();
+
+        let own_instance = std::process::id().to_string();
+        let mut can_receive_own = false;
+        let mut peers = std::collections::HashSet::new();
+        let mut buf = [0u8; 1024];
+        let deadline = std::time::Instant::now() + MULTICAST_TEST_LISTEN;
+
+        while std::time::Instant::now() < deadline {
+            let Ok((len, _)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            if &buf[..len] == probe.as_bytes() {
+                can_receive_own = true;
+            } else if let Some(announce) = discovery_packet::decode(&buf[..len]) {
+                if announce.instance_id != own_instance {
+                    peers.insert(announce.ip);
+                }
+            }
+        }
+
+        let _ = socket.leave_multicast_v4(group.ip(), &local_ipv4);
+
+        Ok(MulticastTestResult {
+            can_send,
+            can_receive_own,
+            peers_seen: peers.len(),
+        })
+    })
+    .await
+    .map_err(|e| TransferError::Other(format!("Multicast test failed: {}", e)))?
+    .map_err(TransferError::Io)
+}
+
+#[tauri::command]
+/// 获取系统下载目录
+pub fn get_download_dir(app: AppHandle) -> Result<String, TransferError> {
+    // Android: 直接使用标准下载目录
+    #[cfg(target_os = "android")]
+    {
+        return Ok("/storage/emulated/0/Download".to_string());
+    }
+
+    // 桌面端：使用系统下载目录
+    #[allow(unreachable_code)]
+    if let Some(dir) = dirs::download_dir().or_else(dirs::home_dir) {
+        return Ok(dir.to_string_lossy().to_string());
+    }
+
+    let _ = app; // 桌面端避�?unused 警告
+    Err(TransferError::NotFound("无法获取下载目录".into()))
+}
+
+// list_local_ips 探测的目标：公网与各私有网段，分别取系统为其选择的源地址（不实际发送数据）
+const ROUTE_PROBE_TARGETS: &[&str] = &[
+    "8.8.8.8:80",
+    "10.254.254.254:80",
+    "172.31.254.254:80",
+    "192.168.254.254:80",
+    "169.254.254.254:80",
+];
+
+#[tauri::command]
+/// 列出本机用于各网络的 IPv4 地址（第一个为默认出口），供 start_discovery 选择接口
+pub fn list_local_ips() -> Vec<String> {
+    let mut ips: Vec<String> = Vec::new();
+    for target in ROUTE_PROBE_TARGETS {
+        let Ok(socket) = UdpSocket::bind("0.0.0.0:0") else {
+            continue;
+        };
+        if socket.connect(target).is_err() {
+            continue;
+        }
+        let Ok(local_addr) = socket.local_addr() else {
+            continue;
+        };
+        let ip = local_addr.ip();
+        if ip.is_loopback() || ip.is_unspecified() {
+            continue;
+        }
+        let ip = ip.to_string();
+        if !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+    ips
+}
+
+#[tauri::command]
+/// 获取本机局域网IP地址
+pub fn get_local_ip() -> Result<String, TransferError> {
+    // 通过连接到外部地址（不实际发送数据）来获取本机IP
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .map_err(|e| TransferError::Io(format!("Failed to bind socket: {}", e)))?;
+
+    socket.connect("8.8.8.8:80")
+        .map_err(|e| TransferError::Io(format!("Failed to connect: {}", e)))?;
+
+    let local_addr = socket.local_addr()
+        .map_err(|e| TransferError::Io(format!("Failed to get local address: {}", e)))?;
+
+    Ok(local_addr.ip().to_string())
+}
+
+#[tauri::command]
+/// 弹出文件夹选择对话框并返回路径字符�?
+pub async fn select_folder(app: AppHandle) -> Result<Option<String>, TransferError> {
+    #[cfg(not(target_os = "android"))]
+    {
+        use tauri_plugin_dialog::DialogExt;
+
+        // 使用 blocking 版本适合异步命令
+        let folder_path = app.dialog().file().blocking_pick_folder();
+
+        match folder_path {
+            Some(fp) => {
+                let pathbuf = fp.into_path().map_err(|e| TransferError::InvalidInput(e.to_string()))?;
+                Ok(Some(pathbuf.to_string_lossy().to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Android: 使用 SAF 选择文件夹并返回 tree Uri
     #[cfg(target_os = "android")]
     {
         let storage = app.state::<AndroidStorage>();
-        storage.pick_folder()
+        storage.pick_folder().map_err(TransferError::Io)
     }
 }
 
 #[tauri::command]
 /// Android 原生多文件选择器
-pub async fn pick_multiple_files(app: AppHandle) -> Result<Vec<String>, String> {
+pub async fn pick_multiple_files(app: AppHandle) -> Result<Vec<String>, TransferError> {
     #[cfg(target_os = "android")]
     {
         let storage = app.state::<AndroidStorage>();
-        storage.pick_multiple_files()
+        storage.pick_multiple_files().map_err(TransferError::Io)
     }
 
     #[cfg(not(target_os = "android"))]
     {
         let _ = app;
-        Err("pick_multiple_files is only supported on Android".to_string())
+        Err(TransferError::Unsupported("pick_multiple_files is only supported on Android".into()))
     }
 }
 
@@ -347,562 +2609,2394 @@ pub struct FolderFile {
     pub name: String,
     pub relative_path: String,
     pub size: u64,
+    /// 不含任何文件的子目录（发送时在接收端创建）
+    pub is_dir: bool,
+    /// 按扩展名推断的 MIME 类型，未知时为 application/octet-stream
+    pub mime: String,
+}
+
+// 文件夹列表中空目录条目的 MIME 类型
+const DIR_MIME: &str = "inode/directory";
+
+/// 按文件扩展名推断 MIME 类型，未知时为 application/octet-stream
+fn guess_mime(name: &str) -> String {
+    mime_guess::from_path(name).first_or_octet_stream().to_string()
 }
 
 #[tauri::command]
 /// 桌面端：读取文件夹内所有文件
-pub async fn list_folder_files(folder_path: String) -> Result<Vec<FolderFile>, String> {
-    use std::path::Path;
+pub async fn list_folder_files(folder_path: String) -> Result<Vec<FolderFile>, TransferError> {
+    scan_folder(&folder_path, &FolderFilter::default())
+}
 
-    let root = Path::new(&folder_path);
+/// 桌面端：列出要发送的文件夹内容，发送与预览共用同一次遍历
+fn scan_folder(folder_path: &str, filter: &FolderFilter) -> Result<Vec<FolderFile>, TransferError> {
+    let root = Path::new(folder_path);
     if !root.is_dir() {
-        return Err("Not a directory".to_string());
+        return Err(TransferError::InvalidInput("Not a directory".to_string()));
     }
 
     let root_name = root.file_name()
         .map(|n| n.to_string_lossy().to_string())
         .unwrap_or_default();
 
-    let mut files = Vec::new();
-    collect_files(root, &root_name, &mut files)?;
-    Ok(files)
+    let mut files = Vec::new();
+    collect_files(root, &root_name, &mut files, filter, None)?;
+    Ok(files)
+}
+
+/// 桌面端单独发送的文件，相对路径即文件名
+fn list_files(paths: &[String]) -> Result<Vec<FolderFile>, TransferError> {
+    paths.iter().map(|path| {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| TransferError::NotFound(format!("Cannot read {}: {}", path, e)))?;
+        if !metadata.is_file() {
+            return Err(TransferError::InvalidInput(format!("Not a file: {}", path)));
+        }
+        let name = Path::new(path).file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| TransferError::InvalidInput(format!("Invalid file path: {}", path)))?;
+        Ok(FolderFile {
+            path: path.clone(),
+            mime: guess_mime(&name),
+            relative_path: name.clone(),
+            name,
+            size: metadata.len(),
+            is_dir: false,
+        })
+    }).collect()
+}
+
+/// `preview_folder_transfer` 的结果：将要发送的文件及汇总
+#[derive(Serialize, Clone, Debug)]
+pub struct FolderPreview<T> {
+    pub files: Vec<T>,
+    pub file_count: usize,
+    /// 不含任何文件、将在接收端创建的子目录数
+    pub empty_dir_count: usize,
+    pub total_bytes: u64,
+}
+
+impl<T> FolderPreview<T> {
+    fn new(files: Vec<T>, is_dir: impl Fn(&T) -> bool, size: impl Fn(&T) -> u64) -> Self {
+        let empty_dir_count = files.iter().filter(|f| is_dir(f)).count();
+        FolderPreview {
+            file_count: files.len() - empty_dir_count,
+            empty_dir_count,
+            total_bytes: files.iter().map(size).sum(),
+            files,
+        }
+    }
+}
+
+#[tauri::command]
+/// 桌面端：预览发送文件夹时将发送的内容（文件列表、文件数与总大小），不建立任何连接。
+/// include / exclude 与 SendOptions 中的同名字段一致
+pub async fn preview_folder_transfer(
+    folder_path: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<FolderPreview<FolderFile>, TransferError> {
+    let filter = FolderFilter::new(&include.unwrap_or_default(), &exclude.unwrap_or_default())?;
+    let files = tokio::task::spawn_blocking(move || scan_folder(&folder_path, &filter))
+        .await
+        .map_err(|e| TransferError::Other(format!("Failed to list folder: {}", e)))??;
+    Ok(FolderPreview::new(files, |f| f.is_dir, |f| f.size))
+}
+
+/// `validate_folder` 的结果（路径均为相对路径）
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct FolderValidation {
+    pub file_count: usize,
+    pub total_bytes: u64,
+    /// 无法读取的文件或目录（含指向不存在目标的符号链接）
+    pub unreadable: Vec<String>,
+    pub symlinks: Vec<String>,
+}
+
+#[tauri::command]
+/// 桌面端：发送前检查文件夹，尝试打开每个文件，报告无法读取的文件和符号链接（不发送）
+pub async fn validate_folder(path: String) -> Result<FolderValidation, TransferError> {
+    let root = PathBuf::from(&path);
+    if !root.is_dir() {
+        return Err(TransferError::InvalidInput("Not a directory".into()));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let root_name = root.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut files = Vec::new();
+        let mut validation = FolderValidation::default();
+        collect_files(&root, &root_name, &mut files, &FolderFilter::default(), Some(&mut validation))?;
+        validation.file_count = files.iter().filter(|f| !f.is_dir).count();
+        validation.total_bytes = files.iter().map(|f| f.size).sum();
+        Ok(validation)
+    }).await.map_err(|e| TransferError::Other(format!("Failed to validate folder: {}", e)))?
+}
+
+/// 符号链接目录指向自身路径上的某一级目录时，递归进入会形成循环
+fn is_symlink_loop(path: &std::path::Path) -> bool {
+    let Ok(target) = std::fs::canonicalize(path) else {
+        return false;
+    };
+    path.ancestors().skip(1).any(|ancestor| std::fs::canonicalize(ancestor).is_ok_and(|a| a == target))
+}
+
+/// 递归收集文件，不含任何文件的子目录以 is_dir 条目记录。check 为 Some 时为检查模式：
+/// 遇到错误不中断，而是记录到检查结果中，并尝试打开每个文件确认可读
+fn collect_files(
+    dir: &std::path::Path,
+    relative_base: &str,
+    files: &mut Vec<FolderFile>,
+    filter: &FolderFilter,
+    mut check: Option<&mut FolderValidation>,
+) -> Result<(), TransferError> {
+    use std::fs;
+
+    let entries = match (fs::read_dir(dir), check.as_deref_mut()) {
+        (Ok(entries), _) => entries,
+        (Err(_), Some(check)) => {
+            check.unreadable.push(relative_base.to_string());
+            return Ok(());
+        }
+        (Err(e), None) => return Err(TransferError::Io(e.to_string())),
+    };
+
+    for entry in entries {
+        let entry = match (entry, check.as_deref_mut()) {
+            (Ok(entry), _) => entry,
+            (Err(_), Some(check)) => {
+                check.unreadable.push(relative_base.to_string());
+                continue;
+            }
+            (Err(e), None) => return Err(TransferError::Io(e.to_string())),
+        };
+        let path = entry.path();
+        let entry_name = entry.file_name().to_string_lossy().to_string();
+
+        if let Some(check) = check.as_deref_mut() {
+            if entry.file_type().is_ok_and(|t| t.is_symlink()) {
+                let relative = format!("{}/{}", relative_base, entry_name);
+                if !path.exists() {
+                    check.unreadable.push(relative.clone());
+                }
+                check.symlinks.push(relative);
+            }
+        }
+
+        if path.is_dir() {
+            let dir_name = path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let new_base = format!("{}/{}", relative_base, dir_name);
+            // 被排除的目录不再进入
+            if filter.skips_dir(&new_base) {
+                continue;
+            }
+            if entry.file_type().is_ok_and(|t| t.is_symlink()) && is_symlink_loop(&path) {
+                continue;
+            }
+            let before = files.len();
+            collect_files(&path, &new_base, files, filter, check.as_deref_mut())?;
+            if files.len() == before && !filter.has_include() {
+                files.push(FolderFile {
+                    path: path.to_string_lossy().to_string(),
+                    name: dir_name,
+                    relative_path: new_base,
+                    size: 0,
+                    is_dir: true,
+                    mime: DIR_MIME.to_string(),
+                });
+            }
+        } else if path.is_file() {
+            let file_name = path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if !filter.matches_file(&format!("{}/{}", relative_base, file_name)) {
+                continue;
+            }
+            let metadata = match (fs::metadata(&path), check.as_deref_mut()) {
+                (Ok(metadata), Some(check)) => {
+                    if fs::File::open(&path).is_err() {
+                        check.unreadable.push(format!("{}/{}", relative_base, file_name));
+                        continue;
+                    }
+                    metadata
+                }
+                (Ok(metadata), None) => metadata,
+                (Err(_), Some(check)) => {
+                    check.unreadable.push(format!("{}/{}", relative_base, file_name));
+                    continue;
+                }
+                (Err(e), None) => return Err(TransferError::Io(e.to_string())),
+            };
+
+            files.push(FolderFile {
+                path: path.to_string_lossy().to_string(),
+                mime: guess_mime(&file_name),
+                name: file_name.clone(),
+                relative_path: format!("{}/{}", relative_base, file_name),
+                size: metadata.len(),
+                is_dir: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AndroidFolderFile {
+    pub uri: String,
+    pub name: String,
+    pub relative_path: String,
+    pub size: u64,
+    /// 毫秒时间戳，文档提供方不支持时为 0
+    pub last_modified: i64,
+    /// 不含任何文件的子目录（发送时在接收端创建）
+    pub is_dir: bool,
+    /// 文档提供方报告的 MIME 类型，未知时为 application/octet-stream
+    pub mime: String,
+}
+
+#[tauri::command]
+/// Android: 选择文件夹并列出所有文件（用于发送）
+pub async fn pick_folder_for_send(app: AppHandle) -> Result<Vec<AndroidFolderFile>, TransferError> {
+    #[cfg(target_os = "android")]
+    {
+        let storage = app.state::<AndroidStorage>();
+
+        // First pick a folder
+        let folder_uri = storage.pick_folder().map_err(TransferError::Io)?;
+        let folder_uri = folder_uri.ok_or_else(|| TransferError::Cancelled("No folder selected".into()))?;
+
+        // Then list all files recursively
+        list_android_folder(&storage, folder_uri)
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = app;
+        Err(TransferError::Unsupported("pick_folder_for_send is only supported on Android".into()))
+    }
+}
+
+/// Android: 递归列出 SAF 目录中要发送的文件，发送与预览共用
+#[cfg(target_os = "android")]
+fn list_android_folder(storage: &AndroidStorage, tree_uri: String) -> Result<Vec<AndroidFolderFile>, TransferError> {
+    let files = storage.list_folder_contents(tree_uri).map_err(TransferError::Io)?;
+
+    Ok(files.into_iter().map(|f| AndroidFolderFile {
+        uri: f.uri,
+        name: f.name,
+        relative_path: f.relative_path,
+        size: f.size,
+        last_modified: f.last_modified,
+        is_dir: f.is_dir,
+        mime: f.mime,
+    }).collect())
+}
+
+#[tauri::command]
+/// Android: 选择文件夹并预览将发送的内容（文件列表、文件数与总大小），不建立任何连接。
+/// 返回的 files 可直接传给 send_folder_android；include / exclude 与 SendOptions 中的同名字段一致
+pub async fn preview_folder_transfer_android(
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    app: AppHandle,
+) -> Result<FolderPreview<AndroidFolderFile>, TransferError> {
+    #[cfg(target_os = "android")]
+    {
+        let filter = FolderFilter::new(&include.unwrap_or_default(), &exclude.unwrap_or_default())?;
+        let storage = app.state::<AndroidStorage>();
+        let folder_uri = storage.pick_folder().map_err(TransferError::Io)?
+            .ok_or_else(|| TransferError::Cancelled("No folder selected".into()))?;
+        let mut files = list_android_folder(&storage, folder_uri)?;
+        files.retain(|f| filter.keeps(&f.relative_path, f.is_dir));
+        Ok(FolderPreview::new(files, |f| f.is_dir, |f| f.size))
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = (include, exclude, app);
+        Err(TransferError::Unsupported("preview_folder_transfer_android is only supported on Android".into()))
+    }
+}
+
+#[tauri::command]
+/// Android: 从 content:// URI 发送多个文件
+/// 完成后返回本次发送的 ID；进行中可从 list_all_jobs 取得该 ID 并用 cancel_file_sending 取消
+pub async fn send_files_android(
+    uris: Vec<String>,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let options = options.unwrap_or_default();
+    let batch_id = options.resume_batch_id.clone().or_else(|| options.batch_id.clone()).unwrap_or_else(new_transfer_id);
+    let source = SendSource::FilesAndroid { uris: uris.clone() };
+
+    let _job = jobs::register(&batch_id, JobKind::SendQueued, &target_ip);
+    let _cancel = CancelGuard::register(&SEND_CANCELS, &batch_id);
+    window_events::emit(&window, "transfer-send-started", SendStarted {
+        batch_id: batch_id.clone(),
+        target_ip: target_ip.clone(),
+    });
+    let _service = transfer_service::acquire(&app);
+    let result = match metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await {
+        Ok(()) => {
+            let inner = send_files_android_inner(uris, target_ip.clone(), options.clone(), batch_id.clone(), window, app.clone());
+            ports::with_peer_port(port, socket_buffers::with_source_port(options.source_port, inner)).await
+        }
+        Err(e) => Err(e),
+    };
+    crate::network::transfer_history::record_send(&app, batch_id.clone(), target_ip, port, source, options, &result);
+    result.map(|()| batch_id)
+}
+
+async fn send_files_android_inner(
+    uris: Vec<String>,
+    target_ip: String,
+    options: SendOptions,
+    batch_id: String,
+    window: Window,
+    app: AppHandle,
+) -> Result<(), TransferError> {
+    #[cfg(target_os = "android")]
+    {
+        let cancelled = cancel_flag(&SEND_CANCELS, &batch_id);
+        let note = options.note.as_deref().and_then(sanitize_note);
+        let mut pacer = rate_limit::SendPacer::new(&batch_id, options.max_bytes_per_sec);
+        let target_ip = resolve_target(&target_ip)?;
+
+        let storage = app.state::<AndroidStorage>();
+        let resumable = supports(&window, &target_ip, "resume_offset").await;
+        let checksum = supports(&window, &target_ip, "checksum").await;
+        let compress = options.compress && supports(&window, &target_ip, "gzip").await;
+
+        // 续传：跳过接收端在该批次中已完成的文件（无相对路径时按文件名记录）
+        let uris = if options.resume_batch_id.is_some() {
+            let completed = query_batch_status(&window, &target_ip, &batch_id).await;
+            uris.into_iter()
+                .filter(|uri| storage.get_file_info(uri.clone()).map_or(true, |(name, _)| !completed.contains(&name)))
+                .collect()
+        } else {
+            uris
+        };
+
+        // 清单优先：先让接收端挑选文件，只发送被接受的部分
+        let mut connection = None;
+        let uris = if options.manifest_first {
+            let mut entries = Vec::with_capacity(uris.len());
+            for (index, uri) in uris.iter().enumerate() {
+                let (name, size) = storage.get_file_info(uri.clone())
+                    .map_err(|e| TransferError::Io(format!("Failed to get file info for {}: {}", uri, e)))?;
+                entries.push(ManifestEntry { index: index as u32, name, size, relative_path: None });
+            }
+            let (mut write, mut read) = connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split();
+            let accepted = negotiate_manifest(&mut write, &mut read, &batch_id, entries).await?;
+            // 清单与第一个文件使用同一个连接
+            connection = Some((write, read));
+            retain_accepted(uris, &accepted)
+        } else {
+            uris
+        };
+        let total = uris.len() as u32;
+
+        for (index, uri) in uris.iter().enumerate() {
+            // Check if cancelled before starting next file
+            if cancelled.load(Ordering::SeqCst) {
+                emit_transfer_cancelled(&window, &batch_id, "send", None, 0);
+                return Err(TransferError::Cancelled("Cancelled by user".into()));
+            }
+            // 网络可能在批次进行中切换为计费网络，每个文件开始前重新检查
+            if index > 0 {
+                metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await?;
+            }
+            // 1. 获取文件信息
+            let (file_name, file_size) = storage.get_file_info(uri.clone())
+                .map_err(|e| TransferError::Io(format!("Failed to get file info for {}: {}", uri, e)))?;
+            let mime = storage.get_mime_type(uri.clone()).unwrap_or_else(|_| guess_mime(&file_name));
+            let last_modified = storage.get_last_modified(uri.clone()).unwrap_or(0);
+
+            let sending = FileSending { name: file_name.clone(), index: index as u32, total, size: file_size };
+            window_events::emit(&window, "file-sending", &sending);
+
+            // 2. 建立 WebSocket 连接
+            let (mut write, mut read) = match connection.take() {
+                Some(connection) => connection,
+                None => connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split(),
+            };
+
+            // 续传：接收端保留了上次中断的部分文件时从其末尾继续
+            let resume_from = if resumable {
+                query_resume_offset(&mut write, &mut read, &file_name, None, file_size).await
+            } else {
+                0
+            };
+
+            // 3. 发送文件元数据
+            let meta = serde_json::json!({
+                "name": file_name,
+                "size": file_size,
+                "index": index,
+                "total": total,
+                "batch_id": batch_id,
+                "mime": mime,
+                "note": note,
+                "resume_from": resume_from,
+                "checksum_follows": checksum,
+                "compress": compress,
+                "mtime": (last_modified > 0).then_some(last_modified / 1000),
+            });
+            let resume_from = send_file_meta(&mut write, &mut read, meta, &window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?;
+            let mut hasher = checksum.then(|| Context::new(&SHA256));
+
+            // 4. 分块读取并发送文件内容
+            let mut bytes_sent: u64 = resume_from;
+            let mut rate = ProgressRate::new(resume_from);
+            let mut last_progress_emit = Instant::now();
+            // 各块复用同一个读取缓冲区
+            let mut binary_data = Vec::with_capacity(ANDROID_READ_CHUNK_SIZE as usize);
+            let reader = storage.open_uri_reader(uri.clone());
+            if let Some(hasher) = hasher.as_mut().filter(|_| resume_from > 0) {
+                hash_uri_prefix(&reader, resume_from, hasher, &mut binary_data)?;
+            }
+
+            let end = send_chunks(
+                &mut write,
+                |offset, buffer| reader.read_chunk(offset, ANDROID_READ_CHUNK_SIZE, buffer)
+                    .map_err(|e| TransferError::Io(format!("Failed to read chunk: {}", e))),
+                &mut binary_data,
+                file_size,
+                &mut bytes_sent,
+                compress,
+                &cancelled,
+                hasher.as_mut(),
+                &mut pacer,
+                |bytes_sent| {
+                    // 按时间节流进度事件，最后一帧总是发送
+                    if bytes_sent < file_size && last_progress_emit.elapsed() < PROGRESS_EMIT_INTERVAL {
+                        return;
+                    }
+                    last_progress_emit = Instant::now();
+                    jobs::update_progress(&batch_id, &file_name, bytes_sent, file_size);
+                    window_events::emit(&window, "file-transfer-progress", FileProgress::new(
+                        file_name.clone(),
+                        bytes_sent,
+                        file_size,
+                        &mut rate,
+                    ));
+                },
+            ).await?;
+            let mut receipt = match end {
+                ChunksEnd::Done => None,
+                ChunksEnd::Cancelled => {
+                    let _ = write.send(Message::Close(None)).await;
+                    emit_transfer_cancelled(&window, &batch_id, "send", Some(file_name.clone()), bytes_sent);
+                    return Err(TransferError::Cancelled("Cancelled by user".into()));
+                }
+                // 连接断开，检查是否是接收端取消或跳过（Close 码可能在接收缓冲区中）
+                ChunksEnd::WriteFailed(error) => Some(receiver_closed(&mut read, error).await?),
+            };
+
+            // 5. 发送校验和（接收端跳过该文件时已关闭连接）
+            if let (None, Some(hasher)) = (receipt, hasher) {
+                if let Err(e) = send_checksum(&mut write, hasher).await {
+                    receipt = Some(receiver_closed(&mut read, e).await?);
+                }
+            }
+
+            // 6. 关闭连接，等待接收端确认与关闭响应，检测是否被取消
+            let receipt = match receipt {
+                Some(receipt) => receipt,
+                None => finish_file(&mut write, &mut read, false).await?,
+            };
+            report_receipt(&window, receipt, &batch_id, &file_name);
+            if receipt == Receipt::Skipped {
+                continue;
+            }
+
+            window_events::emit(&window, "file-sent", &sending);
+            crate::network::transfer_history::record_file(&app, &batch_id, "send", &target_ip, &file_name, file_size, None);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = (uris, target_ip, options, batch_id, window, app);
+        Err(TransferError::Unsupported("send_files_android is only supported on Android".to_string()))
+    }
 }
 
-fn collect_files(dir: &std::path::Path, relative_base: &str, files: &mut Vec<FolderFile>) -> Result<(), String> {
-    use std::fs;
+/// 发送记录中保存的发送来源，用于重新发送
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SendSource {
+    FilesAndroid { uris: Vec<String> },
+    FolderAndroid { files: Vec<FolderFileToSend> },
+    FolderDesktop { folder_path: String },
+    /// 桌面端：若干单独的文件（不含目录），按文件名保存在接收端
+    FilesDesktop { paths: Vec<String> },
+}
 
-    let entries = fs::read_dir(dir).map_err(|e| e.to_string())?;
+/// 发送命令的可选参数（均可省略，兼容旧的调用方式）
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct SendOptions {
+    /// 先发送文件清单，由接收端挑选要接收的文件
+    pub manifest_first: bool,
+    /// 文件夹发送顺序
+    pub sort: SortOrder,
+    /// 续传：沿用之前的批次 ID，跳过接收端已完成的文件
+    pub resume_batch_id: Option<String>,
+    /// 随文件一起发送的简短备注
+    pub note: Option<String>,
+    /// 稀疏发送：长段全零数据以 ZeroFill 代替（目前仅桌面端文件夹发送；需接收端支持，否则照常发送）
+    pub sparse: bool,
+    /// 增量同步：只发送接收端缺失或内容有变化的文件（目前仅桌面端文件夹发送）
+    pub changed_only: bool,
+    /// 固定本地源端口（适配只放行特定源端口的防火墙）。同一端口同时只能有一个连接
+    pub source_port: Option<u16>,
+    /// 忽略计费网络策略，允许本次发送使用计费网络
+    pub allow_metered: bool,
+    /// 以 gzip 压缩数据块（适合文本较多的文件夹；接收端不支持时照常发送）
+    pub compress: bool,
+    /// 本次发送的速率上限（字节/秒），只限制这一次发送；全局上限（set_transfer_rate_limit）同时生效
+    pub max_bytes_per_sec: Option<u64>,
+    /// 连接接收端失败时的重试次数（默认 3，0 表示不重试）
+    pub connect_retries: Option<u32>,
+    /// 文件夹发送：只发送 relative_path 匹配任一 glob 的文件（如 `**/*.jpg`），为空时发送全部
+    pub include: Vec<String>,
+    /// 文件夹发送：跳过 relative_path 匹配任一 glob 的文件；`**/node_modules/**` 这类模式会整体跳过目录
+    pub exclude: Vec<String>,
+    /// 调用方预先分配的批次 ID（多目标发送据此汇总进度与取消），与续传不同，不查询接收端已完成的文件
+    #[serde(skip)]
+    pub(crate) batch_id: Option<String>,
+}
 
-    for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
+/// 按来源调用对应的发送命令（重试、拉取等复用）
+pub(crate) async fn send_source(
+    source: SendSource,
+    target_ip: String,
+    port: Option<u16>,
+    options: SendOptions,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    match source {
+        SendSource::FilesAndroid { uris } => send_files_android(uris, target_ip, Some(options), port, window, app).await,
+        SendSource::FolderAndroid { files } => send_folder_android(files, target_ip, Some(options), port, window, app).await,
+        SendSource::FolderDesktop { folder_path } => {
+            send_folder_desktop(folder_path, target_ip, Some(options), port, window, app).await
+        }
+        SendSource::FilesDesktop { paths } => send_files_desktop(paths, target_ip, Some(options), port, window, app).await,
+    }
+}
 
-        if path.is_dir() {
-            let dir_name = path.file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
-            let new_base = format!("{}/{}", relative_base, dir_name);
-            collect_files(&path, &new_base, files)?;
-        } else if path.is_file() {
-            let file_name = path.file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
-            let metadata = fs::metadata(&path).map_err(|e| e.to_string())?;
+/// 文件夹发送顺序（默认保持文件系统返回的顺序）
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    None,
+    /// 按相对路径字母顺序
+    NameAsc,
+    /// 小文件优先，慢速链路上能更快完成更多文件
+    SizeAsc,
+    SizeDesc,
+}
 
-            files.push(FolderFile {
-                path: path.to_string_lossy().to_string(),
-                name: file_name.clone(),
-                relative_path: format!("{}/{}", relative_base, file_name),
-                size: metadata.len(),
-            });
-        }
+/// 按指定顺序排序待发送文件（稳定排序，相同键保持原有顺序）
+fn sort_files<T>(files: &mut [T], order: SortOrder, key: impl Fn(&T) -> (&str, u64)) {
+    match order {
+        SortOrder::None => {}
+        SortOrder::NameAsc => files.sort_by(|a, b| key(a).0.cmp(key(b).0)),
+        SortOrder::SizeAsc => files.sort_by_key(|f| key(f).1),
+        SortOrder::SizeDesc => files.sort_by_key(|f| std::cmp::Reverse(key(f).1)),
     }
-    Ok(())
 }
 
-#[derive(Serialize, Clone, Debug)]
-pub struct AndroidFolderFile {
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(dead_code)]
+pub struct FolderFileToSend {
     pub uri: String,
     pub name: String,
     pub relative_path: String,
     pub size: u64,
+    /// 毫秒时间戳（来自 pick_folder_for_send），未知时为 0
+    #[serde(default)]
+    pub last_modified: i64,
+    /// 空目录（来自 pick_folder_for_send），在接收端创建
+    #[serde(default)]
+    pub is_dir: bool,
+    /// 来自 pick_folder_for_send，随元数据发送给接收端
+    #[serde(default)]
+    pub mime: Option<String>,
 }
 
 #[tauri::command]
-/// Android: 选择文件夹并列出所有文件（用于发送）
-pub async fn pick_folder_for_send(app: AppHandle) -> Result<Vec<AndroidFolderFile>, String> {
+/// Android: 发送文件夹中的文件（带相对路径）
+/// 完成后返回本次发送的 ID；进行中可从 list_all_jobs 取得该 ID 并用 cancel_file_sending 取消
+pub async fn send_folder_android(
+    files: Vec<FolderFileToSend>,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let options = options.unwrap_or_default();
+    let batch_id = options.resume_batch_id.clone().or_else(|| options.batch_id.clone()).unwrap_or_else(new_transfer_id);
+    let source = SendSource::FolderAndroid { files: files.clone() };
+
+    let _job = jobs::register(&batch_id, JobKind::SendQueued, &target_ip);
+    let _cancel = CancelGuard::register(&SEND_CANCELS, &batch_id);
+    window_events::emit(&window, "transfer-send-started", SendStarted {
+        batch_id: batch_id.clone(),
+        target_ip: target_ip.clone(),
+    });
+    let _service = transfer_service::acquire(&app);
+    let result = match metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await {
+        Ok(()) => {
+            let inner = send_folder_android_inner(files, target_ip.clone(), options.clone(), batch_id.clone(), window, app.clone());
+            ports::with_peer_port(port, socket_buffers::with_source_port(options.source_port, inner)).await
+        }
+        Err(e) => Err(e),
+    };
+    crate::network::transfer_history::record_send(&app, batch_id.clone(), target_ip, port, source, options, &result);
+    result.map(|()| batch_id)
+}
+
+async fn send_folder_android_inner(
+    files: Vec<FolderFileToSend>,
+    target_ip: String,
+    options: SendOptions,
+    batch_id: String,
+    window: Window,
+    app: AppHandle,
+) -> Result<(), TransferError> {
     #[cfg(target_os = "android")]
     {
+        let cancelled = cancel_flag(&SEND_CANCELS, &batch_id);
+        let note = options.note.as_deref().and_then(sanitize_note);
+        let mut pacer = rate_limit::SendPacer::new(&batch_id, options.max_bytes_per_sec);
+        let target_ip = resolve_target(&target_ip)?;
+
         let storage = app.state::<AndroidStorage>();
+        let resumable = supports(&window, &target_ip, "resume_offset").await;
+        let checksum = supports(&window, &target_ip, "checksum").await;
+        let compress = options.compress && supports(&window, &target_ip, "gzip").await;
+        // 接收端支持时所有文件复用同一个连接，省去逐个文件的握手
+        let multi_file = supports(&window, &target_ip, "multi_file").await;
+        let mut connection = None;
+
+        // Android 的文件列表由前端传入，只能在这里过滤
+        let filter = FolderFilter::new(&options.include, &options.exclude)?;
+        let (dirs, mut files): (Vec<FolderFileToSend>, Vec<FolderFileToSend>) = files
+            .into_iter()
+            .filter(|f| filter.keeps(&f.relative_path, f.is_dir))
+            .partition(|f| f.is_dir);
+        let empty_dirs = empty_dirs_to_send(&window, &target_ip, dirs.into_iter().map(|d| d.relative_path).collect()).await;
+        sort_files(&mut files, options.sort, |f| (f.relative_path.as_str(), f.size));
+
+        // 续传：沿用原批次 ID，跳过接收端已完成的文件
+        let skipped = match options.resume_batch_id {
+            Some(_) => {
+                let completed = query_batch_status(&window, &target_ip, &batch_id).await;
+                let before = files.len();
+                files.retain(|f| !completed.contains(&f.relative_path));
+                (before - files.len()) as u32
+            }
+            None => 0,
+        };
+        window_events::emit(&window, "transfer-batch-started", BatchStarted {
+            batch_id: batch_id.clone(),
+            total: files.len() as u32,
+            skipped,
+            unchanged: 0,
+        });
 
-        // First pick a folder
-        let folder_uri = storage.pick_folder()?;
-        let folder_uri = folder_uri.ok_or("No folder selected")?;
+        let files = if options.manifest_first {
+            let entries = files.iter().enumerate().map(|(index, f)| ManifestEntry {
+                index: index as u32,
+                name: f.name.clone(),
+                size: f.size,
+                relative_path: Some(f.relative_path.clone()),
+            }).collect();
+            let (mut write, mut read) = connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split();
+            let accepted = negotiate_manifest(&mut write, &mut read, &batch_id, entries).await?;
+            // 清单与随后的文件使用同一个连接
+            connection = Some((write, read));
+            retain_accepted(files, &accepted)
+        } else {
+            files
+        };
+        let total = files.len() as u32;
+        let mut folder_progress = FolderProgress::new(&batch_id, total, files.iter().map(|f| f.size).sum());
 
-        // Then list all files recursively
-        let files = storage.list_folder_contents(folder_uri)?;
+        // 空目录在文件之前发送，打包为 zip 的接收端在最后一个文件到达时完成压缩包
+        for dir in &empty_dirs {
+            if cancelled.load(Ordering::SeqCst) {
+                emit_transfer_cancelled(&window, &batch_id, "send", None, 0);
+                return Err(TransferError::Cancelled("Cancelled by user".into()));
+            }
+            let (mut write, mut read) = match connection.take() {
+                Some(connection) => connection,
+                None => connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split(),
+            };
+            let receipt = send_empty_dir(&mut write, &mut read, dir, &batch_id, &note, total, multi_file).await?;
+            report_receipt(&window, receipt, &batch_id, dir);
+            if multi_file {
+                connection = Some((write, read));
+            }
+        }
 
-        Ok(files.into_iter().map(|f| AndroidFolderFile {
-            uri: f.uri,
-            name: f.name,
-            relative_path: f.relative_path,
-            size: f.size,
-        }).collect())
+        for (index, file_info) in files.iter().enumerate() {
+            if cancelled.load(Ordering::SeqCst) {
+                emit_transfer_cancelled(&window, &batch_id, "send", None, 0);
+                return Err(TransferError::Cancelled("Cancelled by user".into()));
+            }
+            // 网络可能在批次进行中切换为计费网络，每个文件开始前重新检查
+            if index > 0 {
+                metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await?;
+            }
+
+            let sending = FileSending { name: file_info.name.clone(), index: index as u32, total, size: file_info.size };
+            window_events::emit(&window, "file-sending", &sending);
+
+            let (mut write, mut read) = match connection.take() {
+                Some(connection) => connection,
+                None => connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split(),
+            };
+
+            // 续传：接收端保留了上次中断的部分文件时从其末尾继续
+            let resume_from = if resumable {
+                query_resume_offset(&mut write, &mut read, &file_info.name, Some(&file_info.relative_path), file_info.size).await
+            } else {
+                0
+            };
+
+            // Send metadata with relative_path
+            let meta = serde_json::json!({
+                "name": file_info.name,
+                "size": file_info.size,
+                "index": index,
+                "total": total,
+                "relative_path": file_info.relative_path,
+                "batch_id": batch_id,
+                "mime": file_info.mime.clone().unwrap_or_else(|| guess_mime(&file_info.name)),
+                "note": note,
+                "resume_from": resume_from,
+                "checksum_follows": checksum,
+                "compress": compress,
+                "mtime": (file_info.last_modified > 0).then_some(file_info.last_modified / 1000),
+            });
+            let resume_from = send_file_meta(&mut write, &mut read, meta, &window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?;
+            let mut hasher = checksum.then(|| Context::new(&SHA256));
+
+            let mut bytes_sent: u64 = resume_from;
+            let mut rate = ProgressRate::new(resume_from);
+            let mut last_progress_emit = Instant::now();
+            // 各块复用同一个读取缓冲区
+            let mut binary_data = Vec::with_capacity(ANDROID_READ_CHUNK_SIZE as usize);
+            let reader = storage.open_uri_reader(file_info.uri.clone());
+            if let Some(hasher) = hasher.as_mut().filter(|_| resume_from > 0) {
+                hash_uri_prefix(&reader, resume_from, hasher, &mut binary_data)?;
+            }
+
+            let end = send_chunks(
+                &mut write,
+                |offset, buffer| reader.read_chunk(offset, ANDROID_READ_CHUNK_SIZE, buffer)
+                    .map_err(|e| TransferError::Io(format!("Failed to read chunk: {}", e))),
+                &mut binary_data,
+                file_info.size,
+                &mut bytes_sent,
+                compress,
+                &cancelled,
+                hasher.as_mut(),
+                &mut pacer,
+                |bytes_sent| {
+                    // 按时间节流进度事件，最后一帧总是发送
+                    if bytes_sent < file_info.size && last_progress_emit.elapsed() < PROGRESS_EMIT_INTERVAL {
+                        return;
+                    }
+                    last_progress_emit = Instant::now();
+                    jobs::update_progress(&batch_id, &file_info.name, bytes_sent, file_info.size);
+                    window_events::emit(&window, "file-transfer-progress", FileProgress::new(
+                        file_info.name.clone(),
+                        bytes_sent,
+                        file_info.size,
+                        &mut rate,
+                    ));
+                    folder_progress.emit(&window, bytes_sent);
+                },
+            ).await?;
+            let mut receipt = match end {
+                ChunksEnd::Done => None,
+                ChunksEnd::Cancelled => {
+                    let _ = write.send(Message::Close(None)).await;
+                    emit_transfer_cancelled(&window, &batch_id, "send", Some(file_info.name.clone()), bytes_sent);
+                    return Err(TransferError::Cancelled("Cancelled by user".into()));
+                }
+                // 连接断开，检查是否是接收端取消或跳过（Close 码可能在接收缓冲区中）
+                ChunksEnd::WriteFailed(error) => Some(receiver_closed(&mut read, error).await?),
+            };
+
+            // 接收端跳过该文件时已关闭连接，之后的文件重新连接
+            if let (None, Some(hasher)) = (receipt, hasher) {
+                if let Err(e) = send_checksum(&mut write, hasher).await {
+                    receipt = Some(receiver_closed(&mut read, e).await?);
+                }
+            }
+
+            let receipt = match receipt {
+                Some(receipt) => receipt,
+                None => finish_file(&mut write, &mut read, multi_file).await?,
+            };
+            report_receipt(&window, receipt, &batch_id, &file_info.name);
+            if receipt == Receipt::Skipped {
+                folder_progress.file_done(&window, file_info.size);
+                continue;
+            }
+            if multi_file {
+                connection = Some((write, read));
+            }
+
+            window_events::emit(&window, "file-sent", &sending);
+            crate::network::transfer_history::record_file(&app, &batch_id, "send", &target_ip, &file_info.relative_path, file_info.size, None);
+            folder_progress.file_done(&window, file_info.size);
+        }
+
+        if let Some((mut write, mut read)) = connection {
+            write.send(Message::Close(None)).await
+                .map_err(|e| TransferError::ConnectionFailed(format!("Failed to close connection: {}", e), None))?;
+            await_receiver_close(&mut read).await?;
+        }
+
+        Ok(())
     }
 
     #[cfg(not(target_os = "android"))]
     {
-        let _ = app;
-        Err("pick_folder_for_send is only supported on Android".to_string())
+        let _ = (files, target_ip, options, batch_id, window, app);
+        Err(TransferError::Unsupported("send_folder_android is only supported on Android".to_string()))
     }
 }
 
 #[tauri::command]
-/// Android: 从 content:// URI 发送多个文件
-pub async fn send_files_android(
-    uris: Vec<String>,
+/// 桌面端：发送文件夹
+/// 完成后返回本次发送的 ID；进行中可从 list_all_jobs 取得该 ID 并用 cancel_file_sending 取消
+pub async fn send_folder_desktop(
+    folder_path: String,
     target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
     window: Window,
     app: AppHandle,
-) -> Result<(), String> {
-    #[cfg(target_os = "android")]
-    {
-        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
-        use tokio_tungstenite::connect_async;
-        use base64::{engine::general_purpose, Engine as _};
+) -> Result<String, TransferError> {
+    send_desktop(SendSource::FolderDesktop { folder_path }, target_ip, options, port, window, app).await
+}
 
-        // Reset cancel flag at start
-        CANCEL_SENDING.store(false, Ordering::SeqCst);
+#[tauri::command]
+/// 桌面端：发送若干文件（路径需为普通文件），按文件名保存在接收端
+/// 完成后返回本次发送的 ID；进行中可从 list_all_jobs 取得该 ID 并用 cancel_file_sending 取消
+pub async fn send_files_desktop(
+    paths: Vec<String>,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    if paths.is_empty() {
+        return Err(TransferError::InvalidInput("No files to send".into()));
+    }
+    send_desktop(SendSource::FilesDesktop { paths }, target_ip, options, port, window, app).await
+}
 
-        let storage = app.state::<AndroidStorage>();
-        let total = uris.len() as u32;
+/// 桌面端文件夹与文件发送共用的流程：登记任务与取消标志，发送后写入发送记录
+async fn send_desktop(
+    source: SendSource,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let options = options.unwrap_or_default();
+    let batch_id = options.resume_batch_id.clone().or_else(|| options.batch_id.clone()).unwrap_or_else(new_transfer_id);
+
+    let _job = jobs::register(&batch_id, JobKind::SendQueued, &target_ip);
+    let _cancel = CancelGuard::register(&SEND_CANCELS, &batch_id);
+    window_events::emit(&window, "transfer-send-started", SendStarted {
+        batch_id: batch_id.clone(),
+        target_ip: target_ip.clone(),
+    });
+    let _service = transfer_service::acquire(&app);
+    let result = match metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await {
+        Ok(()) => {
+            let inner = send_desktop_inner(source.clone(), target_ip.clone(), options.clone(), batch_id.clone(), window, app.clone());
+            ports::with_peer_port(port, socket_buffers::with_source_port(options.source_port, inner)).await
+        }
+        Err(e) => Err(e),
+    };
+    crate::network::transfer_history::record_send(&app, batch_id.clone(), target_ip, port, source, options, &result);
+    result.map(|()| batch_id)
+}
 
-        for (index, uri) in uris.iter().enumerate() {
-            // Check if cancelled before starting next file
-            if CANCEL_SENDING.load(Ordering::SeqCst) {
-                CANCEL_SENDING.store(false, Ordering::SeqCst);
-                return Err("Cancelled by user".to_string());
-            }
-            // 1. 获取文件信息
-            let (file_name, file_size) = storage.get_file_info(uri.clone())
-                .map_err(|e| format!("Failed to get file info for {}: {}", uri, e))?;
+async fn send_desktop_inner(
+    source: SendSource,
+    target_ip: String,
+    options: SendOptions,
+    batch_id: String,
+    window: Window,
+    app: AppHandle,
+) -> Result<(), TransferError> {
+    use tokio::fs::File as TokioFile;
+    use tokio::io::AsyncReadExt;
 
-            window.emit("file-sending", &file_name)
-                .map_err(|e| format!("Failed to emit event: {}", e))?;
+    let cancelled = cancel_flag(&SEND_CANCELS, &batch_id);
+    let note = options.note.as_deref().and_then(sanitize_note);
+    let mut pacer = rate_limit::SendPacer::new(&batch_id, options.max_bytes_per_sec);
+    let target_ip = resolve_target(&target_ip)?;
+    // 稀疏发送需要接收端支持，否则照常发送全部数据
+    let sparse = options.sparse && supports(&window, &target_ip, "sparse").await;
+    let resumable = supports(&window, &target_ip, "resume_offset").await;
+    let checksum = supports(&window, &target_ip, "checksum").await;
+    let compress = options.compress && supports(&window, &target_ip, "gzip").await;
+    // 接收端支持时所有文件复用同一个连接，省去逐个文件的握手
+    let multi_file = supports(&window, &target_ip, "multi_file").await;
+    let mut connection = None;
 
-            // 2. 建立 WebSocket 连接
-            let ws_url = format!("ws://{}:7878", target_ip);
-            let request = ws_url.into_client_request()
-                .map_err(|e| format!("Failed to create request: {}", e))?;
+    // Get file list
+    let listed = match &source {
+        SendSource::FolderDesktop { folder_path } => {
+            let filter = FolderFilter::new(&options.include, &options.exclude)?;
+            scan_folder(folder_path, &filter)?
+        }
+        SendSource::FilesDesktop { paths } => list_files(paths)?,
+        _ => return Err(TransferError::Unsupported("Not a desktop send source".to_string())),
+    };
+    let (dirs, mut files): (Vec<FolderFile>, Vec<FolderFile>) = listed.into_iter().partition(|f| f.is_dir);
+    if files.is_empty() && dirs.is_empty() {
+        return Err(TransferError::InvalidInput("Empty folder".to_string()));
+    }
+    let empty_dirs = empty_dirs_to_send(&window, &target_ip, dirs.into_iter().map(|d| d.relative_path).collect()).await;
+    sort_files(&mut files, options.sort, |f| (f.relative_path.as_str(), f.size));
+
+    // 续传：沿用原批次 ID，跳过接收端已完成的文件
+    let skipped = match options.resume_batch_id {
+        Some(_) => {
+            let completed = query_batch_status(&window, &target_ip, &batch_id).await;
+            let before = files.len();
+            files.retain(|f| !completed.contains(&f.relative_path));
+            (before - files.len()) as u32
+        }
+        None => 0,
+    };
 
-            let (ws_stream, _) = connect_async(request).await
-                .map_err(|e| format!("Failed to connect to {}: {}", target_ip, e))?;
+    // 增量同步：只保留接收端缺失或内容不同的文件
+    let unchanged = if options.changed_only {
+        let before = files.len();
+        let changed = query_changed_files(&window, &target_ip, &files).await;
+        files = retain_accepted(files, &changed);
+        (before - files.len()) as u32
+    } else {
+        0
+    };
+    if files.is_empty() {
+        println!("Folder sync: nothing changed");
+    }
+    window_events::emit(&window, "transfer-batch-started", BatchStarted {
+        batch_id: batch_id.clone(),
+        total: files.len() as u32,
+        skipped,
+        unchanged,
+    });
 
-            let (mut write, mut read) = ws_stream.split();
+    // 清单优先：先让接收端挑选文件，只发送被接受的部分
+    let files = if options.manifest_first {
+        let entries = files.iter().enumerate().map(|(index, f)| ManifestEntry {
+            index: index as u32,
+            name: f.name.clone(),
+            size: f.size,
+            relative_path: Some(f.relative_path.clone()),
+        }).collect();
+        let (mut write, mut read) = connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split();
+        let accepted = negotiate_manifest(&mut write, &mut read, &batch_id, entries).await?;
+        // 清单与随后的文件使用同一个连接
+        connection = Some((write, read));
+        retain_accepted(files, &accepted)
+    } else {
+        files
+    };
+    let total = files.len() as u32;
+    let mut folder_progress = FolderProgress::new(&batch_id, total, files.iter().map(|f| f.size).sum());
 
-            // 3. 发送文件元数据
-            let meta = serde_json::json!({
-                "name": file_name,
-                "size": file_size,
-                "index": index,
-                "total": total,
-            });
-            let meta_str = serde_json::to_string(&meta)
-                .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+    // 空目录在文件之前发送，打包为 zip 的接收端在最后一个文件到达时完成压缩包
+    for dir in &empty_dirs {
+        if cancelled.load(Ordering::SeqCst) {
+            emit_transfer_cancelled(&window, &batch_id, "send", None, 0);
+            return Err(TransferError::Cancelled("Cancelled by user".into()));
+        }
+        let (mut write, mut read) = match connection.take() {
+            Some(connection) => connection,
+            None => connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split(),
+        };
+        let receipt = send_empty_dir(&mut write, &mut read, dir, &batch_id, &note, total, multi_file).await?;
+        report_receipt(&window, receipt, &batch_id, dir);
+        if multi_file {
+            connection = Some((write, read));
+        }
+    }
+
+    for (index, file_info) in files.iter().enumerate() {
+        if cancelled.load(Ordering::SeqCst) {
+            emit_transfer_cancelled(&window, &batch_id, "send", None, 0);
+            return Err(TransferError::Cancelled("Cancelled by user".into()));
+        }
+        // 网络可能在批次进行中切换为计费网络，每个文件开始前重新检查
+        if index > 0 {
+            metered::wait_until_allowed(&window, &app, &batch_id, options.allow_metered).await?;
+        }
 
-            write.send(Message::Text(meta_str)).await
-                .map_err(|e| format!("Failed to send metadata: {}", e))?;
+        let sending = FileSending { name: file_info.name.clone(), index: index as u32, total, size: file_info.size };
+        window_events::emit(&window, "file-sending", &sending);
 
-            // 4. 分块读取并发送文件内容
-            const CHUNK_SIZE: i32 = 256 * 1024; // 256KB chunks
-            let mut offset: u64 = 0;
-            let mut bytes_sent: u64 = 0;
-
-            loop {
-                // Check if cancelled during file transfer
-                if CANCEL_SENDING.load(Ordering::SeqCst) {
-                    // Close the connection gracefully
-                    let _ = write.send(Message::Close(None)).await;
-                    CANCEL_SENDING.store(false, Ordering::SeqCst);
-                    return Err("Cancelled by user".to_string());
-                }
+        let (mut write, mut read) = match connection.take() {
+            Some(connection) => connection,
+            None => connect_peer_with_retry(&window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?.split(),
+        };
 
-                let (base64_data, bytes_read) = storage.read_uri_chunk(
-                    uri.clone(),
-                    offset,
-                    CHUNK_SIZE
-                ).map_err(|e| format!("Failed to read chunk: {}", e))?;
+        // 续传：接收端保留了上次中断的部分文件时从其末尾继续
+        let resume_from = if resumable {
+            query_resume_offset(&mut write, &mut read, &file_info.name, Some(&file_info.relative_path), file_info.size).await
+        } else {
+            0
+        };
 
-                if bytes_read == 0 {
-                    break;
+        // Send metadata
+        let mtime = tokio::fs::metadata(&file_info.path).await.ok().as_ref().and_then(unix_mtime);
+        let meta = serde_json::json!({
+            "name": file_info.name,
+            "size": file_info.size,
+            "index": index,
+            "total": total,
+            "relative_path": file_info.relative_path,
+            "batch_id": batch_id,
+            "mime": file_info.mime,
+            "note": note,
+            "resume_from": resume_from,
+            "checksum_follows": checksum,
+            "compress": compress,
+            "mtime": mtime,
+        });
+        let resume_from = send_file_meta(&mut write, &mut read, meta, &window, &target_ip, &batch_id, options.connect_retries, &cancelled).await?;
+        let mut hasher = checksum.then(|| Context::new(&SHA256));
+
+        // Read and send file
+        let mut file = TokioFile::open(&file_info.path).await
+            .map_err(|e| TransferError::Io(format!("Failed to open file: {}", e)))?;
+        if resume_from > 0 {
+            println!("Resuming {} from byte {}", file_info.relative_path, resume_from);
+            match hasher.as_mut() {
+                // 校验和覆盖整个文件：读过已传输的部分即到达续传位置
+                Some(hasher) => hash_prefix(&mut file, resume_from, |data| hasher.update(data)).await
+                    .map_err(|e| TransferError::Io(format!("Failed to read file: {}", e)))?,
+                None => {
+                    file.seek(std::io::SeekFrom::Start(resume_from)).await
+                        .map_err(|e| TransferError::Io(format!("Failed to seek file: {}", e)))?;
                 }
+            }
+        }
 
-                // Decode base64 to binary
-                let binary_data = general_purpose::STANDARD.decode(&base64_data)
-                    .map_err(|e| format!("Failed to decode base64: {}", e))?;
+        let mut buffer = vec![0u8; 256 * 1024];
+        let mut bytes_sent: u64 = resume_from;
+        let mut rate = ProgressRate::new(resume_from);
 
-                // Send binary chunk
-                if let Err(e) = write.send(Message::Binary(binary_data)).await {
-                    // 连接断开，检查是否是接收端取消（Close 4001 可能在接收缓冲区中）
-                    if let Ok(Some(Ok(Message::Close(Some(frame))))) =
-                        tokio::time::timeout(Duration::from_millis(500), read.next()).await
-                    {
-                        let code: u16 = frame.code.into();
-                        if code == 4001 {
-                            return Err("Cancelled by receiver".to_string());
+        let mut receipt = None;
+        'chunks: loop {
+            if cancelled.load(Ordering::SeqCst) {
+                let _ = write.send(Message::Close(None)).await;
+                emit_transfer_cancelled(&window, &batch_id, "send", Some(file_info.name.clone()), bytes_sent);
+                return Err(TransferError::Cancelled("Cancelled by user".into()));
+            }
+
+            let n = file.read(&mut buffer).await
+                .map_err(|e| TransferError::Io(format!("Failed to read file: {}", e)))?;
+
+            if n == 0 {
+                break;
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..n]);
+            }
+
+            if sparse {
+                for segment in split_sparse(&buffer[..n]) {
+                    let msg = match segment {
+                        SparseSegment::Data(data) => binary_frame(data, compress)?,
+                        SparseSegment::Zeros(len) => {
+                            let zero_fill = serde_json::to_string(&ControlMessage::ZeroFill { len })
+                                .map_err(|e| TransferError::Other(format!("Failed to serialize zero fill: {}", e)))?;
+                            Message::Text(zero_fill)
                         }
+                    };
+                    if let Err(e) = write.send(msg).await {
+                        // 连接断开，检查是否是接收端取消或跳过（Close 码可能在接收缓冲区中）
+                        let error = TransferError::ConnectionFailed(format!("Failed to send chunk: {}", e), None);
+                        receipt = Some(receiver_closed(&mut read, error).await?);
+                        break 'chunks;
                     }
-                    return Err(format!("Failed to send chunk: {}", e));
                 }
+            } else if let Err(e) = write.send(binary_frame(&buffer[..n], compress)?).await {
+                let error = TransferError::ConnectionFailed(format!("Failed to send chunk: {}", e), None);
+                receipt = Some(receiver_closed(&mut read, error).await?);
+                break;
+            }
 
-                offset += bytes_read as u64;
-                bytes_sent += bytes_read as u64;
+            bytes_sent += n as u64;
+            throughput::add_sent(n as u64);
+            pacer.pace(n as u64).await;
+
+            jobs::update_progress(&batch_id, &file_info.name, bytes_sent, file_info.size);
+            window_events::emit(&window, "file-transfer-progress", FileProgress::new(
+                file_info.name.clone(),
+                bytes_sent,
+                file_info.size,
+                &mut rate,
+            ));
+            folder_progress.emit(&window, bytes_sent);
+        }
 
-                // Emit progress
-                let percentage = (bytes_sent as f64 / file_size as f64) * 100.0;
-                let _ = window.emit("file-transfer-progress", FileProgress {
-                    file_name: file_name.clone(),
-                    bytes_received: bytes_sent,
-                    total_bytes: file_size,
-                    percentage,
-                });
+        // 接收端跳过该文件时已关闭连接，之后的文件重新连接
+        if let (None, Some(hasher)) = (receipt, hasher) {
+            if let Err(e) = send_checksum(&mut write, hasher).await {
+                receipt = Some(receiver_closed(&mut read, e).await?);
+            }
+        }
 
-                if bytes_sent >= file_size {
-                    break;
+        // Check for receiver cancel and completion ack
+        let receipt = match receipt {
+            Some(receipt) => receipt,
+            None => finish_file(&mut write, &mut read, multi_file).await?,
+        };
+        report_receipt(&window, receipt, &batch_id, &file_info.name);
+        if receipt == Receipt::Skipped {
+            folder_progress.file_done(&window, file_info.size);
+            continue;
+        }
+        if multi_file {
+            connection = Some((write, read));
+        }
+
+        window_events::emit(&window, "file-sent", &sending);
+        crate::network::transfer_history::record_file(window.app_handle(), &batch_id, "send", &target_ip, &file_info.relative_path, file_info.size, None);
+        folder_progress.file_done(&window, file_info.size);
+    }
+
+    if let Some((mut write, mut read)) = connection {
+        write.send(Message::Close(None)).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to close connection: {}", e), None))?;
+        await_receiver_close(&mut read).await?;
+    }
+
+    Ok(())
+}
+
+/// 将内存中的数据作为单个文件发送（剪贴板转文件等场景）
+pub(crate) async fn send_bytes_as_file(
+    window: &Window,
+    target_ip: &str,
+    file_name: &str,
+    mime: &str,
+    data: Vec<u8>,
+) -> Result<String, TransferError> {
+    let transfer_id = new_transfer_id();
+    let cancel = CancelGuard::register(&SEND_CANCELS, &transfer_id);
+    let cancelled = cancel.flag.clone();
+
+    let sending = FileSending { name: file_name.to_string(), index: 0, total: 1, size: data.len() as u64 };
+    window_events::emit(window, "file-sending", &sending);
+
+    let ws_stream = connect_peer(window, target_ip).await?;
+    let (mut write, mut read) = ws_stream.split();
+
+    let file_size = data.len() as u64;
+    let meta = serde_json::json!({
+        "name": file_name,
+        "size": file_size,
+        "index": 0,
+        "total": 1,
+        "mime": mime,
+        "sha256": folder_sync::hex_digest(digest::digest(&SHA256, &data)),
+    });
+    let meta_str = serde_json::to_string(&meta)
+        .map_err(|e| TransferError::Other(format!("Failed to serialize metadata: {}", e)))?;
+
+    write.send(Message::Text(meta_str)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send metadata: {}", e), None))?;
+
+    let _job = jobs::register(&transfer_id, JobKind::SendActive, target_ip);
+    let mut bytes_sent: u64 = 0;
+    let mut rate = ProgressRate::new(0);
+    for chunk in data.chunks(256 * 1024) {
+        if cancelled.load(Ordering::SeqCst) {
+            let _ = write.send(Message::Close(None)).await;
+            emit_transfer_cancelled(window, &transfer_id, "send", Some(file_name.to_string()), bytes_sent);
+            return Err(TransferError::Cancelled("Cancelled by user".into()));
+        }
+
+        write.send(Message::Binary(chunk.to_vec())).await
+            .map_err(|e| TransferError::ConnectionFailed(format!("Failed to send chunk: {}", e), None))?;
+
+        bytes_sent += chunk.len() as u64;
+        throughput::add_sent(chunk.len() as u64);
+        rate_limit::pace(chunk.len() as u64).await;
+        jobs::update_progress(&transfer_id, file_name, bytes_sent, file_size);
+        window_events::emit(window, "file-transfer-progress", FileProgress::new(
+            file_name.to_string(),
+            bytes_sent,
+            file_size,
+            &mut rate,
+        ));
+    }
+
+    write.send(Message::Close(None)).await
+        .map_err(|e| TransferError::ConnectionFailed(format!("Failed to close connection: {}", e), None))?;
+
+    let receipt = await_receiver_close(&mut read).await?;
+    report_receipt(window, receipt, &transfer_id, file_name);
+    if receipt == Receipt::Skipped {
+        return Ok(transfer_id);
+    }
+
+    window_events::emit(window, "file-sent", &sending);
+    crate::network::transfer_history::record_file(window.app_handle(), &transfer_id, "send", target_ip, file_name, file_size, None);
+
+    Ok(transfer_id)
+}
+
+#[tauri::command]
+/// 启动接收服务。port 为监听端口（None 时沿用当前端口，默认 7878；0 表示由系统分配），
+/// 绑定成功后发送 `server-bound` 事件报告实际端口。collision 为同名文件的处理方式
+/// （None 时沿用当前设置，默认 Rename）
+pub fn start_websocket_server(
+    save_dir: String,
+    port: Option<u16>,
+    collision: Option<CollisionPolicy>,
+    window: Window,
+    app: AppHandle,
+) {
+    // 始终更新保存目录（即使服务器已在运行）
+    *CURRENT_SAVE_DIR.lock().unwrap() = save_dir;
+    if let Some(policy) = collision {
+        collision::set_policy(policy);
+    }
+
+    crate::network::save_profiles::load_active_profile(&app);
+
+    let new_port = port.filter(|&port| port != ports::local(Service::Transfer));
+
+    // 仅在服务器未运行时启动；运行中指定了新端口时换到新端口
+    if WEBSOCKET_RUNNING.swap(true, Ordering::SeqCst) {
+        match new_port {
+            // 绑定成功后才记录新端口，失败时旧监听继续使用原端口
+            Some(port) => match bind_transfer_listener(port) {
+                Ok(listener) => {
+                    ports::set_local(Service::Transfer, port);
+                    rebind_websocket_server(window, app, listener);
+                }
+                Err(e) => {
+                    eprintln!("Failed to move transfer server to port {}: {}", port, e);
+                    window_events::emit(&window, "websocket-server-error", format!("Failed to bind port {}: {}", port, e));
                 }
+            },
+            None => println!("WebSocket server already running, save directory updated"),
+        }
+        return;
+    }
+    // 指定了新端口时先绑定，成功后由服务记录实际端口；失败时保留原端口
+    let listener = match new_port.map(bind_transfer_listener).transpose() {
+        Ok(listener) => listener,
+        Err(e) => {
+            WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
+            eprintln!("Failed to start transfer server on port {}: {}", port.unwrap_or_default(), e);
+            window_events::emit(&window, "websocket-server-error", format!("Failed to start transfer server: {}", e));
+            return;
+        }
+    };
+
+    spawn_websocket_server(window, app, true, listener);
+}
+
+/// 在独立线程的运行时中运行接收服务。换端口时旧运行时中可能仍有进行中的接收，
+/// 不能清理临时文件（sweep_partials 为 false），并使用已绑定好的监听（listener）
+fn spawn_websocket_server(window: Window, app: AppHandle, sweep_partials: bool, listener: Option<std::net::TcpListener>) {
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            // 启动时清理上次崩溃或强制退出残留的临时文件
+            if sweep_partials {
+                let sweep_window = window.clone();
+                let sweep_app = app.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = crate::network::partials::sweep(&sweep_window, &sweep_app).await {
+                        eprintln!("{}", e);
+                    }
+                });
             }
 
-            // 5. 关闭连接
-            write.send(Message::Close(None)).await
-                .map_err(|e| format!("Failed to close connection: {}", e))?;
-
-            // 6. 等待接收端关闭响应，检测是否被取消
-            if let Some(Ok(Message::Close(Some(frame)))) = read.next().await {
-                let code: u16 = frame.code.into();
-                if code == 4001 {
-                    return Err("Cancelled by receiver".to_string());
+            // 保存任务句柄，force_reset_servers 可以中止监听（运行时随线程退出，残留连接一并销毁）
+            let error_window = window.clone();
+            let task = tokio::spawn(async move {
+                run_websocket_server(window, app, listener).await.map_err(|e| e.to_string())
+            });
+            *WEBSOCKET_SERVER_TASK.lock().unwrap() = Some(task.abort_handle());
+            match task.await {
+                Ok(Err(e)) => {
+                    eprintln!("WebSocket server error: {}", e);
+                    WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
+                    // 例如端口已被占用：通知前端，而不是静默地收不到文件
+                    window_events::emit(&error_window, "websocket-server-error", format!("Failed to start transfer server: {}", e));
                 }
+                Err(e) if e.is_cancelled() => println!("WebSocket server aborted"),
+                _ => {}
             }
 
-            window.emit("file-sent", &file_name)
-                .map_err(|e| format!("Failed to emit event: {}", e))?;
-        }
+            // 优雅停止：运行时退出会销毁残留连接，先等进行中的接收完成或超时
+            while draining() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+    });
+}
 
-        Ok(())
+/// 换端口后重新监听：旧监听立即停止，进行中的接收留在旧运行时中继续完成（最多 30 秒），
+/// 新连接由新端口上的服务接受。服务未运行时不做任何事
+pub(crate) fn rebind_websocket_server(window: Window, app: AppHandle, listener: std::net::TcpListener) {
+    if !WEBSOCKET_RUNNING.load(Ordering::SeqCst) {
+        return;
     }
-
-    #[cfg(not(target_os = "android"))]
-    {
-        let _ = (uris, target_ip, window, app);
-        Err("send_files_android is only supported on Android".to_string())
+    *DRAIN_DEADLINE.lock().unwrap() = Some(std::time::Instant::now() + DEFAULT_DRAIN_TIMEOUT);
+    if let Some(task) = WEBSOCKET_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
     }
+    spawn_websocket_server(window, app, false, Some(listener));
 }
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-pub struct FolderFileToSend {
-    pub uri: String,
-    pub name: String,
-    pub relative_path: String,
-    pub size: u64,
+#[tauri::command]
+/// 查询对端是否支持某项能力（如 "resume"、"sparse"、"diff"、"pull"），供前端决定显示哪些发送选项。
+/// 结果缓存 60 秒，过期后重新向对端查询；对端不可达或为不支持查询的旧版本时返回 false
+pub async fn peer_supports(ip: String, capability: String, window: Window) -> Result<bool, TransferError> {
+    let ip = resolve_target(&ip)?;
+    Ok(supports(&window, &ip, &capability).await)
 }
 
 #[tauri::command]
-/// Android: 发送文件夹中的文件（带相对路径）
-pub async fn send_folder_android(
-    files: Vec<FolderFileToSend>,
-    target_ip: String,
-    window: Window,
-    app: AppHandle,
-) -> Result<(), String> {
-    #[cfg(target_os = "android")]
-    {
-        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
-        use tokio_tungstenite::connect_async;
-        use base64::{engine::general_purpose, Engine as _};
-
-        CANCEL_SENDING.store(false, Ordering::SeqCst);
-
-        let storage = app.state::<AndroidStorage>();
-        let total = files.len() as u32;
+/// 比较本机与对端的时钟：返回对端时钟的偏差与测量时的往返时间，供依赖时间戳的功能校正，
+/// 偏差过大时前端可以提示用户。对端为不支持的旧版本时返回错误
+pub async fn measure_clock_skew(ip: String, window: Window) -> Result<ClockSkew, TransferError> {
+    let ip = resolve_target(&ip)?;
+    let mut ws_stream = connect_peer(&window, &ip).await?;
+
+    let skew = exchange_clock(&mut ws_stream).await;
+    let _ = ws_stream.send(Message::Close(None)).await;
+    skew.ok_or_else(|| TransferError::Unsupported("Peer does not support clock comparison".into()))
+}
 
-        for (index, file_info) in files.iter().enumerate() {
-            if CANCEL_SENDING.load(Ordering::SeqCst) {
-                CANCEL_SENDING.store(false, Ordering::SeqCst);
-                return Err("Cancelled by user".to_string());
-            }
+#[tauri::command]
+/// 优雅停止接收服务：立即停止接受新连接，等待进行中的接收完成（最多 timeout_secs 秒，默认 30 秒）后再关闭。
+/// 返回是否所有接收都已完成（false 表示超时，剩余连接被断开）
+pub async fn stop_websocket_server_graceful(timeout_secs: Option<u64>) -> Result<bool, TransferError> {
+    let timeout = timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+    *DRAIN_DEADLINE.lock().unwrap() = Some(std::time::Instant::now() + timeout);
+
+    WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(task) = WEBSOCKET_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    println!("WebSocket server stopped accepting, draining {} active receives",
+             ACTIVE_RECEIVES.load(Ordering::SeqCst));
 
-            window.emit("file-sending", &file_info.name)
-                .map_err(|e| format!("Failed to emit event: {}", e))?;
+    while draining() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    DRAIN_DEADLINE.lock().unwrap().take();
 
-            let ws_url = format!("ws://{}:7878", target_ip);
-            let request = ws_url.into_client_request()
-                .map_err(|e| format!("Failed to create request: {}", e))?;
+    let drained = ACTIVE_RECEIVES.load(Ordering::SeqCst) == 0;
+    if !drained {
+        println!("Drain timed out, dropping remaining receives");
+    }
+    Ok(drained)
+}
 
-            let (ws_stream, _) = connect_async(request).await
-                .map_err(|e| format!("Failed to connect to {}: {}", target_ip, e))?;
+#[tauri::command]
+/// 停止接收服务：监听循环退出并释放端口，之后可用新的保存目录重新启动。
+/// 进行中的接收继续完成，需要中断时调用 cancel_file_receiving
+pub async fn stop_websocket_server() -> Result<(), TransferError> {
+    WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
+    let stop = WEBSOCKET_STOP.lock().unwrap().take();
+    let Some(stop) = stop else {
+        return Ok(());
+    };
 
-            let (mut write, mut read) = ws_stream.split();
+    let (ack_tx, ack_rx) = oneshot::channel();
+    if stop.send(ack_tx).is_ok() && tokio::time::timeout(WEBSOCKET_STOP_TIMEOUT, ack_rx).await.is_err() {
+        return Err(TransferError::Timeout("Timed out waiting for the transfer server to stop".into()));
+    }
+    println!("WebSocket server stopped");
+    Ok(())
+}
 
-            // Send metadata with relative_path
-            let meta = serde_json::json!({
-                "name": file_info.name,
-                "size": file_info.size,
-                "index": index,
-                "total": total,
-                "relative_path": file_info.relative_path,
-            });
-            let meta_str = serde_json::to_string(&meta)
-                .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+#[tauri::command]
+/// 强制重置所有网络服务：清除运行标志、中止监听任务并断开残留连接，便于前端提供“重启网络”按钮
+pub async fn force_reset_servers(app: AppHandle) -> Result<(), TransferError> {
+    // 中断进行中的优雅停止，残留连接立即销毁
+    DRAIN_DEADLINE.lock().unwrap().take();
+    WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(task) = WEBSOCKET_SERVER_TASK.lock().unwrap().take() {
+        task.abort();
+    }
 
-            write.send(Message::Text(meta_str)).await
-                .map_err(|e| format!("Failed to send metadata: {}", e))?;
+    DISCOVERY_GENERATION.fetch_add(1, Ordering::SeqCst);
+    DISCOVERY_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(task) = DISCOVERY_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+    DISCOVERED_DEVICES.lock().unwrap().clear();
 
-            const CHUNK_SIZE: i32 = 256 * 1024;
-            let mut offset: u64 = 0;
-            let mut bytes_sent: u64 = 0;
+    crate::network::chat::force_reset(&app).await;
+    crate::network::clipboard::force_reset(&app).await;
+    crate::network::control::force_reset(&app).await;
 
-            loop {
-                if CANCEL_SENDING.load(Ordering::SeqCst) {
-                    let _ = write.send(Message::Close(None)).await;
-                    CANCEL_SENDING.store(false, Ordering::SeqCst);
-                    return Err("Cancelled by user".to_string());
-                }
+    println!("All network servers reset");
+    Ok(())
+}
 
-                let (base64_data, bytes_read) = storage.read_uri_chunk(
-                    file_info.uri.clone(),
-                    offset,
-                    CHUNK_SIZE
-                ).map_err(|e| format!("Failed to read chunk: {}", e))?;
+/// `get_status` 的结果
+#[derive(Serialize, Clone, Debug)]
+pub struct ServiceStatus {
+    pub transfer_server_running: bool,
+    /// 传输服务当前监听（或下次启动时使用）的端口
+    pub transfer_port: u16,
+    pub chat_server_running: bool,
+    pub clipboard_server_running: bool,
+    pub discovery_running: bool,
+    /// 已建立聊天连接的对端 IP
+    pub chat_peers: Vec<String>,
+    /// 已建立剪贴板同步连接的对端 IP
+    pub clipboard_peers: Vec<String>,
+}
 
-                if bytes_read == 0 {
-                    break;
-                }
+#[tauri::command]
+/// 查询各服务是否在运行、传输端口及当前的聊天、剪贴板连接，前端重新加载或错过事件后据此恢复状态
+pub async fn get_status(app: AppHandle) -> ServiceStatus {
+    let mut chat_peers: Vec<String> = app.state::<crate::network::chat::ChatConnections>()
+        .lock().await.keys().cloned().collect();
+    chat_peers.sort();
+    let mut clipboard_peers: Vec<String> = app.state::<crate::network::clipboard::ClipboardConnections>()
+        .lock().await.keys().cloned().collect();
+    clipboard_peers.sort();
+
+    ServiceStatus {
+        transfer_server_running: WEBSOCKET_RUNNING.load(Ordering::SeqCst),
+        transfer_port: ports::local(Service::Transfer),
+        chat_server_running: crate::network::chat::server_running(),
+        clipboard_server_running: crate::network::clipboard::server_running(),
+        discovery_running: DISCOVERY_RUNNING.load(Ordering::SeqCst),
+        chat_peers,
+        clipboard_peers,
+    }
+}
 
-                let binary_data = general_purpose::STANDARD.decode(&base64_data)
-                    .map_err(|e| format!("Failed to decode base64: {}", e))?;
+// 自检发送的测试文件大小
+const LOOPBACK_TEST_SIZE: usize = 64 * 1024;
+// 自检时等待接收服务开始监听的时间
+const LOOPBACK_LISTEN_TIMEOUT: Duration = Duration::from_secs(3);
+// 进行中的自检批次：来自本机的这些批次无需用户确认
+static LOOPBACK_BATCHES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// 是否为本机发起的自检传输
+fn is_loopback_test(peer_ip: &str, batch_id: Option<&str>) -> bool {
+    let loopback = peer_ip.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    loopback && batch_id.is_some_and(|batch_id| LOOPBACK_BATCHES.lock().unwrap().iter().any(|id| id == batch_id))
+}
 
-                if let Err(e) = write.send(Message::Binary(binary_data)).await {
-                    if let Ok(Some(Ok(Message::Close(Some(frame))))) =
-                        tokio::time::timeout(Duration::from_millis(500), read.next()).await
-                    {
-                        let code: u16 = frame.code.into();
-                        if code == 4001 {
-                            return Err("Cancelled by receiver".to_string());
-                        }
-                    }
-                    return Err(format!("Failed to send chunk: {}", e));
-                }
+/// `run_loopback_test` 的诊断结果
+#[derive(Serialize, Clone, Debug)]
+pub struct LoopbackReport {
+    /// 测试前接收服务已在运行（否则临时启动，测试后停止）
+    pub server_was_running: bool,
+    /// 测试使用的本机传输端口
+    pub port: u16,
+    /// 接收服务已在该端口监听
+    pub bind_ok: bool,
+    /// 已通过 WebSocket 连接到接收服务
+    pub connect_ok: bool,
+    /// 接收端按 SHA-256 校验一致并确认收到
+    pub bytes_matched: bool,
+    pub bytes: u64,
+    pub elapsed_ms: u64,
+    /// 失败步骤的错误信息
+    pub error: Option<String>,
+}
 
-                offset += bytes_read as u64;
-                bytes_sent += bytes_read as u64;
+/// 等待接收服务监听、连接并发送测试文件，按进度填写报告，成功时返回测试文件名
+async fn loopback_transfer(report: &mut LoopbackReport) -> Result<String, String> {
+    // 服务启动失败（如端口被占用）时运行标志会被清除
+    let deadline = Instant::now() + LOOPBACK_LISTEN_TIMEOUT;
+    loop {
+        report.port = ports::local(Service::Transfer);
+        if !WEBSOCKET_RUNNING.load(Ordering::SeqCst) {
+            return Err(format!("Transfer server failed to start on port {}", report.port));
+        }
+        if tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, report.port)).await.is_ok() {
+            break;
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("Transfer server is not listening on port {}", report.port));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    report.bind_ok = true;
 
-                let percentage = (bytes_sent as f64 / file_info.size as f64) * 100.0;
-                let _ = window.emit("file-transfer-progress", FileProgress {
-                    file_name: file_info.name.clone(),
-                    bytes_received: bytes_sent,
-                    total_bytes: file_info.size,
-                    percentage,
-                });
+    let target_ip = Ipv4Addr::LOCALHOST.to_string();
+    let ws_stream = match ports::with_peer_port(Some(report.port), try_connect_peer(&target_ip)).await.map_err(|e| e.to_string())? {
+        Ok(ws_stream) => ws_stream,
+        Err((_, detail)) => return Err(format!("Failed to connect to {}:{}: {}", target_ip, report.port, detail)),
+    };
+    report.connect_ok = true;
+    let (mut write, mut read) = ws_stream.split();
 
-                if bytes_sent >= file_info.size {
-                    break;
-                }
-            }
+    let data: Vec<u8> = (0..LOOPBACK_TEST_SIZE).map(|i| (i * 31 % 251) as u8).collect();
+    let batch_id = new_transfer_id();
+    let file_name = format!("lan-transfer-loopback-{}.bin", batch_id);
+    let meta = serde_json::json!({
+        "name": file_name,
+        "size": data.len(),
+        "index": 0,
+        "total": 1,
+        "batch_id": batch_id,
+        "sha256": folder_sync::hex_digest(digest::digest(&SHA256, &data)),
+    });
+    let meta_str = serde_json::to_string(&meta)
+        .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
 
-            write.send(Message::Close(None)).await
-                .map_err(|e| format!("Failed to close connection: {}", e))?;
+    LOOPBACK_BATCHES.lock().unwrap().push(batch_id.clone());
+    let result = async {
+        write.send(Message::Text(meta_str)).await
+            .map_err(|e| format!("Failed to send metadata: {}", e))?;
+        write.send(Message::Binary(data)).await
+            .map_err(|e| format!("Failed to send chunk: {}", e))?;
+        write.send(Message::Close(None)).await
+            .map_err(|e| format!("Failed to close connection: {}", e))?;
+        await_receiver_close(&mut read).await.map_err(|e| e.to_string())
+    }.await;
+    LOOPBACK_BATCHES.lock().unwrap().retain(|id| *id != batch_id);
 
-            if let Some(Ok(Message::Close(Some(frame)))) = read.next().await {
-                let code: u16 = frame.code.into();
-                if code == 4001 {
-                    return Err("Cancelled by receiver".to_string());
-                }
-            }
+    report.bytes_matched = result? == Receipt::Confirmed;
+    if !report.bytes_matched {
+        return Err("Receiver did not confirm the test file".to_string());
+    }
+    Ok(file_name)
+}
 
-            window.emit("file-sent", &file_info.name)
-                .map_err(|e| format!("Failed to emit event: {}", e))?;
+/// 删除自检收到的测试文件
+fn remove_loopback_file(app: &AppHandle, save_dir: &str, file_name: &str) {
+    #[cfg(target_os = "android")]
+    if save_dir.starts_with("content://") {
+        let storage = app.state::<AndroidStorage>();
+        if let Ok(Some(uri)) = storage.find_document(save_dir.to_string(), file_name.to_string()) {
+            let _ = storage.delete_document(uri);
         }
+        return;
+    }
+    let _ = app;
+    let _ = std::fs::remove_file(Path::new(save_dir).join(file_name));
+}
 
-        Ok(())
+#[tauri::command]
+/// 自检：向本机接收服务（127.0.0.1）发送一个内存中的小文件，报告监听、连接与数据校验是否正常，
+/// 无需第二台设备即可排查端口、权限或防火墙问题。接收服务未运行时临时启动，测试后停止；收到的测试文件随后删除
+pub async fn run_loopback_test(window: Window, app: AppHandle) -> LoopbackReport {
+    let started_at = Instant::now();
+    let server_was_running = WEBSOCKET_RUNNING.load(Ordering::SeqCst);
+    let previous_save_dir = current_save_dir();
+    if !server_was_running {
+        // 尚未选择保存目录时测试文件写入临时目录
+        let save_dir = match previous_save_dir.is_empty() {
+            true => std::env::temp_dir().to_string_lossy().to_string(),
+            false => previous_save_dir.clone(),
+        };
+        start_websocket_server(save_dir, None, None, window, app.clone());
+    }
+    let save_dir = crate::network::save_profiles::active_profile_dir().unwrap_or_else(current_save_dir);
+
+    let mut report = LoopbackReport {
+        server_was_running,
+        port: ports::local(Service::Transfer),
+        bind_ok: false,
+        connect_ok: false,
+        bytes_matched: false,
+        bytes: LOOPBACK_TEST_SIZE as u64,
+        elapsed_ms: 0,
+        error: None,
+    };
+    let result = loopback_transfer(&mut report).await;
+    report.elapsed_ms = started_at.elapsed().as_millis() as u64;
+    match result {
+        Ok(file_name) => remove_loopback_file(&app, &save_dir, &file_name),
+        Err(e) => report.error = Some(e),
     }
 
-    #[cfg(not(target_os = "android"))]
-    {
-        let _ = (files, target_ip, window, app);
-        Err("send_folder_android is only supported on Android".to_string())
+    if !server_was_running {
+        let _ = stop_websocket_server().await;
+        *CURRENT_SAVE_DIR.lock().unwrap() = previous_save_dir;
     }
+    println!("Loopback test: {:?}", report);
+    report
 }
 
-#[tauri::command]
-/// 桌面端：发送文件夹
-pub async fn send_folder_desktop(
-    folder_path: String,
-    target_ip: String,
-    window: Window,
-) -> Result<(), String> {
-    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
-    use tokio_tungstenite::connect_async;
-    use tokio::fs::File as TokioFile;
-    use tokio::io::AsyncReadExt;
+/// 绑定传输服务的监听：优先监听双栈地址，通过 IPv6 发现的设备也能连接；系统不支持 IPv6 时只监听 IPv4
+pub(crate) fn bind_transfer_listener(port: u16) -> std::io::Result<std::net::TcpListener> {
+    match socket_buffers::bind_listener(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))) {
+        Ok(listener) => Ok(listener),
+        Err(e) => {
+            println!("Dual-stack listen failed ({}), listening on IPv4 only", e);
+            socket_buffers::bind_listener(SocketAddr::from(([0, 0, 0, 0], port)))
+        }
+    }
+}
 
-    // Reset cancel flag
-    CANCEL_SENDING.store(false, Ordering::SeqCst);
+async fn run_websocket_server(
+    window: Window,
+    app: AppHandle,
+    listener: Option<std::net::TcpListener>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    transfer_tls::init(&app);
 
-    // Get file list
-    let files = list_folder_files(folder_path).await?;
-    if files.is_empty() {
-        return Err("Empty folder".to_string());
-    }
+    let listener = match listener {
+        Some(listener) => listener,
+        None => bind_transfer_listener(ports::local(Service::Transfer))?,
+    };
+    let listener = TcpListener::from_std(listener)?;
+    // 端口为 0 时由系统分配，记录实际端口供设备发现广播
+    let port = listener.local_addr()?.port();
+    ports::set_local(Service::Transfer, port);
+    println!("WebSocket server listening on ws://{}", listener.local_addr()?);
+    window_events::emit(&window, "server-bound", ports::ServerPort { service: Service::Transfer, port });
 
-    let total = files.len() as u32;
+    let (stop_tx, mut stop_rx) = oneshot::channel::<oneshot::Sender<()>>();
+    *WEBSOCKET_STOP.lock().unwrap() = Some(stop_tx);
+    let mut stopped_ack = None;
 
-    for (index, file_info) in files.iter().enumerate() {
-        if CANCEL_SENDING.load(Ordering::SeqCst) {
-            CANCEL_SENDING.store(false, Ordering::SeqCst);
-            return Err("Cancelled by user".to_string());
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let Ok((stream, peer_addr)) = result else {
+                    break;
+                };
+                // 每次新连接时读取最新的保存目录（激活的保存配置优先）
+                let save_dir = crate::network::save_profiles::active_profile_dir()
+                    .unwrap_or_else(|| CURRENT_SAVE_DIR.lock().unwrap().clone());
+                // 双栈监听时 IPv4 对端显示为 ::ffff:a.b.c.d，还原为 IPv4 地址
+                let peer_ip = peer_addr.ip().to_canonical().to_string();
+                let window = window.clone();
+                let app = app.clone();
+
+                tokio::spawn(async move {
+                    let _active = ActiveReceiveGuard::new();
+                    let stream = match transfer_tls::accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            eprintln!("TLS handshake with {} failed: {}", peer_ip, e);
+                            return;
+                        }
+                    };
+                    if let Err(e) = handle_websocket_connection(stream, peer_ip, save_dir, window, app).await {
+                        eprintln!("WebSocket connection error: {}", e);
+                    }
+                });
+            }
+            // stop_websocket_server 要求退出（Sender 被新启动的服务替换时同样退出）
+            ack = &mut stop_rx => {
+                stopped_ack = ack.ok();
+                break;
+            }
         }
+    }
 
-        window.emit("file-sending", &file_info.name)
-            .map_err(|e| format!("Failed to emit event: {}", e))?;
-
-        let ws_url = format!("ws://{}:7878", target_ip);
-        let request = ws_url.into_client_request()
-            .map_err(|e| format!("Failed to create request: {}", e))?;
+    // 先释放端口，之后可以立即重新启动服务
+    drop(listener);
+    if let Some(ack) = stopped_ack {
+        let _ = ack.send(());
+    }
+    println!("WebSocket server stopped accepting connections");
 
-        let (ws_stream, _) = connect_async(request).await
-            .map_err(|e| format!("Failed to connect to {}: {}", target_ip, e))?;
+    // 进行中的接收继续完成（运行时退出会断开它们），可用 cancel_file_receiving 取消
+    wait_receives_finished().await;
+    Ok(())
+}
 
-        let (mut write, mut read) = ws_stream.split();
+type ServerWsSink = futures_util::stream::SplitSink<
+    tokio_tungstenite::WebSocketStream<transfer_tls::ServerStream>,
+    Message,
+>;
+
+/// 接收端：取得并发接收许可（已取得时直接返回）。超出并发上限时排队，等待期间继续读取以回应 ping，
+/// 其他消息留到取得许可后处理。连接在等待期间关闭时返回 false
+async fn acquire_receive_permit<R>(
+    permit: &mut Option<tokio::sync::OwnedSemaphorePermit>,
+    read: &mut R,
+    write: &mut ServerWsSink,
+    queued: &mut VecDeque<Result<Message, tokio_tungstenite::tungstenite::Error>>,
+) -> Result<bool, Box<dyn std::error::Error>>
+where
+    R: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    if permit.is_some() {
+        return Ok(true);
+    }
+    let acquire = RECEIVE_PERMITS.clone().acquire_owned();
+    tokio::pin!(acquire);
+    loop {
+        tokio::select! {
+            acquired = &mut acquire => {
+                *permit = Some(acquired?);
+                return Ok(true);
+            }
+            msg = read.next(), if queued.is_empty() => match msg {
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => write.flush().await?,
+                Some(msg) => queued.push_back(msg),
+                None => return Ok(false),
+            },
+        }
+    }
+}
 
-        // Send metadata
-        let meta = serde_json::json!({
-            "name": file_info.name,
-            "size": file_info.size,
-            "index": index,
-            "total": total,
-            "relative_path": file_info.relative_path,
-        });
-        let meta_str = serde_json::to_string(&meta)
-            .map_err(|e| format!("Failed to serialize metadata: {}", e))?;
+/// 接收端：数据已全部写入后发送 complete 确认，需赶在发送端的 Close 之前送达。
+/// 返回是否已发送
+async fn send_complete_ack(
+    write: &mut ServerWsSink,
+    file: Option<&mut File>,
+    file_name: &Option<String>,
+    bytes: u64,
+) -> bool {
+    if let Some(f) = file {
+        if let Err(e) = f.flush().await {
+            eprintln!("Failed to flush file before completion ack: {}", e);
+            return false;
+        }
+    }
+    let ack = ControlMessage::Complete {
+        name: file_name.clone().unwrap_or_default(),
+        bytes,
+    };
+    match serde_json::to_string(&ack) {
+        Ok(text) => write.send(Message::Text(text)).await.is_ok(),
+        Err(_) => false,
+    }
+}
 
-        write.send(Message::Text(meta_str)).await
-            .map_err(|e| format!("Failed to send metadata: {}", e))?;
+/// 接收端：边接收边计算 SHA-256，与发送端给出的值比对
+#[derive(Default)]
+struct ReceiveChecksum {
+    context: Option<Context>,
+    /// 发送端给出的摘要（小写十六进制）；checksum_follows 时在数据之后到达
+    expected: Option<String>,
+    follows: bool,
+}
 
-        // Read and send file
-        let mut file = TokioFile::open(&file_info.path).await
-            .map_err(|e| format!("Failed to open file: {}", e))?;
+impl ReceiveChecksum {
+    /// 发送端提供校验和时才计算
+    fn for_meta(meta: &FileMeta) -> Self {
+        if meta.sha256.is_none() && !meta.checksum_follows {
+            return Self::default();
+        }
+        ReceiveChecksum {
+            context: Some(Context::new(&SHA256)),
+            expected: meta.sha256.as_ref().map(|s| s.to_ascii_lowercase()),
+            follows: meta.checksum_follows,
+        }
+    }
 
-        let mut buffer = vec![0u8; 256 * 1024];
-        let mut bytes_sent: u64 = 0;
+    /// 发送端提供了校验和，需要计算摘要
+    fn active(&self) -> bool {
+        self.context.is_some()
+    }
 
-        loop {
-            if CANCEL_SENDING.load(Ordering::SeqCst) {
-                let _ = write.send(Message::Close(None)).await;
-                CANCEL_SENDING.store(false, Ordering::SeqCst);
-                return Err("Cancelled by user".to_string());
-            }
+    fn update(&mut self, data: &[u8]) {
+        if let Some(context) = self.context.as_mut() {
+            context.update(data);
+        }
+    }
 
-            let n = file.read(&mut buffer).await
-                .map_err(|e| format!("Failed to read file: {}", e))?;
+    /// ZeroFill 跳过的部分按 0 计入
+    fn update_zeros(&mut self, len: u64) {
+        if self.context.is_none() {
+            return;
+        }
+        let zeros = vec![0u8; SPARSE_MIN_RUN];
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(zeros.len() as u64) as usize;
+            self.update(&zeros[..n]);
+            remaining -= n as u64;
+        }
+    }
 
-            if n == 0 {
-                break;
-            }
+    /// 收到数据之后的 Checksum 消息
+    fn set_expected(&mut self, sha256: &str) {
+        if self.context.is_some() {
+            self.expected = Some(sha256.to_ascii_lowercase());
+        }
+    }
 
-            write.send(Message::Binary(buffer[..n].to_vec())).await
-                .map_err(|e| format!("Failed to send chunk: {}", e))?;
+    /// 还在等待发送端的 Checksum 消息
+    fn pending(&self) -> bool {
+        self.context.is_some() && self.follows && self.expected.is_none()
+    }
 
-            bytes_sent += n as u64;
+    fn digest(&self) -> Option<String> {
+        self.context.as_ref().map(|context| folder_sync::hex_digest(context.clone().finish()))
+    }
 
-            let percentage = (bytes_sent as f64 / file_info.size as f64) * 100.0;
-            let _ = window.emit("file-transfer-progress", FileProgress {
-                file_name: file_info.name.clone(),
-                bytes_received: bytes_sent,
-                total_bytes: file_info.size,
-                percentage,
-            });
+    fn mismatch(&self) -> bool {
+        match (&self.expected, self.digest()) {
+            (Some(expected), Some(actual)) => *expected != actual,
+            _ => false,
         }
+    }
 
-        write.send(Message::Close(None)).await
-            .map_err(|e| format!("Failed to close connection: {}", e))?;
+}
 
-        // Check for receiver cancel
-        if let Some(Ok(Message::Close(Some(frame)))) = read.next().await {
-            let code: u16 = frame.code.into();
-            if code == 4001 {
-                return Err("Cancelled by receiver".to_string());
+/// 桌面端/非 SAF：文件在保存目录中的路径，返回 (路径, 是否位于子目录中)
+fn receive_path(save_dir: &str, name: &str, relative_path: Option<&str>) -> (PathBuf, bool) {
+    let mut full_path = PathBuf::from(save_dir);
+    match relative_path {
+        Some(rel_path) => match sanitize_relative_path(rel_path) {
+            Some(sanitized) => {
+                full_path.push(&sanitized);
+                return (full_path, true);
             }
-        }
+            None => {
+                eprintln!("Invalid relative path: {}, saving to root", rel_path);
+                full_path.push(name);
+            }
+        },
+        None => full_path.push(name),
+    }
+    (full_path, false)
+}
 
-        window.emit("file-sent", &file_info.name)
-            .map_err(|e| format!("Failed to emit event: {}", e))?;
+/// 接收端：同一文件（路径与大小相同）上次连接中断时保留的部分数据长度。
+/// SAF 保存目录与打包为 zip 时不支持续传
+async fn partial_offset(save_dir: &str, name: &str, relative_path: Option<&str>, size: u64) -> u64 {
+    if save_dir.starts_with("content://") || receive_zip::enabled() {
+        return 0;
+    }
+    let (path, _) = receive_path(save_dir, name, relative_path);
+    // 已有同名文件且策略为跳过时不续传，元数据到达后直接跳过
+    if collision::resolve_path(&path).is_none() {
+        return 0;
+    }
+    let partial = crate::network::partials::partial_path(&path);
+    if crate::network::partials::kept_size(&partial).await != Some(size) {
+        return 0;
     }
+    match tokio::fs::metadata(&partial).await {
+        // 超过声明大小的部分文件无效，重新完整传输
+        Ok(m) if m.is_file() && m.len() <= size => m.len(),
+        _ => 0,
+    }
+}
 
-    Ok(())
+/// 接收端：打开保留的部分文件继续写入。文件必须是为同样大小的文件保留的，
+/// 且当前长度与发送端的续传位置一致
+async fn open_partial_for_resume(partial: &Path, resume_from: u64, size: u64) -> Result<File, String> {
+    let kept_size = crate::network::partials::take_resume_state(partial).await;
+    if kept_size != Some(size) || resume_from > size {
+        return Err("No matching partial file".to_string());
+    }
+    let mut f = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(partial)
+        .await
+        .map_err(|e| format!("Failed to open partial file: {}", e))?;
+    let len = f.metadata().await
+        .map_err(|e| format!("Failed to read partial file: {}", e))?
+        .len();
+    if len != resume_from {
+        return Err(format!("Partial file has {} bytes, sender resumes from {}", len, resume_from));
+    }
+    f.seek(std::io::SeekFrom::Start(resume_from)).await
+        .map_err(|e| format!("Failed to seek partial file: {}", e))?;
+    Ok(f)
 }
 
-#[tauri::command]
-pub fn start_websocket_server(save_dir: String, window: Window, app: AppHandle) {
-    // 始终更新保存目录（即使服务器已在运行）
-    *CURRENT_SAVE_DIR.lock().unwrap() = save_dir;
+/// 接收端：无法按发送端的位置续传时以 4409 关闭，发送端重试时从头发送
+async fn reject_resume(write: &mut ServerWsSink, name: &str, reason: &str) {
+    eprintln!("Rejecting resume of {}: {}", name, reason);
+    let _ = write.send(Message::Close(Some(CloseFrame {
+        code: 4409u16.into(),
+        reason: "Resume rejected".into(),
+    }))).await;
+}
 
-    // 仅在服务器未运行时启动
-    if WEBSOCKET_RUNNING.swap(true, Ordering::SeqCst) {
-        println!("WebSocket server already running, save directory updated");
-        return;
+/// 接收端：同名文件策略为跳过且已有同名文件，在接收数据之前关闭连接
+async fn skip_existing_file(write: &mut ServerWsSink, window: &Window, name: &str) {
+    println!("Skipped {}: a file with the same name already exists", name);
+    window_events::emit(window, "file-skipped", name);
+    let _ = write.send(Message::Close(Some(CloseFrame {
+        code: FILE_EXISTS_CODE.into(),
+        reason: "File exists".into(),
+    }))).await;
+}
+
+async fn handle_websocket_connection(
+    stream: transfer_tls::ServerStream,
+    peer_ip: String,
+    save_dir: String,
+    window: Window,
+    app: AppHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ws_config = WebSocketConfig {
+        max_message_size: None,
+        max_frame_size: None,
+        ..Default::default()
+    };
+    let ws_stream = accept_async_with_config(stream, Some(ws_config)).await?;
+    let (mut write, mut read) = ws_stream.split();
+    // 设置了连接 PIN 时，第一条消息须为匹配的 PIN，否则以 4006 关闭
+    if !connection_pin::authorize(&mut read, &mut write, &peer_ip).await {
+        return Ok(());
     }
 
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async move {
-            if let Err(e) = run_websocket_server(window, app).await {
-                eprintln!("WebSocket server error: {}", e);
-                WEBSOCKET_RUNNING.store(false, Ordering::SeqCst);
-            }
-        });
-    });
-}
+    // 取得许可前读到的消息，按顺序在之后处理
+    let mut queued: VecDeque<Result<Message, tokio_tungstenite::tungstenite::Error>> = VecDeque::new();
+    // 并发接收许可：首个文件或清单到达时才取得，仅查询的连接不占用
+    let mut receive_permit: Option<tokio::sync::OwnedSemaphorePermit> = None;
+
+    // 每个连接的唯一 ID（对端 IP 可能相同，例如同机的多个实例），也用于取消该连接的接收
+    let connection_id = new_transfer_id();
+    let cancel = CancelGuard::register(&RECEIVE_CANCELS, &connection_id);
+    // 同批次文件沿用发送端的 batch_id，否则按连接生成
+    let mut transfer_id = connection_id.clone();
+    // 收到首个文件元数据后登记为接收任务（仅查询状态的连接不出现在任务列表中）
+    let mut receive_job: Option<jobs::JobHandle> = None;
+    // 接收期间保持 Android 前台服务
+    let mut receive_service: Option<transfer_service::TransferServiceGuard> = None;
+    const PROGRESS_INTERVAL: u64 = 100 * 1024; // 100KB
+    #[cfg(target_os = "android")]
+    let is_content_uri = save_dir.starts_with("content://");
+
+    // 用户已接受该连接的传输（每个连接只询问一次）
+    let mut transfer_approved = false;
+    // 同一连接可依次发送多个文件，文件之间以 EndOfFile 分隔
+    let mut end_of_file = false;
+    // 连接因读取出错而中断（发送端未正常关闭）
+    let mut connection_dropped = false;
+    // 打包为 zip：本连接已追加、批次尚未完成的压缩包
+    let mut open_zip: Option<String> = None;
+
+    loop {
+        // 上一个文件已处理完，告知发送端可以发送下一个
+        if end_of_file {
+            write.send(Message::Text(serde_json::to_string(&ControlMessage::EndOfFile)?)).await?;
+            end_of_file = false;
+        }
 
-async fn run_websocket_server(window: Window, app: AppHandle) -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind("0.0.0.0:7878").await?;
-    println!("WebSocket server listening on ws://0.0.0.0:7878");
+        let mut file: Option<File> = None;
+        #[cfg(target_os = "android")]
+        let mut writer_handle: Option<i64> = None;
+        #[cfg(target_os = "android")]
+        let mut document_uri: Option<String> = None;
+        // SAF 临时文件的 (发送端给出的文件名, 元数据到达时按同名文件策略决定的最终文件名)
+        #[cfg(target_os = "android")]
+        let mut document_name: Option<(String, String)> = None;
+        // SAF 文件所在目录，完成时在其中检查同名文件
+        #[cfg(target_os = "android")]
+        let mut document_dir: Option<String> = None;
+        let mut file_name: Option<String> = None;
+        let mut file_path: Option<PathBuf> = None;
+        // 元数据到达时按同名文件策略决定的最终路径，完成时临时文件改为此名
+        let mut target_path: Option<PathBuf> = None;
+        // 正在写入的临时文件，登记期间不会被过期清理删除
+        let mut _active_partial: Option<crate::network::partials::ActivePartial> = None;
+        // 打包为 zip 时当前文件所属的 (batch_id, 压缩包内路径, 是否为批次最后一个文件)
+        let mut zip_entry: Option<(String, String, bool)> = None;
+        let mut current_batch: Option<String> = None;
+        // 本机自检的测试文件：不通知前端，也不写入传输记录
+        let mut loopback_test = false;
+        let mut note: Option<String> = None;
+        // 发送方声明的 MIME 类型，随 file-received 事件通知前端
+        let mut mime: Option<String> = None;
+        // 当前文件在批次中的 (序号, 总数)，随 file-received 事件通知前端
+        let mut position: (u32, u32) = (0, 0);
+        // 收到过 ZeroFill：文件末尾可能只是 seek 过去而未写入，需要 set_len
+        let mut zero_filled = false;
+        // 写入出错的文件不发送 complete 确认，发送端据此提醒用户
+        let mut write_failed = false;
+        let mut complete_sent = false;
+        // 启用校验时的 SHA-256；数据不一致时不确认并删除文件
+        let mut checksum = ReceiveChecksum::default();
+        let mut corrupted = false;
+        // 发送端的数据超出声明的文件大小（ZeroFill 越界或解压后过大）；以 4406 关闭并删除文件
+        let mut out_of_range = false;
+        // 发送端压缩了数据块；进度与大小均按解压后的数据计算
+        let mut compressed = false;
+        let mut mtime: Option<i64> = None;
+        let mut bytes_received: u64 = 0;
+        let mut total_bytes: Option<u64> = None;
+        let mut last_progress_emit: u64 = 0;
+        let mut rate = ProgressRate::new(0);
+
+        while let Some(msg_result) = match queued.pop_front() {
+            Some(msg) => Some(msg),
+            None => read.next().await,
+        } {
+            let msg = match msg_result {
+                Ok(msg) => msg,
+                Err(e) => {
+                    // 按未完成处理：保留续传用的临时文件、丢弃未完成的压缩包
+                    eprintln!("Connection from {} dropped: {}", peer_ip, e);
+                    connection_dropped = true;
+                    break;
+                }
+            };
+            match msg {
+                // 本机未设置 PIN 时忽略连接方发送的 PIN
+                Message::Text(json_str) if connection_pin::is_auth_message(&json_str) => {}
+                Message::Text(json_str) => {
+                    if let Ok(control) = serde_json::from_str::<ControlMessage>(&json_str) {
+                        match control {
+                            ControlMessage::Manifest { batch_id, files } => {
+                                if !acquire_receive_permit(&mut receive_permit, &mut read, &mut write, &mut queued).await? {
+                                    return Ok(());
+                                }
+                                println!("Received manifest {} with {} files from {}", batch_id, files.len(), peer_ip);
+                                // 按对端信任级别：自动接受全部、直接拒绝，或等待用户选择
+                                let accepted = match peer_trust::trust_level(&app, &peer_ip) {
+                                    TrustLevel::AutoAccept => (0..files.len() as u32).collect(),
+                                    TrustLevel::Untrusted => Vec::new(),
+                                    TrustLevel::Ask => {
+                                        let _held = jobs::register(&batch_id, JobKind::ReceiveHeld, &peer_ip);
+                                        await_manifest_decision(&window, &peer_ip, batch_id.clone(), files.clone()).await
+                                    }
+                                };
+                                // 用户已在清单中选择过，批次中的文件到达时不再逐个询问
+                                if !accepted.is_empty() {
+                                    remember_accepted_batch(&peer_ip, &batch_id);
+                                }
+                                // 回复前先为接受的文件建好目录结构，文件到达时无需再逐个创建
+                                let dirs = manifest_parent_dirs(&retain_accepted(files, &accepted));
+                                if !dirs.is_empty() {
+                                    if let Err(e) = precreate_dirs(save_dir.clone(), dirs, app.clone()) {
+                                        eprintln!("Failed to pre-create directories: {}", e);
+                                    }
+                                }
+                                let reply = ControlMessage::ManifestResponse { batch_id, accepted };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::BatchStatusQuery { batch_id } => {
+                                let completed = crate::network::batch_progress::completed_files(&app, &batch_id);
+                                println!("Batch {} status query from {}: {} completed", batch_id, peer_ip, completed.len());
+                                let reply = ControlMessage::BatchStatus { batch_id, completed };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::CapabilitiesQuery => {
+                                let reply = ControlMessage::Capabilities {
+                                    capabilities: RECEIVER_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+                                };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::ZeroFill { len } => {
+                                // 填充须落在声明的文件大小之内，否则可能按对端给出的长度哈希、写入任意多的 0
+                                let end = bytes_received.checked_add(len)
+                                    .filter(|end| total_bytes.is_some_and(|t| *end <= t) && len <= i64::MAX as u64);
+                                let Some(end) = end else {
+                                    eprintln!("Rejecting zero fill of {} bytes at offset {} from {}", len, bytes_received, peer_ip);
+                                    window_events::emit(&window, "transfer-rejected-invalid", InvalidMetadata {
+                                        peer_ip: peer_ip.clone(),
+                                        name: file_name.clone().unwrap_or_default(),
+                                        reason: format!("zero fill of {} bytes exceeds the declared size", len),
+                                    });
+                                    out_of_range = true;
+                                    break;
+                                };
+                                checksum.update_zeros(len);
+                                #[cfg(target_os = "android")]
+                                if is_content_uri {
+                                    // SAF 输出流无法 seek，只能写入真实的 0
+                                    if let Some(handle) = writer_handle {
+                                        let storage = app.state::<AndroidStorage>();
+                                        let zeros = vec![0u8; SPARSE_MIN_RUN];
+                                        let mut remaining = len;
+                                        while remaining > 0 {
+                                            let n = remaining.min(zeros.len() as u64) as usize;
+                                            if let Err(e) = storage.write_chunk(handle, general_purpose::STANDARD.encode(&zeros[..n])) {
+                                                eprintln!("Failed to write zero fill via SAF: {}", e);
+                                                write_failed = true;
+                                                break;
+                                            }
+                                            remaining -= n as u64;
+                                        }
+                                        bytes_received = end;
+                                    }
+                                }
 
-    while let Ok((stream, _)) = listener.accept().await {
-        // 每次新连接时读取最新的保存目录
-        let save_dir = CURRENT_SAVE_DIR.lock().unwrap().clone();
-        let window = window.clone();
-        let app = app.clone();
+                                // 跳过而不写入，在支持的文件系统上形成稀疏文件；结束时用 set_len 补齐末尾
+                                if let Some(f) = file.as_mut() {
+                                    if let Err(e) = f.seek(std::io::SeekFrom::Current(len as i64)).await {
+                                        eprintln!("Failed to seek for zero fill: {}", e);
+                                        write_failed = true;
+                                    }
+                                    bytes_received = end;
+                                    zero_filled = true;
+                                }
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_websocket_connection(stream, save_dir, window, app).await {
-                eprintln!("WebSocket connection error: {}", e);
-            }
-        });
-    }
+                                if !complete_sent && !write_failed && !checksum.pending() && total_bytes.is_some_and(|t| bytes_received >= t) {
+                                    if checksum.mismatch() {
+                                        corrupted = true;
+                                        break;
+                                    }
+                                    complete_sent = send_complete_ack(&mut write, file.as_mut(), &file_name, bytes_received).await;
+                                }
+                            }
+                            ControlMessage::Checksum { sha256 } => {
+                                checksum.set_expected(&sha256);
+                                if !complete_sent && !write_failed && total_bytes.is_some_and(|t| bytes_received >= t) {
+                                    if checksum.mismatch() {
+                                        corrupted = true;
+                                        break;
+                                    }
+                                    complete_sent = send_complete_ack(&mut write, file.as_mut(), &file_name, bytes_received).await;
+                                }
+                            }
+                            ControlMessage::EndOfFile => {
+                                end_of_file = true;
+                                break;
+                            }
+                            ControlMessage::DiffQuery { files } => {
+                                // SAF 保存目录无法在 Rust 端读取，视为全部有变化
+                                let (changed, needs_hash) = if save_dir.starts_with("content://") {
+                                    (files.iter().map(|f| f.index).collect(), Vec::new())
+                                } else {
+                                    let dir = save_dir.clone();
+                                    tokio::task::spawn_blocking(move || folder_sync::diff_against_dir(&dir, &files)).await?
+                                };
+                                println!("Folder diff from {}: {} changed, {} need hash", peer_ip, changed.len(), needs_hash.len());
+                                let reply = ControlMessage::DiffResult { changed, needs_hash };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::PullRequest { token, port } => {
+                                // 令牌有效时向请求方推送登记的文件（请求方需运行接收服务）
+                                let reply = match crate::network::pull_tokens::redeem(&token, &peer_ip) {
+                                    Ok((source, options)) => {
+                                        println!("Serving pull request from {}", peer_ip);
+                                        let (target_ip, window, app) = (peer_ip.clone(), window.clone(), app.clone());
+                                        tokio::spawn(async move {
+                                            if let Err(e) = send_source(source, target_ip.clone(), port, options, window, app).await {
+                                                eprintln!("Failed to serve pull request from {}: {}", target_ip, e);
+                                            }
+                                        });
+                                        ControlMessage::PullResponse { accepted: true, error: None }
+                                    }
+                                    Err(e) => {
+                                        println!("Rejecting pull request from {}: {}", peer_ip, e);
+                                        ControlMessage::PullResponse { accepted: false, error: Some(e) }
+                                    }
+                                };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::ResumeQuery { name, relative_path, size } => {
+                                let offset = partial_offset(&save_dir, &name, relative_path.as_deref(), size).await;
+                                if offset > 0 {
+                                    println!("Offering resume of {} from byte {}", name, offset);
+                                }
+                                let reply = ControlMessage::ResumeOffset { offset };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::TimeQuery { sent_at_ms } => {
+                                let reply = ControlMessage::TimeReply { sent_at_ms, peer_time_ms: unix_millis() };
+                                write.send(Message::Text(serde_json::to_string(&reply)?)).await?;
+                            }
+                            ControlMessage::ManifestResponse { .. }
+                            | ControlMessage::BatchStatus { .. }
+                            | ControlMessage::Capabilities { .. }
+                            | ControlMessage::Complete { .. }
+                            | ControlMessage::DiffResult { .. }
+                            | ControlMessage::PullResponse { .. }
+                            | ControlMessage::TimeReply { .. }
+                            | ControlMessage::ResumeOffset { .. } => {}
+                        }
+                        continue;
+                    }
 
-    Ok(())
-}
+                    if !acquire_receive_permit(&mut receive_permit, &mut read, &mut write, &mut queued).await? {
+                        return Ok(());
+                    }
+                    let meta = match parse_file_meta(&peer_ip, &json_str) {
+                        Ok(meta) => meta,
+                        Err(bad) => {
+                            // 元数据无法解析：通知前端并以 4400 关闭，避免双方空等
+                            eprintln!("Rejecting malformed file metadata from {}: {}", peer_ip, bad.error);
+                            window_events::emit(&window, "file-receive-bad-metadata", bad);
+                            let _ = write.send(Message::Close(Some(CloseFrame {
+                                code: BAD_METADATA_CODE.into(),
+                                reason: "Bad metadata".into(),
+                            }))).await;
+                            return Ok(());
+                        }
+                    };
+
+                    // 元数据内容不合理：通知前端并以 4406 关闭，不创建任何文件
+                    if let Err(reason) = validate_meta(&meta) {
+                        eprintln!("Rejecting invalid file metadata from {}: {}", peer_ip, reason);
+                        window_events::emit(&window, "transfer-rejected-invalid", InvalidMetadata {
+                            peer_ip: peer_ip.clone(),
+                            name: meta.name.chars().take(BAD_METADATA_PREVIEW_CHARS).collect(),
+                            reason,
+                        });
+                        let _ = write.send(Message::Close(Some(CloseFrame {
+                            code: INVALID_METADATA_CODE.into(),
+                            reason: "Invalid metadata".into(),
+                        }))).await;
+                        return Ok(());
+                    }
 
-async fn handle_websocket_connection(
-    stream: tokio::net::TcpStream,
-    save_dir: String,
-    window: Window,
-    #[allow(unused_variables)] app: AppHandle,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let ws_config = WebSocketConfig {
-        max_message_size: None,
-        max_frame_size: None,
-        ..Default::default()
-    };
-    let ws_stream = accept_async_with_config(stream, Some(ws_config)).await?;
-    let (mut write, mut read) = ws_stream.split();
+                    // 不受信任的对端直接拒绝
+                    if peer_trust::trust_level(&app, &peer_ip) == TrustLevel::Untrusted {
+                        println!("Rejecting file {} from untrusted peer {}", meta.name, peer_ip);
+                        let _ = write.send(Message::Close(Some(CloseFrame {
+                            code: 4003u16.into(),
+                            reason: "Rejected by receiver".into(),
+                        }))).await;
+                        window_events::emit(&window, "file-receive-rejected", &meta.name);
+                        return Ok(());
+                    }
 
-    // Reset cancel receiving flag at start of each connection
-    CANCEL_RECEIVING.store(false, Ordering::SeqCst);
+                    // 保存目录所在卷空间不足时以 4005 拒绝，避免写到一半失败留下不完整的文件
+                    let required = meta.size.saturating_sub(meta.resume_from);
+                    if let Some(available) = disk_space::available_space(&app, &save_dir).filter(|available| required > *available) {
+                        println!("Rejecting {} from {}: needs {} bytes, {} available", meta.name, peer_ip, required, available);
+                        let _ = write.send(Message::Close(Some(CloseFrame {
+                            code: 4005u16.into(),
+                            reason: "Insufficient space".into(),
+                        }))).await;
+                        window_events::emit(&window, "transfer-insufficient-space", disk_space::InsufficientSpace {
+                            peer_ip: peer_ip.clone(),
+                            file_name: meta.relative_path.clone().unwrap_or_else(|| meta.name.clone()),
+                            required,
+                            available,
+                        });
+                        return Ok(());
+                    }
 
-    let mut file: Option<File> = None;
-    #[cfg(target_os = "android")]
-    let mut writer_handle: Option<i64> = None;
-    #[cfg(target_os = "android")]
-    let mut document_uri: Option<String> = None;
-    let mut file_name: Option<String> = None;
-    let mut bytes_received: u64 = 0;
-    let mut total_bytes: Option<u64> = None;
-    let mut last_progress_emit: u64 = 0;
-    const PROGRESS_INTERVAL: u64 = 100 * 1024; // 100KB
-    #[cfg(target_os = "android")]
-    let is_content_uri = save_dir.starts_with("content://");
+                    // 连接上的首个文件：需用户确认的对端等待用户接受，拒绝或超时以 4003 关闭
+                    let needs_approval = !transfer_approved
+                        && peer_trust::trust_level(&app, &peer_ip) == TrustLevel::Ask
+                        && !meta.batch_id.as_deref().is_some_and(|batch_id| batch_accepted(&peer_ip, batch_id))
+                        && !is_loopback_test(&peer_ip, meta.batch_id.as_deref());
+                    if needs_approval {
+                        let request = TransferRequest {
+                            connection_id: connection_id.clone(),
+                            peer_ip: peer_ip.clone(),
+                            file_name: meta.relative_path.clone().unwrap_or_else(|| meta.name.clone()),
+                            size: meta.size,
+                            total: meta.total,
+                            batch_id: meta.batch_id.clone(),
+                        };
+                        let decision = {
+                            let _held = jobs::register(&connection_id, JobKind::ReceiveHeld, &peer_ip);
+                            let decision = await_transfer_decision(&window, request);
+                            tokio::pin!(decision);
+                            // 等待期间继续读取：回应 ping 并察觉发送端断开；提前到达的数据帧先缓存，接受后再处理
+                            let mut buffered = 0;
+                            loop {
+                                tokio::select! {
+                                    accepted = &mut decision => break Some(accepted),
+                                    msg = read.next(), if buffered < PENDING_APPROVAL_BUFFER => match msg {
+                                        Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => write.flush().await?,
+                                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+                                        Some(msg) => {
+                                            buffered += msg.as_ref().map_or(0, Message::len);
+                                            queued.push_back(msg);
+                                        }
+                                    },
+                                }
+                            }
+                        };
+                        let Some(accepted) = decision else {
+                            println!("{} disconnected before the transfer of {} was answered", peer_ip, meta.name);
+                            withdraw_transfer_request(&window, &connection_id);
+                            return Ok(());
+                        };
+                        if !accepted {
+                            println!("Transfer of {} from {} rejected", meta.name, peer_ip);
+                            let _ = write.send(Message::Close(Some(CloseFrame {
+                                code: 4003u16.into(),
+                                reason: "Rejected by receiver".into(),
+                            }))).await;
+                            window_events::emit(&window, "file-receive-rejected", &meta.name);
+                            return Ok(());
+                        }
+                        if let Some(batch_id) = &meta.batch_id {
+                            remember_accepted_batch(&peer_ip, batch_id);
+                        }
+                    }
+                    transfer_approved = true;
+
+                    // 空目录：没有数据帧，创建后直接确认
+                    if meta.is_dir {
+                        let dir = meta.relative_path.clone().unwrap_or_else(|| meta.name.clone());
+                        // 打包为 zip：写入批次压缩包；批次中没有文件（压缩包不会完成）时照常创建
+                        if let (Some(batch_id), true) = (&meta.batch_id, receive_zip::enabled() && meta.total > 0) {
+                            let Some(entry) = sanitize_relative_path(&dir) else {
+                                eprintln!("Ignoring empty directory with an invalid path: {}", dir);
+                                continue;
+                            };
+                            let (zip_dir, append_id) = (save_dir.clone(), batch_id.clone());
+                            let result = tokio::task::spawn_blocking(move || receive_zip::append_dir(&zip_dir, &append_id, &entry)).await;
+                            match result.map_err(|e| e.to_string()).and_then(|r| r) {
+                                Ok(()) => {
+                                    open_zip = Some(batch_id.clone());
+                                    send_complete_ack(&mut write, None, &Some(dir), 0).await;
+                                }
+                                Err(e) => {
+                                    eprintln!("Failed to add directory to zip: {}", e);
+                                    receive_zip::abort(batch_id);
+                                }
+                            }
+                            continue;
+                        }
+                        match precreate_dirs(save_dir.clone(), vec![dir.clone()], app.clone()) {
+                            Ok(()) => {
+                                println!("Created empty directory {}", dir);
+                                send_complete_ack(&mut write, None, &Some(dir), 0).await;
+                            }
+                            Err(e) => eprintln!("Failed to create directory {}: {}", dir, e),
+                        }
+                        continue;
+                    }
 
-    while let Some(msg_result) = read.next().await {
-        match msg_result? {
-            Message::Text(json_str) => {
-                if let Ok(meta) = serde_json::from_str::<FileMeta>(&json_str) {
                     // Use relative_path for display if available, otherwise use name
                     file_name = Some(meta.relative_path.clone().unwrap_or_else(|| meta.name.clone()));
                     total_bytes = Some(meta.size);
                     bytes_received = 0;
                     last_progress_emit = 0;
+                    rate = ProgressRate::new(0);
+                    write_failed = false;
+                    complete_sent = false;
+                    checksum = ReceiveChecksum::for_meta(&meta);
+                    compressed = meta.compress;
+                    mtime = meta.mtime;
+                    zip_entry = None;
 
                     // Log file info with index/total if available
                     if meta.total > 0 {
@@ -911,11 +5005,33 @@ async fn handle_websocket_connection(
                     } else {
                         println!("Receiving file {} - {} bytes", meta.name, meta.size);
                     }
+                    if let Some(ref batch_id) = meta.batch_id {
+                        println!("File belongs to batch {}", batch_id);
+                        transfer_id = batch_id.clone();
+                    }
+                    current_batch = meta.batch_id.clone();
+                    loopback_test = is_loopback_test(&peer_ip, meta.batch_id.as_deref());
+                    receive_job.get_or_insert_with(|| jobs::register(&connection_id, JobKind::ReceiveActive, &peer_ip));
+                    receive_service.get_or_insert_with(|| transfer_service::acquire(&app));
+                    jobs::update_progress(&connection_id, &meta.name, 0, meta.size);
+                    note = meta.note.as_deref().and_then(sanitize_note);
+                    if let Some(ref mime) = meta.mime {
+                        println!("Declared MIME type: {}", mime);
+                    }
+                    mime = meta.mime.clone();
+                    position = (meta.index, meta.total);
 
                     #[cfg(target_os = "android")]
                     if is_content_uri {
                         let storage = app.state::<AndroidStorage>();
 
+                        // SAF 输出流只能从头写入，不支持续传
+                        if meta.resume_from > 0 {
+                            let name = file_name.clone().unwrap_or_default();
+                            reject_resume(&mut write, &name, "saving via SAF").await;
+                            return Ok(());
+                        }
+
                         // Handle relative_path for Android SAF
                         let target_tree_uri = if let Some(ref rel_path) = meta.relative_path {
                             if let Some(sanitized) = sanitize_relative_path(rel_path) {
@@ -924,13 +5040,21 @@ async fn handle_websocket_connection(
                                 if let Some(parent) = path.parent() {
                                     let parent_str = parent.to_string_lossy();
                                     if !parent_str.is_empty() {
-                                        // Create subdirectories via SAF
-                                        match storage.find_or_create_subdirectory(save_dir.clone(), parent_str.to_string()) {
-                                            Ok(sub_uri) => sub_uri,
-                                            Err(e) => {
-                                                eprintln!("Failed to create subdirectory {}: {}", parent_str, e);
-                                                save_dir.clone()
-                                            }
+                                        // 优先使用清单阶段预先创建的目录，否则通过 SAF 逐个创建
+                                        let cache_key = (save_dir.clone(), parent_str.to_string());
+                                        let cached = SAF_DIR_CACHE.lock().unwrap().get(&cache_key).cloned();
+                                        match cached {
+                                            Some(sub_uri) => sub_uri,
+                                            None => match storage.find_or_create_subdirectory(save_dir.clone(), parent_str.to_string()) {
+                                                Ok(sub_uri) => {
+                                                    SAF_DIR_CACHE.lock().unwrap().insert(cache_key, sub_uri.clone());
+                                                    sub_uri
+                                                }
+                                                Err(e) => {
+                                                    eprintln!("Failed to create subdirectory {}: {}", parent_str, e);
+                                                    save_dir.clone()
+                                                }
+                                            },
                                         }
                                     } else {
                                         save_dir.clone()
@@ -946,11 +5070,38 @@ async fn handle_websocket_connection(
                             save_dir.clone()
                         };
 
-                        match storage.open_writer(target_tree_uri, meta.name.clone()) {
+                        // 元数据到达时按同名文件策略决定最终文件名，跳过时不再接收数据
+                        let Some(planned_name) = collision::plan_document_name(&storage, &target_tree_uri, &meta.name) else {
+                            skip_existing_file(&mut write, &window, &meta.name).await;
+                            return Ok(());
+                        };
+
+                        // 先写入临时文件，完整接收后再重命名
+                        match storage.open_writer(target_tree_uri.clone(), crate::network::partials::partial_name(&meta.name)) {
                             Ok((handle, uri)) => {
+                                _active_partial = Some(crate::network::partials::ActivePartial::register(uri.clone()));
                                 writer_handle = Some(handle);
                                 document_uri = Some(uri);
-                                let _ = window.emit("file-receiving", &meta.name);
+                                document_name = Some((meta.name.clone(), planned_name));
+                                document_dir = Some(target_tree_uri);
+                                window_events::emit(&window, "file-receiving", &meta.name);
+                                window_events::emit(&window, "file-receiving-details", FileReceiving {
+                                    name: meta.name.clone(),
+                                    peer_ip: peer_ip.clone(),
+                                    connection_id: connection_id.clone(),
+                                    note: note.clone(),
+                                    index: meta.index,
+                                    total: meta.total,
+                                    size: meta.size,
+                                });
+                                // 空文件不会有数据帧，直接确认
+                                if meta.size == 0 && !checksum.pending() {
+                                    if checksum.mismatch() {
+                                        corrupted = true;
+                                        break;
+                                    }
+                                    complete_sent = send_complete_ack(&mut write, None, &file_name, 0).await;
+                                }
                             }
                             Err(e) => {
                                 eprintln!("Failed to open SAF writer: {}", e);
@@ -959,188 +5110,850 @@ async fn handle_websocket_connection(
                         continue;
                     }
 
+                    // 打包为 zip：批次中的文件先接收到临时位置，完成后追加到批次压缩包
+                    if let (Some(batch_id), true) = (&meta.batch_id, receive_zip::enabled() && meta.total > 0) {
+                        let entry = meta.relative_path.as_deref()
+                            .and_then(sanitize_relative_path)
+                            .unwrap_or_else(|| meta.name.clone());
+                        zip_entry = Some((batch_id.clone(), entry, meta.index + 1 >= meta.total));
+                    }
+
                     // Desktop/Android non-SAF: handle relative_path by creating parent directories
-                    let mut full_path = PathBuf::from(&save_dir);
-                    if let Some(ref rel_path) = meta.relative_path {
-                        if let Some(sanitized) = sanitize_relative_path(rel_path) {
-                            full_path.push(&sanitized);
-                            // Create parent directories if needed
-                            if let Some(parent) = full_path.parent() {
-                                if let Err(e) = tokio::fs::create_dir_all(parent).await {
-                                    eprintln!("Failed to create directory {}: {}", parent.display(), e);
-                                }
+                    let (full_path, has_subdir) = match &zip_entry {
+                        Some((batch_id, _, _)) => (receive_zip::staging_path(&save_dir, batch_id, meta.index), false),
+                        None => receive_path(&save_dir, &meta.name, meta.relative_path.as_deref()),
+                    };
+
+                    // 最终路径必须仍在保存目录内（防止保存目录中的符号链接绕过）
+                    if !is_within_save_dir(Path::new(&save_dir), &full_path) {
+                        eprintln!("Refusing to write outside save directory: {}", full_path.display());
+                        let _ = write.send(Message::Close(Some(CloseFrame {
+                            code: 4003u16.into(),
+                            reason: "Rejected by receiver".into(),
+                        }))).await;
+                        window_events::emit(&window, "file-receive-rejected", &meta.name);
+                        return Ok(());
+                    }
+
+                    // 元数据到达时按同名文件策略决定最终路径，跳过时不再接收数据
+                    if zip_entry.is_none() {
+                        match collision::resolve_path(&full_path) {
+                            Some(target) => target_path = Some(target),
+                            None => {
+                                skip_existing_file(&mut write, &window, &meta.name).await;
+                                return Ok(());
+                            }
+                        }
+                    }
+
+                    // Create parent directories if needed
+                    if has_subdir {
+                        if let Some(parent) = full_path.parent() {
+                            if let Err(e) = tokio::fs::create_dir_all(parent).await {
+                                eprintln!("Failed to create directory {}: {}", parent.display(), e);
                             }
-                        } else {
-                            eprintln!("Invalid relative path: {}, saving to root", rel_path);
-                            full_path.push(&meta.name);
                         }
-                    } else {
-                        full_path.push(&meta.name);
                     }
 
-                    match File::create(&full_path).await {
+                    // 先写入临时文件，完整接收后再重命名，崩溃残留可由启动清理识别；
+                    // 续传时在上次保留的临时文件末尾继续写入
+                    let partial = crate::network::partials::partial_path(&full_path);
+                    let opened = if meta.resume_from > 0 {
+                        let resumed = match zip_entry {
+                            Some(_) => Err("packaging as zip".to_string()),
+                            None => open_partial_for_resume(&partial, meta.resume_from, meta.size).await,
+                        };
+                        // 校验和覆盖整个文件：先把保留的部分计入摘要
+                        let resumed = match resumed {
+                            Ok(f) if checksum.active() => {
+                                let hashed = match File::open(&partial).await {
+                                    Ok(mut prefix) => hash_prefix(&mut prefix, meta.resume_from, |data| checksum.update(data)).await,
+                                    Err(e) => Err(e),
+                                };
+                                hashed.map(|()| f).map_err(|e| format!("Failed to hash partial file: {}", e))
+                            }
+                            other => other,
+                        };
+                        match resumed {
+                            Ok(f) => {
+                                println!("Resuming {} from byte {}", full_path.display(), meta.resume_from);
+                                // 确认续传，发送端据此区分接受与 4409 拒绝
+                                let ack = ControlMessage::ResumeOffset { offset: meta.resume_from };
+                                write.send(Message::Text(serde_json::to_string(&ack)?)).await?;
+                                bytes_received = meta.resume_from;
+                                rate = ProgressRate::new(meta.resume_from);
+                                last_progress_emit = meta.resume_from;
+                                Ok(f)
+                            }
+                            Err(e) => {
+                                let _ = tokio::fs::remove_file(&partial).await;
+                                reject_resume(&mut write, &meta.name, &e).await;
+                                return Ok(());
+                            }
+                        }
+                    } else {
+                        crate::network::partials::forget_resume(&partial).await;
+                        File::create(&partial).await
+                    };
+                    match opened {
                         Ok(f) => {
+                            _active_partial = Some(crate::network::partials::ActivePartial::register(partial.to_string_lossy()));
                             file = Some(f);
-                            let _ = window.emit("file-receiving", &meta.name);
+                            file_path = Some(full_path.clone());
+                            window_events::emit(&window, "file-receiving", &meta.name);
+                            window_events::emit(&window, "file-receiving-details", FileReceiving {
+                                name: meta.name.clone(),
+                                peer_ip: peer_ip.clone(),
+                                connection_id: connection_id.clone(),
+                                note: note.clone(),
+                                index: meta.index,
+                                total: meta.total,
+                                size: meta.size,
+                            });
+                            // 空文件（或续传时已全部收到）不会有数据帧，直接确认
+                            if bytes_received >= meta.size && !checksum.pending() {
+                                if checksum.mismatch() {
+                                    corrupted = true;
+                                    break;
+                                }
+                                complete_sent = send_complete_ack(&mut write, file.as_mut(), &file_name, bytes_received).await;
+                            }
                         }
                         Err(e) => {
                             eprintln!("Failed to create file {}: {}", full_path.display(), e);
                         }
                     }
                 }
-            }
-            Message::Binary(data) => {
-                // Check if receiving was cancelled
-                if CANCEL_RECEIVING.load(Ordering::SeqCst) {
-                    println!("File receiving cancelled by user");
-                    // 立即发送 Close(4001) 通知发送端，此时连接仍然存活
-                    let _ = write.send(Message::Close(Some(CloseFrame {
-                        code: 4001u16.into(),
-                        reason: "Cancelled by receiver".into(),
-                    }))).await;
-                    break;
-                }
+                Message::Binary(data) => {
+                    // Check if receiving was cancelled
+                    if cancel.flag.load(Ordering::SeqCst) {
+                        println!("File receiving cancelled by user");
+                        // 立即发送 Close(4001) 通知发送端，此时连接仍然存活
+                        let _ = write.send(Message::Close(Some(CloseFrame {
+                            code: 4001u16.into(),
+                            reason: "Cancelled by receiver".into(),
+                        }))).await;
+                        break;
+                    }
+                    let data = if compressed {
+                        // 解压后的数据不能超过文件剩余的大小，防止很小的数据块解压出巨量数据
+                        let remaining = total_bytes.unwrap_or(0).saturating_sub(bytes_received);
+                        match compression::gunzip(&data, remaining) {
+                            Ok(data) if data.len() as u64 > remaining => {
+                                eprintln!("Rejecting compressed chunk from {}: expands past the declared size", peer_ip);
+                                window_events::emit(&window, "transfer-rejected-invalid", InvalidMetadata {
+                                    peer_ip: peer_ip.clone(),
+                                    name: file_name.clone().unwrap_or_default(),
+                                    reason: "compressed chunk exceeds the declared size".to_string(),
+                                });
+                                out_of_range = true;
+                                break;
+                            }
+                            Ok(data) => data,
+                            Err(e) => {
+                                // 无法解压的数据块无法写入，文件不完整，不发送 complete 确认
+                                eprintln!("Failed to decompress chunk: {}", e);
+                                write_failed = true;
+                                continue;
+                            }
+                        }
+                    } else {
+                        data
+                    };
+                    checksum.update(&data);
 
-                #[cfg(target_os = "android")]
-                if is_content_uri {
-                    if let Some(handle) = writer_handle {
-                        let storage = app.state::<AndroidStorage>();
-                        let encoded = general_purpose::STANDARD.encode(&data);
+                    #[cfg(target_os = "android")]
+                    if is_content_uri {
+                        if let Some(handle) = writer_handle {
+                            let storage = app.state::<AndroidStorage>();
+                            let encoded = general_purpose::STANDARD.encode(&data);
+                            bytes_received += data.len() as u64;
+                            throughput::add_received(data.len() as u64);
+                            if let Err(e) = storage.write_chunk(handle, encoded) {
+                                eprintln!("Failed to write chunk via SAF: {}", e);
+                                write_failed = true;
+                            }
+
+                            // Emit progress for Android SAF
+                            if let Some(total) = total_bytes {
+                                let should_emit = bytes_received - last_progress_emit >= PROGRESS_INTERVAL
+                                               || bytes_received >= total;
+                                if should_emit {
+                                    jobs::update_progress(&connection_id, file_name.as_deref().unwrap_or_default(), bytes_received, total);
+                                    window_events::emit(&window, "file-transfer-progress", FileProgress::new(
+                                        file_name.clone().unwrap_or_default(),
+                                        bytes_received,
+                                        total,
+                                        &mut rate,
+                                    ));
+                                    last_progress_emit = bytes_received;
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(f) = file.as_mut() {
                         bytes_received += data.len() as u64;
-                        if let Err(e) = storage.write_chunk(handle, encoded) {
-                            eprintln!("Failed to write chunk via SAF: {}", e);
+                        throughput::add_received(data.len() as u64);
+                        if let Err(e) = f.write_all(&data).await {
+                            eprintln!("Failed to write to file: {}", e);
+                            write_failed = true;
                         }
 
-                        // Emit progress for Android SAF
+                        // Emit progress for regular file write
                         if let Some(total) = total_bytes {
                             let should_emit = bytes_received - last_progress_emit >= PROGRESS_INTERVAL
                                            || bytes_received >= total;
                             if should_emit {
-                                let percentage = (bytes_received as f64 / total as f64) * 100.0;
-                                let _ = window.emit("file-transfer-progress", FileProgress {
-                                    file_name: file_name.clone().unwrap_or_default(),
+                                jobs::update_progress(&connection_id, file_name.as_deref().unwrap_or_default(), bytes_received, total);
+                                window_events::emit(&window, "file-transfer-progress", FileProgress::new(
+                                    file_name.clone().unwrap_or_default(),
                                     bytes_received,
-                                    total_bytes: total,
-                                    percentage,
-                                });
+                                    total,
+                                    &mut rate,
+                                ));
                                 last_progress_emit = bytes_received;
                             }
                         }
-                        continue;
+                    }
+
+                    if !complete_sent && !write_failed && !checksum.pending() && total_bytes.is_some_and(|t| bytes_received >= t) {
+                        if checksum.mismatch() {
+                            corrupted = true;
+                            break;
+                        }
+                        complete_sent = send_complete_ack(&mut write, file.as_mut(), &file_name, bytes_received).await;
                     }
                 }
+                Message::Close(_) => {
+                    println!("WebSocket connection closed");
+                    break;
+                }
+                _ => {}
+            }
+        }
 
-                if let Some(f) = file.as_mut() {
-                    bytes_received += data.len() as u64;
-                    if let Err(e) = f.write_all(&data).await {
-                        eprintln!("Failed to write to file: {}", e);
+        // 检查文件是否完整接收
+        let was_cancelled = cancel.flag.load(Ordering::SeqCst);
+
+        let transfer_complete = if was_cancelled || corrupted || out_of_range {
+            false // 用户主动取消或校验和不一致，即使数据已全部接收也视为未完成
+        } else if let Some(expected_size) = total_bytes {
+            // 启用校验时还需收到发送端的校验和
+            bytes_received >= expected_size && !checksum.pending()
+        } else {
+            true // 旧协议没有size字段，假设完整
+        };
+
+        if !transfer_complete {
+            println!("Transfer incomplete: received {} of {} bytes",
+                     bytes_received, total_bytes.unwrap_or(0));
+
+            // 通知发送端：接收方已取消（Close code 4001）或校验失败（4422）
+            let (code, reason) = if corrupted {
+                (4422u16, "Checksum mismatch")
+            } else if out_of_range {
+                (INVALID_METADATA_CODE, "Invalid metadata")
+            } else {
+                (4001u16, "Cancelled by receiver")
+            };
+            let _ = write.send(Message::Close(Some(CloseFrame {
+                code: code.into(),
+                reason: reason.into(),
+            }))).await;
+
+            // Android: 关闭并删除不完整的 SAF 文件
+            #[cfg(target_os = "android")]
+            if let Some(_handle) = writer_handle {
+                let storage = app.state::<AndroidStorage>();
+                if let Some(uri) = &document_uri {
+                    // delete_document will close the output stream and delete the file
+                    if let Err(e) = storage.delete_document(uri.clone()) {
+                        eprintln!("Failed to delete incomplete SAF file: {}", e);
+                    } else {
+                        println!("Deleted incomplete SAF file");
                     }
+                } else {
+                    let _ = storage.close_writer(_handle);
+                }
+            }
 
-                    // Emit progress for regular file write
-                    if let Some(total) = total_bytes {
-                        let should_emit = bytes_received - last_progress_emit >= PROGRESS_INTERVAL
-                                       || bytes_received >= total;
-                        if should_emit {
-                            let percentage = (bytes_received as f64 / total as f64) * 100.0;
-                            let _ = window.emit("file-transfer-progress", FileProgress {
-                                file_name: file_name.clone().unwrap_or_default(),
-                                bytes_received,
-                                total_bytes: total,
-                                percentage,
-                            });
-                            last_progress_emit = bytes_received;
-                        }
+            // 桌面端：用户取消或校验失败时删除不完整的临时文件；连接意外中断时保留，发送端重试时可从断点续传
+            if let Some(mut f) = file {
+                let partial = file_path.as_ref().map(|path| crate::network::partials::partial_path(path));
+                let keep = !was_cancelled && !corrupted && !out_of_range && !write_failed && zip_entry.is_none() && bytes_received > 0;
+                if keep {
+                    let _ = f.flush().await;
+                }
+                drop(f); // 关闭文件
+                if let (Some(path), Some(partial)) = (&file_path, partial) {
+                    if keep {
+                        println!("Keeping partial file {} ({} bytes) for resume", path.display(), bytes_received);
+                        crate::network::partials::keep_for_resume(&partial, total_bytes.unwrap_or(0)).await;
+                    } else {
+                        let _ = tokio::fs::remove_file(partial).await;
+                        println!("Removed incomplete file: {}", path.display());
                     }
                 }
             }
-            Message::Close(_) => {
-                println!("WebSocket connection closed");
-                break;
+
+            // 打包为 zip：批次中断，丢弃未完成的压缩包
+            if let Some((batch_id, _, _)) = &zip_entry {
+                receive_zip::abort(batch_id);
             }
-            _ => {}
-        }
-    }
 
-    // 检查文件是否完整接收
-    let was_cancelled = CANCEL_RECEIVING.load(Ordering::SeqCst);
-    CANCEL_RECEIVING.store(false, Ordering::SeqCst);
+            // 通知前端传输取消（校验失败时为 file-receive-corrupted）
+            if let Some(name) = file_name.clone().filter(|_| !loopback_test) {
+                let error = if corrupted {
+                    "Checksum mismatch"
+                } else if out_of_range {
+                    "Data exceeds the declared file size"
+                } else if was_cancelled {
+                    "Cancelled by user"
+                } else {
+                    "Connection closed before the file was complete"
+                };
+                crate::network::transfer_history::record_file(&app, &transfer_id, "receive", &peer_ip, &name, bytes_received, Some(error.to_string()));
+                if corrupted {
+                    eprintln!("Checksum mismatch for {}, discarded the received data", name);
+                    window_events::emit(&window, "file-receive-corrupted", name);
+                } else {
+                    window_events::emit(&window, "file-receive-cancelled", name);
+                }
+            }
 
-    let transfer_complete = if was_cancelled {
-        false // 用户主动取消，即使数据已全部接收也视为未完成
-    } else if let Some(expected_size) = total_bytes {
-        bytes_received >= expected_size
-    } else {
-        true // 旧协议没有size字段，假设完整
-    };
+            // 用户取消：此时循环已退出且残留文件已删除，确认取消已生效
+            if was_cancelled {
+                emit_transfer_cancelled(&window, &connection_id, "receive", file_name, bytes_received);
+            }
 
-    if !transfer_complete {
-        println!("Transfer incomplete: received {} of {} bytes",
-                 bytes_received, total_bytes.unwrap_or(0));
+            return Ok(());
+        }
 
-        // 通知发送端：接收方已取消（Close code 4001）
-        let _ = write.send(Message::Close(Some(CloseFrame {
-            code: 4001u16.into(),
-            reason: "Cancelled by receiver".into(),
-        }))).await;
+        // 同名文件策略为 Skip 且已有同名文件：丢弃收到的文件
+        let mut skipped = false;
 
-        // Android: 关闭并删除不完整的 SAF 文件
+        // 传输完整，正常关闭
         #[cfg(target_os = "android")]
-        if let Some(_handle) = writer_handle {
+        if let Some(handle) = writer_handle {
             let storage = app.state::<AndroidStorage>();
-            if let Some(uri) = &document_uri {
-                // delete_document will close the output stream and delete the file
-                if let Err(e) = storage.delete_document(uri.clone()) {
-                    eprintln!("Failed to delete incomplete SAF file: {}", e);
-                } else {
-                    println!("Deleted incomplete SAF file");
+            if let Err(e) = storage.close_writer(handle) {
+                eprintln!("Failed to close SAF writer: {}", e);
+            }
+            // 临时文件改回最终文件名（按同名文件策略）
+            if let (Some(uri), Some((name, planned))) = (document_uri.clone(), document_name.take()) {
+                let dir = document_dir.clone().unwrap_or_else(|| save_dir.clone());
+                // 接收期间出现了同名文件时重新按策略决定
+                match collision::resolve_document_name(&storage, &dir, &planned) {
+                    Some(final_name) => {
+                        match storage.rename_document(uri, final_name.clone()) {
+                            Ok(renamed) => {
+                                // 文档提供方不支持设置修改时间时忽略
+                                if let Some(mtime) = mtime {
+                                    let _ = storage.set_document_mtime(renamed.clone(), mtime.saturating_mul(1000));
+                                }
+                                document_uri = Some(renamed);
+                            }
+                            Err(e) => eprintln!("Failed to rename partial SAF file: {}", e),
+                        }
+                        if final_name != name {
+                            window_events::emit(&window, "file-renamed", collision::FileRenamed {
+                                original: name,
+                                renamed: final_name,
+                                peer_ip: peer_ip.clone(),
+                            });
+                        }
+                    }
+                    None => {
+                        if let Err(e) = storage.delete_document(uri) {
+                            eprintln!("Failed to delete skipped SAF file: {}", e);
+                        }
+                        document_uri = None;
+                        skipped = true;
+                    }
                 }
-            } else {
-                let _ = storage.close_writer(_handle);
             }
         }
 
-        // 桌面端：删除不完整的文件
-        if let Some(f) = file {
-            drop(f); // 关闭文件
-            if let Some(name) = &file_name {
-                let mut path = PathBuf::from(&save_dir);
-                path.push(name);
-                let _ = tokio::fs::remove_file(path).await;
-                println!("Removed incomplete file: {}", name);
+        // 确保文件被正确关闭和刷新
+        if let Some(mut f) = file {
+            let _ = f.flush().await;
+            if zero_filled {
+                if let Err(e) = f.set_len(bytes_received).await {
+                    eprintln!("Failed to extend sparse file: {}", e);
+                }
+            }
+            drop(f);
+
+            if let (Some(path), Some((batch_id, entry, _))) = (&file_path, zip_entry.clone()) {
+                // 打包为 zip：临时文件直接追加到批次压缩包
+                let (dir, source) = (save_dir.clone(), crate::network::partials::partial_path(path));
+                let append_id = batch_id.clone();
+                let received = ReceivedFile {
+                    path: entry.clone(),
+                    location: None,
+                    size: bytes_received,
+                    sha256: checksum.digest(),
+                    received_at: unix_millis(),
+                    sender: peer_ip.clone(),
+                };
+                let result = tokio::task::spawn_blocking(move || receive_zip::append(&dir, &append_id, &entry, &source, received)).await;
+                match result.map_err(|e| e.to_string()).and_then(|r| r) {
+                    Ok(()) => open_zip = Some(batch_id),
+                    Err(e) => {
+                        eprintln!("Failed to add file to zip: {}", e);
+                        receive_zip::abort(&batch_id);
+                        zip_entry = None;
+                    }
+                }
+            } else if let Some(path) = file_path.clone() {
+                // 临时文件改回最终文件名（按同名文件策略）
+                let partial = crate::network::partials::partial_path(&path);
+                // 接收期间出现了同名文件时重新按策略决定
+                match collision::resolve_path(target_path.as_deref().unwrap_or(&path)) {
+                    Some(target) => {
+                        if let Err(e) = tokio::fs::rename(&partial, &target).await {
+                            eprintln!("Failed to rename partial file {}: {}", target.display(), e);
+                        } else if let Some(mtime) = mtime {
+                            apply_mtime(&target, mtime);
+                        }
+                        if target != path {
+                            let file_name_of = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                            window_events::emit(&window, "file-renamed", collision::FileRenamed {
+                                original: file_name_of(&path),
+                                renamed: file_name_of(&target),
+                                peer_ip: peer_ip.clone(),
+                            });
+                            file_path = Some(target);
+                        }
+                    }
+                    None => {
+                        let _ = tokio::fs::remove_file(&partial).await;
+                        skipped = true;
+                    }
+                }
+            }
+        }
+
+        // 保留已有的同名文件，不作为新接收的文件记录
+        if skipped {
+            if let Some(name) = file_name.take() {
+                println!("Skipped {}: a file with the same name already exists", name);
+                window_events::emit(&window, "file-skipped", name);
+            }
+        }
+
+        // 自检的测试文件由 run_loopback_test 校验后删除
+        if loopback_test {
+            if let Some(name) = file_name.take() {
+                println!("Loopback test file received: {} ({} bytes)", name, bytes_received);
             }
         }
 
-        // 通知前端传输取消
+        // 通知前端接收完成
         if let Some(name) = file_name {
-            let _ = window.emit("file-receive-cancelled", name);
+            window_events::emit(&window, "file-received", serde_json::json!({
+                "name": name,
+                "size": bytes_received,
+                "peer_ip": peer_ip,
+                "connection_id": connection_id,
+                "note": note,
+                "mime": mime,
+                "index": position.0,
+                "total": position.1,
+            }));
+            println!("File received: {} ({} bytes)", name, bytes_received);
+            crate::network::transfer_history::record_file(&app, &transfer_id, "receive", &peer_ip, &name, bytes_received, None);
+
+            // 打包为 zip：单个文件不再单独记录，批次最后一个文件到达后完成压缩包
+            if let Some((batch_id, _, is_last)) = zip_entry {
+                if is_last {
+                    open_zip = None;
+                    let finish_id = batch_id.clone();
+                    let finished = tokio::task::spawn_blocking(move || receive_zip::finish(&finish_id)).await;
+                    match finished.map_err(|e| e.to_string()).and_then(|r| r) {
+                        Ok(zip) => {
+                            let location = zip.path.to_string_lossy().to_string();
+                            let zip_name = zip.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                            // 包内文件（含最后一个）记入批次进度，用于续传时跳过和导出清单
+                            for entry in zip.entries {
+                                crate::network::batch_progress::record_completed(&app, &batch_id, &save_dir, entry);
+                            }
+                            window_events::emit(&window, "folder-received", receive_zip::FolderReceived {
+                                batch_id,
+                                path: location.clone(),
+                                files: zip.files,
+                                bytes: zip.bytes,
+                                peer_ip: peer_ip.clone(),
+                            });
+                            let zip_size = std::fs::metadata(&zip.path).map(|m| m.len()).unwrap_or(zip.bytes);
+                            crate::network::receive_quota::record_received(location, zip_name, zip_size, &window, &app).await;
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to finish zip: {}", e);
+                            receive_zip::abort(&batch_id);
+                        }
+                    }
+                }
+                if !end_of_file {
+                    abort_dropped_zip(connection_dropped, &mut open_zip);
+                    return Ok(());
+                }
+                continue;
+            }
+
+            #[cfg(target_os = "android")]
+            let location = document_uri.or_else(|| file_path.map(|p| p.to_string_lossy().to_string()));
+            #[cfg(not(target_os = "android"))]
+            let location = file_path.map(|p| p.to_string_lossy().to_string());
+
+            // 记录批次进度，发送端续传时可跳过已完成的文件，也用于导出接收清单
+            if let Some(batch_id) = &current_batch {
+                crate::network::batch_progress::record_completed(&app, batch_id, &save_dir, ReceivedFile {
+                    path: name.clone(),
+                    location: location.clone(),
+                    size: bytes_received,
+                    sha256: checksum.digest(),
+                    received_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64,
+                    sender: peer_ip.clone(),
+                });
+            }
+
+            // 记录到接收索引，超出配额时淘汰最旧的文件
+            if let Some(location) = location {
+                crate::network::receive_quota::record_received(location, name, bytes_received, &window, &app).await;
+            }
         }
 
-        return Ok(());
+        if !end_of_file {
+            abort_dropped_zip(connection_dropped, &mut open_zip);
+            return Ok(());
+        }
     }
+}
 
-    // 传输完整，正常关闭
-    #[cfg(target_os = "android")]
-    if let Some(handle) = writer_handle {
-        let storage = app.state::<AndroidStorage>();
-        if let Err(e) = storage.close_writer(handle) {
-            eprintln!("Failed to close SAF writer: {}", e);
+/// 打包为 zip：连接在批次的两个文件之间中断（而非发送端正常关闭）时丢弃未完成的压缩包。
+/// 文件中途中断时由未完成文件的处理一并丢弃
+fn abort_dropped_zip(dropped: bool, open_zip: &mut Option<String>) {
+    if let Some(batch_id) = open_zip.take().filter(|_| dropped) {
+        receive_zip::abort(&batch_id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_minimal_file_meta() {
+        let meta = parse_file_meta("10.0.0.2", r#"{"name":"a.txt","size":3}"#).unwrap();
+        assert_eq!(meta.name, "a.txt");
+        assert_eq!(meta.size, 3);
+        assert_eq!(meta.total, 0);
+        assert!(!meta.compress);
+    }
+
+    #[test]
+    fn malformed_file_meta_is_reported() {
+        for raw in [r#"{"size":3}"#, r#"{"name":"a.txt","size":-1}"#, r#"{"name":"a.txt","#, "not json"] {
+            let bad = match parse_file_meta("10.0.0.2", raw) {
+                Err(bad) => bad,
+                Ok(_) => panic!("accepted malformed metadata {}", raw),
+            };
+            assert_eq!(bad.peer_ip, "10.0.0.2");
+            assert_eq!(bad.raw, raw);
+            assert!(!bad.error.is_empty());
+        }
+    }
+
+    #[test]
+    fn malformed_file_meta_preview_is_truncated() {
+        let raw = format!("{{\"name\":\"{}\"", "é".repeat(1000));
+        let bad = parse_file_meta("10.0.0.2", &raw).err().unwrap();
+        assert_eq!(bad.raw.chars().count(), BAD_METADATA_PREVIEW_CHARS);
+        assert!(raw.starts_with(&bad.raw));
+    }
+
+    fn meta(value: serde_json::Value) -> FileMeta {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn validate_meta_accepts_plain_files() {
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.txt", "size": 3, "index": 0, "total": 1}))).is_ok());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.txt", "relative_path": "docs/sub/a.txt"}))).is_ok());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "console.log"}))).is_ok());
+    }
+
+    #[test]
+    fn validate_meta_rejects_traversal_names() {
+        for name in ["", ".", "..", "../a.txt", "a/b.txt", "a\\b.txt"] {
+            assert!(validate_meta(&meta(serde_json::json!({"name": name}))).is_err(), "accepted {:?}", name);
+        }
+        for path in ["../a.txt", "docs/../../a.txt", "docs\\..\\a.txt"] {
+            let value = serde_json::json!({"name": "a.txt", "relative_path": path});
+            assert!(validate_meta(&meta(value)).is_err(), "accepted {:?}", path);
+        }
+    }
+
+    #[test]
+    fn validate_meta_rejects_absolute_paths() {
+        for path in ["/etc/passwd", "\\server\\share\\a.txt", "C:\\Windows\\a.txt", "c:a.txt"] {
+            let value = serde_json::json!({"name": "a.txt", "relative_path": path});
+            assert!(validate_meta(&meta(value)).is_err(), "accepted {:?}", path);
+        }
+    }
+
+    #[test]
+    fn validate_meta_rejects_control_characters() {
+        for name in ["a\0.txt", "a\n.txt", "a\u{7f}.txt"] {
+            assert!(validate_meta(&meta(serde_json::json!({"name": name}))).is_err(), "accepted {:?}", name);
+        }
+        let value = serde_json::json!({"name": "a.txt", "relative_path": "docs\0/a.txt"});
+        assert!(validate_meta(&meta(value)).is_err());
+    }
+
+    #[test]
+    fn validate_meta_rejects_reserved_windows_names() {
+        for name in ["CON", "con.txt", "Nul", "aux.tar.gz", "COM1", "lpt9.log", "PRN ."] {
+            assert!(validate_meta(&meta(serde_json::json!({"name": name}))).is_err(), "accepted {:?}", name);
+        }
+        let value = serde_json::json!({"name": "a.txt", "relative_path": "docs/con/a.txt"});
+        assert!(validate_meta(&meta(value)).is_err());
+        for name in ["COM0", "COM10", "LPT", "icon.png"] {
+            assert!(validate_meta(&meta(serde_json::json!({"name": name}))).is_ok(), "rejected {:?}", name);
+        }
+    }
+
+    #[test]
+    fn validate_meta_rejects_oversized_files() {
+        let max = MAX_RECEIVE_FILE_SIZE.load(Ordering::Relaxed);
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.bin", "size": max}))).is_ok());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.bin", "size": max + 1}))).is_err());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.bin", "size": u64::MAX}))).is_err());
+        let long_name = format!("{}.txt", "a".repeat(MAX_FILE_NAME_BYTES));
+        assert!(validate_meta(&meta(serde_json::json!({"name": long_name}))).is_err());
+    }
+
+    #[test]
+    fn validate_meta_rejects_out_of_range_index() {
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.txt", "index": 1, "total": 1}))).is_err());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.txt", "total": MAX_BATCH_FILES + 1}))).is_err());
+        assert!(validate_meta(&meta(serde_json::json!({"name": "a.txt", "size": 3, "resume_from": 4}))).is_err());
+    }
+
+    /// 测试用的临时目录，名称按测试名与进程号区分
+    #[cfg(unix)]
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lan-transfer-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_dir_jail_rejects_symlinked_subdirectory() {
+        let base = temp_dir("jail");
+        let save_dir = base.join("save");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(save_dir.join("inner")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, save_dir.join("escape")).unwrap();
+        std::os::unix::fs::symlink(&save_dir, save_dir.join("inner/loop")).unwrap();
+
+        assert!(is_within_save_dir(&save_dir, &save_dir.join("inner/a.txt")));
+        assert!(is_within_save_dir(&save_dir, &save_dir.join("new/dir/a.txt")));
+        assert!(is_within_save_dir(&save_dir, &save_dir.join("inner/loop/inner/loop/a.txt")));
+        assert!(!is_within_save_dir(&save_dir, &save_dir.join("escape/a.txt")));
+        assert!(!is_within_save_dir(&save_dir, &save_dir.join("escape/new/a.txt")));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_files_skips_symlink_loops() {
+        let root = temp_dir("collect");
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a/one.txt"), b"1").unwrap();
+        std::fs::write(root.join("b/two.txt"), b"22").unwrap();
+        // 指回根目录，以及 a、b 之间互相指向
+        std::os::unix::fs::symlink(&root, root.join("a/up")).unwrap();
+        std::os::unix::fs::symlink(root.join("b"), root.join("a/to_b")).unwrap();
+        std::os::unix::fs::symlink(root.join("a"), root.join("b/to_a")).unwrap();
+
+        let mut files = Vec::new();
+        collect_files(&root, "root", &mut files, &FolderFilter::default(), None).unwrap();
+        let mut paths: Vec<&str> = files.iter().map(|f| f.relative_path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, ["root/a/one.txt", "root/a/to_b/two.txt", "root/b/to_a/one.txt", "root/b/two.txt"]);
+
+        let mut validation = FolderValidation::default();
+        let mut files = Vec::new();
+        collect_files(&root, "root", &mut files, &FolderFilter::default(), Some(&mut validation)).unwrap();
+        assert!(validation.unreadable.is_empty());
+        assert!(validation.symlinks.iter().any(|link| link == "root/a/up"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 模拟 8 MiB/s 链路上的 Android 大文件发送：每帧按大小占用相应的（虚拟）时间，
+    /// 读取端每次返回 1 MiB。取消后应在一帧之内停止，不再读取新的块
+    #[tokio::test(start_paused = true)]
+    async fn android_send_stops_within_a_frame_of_cancel() {
+        const LINK_BYTES_PER_SEC: f64 = 8.0 * 1024.0 * 1024.0;
+        let frame_time = Duration::from_secs_f64(SEND_FRAME_SIZE as f64 / LINK_BYTES_PER_SEC);
+        let mut sink = Box::pin(futures_util::sink::unfold((), |(), msg: Message| async move {
+            tokio::time::sleep(Duration::from_secs_f64(msg.len() as f64 / LINK_BYTES_PER_SEC)).await;
+            Ok::<_, std::convert::Infallible>(())
+        }));
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_at = Arc::new(Mutex::new(None));
+        {
+            let (cancelled, cancelled_at) = (cancelled.clone(), cancelled_at.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                *cancelled_at.lock().unwrap() = Some(tokio::time::Instant::now());
+                cancelled.store(true, Ordering::SeqCst);
+            });
         }
+
+        let mut reads = 0;
+        let read_chunk = |_offset: u64, buffer: &mut Vec<u8>| {
+            reads += 1;
+            buffer.clear();
+            buffer.resize(ANDROID_READ_CHUNK_SIZE as usize, 0x5a);
+            Ok(buffer.len())
+        };
+        let mut buffer = Vec::new();
+        let mut bytes_sent = 0;
+        let mut pacer = rate_limit::SendPacer::new("cancel-latency-test", None);
+        let end = send_chunks(&mut sink, read_chunk, &mut buffer, 1 << 40, &mut bytes_sent, false, &cancelled, None, &mut pacer, |_| {})
+            .await
+            .unwrap();
+
+        assert!(matches!(end, ChunksEnd::Cancelled), "{:?}", end);
+        let latency = cancelled_at.lock().unwrap().unwrap().elapsed();
+        assert!(latency <= frame_time, "cancel took {:?}", latency);
+        // 约 1 秒的数据（8 MiB）之后不再继续读取
+        assert!(bytes_sent <= 9 * 1024 * 1024, "sent {} bytes", bytes_sent);
+        assert!(reads <= 9, "read {} chunks", reads);
     }
 
-    // 确保文件被正确关闭和刷新
-    if let Some(mut f) = file {
-        let _ = f.flush().await;
+    #[tokio::test(start_paused = true)]
+    async fn android_send_reports_every_frame_until_done() {
+        let mut sink = Box::pin(futures_util::sink::unfold(0usize, |frames, _msg: Message| async move {
+            Ok::<_, std::convert::Infallible>(frames + 1)
+        }));
+        let data: Vec<u8> = (0..(2 * ANDROID_READ_CHUNK_SIZE as u32 + 100)).map(|i| (i % 251) as u8).collect();
+        let read_chunk = |offset: u64, buffer: &mut Vec<u8>| {
+            let start = (offset as usize).min(data.len());
+            let end = (start + ANDROID_READ_CHUNK_SIZE as usize).min(data.len());
+            buffer.clear();
+            buffer.extend_from_slice(&data[start..end]);
+            Ok(buffer.len())
+        };
+        let cancelled = AtomicBool::new(false);
+        let mut hasher = Context::new(&SHA256);
+        let mut progress = Vec::new();
+        let mut buffer = Vec::new();
+        let mut bytes_sent = 0;
+        let mut pacer = rate_limit::SendPacer::new("chunk-progress-test", None);
+        let end = send_chunks(
+            &mut sink, read_chunk, &mut buffer, data.len() as u64, &mut bytes_sent,
+            false, &cancelled, Some(&mut hasher), &mut pacer, |sent| progress.push(sent),
+        ).await.unwrap();
+
+        assert!(matches!(end, ChunksEnd::Done), "{:?}", end);
+        assert_eq!(bytes_sent, data.len() as u64);
+        assert_eq!(progress.last(), Some(&(data.len() as u64)));
+        assert_eq!(progress.len(), data.len().div_ceil(SEND_FRAME_SIZE));
+        assert_eq!(hasher.finish().as_ref(), digest::digest(&SHA256, &data).as_ref());
     }
 
-    // 通知前端接收完成
-    if let Some(name) = file_name {
-        let _ = window.emit("file-received", serde_json::json!({
-            "name": name,
-            "size": bytes_received
+    #[tokio::test]
+    async fn android_send_reports_a_failed_write() {
+        let mut sink = Box::pin(futures_util::sink::unfold((), |(), _msg: Message| async move {
+            Err::<(), _>("connection reset")
         }));
-        println!("File received: {} ({} bytes)", name, bytes_received);
+        let cancelled = AtomicBool::new(false);
+        let mut bytes_sent = 0;
+        let mut pacer = rate_limit::SendPacer::new("chunk-failure-test", None);
+        let end = send_chunks(
+            &mut sink, |_, buffer: &mut Vec<u8>| { *buffer = vec![1; 10]; Ok(10) }, &mut Vec::new(), 10, &mut bytes_sent,
+            false, &cancelled, None, &mut pacer, |_| {},
+        ).await.unwrap();
+
+        match end {
+            ChunksEnd::WriteFailed(error) => assert_eq!(error.code(), "connection_failed"),
+            other => panic!("unexpected end {:?}", other),
+        }
+        assert_eq!(bytes_sent, 0);
     }
 
-    Ok(())
-}
+    #[test]
+    fn sort_files_orders_by_each_sort_order() {
+        let files = [("b/two.txt", 20), ("a/one.txt", 300), ("c/three.txt", 20), ("a/four.txt", 1)];
+        let sorted = |order| {
+            let mut files = files;
+            sort_files(&mut files, order, |f| (f.0, f.1));
+            files.map(|f| f.0)
+        };
+        assert_eq!(sorted(SortOrder::None), ["b/two.txt", "a/one.txt", "c/three.txt", "a/four.txt"]);
+        assert_eq!(sorted(SortOrder::NameAsc), ["a/four.txt", "a/one.txt", "b/two.txt", "c/three.txt"]);
+        // 相同大小保持原有顺序
+        assert_eq!(sorted(SortOrder::SizeAsc), ["a/four.txt", "b/two.txt", "c/three.txt", "a/one.txt"]);
+        assert_eq!(sorted(SortOrder::SizeDesc), ["a/one.txt", "b/two.txt", "c/three.txt", "a/four.txt"]);
+    }
+
+    #[tokio::test]
+    async fn resumed_checksum_covers_the_whole_file() {
+        use tokio::io::AsyncReadExt;
+
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = std::io::Cursor::new(data.clone());
+        let mut context = Context::new(&SHA256);
+        hash_prefix(&mut reader, 400_000, |chunk| context.update(chunk)).await.unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, &data[400_000..]);
+        context.update(&rest);
+        assert_eq!(context.finish().as_ref(), digest::digest(&SHA256, &data).as_ref());
+
+        let mut short = std::io::Cursor::new(vec![0u8; 10]);
+        assert!(hash_prefix(&mut short, 11, |_| {}).await.is_err());
+    }
+
+    #[test]
+    fn discovery_group_defaults_and_validation() {
+        assert_eq!(parse_discovery_group(None, None).unwrap(), SocketAddrV4::new(MULTICAST_GROUP, DISCOVERY_PORT));
+        assert_eq!(
+            parse_discovery_group(Some(" 239.1.2.3 "), Some(40000)).unwrap(),
+            SocketAddrV4::new(Ipv4Addr::new(239, 1, 2, 3), 40000),
+        );
+        for addr in ["224.0.0.251", "192.168.1.10", "not an address", "239.1.2"] {
+            let err = parse_discovery_group(Some(addr), None).unwrap_err();
+            assert_eq!(err.code(), "invalid_input", "{}", addr);
+        }
+        assert_eq!(parse_discovery_group(None, Some(0)).unwrap_err().code(), "invalid_input");
+    }
 
+    #[test]
+    fn ipv6_discovery_group_follows_the_ipv4_group() {
+        assert_eq!(discovery_group_v6(MULTICAST_GROUP), MULTICAST_GROUP_V6);
+        let custom = discovery_group_v6(Ipv4Addr::new(239, 1, 2, 3));
+        assert_eq!(custom, "ff02::ef01:203".parse::<Ipv6Addr>().unwrap());
+        assert!(custom.is_multicast());
+        assert_ne!(custom, discovery_group_v6(Ipv4Addr::new(239, 1, 2, 4)));
+    }
+
+    #[test]
+    fn bad_metadata_close_is_a_rejection() {
+        assert!(matches!(receiver_close_error(BAD_METADATA_CODE), Some(TransferError::Rejected(_))));
+        assert!(matches!(receiver_close_error(INVALID_METADATA_CODE), Some(TransferError::Rejected(_))));
+    }
+}
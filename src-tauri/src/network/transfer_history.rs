@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::Mutex;
+use tauri::{AppHandle, Window};
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+use crate::network::error::TransferError;
+use crate::network::transfer::{SendOptions, SendSource};
+
+const HISTORY_FILE: &str = "transfer_history.jsonl";
+
+// 追加写入需串行，避免多条记录交错
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferRecord {
+    /// 发送记录为批次 ID（续传时沿用，同一 ID 以最新一条为准）
+    pub id: String,
+    pub timestamp: i64,
+    /// "send" 或 "receive"
+    pub direction: String,
+    /// 发送目标（IP 或设备名）或接收来源 IP
+    pub peer_ip: String,
+    /// 发送时指定的对端传输端口，重新发送时沿用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 单个文件的记录：文件名（含相对路径）与已传输的字节数；整次发送的记录中没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// 发送记录：重新发送所需的来源与参数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SendSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<SendOptions>,
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+fn append_record(app: &AppHandle, record: &TransferRecord) -> Result<(), String> {
+    let path = crate::config::config_path(app, HISTORY_FILE)?;
+    let line = serde_json::to_string(record)
+        .map_err(|e| format!("Failed to serialize transfer record: {}", e))?;
+
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    writeln!(file, "{}", line)
+        .map_err(|e| format!("Failed to write transfer record: {}", e))
+}
+
+fn load_records(app: &AppHandle) -> Vec<TransferRecord> {
+    let Ok(path) = crate::config::config_path(app, HISTORY_FILE) else {
+        return Vec::new();
+    };
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let Ok(file) = std::fs::File::open(&path) else {
+        return Vec::new();
+    };
+    // 跳过损坏的行（例如崩溃时写了一半）
+    std::io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// 记录一次发送的结果（在后台写入，不阻塞调用方）
+pub(crate) fn record_send(
+    app: &AppHandle,
+    batch_id: String,
+    target: String,
+    port: Option<u16>,
+    source: SendSource,
+    options: SendOptions,
+    result: &Result<(), TransferError>,
+) {
+    let record = TransferRecord {
+        id: batch_id,
+        timestamp: now_millis(),
+        direction: "send".to_string(),
+        peer_ip: target,
+        port,
+        success: result.is_ok(),
+        error: result.as_ref().err().map(TransferError::to_string),
+        file_name: None,
+        size: None,
+        source: Some(source),
+        options: Some(options),
+    };
+    append_in_background(app, record);
+}
+
+/// 记录单个文件的发送或接收结果，error 为 None 表示成功（在后台写入，不阻塞传输）
+pub(crate) fn record_file(
+    app: &AppHandle,
+    id: &str,
+    direction: &str,
+    peer_ip: &str,
+    file_name: &str,
+    size: u64,
+    error: Option<String>,
+) {
+    let record = TransferRecord {
+        id: id.to_string(),
+        timestamp: now_millis(),
+        direction: direction.to_string(),
+        peer_ip: peer_ip.to_string(),
+        port: None,
+        success: error.is_none(),
+        error,
+        file_name: Some(file_name.to_string()),
+        size: Some(size),
+        source: None,
+        options: None,
+    };
+    append_in_background(app, record);
+}
+
+fn append_in_background(app: &AppHandle, record: TransferRecord) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = append_record(&app, &record) {
+            eprintln!("Failed to record transfer: {}", e);
+        }
+    });
+}
+
+/// 检查发送来源是否仍然存在，返回缺失的项
+pub(crate) fn missing_sources(source: &SendSource, app: &AppHandle) -> Vec<String> {
+    match source {
+        SendSource::FolderDesktop { folder_path } => {
+            if std::path::Path::new(folder_path).is_dir() {
+                Vec::new()
+            } else {
+                vec![folder_path.clone()]
+            }
+        }
+        SendSource::FilesDesktop { paths } => {
+            paths.iter()
+                .filter(|path| !std::path::Path::new(path).is_file())
+                .cloned()
+                .collect()
+        }
+        #[cfg(target_os = "android")]
+        SendSource::FilesAndroid { uris } => {
+            let storage = app.state::<AndroidStorage>();
+            uris.iter()
+                .filter(|uri| storage.get_file_info(uri.to_string()).is_err())
+                .cloned()
+                .collect()
+        }
+        #[cfg(target_os = "android")]
+        SendSource::FolderAndroid { files } => {
+            let storage = app.state::<AndroidStorage>();
+            files.iter()
+                .filter(|f| storage.get_file_info(f.uri.clone()).is_err())
+                .map(|f| f.relative_path.clone())
+                .collect()
+        }
+        #[cfg(not(target_os = "android"))]
+        SendSource::FilesAndroid { .. } | SendSource::FolderAndroid { .. } => {
+            let _ = app;
+            Vec::new()
+        }
+    }
+}
+
+#[tauri::command]
+/// 重新发送一次失败或取消的发送：沿用原来源、目标与参数，并按原批次续传（跳过接收端已完成的文件）
+pub async fn retry_transfer(record_id: String, window: Window, app: AppHandle) -> Result<String, TransferError> {
+    let record = load_records(&app)
+        .into_iter()
+        .rev()
+        .find(|r| r.id == record_id && r.file_name.is_none())
+        .ok_or_else(|| TransferError::NotFound(format!("Transfer record not found: {}", record_id)))?;
+
+    if record.direction != "send" {
+        return Err(TransferError::InvalidInput("Only sent transfers can be retried".into()));
+    }
+    if record.success {
+        return Err(TransferError::InvalidInput("Transfer already completed".into()));
+    }
+    let source = record.source
+        .ok_or_else(|| TransferError::InvalidInput("Transfer record has no send parameters".to_string()))?;
+
+    let missing = missing_sources(&source, &app);
+    if !missing.is_empty() {
+        return Err(TransferError::NotFound(format!("Source files no longer exist: {}", missing.join(", "))));
+    }
+
+    let mut options = record.options.unwrap_or_default();
+    options.resume_batch_id = Some(record.id);
+
+    println!("Retrying transfer {} to {}", record_id, record.peer_ip);
+    crate::network::transfer::send_source(source, record.peer_ip, record.port, options, window, app).await
+}
+
+#[tauri::command]
+/// 查询传输历史，最新的在前，最多返回 limit 条
+pub async fn get_transfer_history(limit: usize, app: AppHandle) -> Result<Vec<TransferRecord>, TransferError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut records = load_records(&app);
+        records.reverse();
+        records.truncate(limit);
+        records
+    }).await.map_err(|e| TransferError::Other(format!("Failed to read transfer history: {}", e)))
+}
+
+#[tauri::command]
+/// 清空传输历史（之后无法再重试其中失败的发送）
+pub fn clear_transfer_history(app: AppHandle) -> Result<(), TransferError> {
+    let path = crate::config::config_path(&app, HISTORY_FILE).map_err(TransferError::Io)?;
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(TransferError::Io(format!("Failed to clear transfer history: {}", e))),
+    }
+}
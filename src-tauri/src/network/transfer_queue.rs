@@ -0,0 +1,294 @@
+use serde::Serialize;
+use std::sync::{LazyLock, Mutex};
+use tauri::{AppHandle, Window};
+
+use crate::network::error::TransferError;
+use crate::network::transfer::{SendOptions, SendSource};
+use crate::network::window_events;
+
+// 默认同时进行的发送数
+const DEFAULT_MAX_ACTIVE: usize = 1;
+
+/// 队列中发送的状态
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    Pending,
+    Active,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// `queue-updated` 事件内容，也是 list_queue 的条目
+#[derive(Serialize, Clone, Debug)]
+pub struct QueueEntry {
+    pub id: String,
+    pub target_ip: String,
+    pub source: SendSource,
+    pub status: QueueStatus,
+    /// 失败原因
+    pub error: Option<String>,
+}
+
+struct QueuedSend {
+    entry: QueueEntry,
+    /// 对端传输端口，None 时按发现结果或默认端口
+    port: Option<u16>,
+    options: SendOptions,
+}
+
+struct TransferQueue {
+    /// 等待中与进行中的发送，按开始顺序排列（结束后移除）
+    sends: Vec<QueuedSend>,
+    /// 暂停时不再开始新的发送，进行中的继续完成
+    paused: bool,
+    max_active: usize,
+    next_id: u64,
+}
+
+impl TransferQueue {
+    fn new() -> Self {
+        TransferQueue {
+            sends: Vec::new(),
+            paused: false,
+            max_active: DEFAULT_MAX_ACTIVE,
+            next_id: 0,
+        }
+    }
+
+    /// 按并发上限把排在最前面的等待中的发送标记为进行中，返回要开始的发送
+    fn take_startable(&mut self) -> Vec<(QueueEntry, Option<u16>, SendOptions)> {
+        if self.paused {
+            return Vec::new();
+        }
+        let active = self.sends.iter().filter(|s| s.entry.status == QueueStatus::Active).count();
+        let free = self.max_active.saturating_sub(active);
+        self.sends.iter_mut()
+            .filter(|s| s.entry.status == QueueStatus::Pending)
+            .take(free)
+            .map(|s| {
+                s.entry.status = QueueStatus::Active;
+                // 以队列条目 ID 作为批次 ID，cancel_file_sending / cancel_job 可直接使用 enqueue_send 返回的 ID
+                s.options.batch_id = Some(s.entry.id.clone());
+                (s.entry.clone(), s.port, s.options.clone())
+            })
+            .collect()
+    }
+
+    /// 将位置 from 的条目移到位置 to
+    fn reorder(&mut self, from: usize, to: usize) -> Result<(), TransferError> {
+        let len = self.sends.len();
+        if from >= len || to >= len {
+            return Err(TransferError::InvalidInput(format!("Queue position out of range (queue has {} entries)", len)));
+        }
+        let send = self.sends.remove(from);
+        self.sends.insert(to, send);
+        Ok(())
+    }
+}
+
+static QUEUE: LazyLock<Mutex<TransferQueue>> = LazyLock::new(|| Mutex::new(TransferQueue::new()));
+
+/// 按并发上限开始排在最前面的等待中的发送
+fn start_pending(window: &Window, app: &AppHandle) {
+    let started = QUEUE.lock().unwrap().take_startable();
+
+    for (entry, port, options) in started {
+        window_events::emit(window, "queue-updated", &entry);
+        let (window, app) = (window.clone(), app.clone());
+        tauri::async_runtime::spawn(async move {
+            let result = crate::network::transfer::send_source(
+                entry.source.clone(),
+                entry.target_ip.clone(),
+                port,
+                options,
+                window.clone(),
+                app.clone(),
+            ).await;
+            finish(&window, &entry.id, result);
+            start_pending(&window, &app);
+        });
+    }
+}
+
+/// 记录发送结果并移出队列
+fn finish(window: &Window, id: &str, result: Result<String, TransferError>) {
+    let entry = {
+        let mut queue = QUEUE.lock().unwrap();
+        let Some(pos) = queue.sends.iter().position(|s| s.entry.id == id) else {
+            return;
+        };
+        let mut entry = queue.sends.remove(pos).entry;
+        match result {
+            Ok(_) => entry.status = QueueStatus::Done,
+            Err(TransferError::Cancelled(_)) => entry.status = QueueStatus::Cancelled,
+            Err(e) => {
+                entry.status = QueueStatus::Failed;
+                entry.error = Some(e.to_string());
+            }
+        }
+        entry
+    };
+    window_events::emit(window, "queue-updated", entry);
+}
+
+#[tauri::command]
+/// 将一次发送加入队列，返回队列条目 ID。队列按顺序开始发送，同时进行的数量由 set_queue_concurrency 设置（默认 1）。
+/// port 为对端传输端口，None 时按设备发现结果或默认端口
+pub fn enqueue_send(
+    source: SendSource,
+    target_ip: String,
+    options: Option<SendOptions>,
+    port: Option<u16>,
+    window: Window,
+    app: AppHandle,
+) -> Result<String, TransferError> {
+    let entry = {
+        let mut queue = QUEUE.lock().unwrap();
+        queue.next_id += 1;
+        let entry = QueueEntry {
+            id: format!("queue-{}", queue.next_id),
+            target_ip,
+            source,
+            status: QueueStatus::Pending,
+            error: None,
+        };
+        queue.sends.push(QueuedSend { entry: entry.clone(), port, options: options.unwrap_or_default() });
+        entry
+    };
+    window_events::emit(&window, "queue-updated", &entry);
+
+    start_pending(&window, &app);
+    Ok(entry.id)
+}
+
+#[tauri::command]
+/// 取消队列中的发送：等待中的直接移出队列，进行中的按其批次 ID 取消。两种情况都以 Cancelled 状态发送 `queue-updated`
+pub fn cancel_queued_send(id: String, window: Window) -> Result<(), TransferError> {
+    let active_batch = {
+        let mut queue = QUEUE.lock().unwrap();
+        let pos = queue.sends.iter().position(|s| s.entry.id == id)
+            .ok_or_else(|| TransferError::NotFound(format!("No queued send with id {}", id)))?;
+        if queue.sends[pos].entry.status == QueueStatus::Pending {
+            let mut entry = queue.sends.remove(pos).entry;
+            entry.status = QueueStatus::Cancelled;
+            drop(queue);
+            window_events::emit(&window, "queue-updated", entry);
+            return Ok(());
+        }
+        let options = &queue.sends[pos].options;
+        options.resume_batch_id.clone().or_else(|| options.batch_id.clone()).unwrap_or(id)
+    };
+    // 发送结束后由 finish 记录 Cancelled 状态并发送 queue-updated
+    crate::network::transfer::cancel_file_sending(active_batch)
+}
+
+#[tauri::command]
+/// 列出队列中等待中与进行中的发送（按开始顺序）
+pub fn list_queue() -> Vec<QueueEntry> {
+    QUEUE.lock().unwrap().sends.iter().map(|s| s.entry.clone()).collect()
+}
+
+#[tauri::command]
+/// 暂停队列：不再开始新的发送，进行中的发送继续完成
+pub fn pause_queue() {
+    QUEUE.lock().unwrap().paused = true;
+}
+
+#[tauri::command]
+/// 恢复队列并开始等待中的发送
+pub fn resume_queue(window: Window, app: AppHandle) {
+    QUEUE.lock().unwrap().paused = false;
+    start_pending(&window, &app);
+}
+
+#[tauri::command]
+/// 调整队列顺序：将位置 from 的条目移到位置 to（位置为 list_queue 中的下标）
+pub fn reorder_queue(from: usize, to: usize) -> Result<(), TransferError> {
+    QUEUE.lock().unwrap().reorder(from, to)
+}
+
+#[tauri::command]
+/// 设置同时进行的发送数（至少为 1），调大时立即开始更多等待中的发送
+pub fn set_queue_concurrency(max_active: usize, window: Window, app: AppHandle) -> Result<(), TransferError> {
+    if max_active == 0 {
+        return Err(TransferError::InvalidInput("Queue concurrency must be at least 1".to_string()));
+    }
+    QUEUE.lock().unwrap().max_active = max_active;
+    start_pending(&window, &app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_of(count: usize) -> TransferQueue {
+        let mut queue = TransferQueue::new();
+        for n in 1..=count {
+            queue.sends.push(QueuedSend {
+                entry: QueueEntry {
+                    id: format!("queue-{}", n),
+                    target_ip: "10.0.0.2".to_string(),
+                    source: SendSource::FolderDesktop { folder_path: format!("/data/{}", n) },
+                    status: QueueStatus::Pending,
+                    error: None,
+                },
+                port: None,
+                options: SendOptions::default(),
+            });
+        }
+        queue
+    }
+
+    fn ids(queue: &TransferQueue) -> Vec<&str> {
+        queue.sends.iter().map(|s| s.entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn reorder_moves_one_entry() {
+        let mut queue = queue_of(4);
+        queue.reorder(3, 0).unwrap();
+        assert_eq!(ids(&queue), ["queue-4", "queue-1", "queue-2", "queue-3"]);
+        queue.reorder(0, 3).unwrap();
+        assert_eq!(ids(&queue), ["queue-1", "queue-2", "queue-3", "queue-4"]);
+        assert!(matches!(queue.reorder(1, 4), Err(TransferError::InvalidInput(_))));
+        assert!(matches!(queue.reorder(4, 0), Err(TransferError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn starts_pending_sends_in_order_up_to_the_limit() {
+        let mut queue = queue_of(4);
+        queue.reorder(2, 0).unwrap();
+        queue.max_active = 2;
+
+        let started = queue.take_startable();
+        let started_ids: Vec<&str> = started.iter().map(|(entry, _, _)| entry.id.as_str()).collect();
+        assert_eq!(started_ids, ["queue-3", "queue-1"]);
+        // 以条目 ID 作为批次 ID
+        assert!(started.iter().all(|(entry, _, options)| options.batch_id.as_deref() == Some(entry.id.as_str())));
+        // 已达到并发上限
+        assert!(queue.take_startable().is_empty());
+
+        // 一个发送结束后开始下一个
+        queue.sends.remove(0);
+        let started = queue.take_startable();
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].0.id, "queue-2");
+
+        // 调大并发上限时开始更多
+        queue.max_active = 4;
+        assert_eq!(queue.take_startable().len(), 1);
+    }
+
+    #[test]
+    fn paused_queue_starts_nothing() {
+        let mut queue = queue_of(2);
+        queue.paused = true;
+        assert!(queue.take_startable().is_empty());
+        assert!(queue.sends.iter().all(|s| s.entry.status == QueueStatus::Pending));
+        queue.paused = false;
+        assert_eq!(queue.take_startable().len(), 1);
+    }
+}
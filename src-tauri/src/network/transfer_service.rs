@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tauri::AppHandle;
+#[cfg(target_os = "android")]
+use std::sync::atomic::AtomicBool;
+#[cfg(target_os = "android")]
+use std::sync::Mutex;
+#[cfg(target_os = "android")]
+use std::time::Duration;
+#[cfg(target_os = "android")]
+use tauri::Manager;
+
+#[cfg(target_os = "android")]
+use crate::android_storage::AndroidStorage;
+#[cfg(target_os = "android")]
+use crate::network::jobs::{self, JobKind};
+
+// 进行中的发送/接收数：从 0 变为 1 时启动 Android 前台服务，归零时停止
+static ACTIVE_TRANSFERS: AtomicUsize = AtomicUsize::new(0);
+// 前台服务是否在运行。启动、停止与通知刷新都在持有该锁时进行，停止后的刷新不会重新拉起服务
+#[cfg(target_os = "android")]
+static SERVICE_RUNNING: Mutex<bool> = Mutex::new(false);
+// 通知进度刷新任务是否在运行（在持有 SERVICE_RUNNING 时读写）
+#[cfg(target_os = "android")]
+static UPDATER_RUNNING: AtomicBool = AtomicBool::new(false);
+// 通知进度的刷新间隔
+#[cfg(target_os = "android")]
+const NOTIFICATION_UPDATE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 传输存活期间持有，释放（包括任务被中止）时减少计数
+pub(crate) struct TransferServiceGuard {
+    app: AppHandle,
+}
+
+impl Drop for TransferServiceGuard {
+    fn drop(&mut self) {
+        if ACTIVE_TRANSFERS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            stop(&self.app);
+        }
+    }
+}
+
+/// 发送或接收开始时调用：确保前台服务在运行，应用切到后台时传输不会被系统终止
+pub(crate) fn acquire(app: &AppHandle) -> TransferServiceGuard {
+    if ACTIVE_TRANSFERS.fetch_add(1, Ordering::SeqCst) == 0 {
+        start(app);
+    }
+    TransferServiceGuard { app: app.clone() }
+}
+
+/// 通知中显示的文字与总进度（0-100，未知时为 -1），取自当前的传输任务
+#[cfg(target_os = "android")]
+fn summary() -> (String, i32) {
+    let active: Vec<jobs::Job> = jobs::list_all_jobs()
+        .into_iter()
+        .filter(|job| job.kind != JobKind::ReceiveHeld)
+        .collect();
+
+    let text = match active.as_slice() {
+        [job] => {
+            let action = if job.kind == JobKind::ReceiveActive { "Receiving" } else { "Sending" };
+            match &job.file_name {
+                Some(name) => format!("{} {}", action, name),
+                None => format!("{} files", action),
+            }
+        }
+        jobs => format!("{} transfers in progress", jobs.len()),
+    };
+
+    let done: u64 = active.iter().map(|job| job.bytes_done).sum();
+    let total: u64 = active.iter().map(|job| job.bytes_total).sum();
+    let progress = if total > 0 { (done.min(total) * 100 / total) as i32 } else { -1 };
+    (text, progress)
+}
+
+fn start(app: &AppHandle) {
+    #[cfg(target_os = "android")]
+    {
+        let (text, progress) = summary();
+        let mut running = SERVICE_RUNNING.lock().unwrap();
+        if let Err(e) = app.state::<AndroidStorage>().start_transfer_service(text, progress) {
+            eprintln!("Failed to start transfer service: {}", e);
+            return;
+        }
+        *running = true;
+
+        if !UPDATER_RUNNING.swap(true, Ordering::SeqCst) {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(NOTIFICATION_UPDATE_INTERVAL).await;
+                    let running = SERVICE_RUNNING.lock().unwrap();
+                    // 服务已停止：退出，下次启动服务时重新开始刷新
+                    if !*running {
+                        UPDATER_RUNNING.store(false, Ordering::SeqCst);
+                        break;
+                    }
+                    let (text, progress) = summary();
+                    if let Err(e) = app.state::<AndroidStorage>().update_transfer_notification(text, progress) {
+                        eprintln!("Failed to update transfer notification: {}", e);
+                    }
+                }
+            });
+        }
+    }
+
+    let _ = app;
+}
+
+fn stop(app: &AppHandle) {
+    #[cfg(target_os = "android")]
+    {
+        let mut running = SERVICE_RUNNING.lock().unwrap();
+        if let Err(e) = app.state::<AndroidStorage>().stop_transfer_service() {
+            eprintln!("Failed to stop transfer service: {}", e);
+        }
+        *running = false;
+    }
+
+    let _ = app;
+}
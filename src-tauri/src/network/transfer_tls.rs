@@ -0,0 +1,235 @@
+use base64::{engine::general_purpose, Engine as _};
+use ring::digest::{digest, SHA256};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tauri::AppHandle;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+use crate::network::error::TransferError;
+
+const TRANSFER_TLS_FILE: &str = "transfer_tls.json";
+
+// TLS 握手记录的首字节（ContentType::Handshake），据此区分 wss:// 与 ws:// 连接
+const TLS_HANDSHAKE_BYTE: u8 = 0x16;
+
+#[derive(Serialize, Deserialize, Default)]
+struct TlsSettings {
+    #[serde(default)]
+    enabled: bool,
+    /// 首次启用时生成的自签名证书与 PKCS#8 私钥（DER，base64）
+    #[serde(default)]
+    cert: Option<String>,
+    #[serde(default)]
+    key: Option<String>,
+}
+
+/// 本机证书：指纹随设备发现广播，对端连接时据此校验
+struct LocalCert {
+    fingerprint: String,
+    server_config: Arc<ServerConfig>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOCAL_CERT: Mutex<Option<LocalCert>> = Mutex::new(None);
+
+/// 证书指纹：DER 的 SHA-256（小写十六进制）
+fn fingerprint(cert: &[u8]) -> String {
+    crate::network::folder_sync::hex_digest(digest(&SHA256, cert))
+}
+
+/// 读取缓存的证书，没有时生成新的自签名证书并保存
+fn load_or_generate(app: &AppHandle, settings: &mut TlsSettings) -> Result<LocalCert, String> {
+    let decode = |value: &Option<String>| value.as_ref().and_then(|v| general_purpose::STANDARD.decode(v).ok());
+    let (cert, key) = match (decode(&settings.cert), decode(&settings.key)) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => {
+            let generated = rcgen::generate_simple_self_signed(vec!["lan-transfer".to_string()])
+                .map_err(|e| format!("Failed to generate certificate: {}", e))?;
+            let cert = generated.serialize_der()
+                .map_err(|e| format!("Failed to serialize certificate: {}", e))?;
+            let key = generated.serialize_private_key_der();
+            settings.cert = Some(general_purpose::STANDARD.encode(&cert));
+            settings.key = Some(general_purpose::STANDARD.encode(&key));
+            crate::config::save_json(app, TRANSFER_TLS_FILE, settings)?;
+            println!("Generated transfer TLS certificate {}", fingerprint(&cert));
+            (cert, key)
+        }
+    };
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(cert.clone())],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+        )
+        .map_err(|e| format!("Invalid transfer certificate: {}", e))?;
+    Ok(LocalCert {
+        fingerprint: fingerprint(&cert),
+        server_config: Arc::new(server_config),
+    })
+}
+
+/// 传输服务启动时读取设置，已启用时加载证书
+pub(crate) fn init(app: &AppHandle) {
+    let mut settings: TlsSettings = crate::config::load_json(app, TRANSFER_TLS_FILE);
+    if !settings.enabled {
+        return;
+    }
+    match load_or_generate(app, &mut settings) {
+        Ok(local) => {
+            *LOCAL_CERT.lock().unwrap() = Some(local);
+            ENABLED.store(true, Ordering::SeqCst);
+        }
+        Err(e) => eprintln!("Transfer TLS disabled: {}", e),
+    }
+}
+
+/// 启用时本机证书的指纹（随设备发现广播），未启用时为 None
+pub(crate) fn local_fingerprint() -> Option<String> {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return None;
+    }
+    LOCAL_CERT.lock().unwrap().as_ref().map(|local| local.fingerprint.clone())
+}
+
+/// 连接对端时校验的证书指纹：两端都启用时为对端在发现广播中公布的指纹，否则为 None（使用明文）
+pub(crate) fn peer_fingerprint(ip: &str) -> Option<String> {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return None;
+    }
+    crate::network::transfer::discovered_tls_fingerprint(ip)
+}
+
+/// 只信任指定指纹的证书（自签名证书无法通过 CA 校验）
+#[derive(Debug)]
+struct PinnedCert {
+    fingerprint: String,
+    provider: CryptoProvider,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("Certificate fingerprint mismatch".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// 发送端：只接受指定指纹证书的 TLS 配置
+pub(crate) fn client_config(fingerprint: &str) -> Arc<ClientConfig> {
+    let verifier = PinnedCert {
+        fingerprint: fingerprint.to_ascii_lowercase(),
+        provider: rustls::crypto::ring::default_provider(),
+    };
+    Arc::new(ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+/// 接收端的连接：对端未使用 TLS（或本机未启用）时为明文
+pub(crate) enum ServerStream {
+    Plain(TcpStream),
+    Tls(Box<tokio_rustls::server::TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for ServerStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            ServerStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ServerStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            ServerStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            ServerStream::Tls(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            ServerStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+/// 接收端：按首字节判断对端是否发起 TLS 握手，是则用本机证书完成握手，否则按明文处理（兼容旧版本）
+pub(crate) async fn accept(stream: TcpStream) -> io::Result<ServerStream> {
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).await? == 0 || first[0] != TLS_HANDSHAKE_BYTE {
+        return Ok(ServerStream::Plain(stream));
+    }
+    let config = LOCAL_CERT.lock().unwrap().as_ref().map(|local| local.server_config.clone());
+    let Some(config) = config else {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "TLS connection but transfer TLS is not enabled"));
+    };
+    let tls = tokio_rustls::TlsAcceptor::from(config).accept(stream).await?;
+    Ok(ServerStream::Tls(Box::new(tls)))
+}
+
+#[tauri::command]
+/// 启用或关闭文件传输加密（wss://）。首次启用时生成自签名证书并缓存，证书指纹随设备发现广播；
+/// 只有两端都启用时才加密，否则仍使用明文以兼容旧版本
+pub fn enable_transfer_tls(enabled: bool, app: AppHandle) -> Result<(), TransferError> {
+    let mut settings: TlsSettings = crate::config::load_json(&app, TRANSFER_TLS_FILE);
+    if enabled {
+        let local = load_or_generate(&app, &mut settings).map_err(TransferError::Other)?;
+        *LOCAL_CERT.lock().unwrap() = Some(local);
+    }
+    settings.enabled = enabled;
+    crate::config::save_json(&app, TRANSFER_TLS_FILE, &settings).map_err(TransferError::Io)?;
+    ENABLED.store(enabled, Ordering::SeqCst);
+    Ok(())
+}
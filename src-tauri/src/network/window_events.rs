@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+use tauri::{Emitter, Window};
+
+// 连续失败达到该次数即认为窗口已关闭（如移动端切到后台后 WebView 被销毁）
+const GONE_AFTER_FAILURES: u32 = 3;
+// 缓冲事件的上限，超出时丢弃最旧的
+const MAX_BUFFERED_EVENTS: usize = 500;
+
+/// 窗口关闭后对事件与进行中传输的处理方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowGonePolicy {
+    /// 停止发送事件，传输继续
+    #[default]
+    Discard,
+    /// 缓存事件，窗口重新连接后按顺序补发，传输继续
+    Buffer,
+    /// 取消进行中的发送与接收
+    Cancel,
+}
+
+static POLICY: Mutex<WindowGonePolicy> = Mutex::new(WindowGonePolicy::Discard);
+// 按窗口 label 记录：连续发送失败次数、已关闭的窗口、关闭期间缓存的事件
+static CONSECUTIVE_FAILURES: LazyLock<Mutex<HashMap<String, u32>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static GONE_WINDOWS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+static BUFFERED: Mutex<Vec<(String, String, serde_json::Value)>> = Mutex::new(Vec::new());
+
+/// 发送事件并跟踪失败：窗口关闭后不再尝试发送，只记录一次日志并按策略处理。
+/// 与窗口关闭无关的错误（如负载序列化失败）只记录日志。返回事件是否已送达窗口
+pub(crate) fn emit<S: Serialize + Clone>(window: &Window, event: &str, payload: S) -> bool {
+    let label = window.label();
+    if GONE_WINDOWS.lock().unwrap().contains(label) {
+        buffer_event(label, event, &payload);
+        return false;
+    }
+
+    let err = match window.emit(event, payload.clone()) {
+        Ok(()) => {
+            CONSECUTIVE_FAILURES.lock().unwrap().remove(label);
+            return true;
+        }
+        Err(err) => err,
+    };
+    if !is_teardown_error(&err) {
+        eprintln!("Failed to emit {}: {}", event, err);
+        return false;
+    }
+
+    let failures = {
+        let mut failures = CONSECUTIVE_FAILURES.lock().unwrap();
+        let count = failures.entry(label.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    };
+    if failures >= GONE_AFTER_FAILURES {
+        eprintln!("Window {} is gone after {} failed events", label, GONE_AFTER_FAILURES);
+        window_destroyed(label);
+    }
+    buffer_event(label, event, &payload);
+    false
+}
+
+/// 发送失败是否可能由窗口（WebView）关闭引起
+fn is_teardown_error(err: &tauri::Error) -> bool {
+    matches!(err, tauri::Error::Runtime(_) | tauri::Error::WindowNotFound | tauri::Error::WebviewNotFound)
+}
+
+/// 标记窗口已关闭并按策略处理（窗口 Destroyed 事件或连续发送失败时调用）
+pub(crate) fn window_destroyed(label: &str) {
+    if !GONE_WINDOWS.lock().unwrap().insert(label.to_string()) {
+        return;
+    }
+    CONSECUTIVE_FAILURES.lock().unwrap().remove(label);
+    let policy = *POLICY.lock().unwrap();
+    println!("Window {} is gone, policy: {:?}", label, policy);
+    if policy == WindowGonePolicy::Cancel {
+        crate::network::transfer::cancel_all_transfers();
+    }
+}
+
+fn buffer_event<S: Serialize>(label: &str, event: &str, payload: &S) {
+    if *POLICY.lock().unwrap() != WindowGonePolicy::Buffer {
+        return;
+    }
+    let Ok(value) = serde_json::to_value(payload) else {
+        return;
+    };
+    let mut buffered = BUFFERED.lock().unwrap();
+    if buffered.len() >= MAX_BUFFERED_EVENTS {
+        buffered.remove(0);
+    }
+    buffered.push((label.to_string(), event.to_string(), value));
+}
+
+#[tauri::command]
+/// 设置窗口关闭后的处理策略
+pub fn set_window_gone_policy(policy: WindowGonePolicy) {
+    *POLICY.lock().unwrap() = policy;
+    if policy != WindowGonePolicy::Buffer {
+        BUFFERED.lock().unwrap().clear();
+    }
+}
+
+#[tauri::command]
+/// 查询窗口关闭后的处理策略
+pub fn get_window_gone_policy() -> WindowGonePolicy {
+    *POLICY.lock().unwrap()
+}
+
+#[tauri::command]
+/// 前端（重新）加载后调用：恢复事件发送，并补发窗口关闭期间缓存的事件。返回补发的事件数
+pub fn window_reattached(window: Window) -> usize {
+    let label = window.label();
+    CONSECUTIVE_FAILURES.lock().unwrap().remove(label);
+    GONE_WINDOWS.lock().unwrap().remove(label);
+
+    let buffered: Vec<_> = {
+        let mut all = BUFFERED.lock().unwrap();
+        let (mine, others) = all.drain(..).partition(|(buffered_label, _, _)| buffered_label.as_str() == label);
+        *all = others;
+        mine
+    };
+    let count = buffered.len();
+    for (_, event, payload) in buffered {
+        let _ = window.emit(&event, payload);
+    }
+    count
+}
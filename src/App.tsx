@@ -8,11 +8,35 @@ interface Device {
   ip: string;
   hostname: string;
   last_seen: number;
+  // 对端公布的服务端口（旧版本不带）
+  ports?: { transfer: number; chat: number; clipboard: number } | null;
+}
+
+// 未从设备发现获知端口时使用的默认传输端口
+const DEFAULT_TRANSFER_PORT = 7878;
+
+function transferPortOf(devices: Device[], ip: string): number {
+  return devices.find(d => d.ip === ip)?.ports?.transfer ?? DEFAULT_TRANSFER_PORT;
 }
 
 interface ReceivedFile {
   name: string;
   size: number;
+  peer_ip?: string;
+  connection_id?: string;
+  note?: string | null;
+  index?: number;
+  total?: number;
+}
+
+interface ReceivingFile {
+  name: string;
+  peer_ip: string;
+  connection_id: string;
+  note: string | null;
+  index: number;
+  total: number;
+  size: number;
 }
 
 interface ChatMessage {
@@ -47,6 +71,27 @@ interface FileQueueItem {
   relativePath?: string;
 }
 
+// 传输、聊天、剪贴板命令的错误：code 用于判断类型，message 用于显示
+interface CommandError {
+  code: string;
+  message: string;
+  // code 为 connection_failed 且建立连接失败时的具体原因（connection_refused、timeout 等）
+  reason?: string;
+}
+
+function errorCode(err: unknown): string | undefined {
+  return (err as CommandError | null)?.code;
+}
+
+function commandError(code: string, message: string): CommandError {
+  return { code, message };
+}
+
+function errorMessage(err: unknown): string {
+  const message = (err as CommandError | null)?.message;
+  return typeof message === 'string' ? message : String(err);
+}
+
 type Mode = 'select' | 'send' | 'receive' | 'chat' | 'clipboard';
 type SendStatus = 'idle' | 'sending' | 'success' | 'error';
 
@@ -91,6 +136,7 @@ export default function App() {
   const [saveDirInput, setSaveDirInput] = useState<string>('');
   const [targetIp, setTargetIp] = useState<string>('');
   const [localIp, setLocalIp] = useState<string>(t('common.loading'));
+  const [localTransferPort, setLocalTransferPort] = useState<number>(DEFAULT_TRANSFER_PORT);
   const [isReceiving, setIsReceiving] = useState<boolean>(false);
   const [devices, setDevices] = useState<Device[]>([]);
   const [selectedDevice, setSelectedDevice] = useState<Device | null>(null);
@@ -98,6 +144,7 @@ export default function App() {
   const [sendingTo, setSendingTo] = useState<string>('');
   const [receivedFiles, setReceivedFiles] = useState<ReceivedFile[]>([]);
   const [receivingFile, setReceivingFile] = useState<string | null>(null);
+  const [receivingConnectionId, setReceivingConnectionId] = useState<string | null>(null);
   const [receiveCancelledFile, setReceiveCancelledFile] = useState<string | null>(null);
   const [receivingProgress, setReceivingProgress] = useState<{
     fileName: string;
@@ -114,6 +161,8 @@ export default function App() {
 
   // 取消发送标志
   const cancelSendingRef = useRef<boolean>(false);
+  // 进行中的 Rust 端发送 ID（来自 transfer-send-started 事件），用于取消
+  const sendTransferIdRef = useRef<string | null>(null);
 
   // 聊天模式状态
   const [chatMessages, setChatMessages] = useState<ChatMessage[]>([]);
@@ -172,7 +221,11 @@ export default function App() {
   useEffect(() => {
     invoke<string>('get_local_ip')
       .then(ip => setLocalIp(ip))
-      .catch(err => setLocalIp(t('error.fetchIpFailed') + err));
+      .catch(err => setLocalIp(t('error.fetchIpFailed') + errorMessage(err)));
+
+    invoke<{ transfer_port: number }>('get_status')
+      .then(status => setLocalTransferPort(status.transfer_port))
+      .catch(() => {});
 
     invoke('start_discovery');
 
@@ -213,6 +266,10 @@ export default function App() {
       setReceivingFile(event.payload);
     });
 
+    const unlistenReceivingDetails = listen<ReceivingFile>('file-receiving-details', (event) => {
+      setReceivingConnectionId(event.payload.connection_id);
+    });
+
     const unlistenReceived = listen<ReceivedFile>('file-received', (event) => {
       setReceivingFile(null);
       setReceivingProgress(null);
@@ -227,6 +284,19 @@ export default function App() {
       setTimeout(() => setReceiveCancelledFile(null), 3000);
     });
 
+    // 接收服务绑定或换端口后更新显示的端口
+    const onServerPort = (event: { payload: { service: string; port: number } }) => {
+      if (event.payload.service === 'transfer') {
+        setLocalTransferPort(event.payload.port);
+      }
+    };
+    const unlistenServerBound = listen<{ service: string; port: number }>('server-bound', onServerPort);
+    const unlistenServerRebound = listen<{ service: string; port: number }>('server-rebound', onServerPort);
+
+    const unlistenSendStarted = listen<{ batch_id: string; target_ip: string }>('transfer-send-started', (event) => {
+      sendTransferIdRef.current = event.payload.batch_id;
+    });
+
     const unlistenProgress = listen<{
       file_name: string;
       bytes_received: number;
@@ -252,8 +322,12 @@ export default function App() {
     return () => {
       unlistenDevices.then(fn => fn());
       unlistenReceiving.then(fn => fn());
+      unlistenReceivingDetails.then(fn => fn());
       unlistenReceived.then(fn => fn());
       unlistenCancelled.then(fn => fn());
+      unlistenSendStarted.then(fn => fn());
+      unlistenServerBound.then(fn => fn());
+      unlistenServerRebound.then(fn => fn());
       unlistenProgress.then(fn => fn());
     };
   }, []);
@@ -465,17 +539,19 @@ export default function App() {
       setSendingProgress(null);
       setSelectedFolderPath(null);
     } catch (error) {
-      const errorMsg = String(error);
-      if (errorMsg.includes('Cancelled by user')) {
+      const code = errorCode(error);
+      if (code === 'cancelled') {
         setSendStatus('idle');
-      } else if (errorMsg.includes('Cancelled by receiver')) {
+      } else if (code === 'cancelled_by_peer') {
         setSendStatus('idle');
         alert(t('send.cancelledByReceiver'));
       } else {
         setSendStatus('error');
-        alert(t('send.failed') + ': ' + error);
+        alert(t('send.failed') + ': ' + errorMessage(error));
       }
       setSendingProgress(null);
+    } finally {
+      sendTransferIdRef.current = null;
     }
   };
 
@@ -509,7 +585,7 @@ export default function App() {
     if (isAndroid) {
       await handleAndroidSend(device.ip);
     } else {
-      await sendFiles(device.ip);
+      await sendFiles(device.ip, device.ports?.transfer ?? DEFAULT_TRANSFER_PORT);
     }
   };
 
@@ -527,7 +603,7 @@ export default function App() {
     if (isAndroid) {
       await handleAndroidSend(targetIp);
     } else {
-      await sendFiles(targetIp);
+      await sendFiles(targetIp, transferPortOf(devices, targetIp));
     }
   };
 
@@ -553,20 +629,23 @@ export default function App() {
       setSelectedDevice(null);
     } catch (error) {
       console.error('Android send failed:', error);
-      const errorMsg = String(error);
+      const code = errorCode(error);
+      const errorMsg = errorMessage(error);
 
-      if (errorMsg.includes('Cancelled by user')) {
+      if (code === 'cancelled') {
         setSendStatus('idle');
-      } else if (errorMsg.includes('Cancelled by receiver') || errorMsg.includes('Broken pipe') || errorMsg.includes('Connection reset')) {
+      } else if (code === 'cancelled_by_peer') {
         setSendStatus('idle');
         alert(t('send.cancelledByReceiver'));
       } else {
         setSendStatus('error');
-        alert(t('send.failed') + ': ' + error);
+        alert(t('send.failed') + ': ' + errorMsg);
       }
 
       setSendingProgress(null);
       setSelectedDevice(null);
+    } finally {
+      sendTransferIdRef.current = null;
     }
   };
 
@@ -576,23 +655,25 @@ export default function App() {
       setSendingTo(ip);
       setSendingProgress(null);
 
-      // 选择文件夹并获取文件列表
-      const files: Array<{uri: string; name: string; relative_path: string; size: number}> =
-        await invoke('pick_folder_for_send');
+      // 选择文件夹并获取文件列表（含空目录、修改时间与 MIME 类型）
+      const files: Array<{
+        uri: string;
+        name: string;
+        relative_path: string;
+        size: number;
+        last_modified: number;
+        is_dir: boolean;
+        mime: string;
+      }> = await invoke('pick_folder_for_send');
 
       if (!files || files.length === 0) {
         setSendStatus('idle');
         return;
       }
 
-      // 发送文件夹中的文件（带相对路径）
+      // 发送文件夹中的文件（带相对路径），原样传递以保留空目录与修改时间
       await invoke('send_folder_android', {
-        files: files.map(f => ({
-          uri: f.uri,
-          name: f.name,
-          relative_path: f.relative_path,
-          size: f.size
-        })),
+        files,
         targetIp: ip
       });
 
@@ -601,20 +682,23 @@ export default function App() {
       setSelectedDevice(null);
     } catch (error) {
       console.error('Android send folder failed:', error);
-      const errorMsg = String(error);
+      const code = errorCode(error);
+      const errorMsg = errorMessage(error);
 
-      if (errorMsg.includes('Cancelled by user') || errorMsg.includes('No folder selected')) {
+      if (code === 'cancelled') {
         setSendStatus('idle');
-      } else if (errorMsg.includes('Cancelled by receiver') || errorMsg.includes('Broken pipe') || errorMsg.includes('Connection reset')) {
+      } else if (code === 'cancelled_by_peer') {
         setSendStatus('idle');
         alert(t('send.cancelledByReceiver'));
       } else {
         setSendStatus('error');
-        alert(t('send.failed') + ': ' + error);
+        alert(t('send.failed') + ': ' + errorMsg);
       }
 
       setSendingProgress(null);
       setSelectedDevice(null);
+    } finally {
+      sendTransferIdRef.current = null;
     }
   };
 
@@ -640,15 +724,18 @@ export default function App() {
   const sendSingleFile = async (
     file: File,
     ip: string,
+    port: number,
     index: number,
     total: number,
     queueIndex: number,
     relativePath?: string
   ): Promise<void> => {
     const HIGH_WATER_MARK = 4 * 1024 * 1024;
+    // 设置了连接 PIN 时，接收端要求连接后的第一条消息为 PIN
+    const authFrame: string | null = await invoke('get_connection_auth_frame');
 
     return new Promise((resolve, reject) => {
-      const socket = new WebSocket(`ws://${ip}:7878`);
+      const socket = new WebSocket(`ws://${ip}:${port}`);
       socket.binaryType = 'arraybuffer';
       let hasError = false;
 
@@ -659,6 +746,10 @@ export default function App() {
 
       socket.onopen = async () => {
         try {
+          if (authFrame) {
+            socket.send(authFrame);
+          }
+
           // Send metadata with new fields including relative_path
           const metadata: Record<string, unknown> = {
             name: file.name,
@@ -680,7 +771,7 @@ export default function App() {
               reader.cancel();
               socket.close();
               hasError = true;
-              reject(new Error('Cancelled by user'));
+              reject(commandError('cancelled', 'Cancelled by user'));
               return;
             }
 
@@ -695,7 +786,7 @@ export default function App() {
                 reader.cancel();
                 socket.close();
                 hasError = true;
-                reject(new Error('Cancelled by user'));
+                reject(commandError('cancelled', 'Cancelled by user'));
                 return;
               }
             }
@@ -724,20 +815,29 @@ export default function App() {
           socket.close();
         } catch (err) {
           hasError = true;
-          reject(err);
+          reject(commandError('io', errorMessage(err)));
         }
       };
 
       socket.onerror = () => {
         hasError = true;
-        reject(new Error('Connection failed'));
+        reject(commandError('connection_failed', 'Connection failed'));
       };
 
       socket.onclose = (event) => {
         if (!hasError) {
           if (event.code === 4001) {
             hasError = true;
-            reject(new Error('Cancelled by receiver'));
+            reject(commandError('cancelled_by_peer', 'Cancelled by receiver'));
+          } else if (event.code === 4003) {
+            hasError = true;
+            reject(commandError('rejected', 'Rejected by receiver'));
+          } else if (event.code === 4400) {
+            hasError = true;
+            reject(commandError('rejected', 'Metadata rejected by receiver'));
+          } else if (event.code === 4006) {
+            hasError = true;
+            reject(commandError('rejected', 'Connection PIN rejected by receiver'));
           } else {
             resolve();
           }
@@ -746,7 +846,7 @@ export default function App() {
     });
   };
 
-  const sendFiles = async (ip: string) => {
+  const sendFiles = async (ip: string, port: number) => {
     if (fileQueue.length === 0) return;
 
     cancelSendingRef.current = false; // Reset cancel flag
@@ -767,12 +867,11 @@ export default function App() {
       updateItemStatus(i, 'sending');
 
       try {
-        await sendSingleFile(fileQueue[i].file, ip, i, totalFiles, i, fileQueue[i].relativePath);
+        await sendSingleFile(fileQueue[i].file, ip, port, i, totalFiles, i, fileQueue[i].relativePath);
         updateItemStatus(i, 'completed');
       } catch (error) {
-        const errMsg = (error as Error).message;
-        const cancelledByReceiver = errMsg.includes('Cancelled by receiver');
-        updateItemStatus(i, 'failed', cancelledByReceiver ? t('send.cancelledByReceiver') : errMsg);
+        const cancelledByReceiver = errorCode(error) === 'cancelled_by_peer';
+        updateItemStatus(i, 'failed', cancelledByReceiver ? t('send.cancelledByReceiver') : errorMessage(error));
         allSucceeded = false;
         if (cancelledByReceiver) {
           alert(t('send.cancelledByReceiver'));
@@ -796,11 +895,14 @@ export default function App() {
     cancelSendingRef.current = true;
     setSendingProgress(null);
 
-    // 同时取消Android端的发送（如果正在进行）
+    // 桌面端逐个文件发送只需上面的标志；由 Rust 执行的发送（Android、文件夹）按 ID 取消
+    const transferId = sendTransferIdRef.current;
+    if (!transferId) return;
     try {
-      await invoke('cancel_file_sending');
+      await invoke('cancel_file_sending', { transferId });
     } catch (error) {
-      // Ignore error if command fails (e.g., on non-Android platforms)
+      console.error('Cancel send failed:', error);
+      alert(t('send.cancelFailed') + errorMessage(error));
     }
   };
 
@@ -820,7 +922,7 @@ export default function App() {
       chatConnectedRef.current = true;
       setChatError(null);
     } catch (err) {
-      setChatError(t('chat.connectionFailed') + err);
+      setChatError(t('chat.connectionFailed') + errorMessage(err));
       setActiveChatIp(null);
       activeChatIpRef.current = null;
     }
@@ -842,7 +944,7 @@ export default function App() {
 
       setChatInput('');
     } catch (err) {
-      alert(t('chat.sendFailed') + err);
+      alert(t('chat.sendFailed') + errorMessage(err));
     }
   };
 
@@ -900,7 +1002,7 @@ export default function App() {
     try {
       await invoke('connect_to_clipboard', { targetIp: device.ip });
     } catch (err) {
-      setClipboardError(t('clipboard.connectionFailed') + err);
+      setClipboardError(t('clipboard.connectionFailed') + errorMessage(err));
     }
   };
 
@@ -919,7 +1021,7 @@ export default function App() {
       const content = await invoke<string>('get_system_clipboard');
       setCurrentClipboard(content);
     } catch (err) {
-      setClipboardError(t('clipboard.syncFailed') + err);
+      setClipboardError(t('clipboard.syncFailed') + errorMessage(err));
     }
   };
 
@@ -1586,7 +1688,9 @@ export default function App() {
                     <button
                       onClick={async () => {
                         try {
-                          await invoke('cancel_file_receiving');
+                          if (receivingConnectionId) {
+                            await invoke('cancel_file_receiving', { transferId: receivingConnectionId });
+                          }
                         } catch (e) {
                           console.error('Failed to cancel receiving:', e);
                         }
@@ -1623,7 +1727,7 @@ export default function App() {
                     {t('receive.waiting')}
                   </div>
                   <div className="text-sm text-slate-500 mt-2">
-                    {t('receive.instruction')}<span className="font-mono text-slate-700">{localIp}:7878</span>
+                    {t('receive.instruction')}<span className="font-mono text-slate-700">{localIp}:{localTransferPort}</span>
                   </div>
                 </div>
               ) : (